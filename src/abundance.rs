@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use sourmash::collection::CollectionSet;
+use sourmash::sketch::minhash::KmerMinHash;
+use std::collections::HashMap;
+
+/// Hash abundances of the datasets of one database, for `--with-abundance`.
+/// A RevIndex only stores which datasets have a hash, so the abundances are
+/// read from the collection's sketches before the scan. Datasets indexed
+/// without abundance tracking (or left out by `mask`) have none.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DatasetAbundances {
+    /// hash -> abundance, by dataset ID
+    by_dataset: Vec<Option<HashMap<u64, u64>>>,
+}
+
+impl DatasetAbundances {
+    pub fn load(collection: &CollectionSet, mask: Option<&[bool]>) -> Result<Self> {
+        let mut by_dataset = Vec::with_capacity(collection.len());
+        for (idx, record) in collection.iter() {
+            let kept = mask.map_or(true, |mask| {
+                mask.get(idx as usize).copied().unwrap_or(false)
+            });
+            if !kept || !record.with_abundance() {
+                by_dataset.push(None);
+                continue;
+            }
+            let sig = collection.sig_for_dataset(idx).map_err(|e| {
+                anyhow!(
+                    "cannot load the sketch of '{}' for its abundances: {e}",
+                    record.name()
+                )
+            })?;
+            let mh: KmerMinHash = sig.try_into()?;
+            by_dataset.push(
+                mh.abunds()
+                    .map(|abunds| mh.mins().into_iter().zip(abunds).collect()),
+            );
+        }
+        let tracked = by_dataset.iter().filter(|d| d.is_some()).count();
+        eprintln!(
+            "Loaded abundances of {tracked} of {} datasets",
+            by_dataset.len()
+        );
+        Ok(Self { by_dataset })
+    }
+
+    /// Abundances of `hash` in each of `datasets`, in order, or `None` unless
+    /// all of them track abundance.
+    pub fn abundances(&self, hash: u64, datasets: &[u32]) -> Option<Vec<u64>> {
+        datasets
+            .iter()
+            .map(|&idx| {
+                self.by_dataset
+                    .get(idx as usize)?
+                    .as_ref()?
+                    .get(&hash)
+                    .copied()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::collections::BTreeSet;
+
+use crate::export::{check_db_compatibility, select_ksize, DatasetResolver, HASHES_CF};
+use crate::naming::DatasetNaming;
+use crate::stats::InvalidDatasets;
+
+/// Result of a health check of one RevIndex database.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CheckReport {
+    /// basename of the database
+    pub source: String,
+    /// RevIndex format version, if the database records one
+    pub format_version: Option<u8>,
+    /// keys in the `hashes` CF
+    pub keys: u64,
+    /// keys that aren't 8-byte hashes
+    pub bad_keys: u64,
+    /// values whose dataset list (or color) couldn't be parsed
+    pub bad_values: u64,
+    /// hashes with an empty dataset list
+    pub empty_values: u64,
+    /// dataset IDs that aren't in the manifest
+    pub invalid_datasets: InvalidDatasets,
+    /// (name, hashes) for each dataset, in manifest order
+    pub dataset_hashes: Vec<(String, u64)>,
+    /// every integrity problem found; empty when the database looks healthy
+    pub problems: Vec<String>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check the integrity of a RevIndex database with one full scan of its
+/// `hashes` CF: every key must be an 8-byte hash, every value a dataset list
+/// (or color) that resolves to dataset IDs in the manifest, the manifest must
+/// have one scaled and moltype per ksize, and every dataset must have hashes.
+/// Hashes are counted per dataset along the way.
+pub fn check_revindex(db_path: &Utf8Path) -> Result<CheckReport> {
+    let source = db_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot get basename of path: {db_path}"))?
+        .to_string();
+    let format_version = check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+    let mut report = CheckReport {
+        source,
+        format_version,
+        ..Default::default()
+    };
+
+    // manifest: one scaled and moltype per ksize
+    let manifest = collection.manifest();
+    if manifest.iter().next().is_none() {
+        report
+            .problems
+            .push("the manifest has no records".to_string());
+    }
+    let ksizes: BTreeSet<u32> = manifest.iter().map(|record| record.ksize()).collect();
+    for ksize in ksizes {
+        if let Err(e) = select_ksize(manifest, db_path, Some(ksize)) {
+            report.problems.push(e.to_string());
+        }
+    }
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    let mut counts = vec![0u64; collection.len()];
+    for (k, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+    {
+        report.keys += 1;
+        if k.len() != 8 {
+            report.bad_keys += 1;
+            continue;
+        }
+        let Some(datasets) = resolver.resolve(&v)? else {
+            report.bad_values += 1;
+            continue;
+        };
+        let mut invalid = Vec::new();
+        let mut empty = true;
+        for idx in datasets {
+            empty = false;
+            match counts.get_mut(idx as usize) {
+                Some(count) => *count += 1,
+                None => invalid.push(idx),
+            }
+        }
+        if empty {
+            report.empty_values += 1;
+        }
+        if !invalid.is_empty() {
+            report
+                .invalid_datasets
+                .record(LittleEndian::read_u64(&k), &invalid);
+        }
+    }
+
+    if report.bad_keys > 0 {
+        report.problems.push(format!(
+            "{} keys in the '{HASHES_CF}' CF are not 8-byte hashes",
+            report.bad_keys
+        ));
+    }
+    if report.bad_values > 0 {
+        report.problems.push(format!(
+            "{} hashes have a dataset list that can't be parsed",
+            report.bad_values
+        ));
+    }
+    if report.empty_values > 0 {
+        report
+            .problems
+            .push(format!("{} hashes have no datasets", report.empty_values));
+    }
+    let invalid = &report.invalid_datasets;
+    if invalid.hashes > 0 {
+        report.problems.push(format!(
+            "{} hashes refer to {} dataset IDs not in the manifest (e.g. hash {})",
+            invalid.hashes,
+            invalid.ids.len(),
+            invalid.example_hashes.first().copied().unwrap_or_default()
+        ));
+    }
+
+    report.dataset_hashes = manifest
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(idx, (record, count))| (DatasetNaming::default().name(idx as u32, record), count))
+        .collect();
+    let without_hashes = report
+        .dataset_hashes
+        .iter()
+        .filter(|(_, count)| *count == 0)
+        .count();
+    if without_hashes > 0 {
+        report
+            .problems
+            .push(format!("{without_hashes} datasets have no hashes"));
+    }
+    Ok(report)
+}
@@ -0,0 +1,92 @@
+use anyhow::{bail, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use crate::export::LCASummary;
+
+/// Progress for a single source (RevIndex) covered by completed part files.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct SourceProgress {
+    /// last hash written for this source; iteration resumes after it
+    pub last_hash: Option<u64>,
+    /// all hashes in this source have been written
+    pub complete: bool,
+    /// LCA summary over the hashes written so far
+    pub summary: LCASummary,
+}
+
+/// Resumable export state. Only data in finalized part files is recorded here,
+/// so a crashed run can always restart from the last saved checkpoint.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    /// final output path this checkpoint belongs to
+    pub output: String,
+    /// finalized part files, in write order
+    pub parts: Vec<String>,
+    pub rows_written: u64,
+    pub sources: BTreeMap<String, SourceProgress>,
+}
+
+impl Checkpoint {
+    pub fn new(output: &Utf8Path) -> Self {
+        Self {
+            output: output.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Load an existing checkpoint for `output`, or start a new one.
+    pub fn load_or_new(path: &Utf8Path, output: &Utf8Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new(output));
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let checkpoint: Checkpoint = serde_json::from_reader(reader)?;
+        if checkpoint.output != output.as_str() {
+            bail!(
+                "checkpoint '{path}' belongs to output '{}', not '{output}'",
+                checkpoint.output
+            );
+        }
+        for part in &checkpoint.parts {
+            if !Utf8Path::new(part).exists() {
+                bail!("checkpoint '{path}' refers to missing part file '{part}'");
+            }
+        }
+
+        eprintln!(
+            "Resuming from checkpoint '{path}': {} rows in {} part(s) already written",
+            checkpoint.rows_written,
+            checkpoint.parts.len()
+        );
+        Ok(checkpoint)
+    }
+
+    /// Atomically write the checkpoint (write to a temp file, then rename).
+    pub fn save(&self, path: &Utf8Path) -> Result<()> {
+        let tmp = Utf8PathBuf::from(format!("{path}.tmp"));
+        {
+            let writer = BufWriter::new(File::create(&tmp)?);
+            serde_json::to_writer_pretty(writer, self)?;
+        }
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn part_path(&self, index: usize) -> Utf8PathBuf {
+        Utf8PathBuf::from(format!("{}.part{index:05}", self.output))
+    }
+
+    pub fn progress(&self, source: &str) -> Option<&SourceProgress> {
+        self.sources.get(source)
+    }
+
+    /// Have all of `sources` been fully written?
+    pub fn is_complete<'a>(&self, mut sources: impl Iterator<Item = &'a str>) -> bool {
+        sources.all(|s| self.progress(s).is_some_and(|p| p.complete))
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Result};
+use arrow2::array::*;
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use sourmash::sketch::minhash::max_hash_for_scaled;
+use std::collections::HashMap;
+
+use crate::export::{
+    annotate_taxonomy, check_db_compatibility, compute_lca_strs, dataset_names, select_ksize,
+    DatasetResolver, HASHES_CF, RANKS,
+};
+use crate::naming::DatasetNaming;
+use crate::query::query_parquet;
+
+/// Where to look up the LCA lineage of each query hash.
+pub enum LcaSource<'a> {
+    /// an export with `hash`, `ksize`, `scaled` and `lca_lineage` columns
+    Parquet(&'a Utf8Path),
+    /// a RevIndex, with taxonomy to compute LCAs from
+    RevIndex {
+        db_path: &'a Utf8Path,
+        taxonomy: &'a HashMap<String, String>,
+    },
+}
+
+/// The most common lineage at one rank.
+#[derive(Debug, Clone, Serialize)]
+pub struct RankAssignment {
+    pub rank: String,
+    pub lineage: String,
+    pub hashes: usize,
+    /// `hashes` as a fraction of the (downsampled) query
+    pub fraction: f64,
+}
+
+/// LCA classification of a query sketch, in the style of `sourmash lca classify`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Classification {
+    /// query hashes, after downsampling to the database scaled
+    pub query_hashes: usize,
+    /// query hashes with an LCA lineage
+    pub classified_hashes: usize,
+    /// most common lineage at each rank, from domain down; stops at the first
+    /// rank with no lineage
+    pub ranks: Vec<RankAssignment>,
+    /// the lowest rank whose most common lineage has at least `threshold` hashes
+    pub assignment: Option<RankAssignment>,
+}
+
+/// Look up LCA lineages for `hashes` in an export; returns the lineages and the
+/// coarsest scaled among the matching rows.
+fn parquet_lineages(
+    path: &Utf8Path,
+    hashes: &[u64],
+    ksize: u32,
+) -> Result<(HashMap<u64, String>, u32)> {
+    let query = query_parquet(path, hashes)?;
+    let column = |name: &str| {
+        query
+            .schema
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| anyhow!("'{path}' has no '{name}' column"))
+    };
+    let (hash_idx, ksize_idx, scaled_idx, lineage_idx) = (
+        column("hash")?,
+        column("ksize")?,
+        column("scaled")?,
+        column("lca_lineage")?,
+    );
+
+    // the same hash may have rows from several sources
+    let mut found: HashMap<u64, Vec<String>> = HashMap::new();
+    let mut scaled = 0;
+    for chunk in &query.chunks {
+        let arrays = chunk.arrays();
+        let downcast_err = || anyhow!("unexpected column types in '{path}'");
+        let hashes = arrays[hash_idx]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(downcast_err)?;
+        let ksizes = arrays[ksize_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let scaleds = arrays[scaled_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let lineages = arrays[lineage_idx]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+
+        for row in 0..chunk.len() {
+            if ksizes.value(row) != ksize {
+                continue;
+            }
+            scaled = scaled.max(scaleds.value(row));
+            if let Some(lineage) = lineages.get(row).filter(|l| !l.is_empty()) {
+                found
+                    .entry(hashes.value(row))
+                    .or_default()
+                    .push(lineage.to_string());
+            }
+        }
+    }
+
+    let lineages = found
+        .into_iter()
+        .map(|(hash, lineages)| (hash, compute_lca_strs(&lineages).0))
+        .collect();
+    Ok((lineages, scaled))
+}
+
+/// Look up LCA lineages for `hashes` in a RevIndex; returns the lineages and
+/// the database scaled.
+fn revindex_lineages(
+    db_path: &Utf8Path,
+    taxonomy: &HashMap<String, String>,
+    hashes: &[u64],
+    ksize: u32,
+) -> Result<(HashMap<u64, String>, u32)> {
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+    let scaled = select_ksize(collection.manifest(), db_path, Some(ksize))?.scaled;
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    let mut lineages = HashMap::new();
+    for &hash in hashes {
+        let Some(value) = db.get_pinned_cf(&cf, hash.to_le_bytes())? else {
+            continue;
+        };
+        let Some(datasets) = resolver.resolve(&value)? else {
+            continue;
+        };
+        let names = dataset_names(collection, datasets, &DatasetNaming::default());
+        if let (_, Some(lineage), _) = annotate_taxonomy(&names, Some(taxonomy)) {
+            if !lineage.is_empty() {
+                lineages.insert(hash, lineage);
+            }
+        }
+    }
+    Ok((lineages, scaled))
+}
+
+/// Classify a query sketch from the LCA lineages of its hashes: at each rank,
+/// find the most common lineage among the query hashes, and assign the query
+/// to the lowest rank where that lineage has at least `threshold` hashes.
+/// Query hashes are downsampled to the database scaled if it is coarser.
+pub fn classify_hashes(
+    source: &LcaSource,
+    query_hashes: &[u64],
+    ksize: u32,
+    scaled: u32,
+    threshold: usize,
+) -> Result<Classification> {
+    let (lineages, db_scaled) = match *source {
+        LcaSource::Parquet(path) => parquet_lineages(path, query_hashes, ksize)?,
+        LcaSource::RevIndex { db_path, taxonomy } => {
+            revindex_lineages(db_path, taxonomy, query_hashes, ksize)?
+        }
+    };
+
+    let max_hash = max_hash_for_scaled(scaled.max(db_scaled) as u64);
+    let mut query: Vec<u64> = query_hashes
+        .iter()
+        .copied()
+        .filter(|&h| h <= max_hash)
+        .collect();
+    query.sort_unstable();
+    query.dedup();
+
+    let lineages: Vec<Vec<&str>> = query
+        .iter()
+        .filter_map(|hash| lineages.get(hash))
+        .map(|lineage| {
+            lineage
+                .split(';')
+                .take_while(|name| !name.is_empty())
+                .collect()
+        })
+        .collect();
+
+    let mut ranks = Vec::new();
+    for (i, rank) in RANKS.iter().enumerate() {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for lineage in lineages.iter().filter(|l| l.len() > i) {
+            *counts.entry(lineage[..=i].join(";")).or_default() += 1;
+        }
+        // ties go to the first lineage by name, so results are reproducible
+        let Some((lineage, hashes)) = counts
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        else {
+            break;
+        };
+        ranks.push(RankAssignment {
+            rank: rank.to_string(),
+            lineage,
+            hashes,
+            fraction: hashes as f64 / query.len().max(1) as f64,
+        });
+    }
+
+    let assignment = ranks.iter().rev().find(|r| r.hashes >= threshold).cloned();
+    Ok(Classification {
+        query_hashes: query.len(),
+        classified_hashes: lineages.len(),
+        ranks,
+        assignment,
+    })
+}
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+
+/// ClickHouse type of an export column. ClickHouse arrays can't be nullable,
+/// so list columns become `Array(String)` or `Array(UInt64)` and a null list
+/// an empty array.
+fn clickhouse_type(field: &Field) -> Result<String> {
+    let base = match field.data_type() {
+        DataType::UInt64 => "UInt64",
+        DataType::UInt32 => "UInt32",
+        DataType::Float64 => "Float64",
+        DataType::Boolean => "Bool",
+        DataType::Utf8 => "String",
+        DataType::List(item) if item.data_type() == &DataType::Utf8 => {
+            return Ok("Array(String)".to_string())
+        }
+        DataType::List(item) if item.data_type() == &DataType::UInt64 => {
+            return Ok("Array(UInt64)".to_string())
+        }
+        other => anyhow::bail!("column '{}' has unsupported type {other:?}", field.name),
+    };
+    Ok(if field.is_nullable {
+        format!("Nullable({base})")
+    } else {
+        base.to_string()
+    })
+}
+
+/// ClickHouse `CREATE TABLE IF NOT EXISTS` statement for a MergeTree table
+/// holding these export columns, ordered by hash when it's selected.
+pub fn clickhouse_create_table_sql(table: &str, schema: &Schema) -> Result<String> {
+    let columns = schema
+        .fields
+        .iter()
+        .map(|field| Ok(format!("`{}` {}", field.name, clickhouse_type(field)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let order_by = if schema.fields.iter().any(|field| field.name == "hash") {
+        "hash"
+    } else {
+        "tuple()"
+    };
+    Ok(format!(
+        "CREATE TABLE IF NOT EXISTS {table} ({}) ENGINE = MergeTree ORDER BY {order_by}",
+        columns.join(", ")
+    ))
+}
+
+/// ClickHouse `INSERT` statement for RowBinary rows of these export columns.
+pub fn clickhouse_insert_sql(table: &str, schema: &Schema) -> String {
+    let names: Vec<String> = schema
+        .fields
+        .iter()
+        .map(|field| format!("`{}`", field.name))
+        .collect();
+    format!(
+        "INSERT INTO {table} ({}) FORMAT RowBinary",
+        names.join(", ")
+    )
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Append the rows of an export chunk to `out` in ClickHouse's RowBinary
+/// format, with the column types of `clickhouse_create_table_sql`.
+pub fn encode_row_binary(
+    schema: &Schema,
+    chunk: &Chunk<Box<dyn Array>>,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let downcast_err = |field: &Field| anyhow!("unexpected array type for column '{}'", field.name);
+    let columns: Vec<(&Field, &dyn Array)> = schema
+        .fields
+        .iter()
+        .zip(chunk.arrays().iter().map(|array| array.as_ref()))
+        .collect();
+    for row in 0..chunk.len() {
+        for &(field, array) in &columns {
+            // nullable scalars are prefixed with a null flag
+            let nullable = field.is_nullable && !matches!(field.data_type(), DataType::List(_));
+            if nullable {
+                let is_null = array.is_null(row);
+                out.push(is_null as u8);
+                if is_null {
+                    continue;
+                }
+            }
+            match field.data_type() {
+                DataType::UInt64 => {
+                    let values = array
+                        .as_any()
+                        .downcast_ref::<UInt64Array>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    out.extend_from_slice(&values.value(row).to_le_bytes());
+                }
+                DataType::UInt32 => {
+                    let values = array
+                        .as_any()
+                        .downcast_ref::<UInt32Array>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    out.extend_from_slice(&values.value(row).to_le_bytes());
+                }
+                DataType::Float64 => {
+                    let values = array
+                        .as_any()
+                        .downcast_ref::<Float64Array>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    out.extend_from_slice(&values.value(row).to_le_bytes());
+                }
+                DataType::Boolean => {
+                    let values = array
+                        .as_any()
+                        .downcast_ref::<BooleanArray>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    out.push(values.value(row) as u8);
+                }
+                DataType::Utf8 => {
+                    let values = array
+                        .as_any()
+                        .downcast_ref::<Utf8Array<i32>>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    write_string(out, values.value(row));
+                }
+                DataType::List(item) => {
+                    let lists = array
+                        .as_any()
+                        .downcast_ref::<ListArray<i32>>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    if lists.is_null(row) {
+                        write_varint(out, 0);
+                        continue;
+                    }
+                    let list = lists.value(row);
+                    write_varint(out, list.len() as u64);
+                    if item.data_type() == &DataType::UInt64 {
+                        let items = list
+                            .as_any()
+                            .downcast_ref::<UInt64Array>()
+                            .ok_or_else(|| downcast_err(field))?;
+                        for item in items.iter() {
+                            out.extend_from_slice(&item.copied().unwrap_or_default().to_le_bytes());
+                        }
+                    } else {
+                        let items = list
+                            .as_any()
+                            .downcast_ref::<Utf8Array<i32>>()
+                            .ok_or_else(|| downcast_err(field))?;
+                        for item in items.iter() {
+                            write_string(out, item.unwrap_or_default());
+                        }
+                    }
+                }
+                other => anyhow::bail!("column '{}' has unsupported type {other:?}", field.name),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow2::offset::Offsets;
+
+    use crate::export::{abundance_fields, contamination_field, ArrowRecord, ExportOptions};
+
+    #[test]
+    fn test_encode_row_binary() {
+        let records = vec![ArrowRecord {
+            hash: 1,
+            dataset_names: vec!["a".into(), "bc".into()],
+            taxonomy_list: None,
+            lca_lineage: None,
+            lca_rank: Some("genus".into()),
+            ksize: 31,
+            scaled: 1000,
+            source: "db".into(),
+            abundances: None,
+        }];
+        let options = ExportOptions {
+            columns: Some(vec![
+                "hash".into(),
+                "dataset_names".into(),
+                "taxonomy_list".into(),
+                "lca_lineage".into(),
+                "lca_rank".into(),
+            ]),
+            ..Default::default()
+        };
+        let settings = options.writer_settings().unwrap();
+        let mut out = Vec::new();
+        encode_row_binary(
+            &settings.schema(),
+            &settings.chunk(&records).unwrap(),
+            &mut out,
+        )
+        .unwrap();
+
+        let mut expected = 1u64.to_le_bytes().to_vec();
+        expected.extend([2, 1, b'a', 2, b'b', b'c']); // Array(String)
+        expected.push(0); // a null list is an empty array
+        expected.push(1); // null lca_lineage
+        expected.extend([0, 5]); // lca_rank
+        expected.extend(b"genus");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_encode_row_binary_opt_in_columns() {
+        let mut fields = abundance_fields();
+        fields.push(contamination_field());
+        fields.push(Field::new("lineage_entropy", DataType::Float64, true));
+        let schema = Schema::from(fields);
+        let mut offsets = Offsets::<i32>::new();
+        offsets.try_push(2).unwrap();
+        offsets.try_push(0).unwrap();
+        let abundances = ListArray::<i32>::new(
+            schema.fields[0].data_type().clone(),
+            offsets.into(),
+            UInt64Array::from_slice([3, 1]).boxed(),
+            Some([true, false].into_iter().collect()),
+        );
+        let chunk = Chunk::new(vec![
+            abundances.boxed(),
+            UInt64Array::from([Some(4), None]).boxed(),
+            BooleanArray::from([Some(true), None]).boxed(),
+            Float64Array::from([Some(0.5), None]).boxed(),
+        ]);
+
+        assert_eq!(
+            clickhouse_create_table_sql("t", &schema).unwrap(),
+            "CREATE TABLE IF NOT EXISTS t (`abundances` Array(UInt64), \
+             `total_abundance` Nullable(UInt64), `contamination_flag` Nullable(Bool), \
+             `lineage_entropy` Nullable(Float64)) ENGINE = MergeTree ORDER BY tuple()"
+        );
+        let mut out = Vec::new();
+        encode_row_binary(&schema, &chunk, &mut out).unwrap();
+
+        let mut expected = vec![2]; // Array(UInt64)
+        expected.extend(3u64.to_le_bytes());
+        expected.extend(1u64.to_le_bytes());
+        expected.push(0);
+        expected.extend(4u64.to_le_bytes());
+        expected.extend([0, 1]); // Bool
+        expected.push(0);
+        expected.extend(0.5f64.to_le_bytes());
+        // second row: empty array and nulls
+        expected.extend([0, 1, 1, 1]);
+        assert_eq!(out, expected);
+    }
+}
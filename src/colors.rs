@@ -0,0 +1,227 @@
+use anyhow::{anyhow, bail, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::*;
+use arrow2::io::parquet::write::*;
+use arrow2::offset::{Offsets, OffsetsBuffer};
+use byteorder::{ByteOrder, LittleEndian};
+use camino::Utf8PathBuf;
+use sourmash::index::revindex::Datasets;
+use std::fs::File;
+
+use crate::export::{parquet_write_options, TmpOutputs, COLORS_CF, DB};
+
+// number of colors per row group
+const COLORS_ROW_GROUP_SIZE: usize = 100_000;
+
+// schema for colors parquet file
+fn create_colors_schema() -> Schema {
+    Schema::from(vec![
+        Field::new("color_id", DataType::UInt64, false),
+        Field::new(
+            "dataset_indices",
+            DataType::List(Box::new(Field::new("item", DataType::UInt32, false))),
+            false,
+        ),
+        Field::new("n_datasets", DataType::UInt32, false),
+    ])
+}
+
+fn colors_to_chunk(rows: &[(u64, Vec<u32>)]) -> Result<Chunk<Box<dyn Array>>> {
+    let color_ids = UInt64Array::from_slice(rows.iter().map(|(c, _)| *c).collect::<Vec<_>>());
+    let n_datasets = UInt32Array::from_slice(
+        rows.iter()
+            .map(|(_, idxs)| idxs.len() as u32)
+            .collect::<Vec<_>>(),
+    );
+
+    let mut offsets = Offsets::<i32>::new();
+    for (_, idxs) in rows {
+        offsets.try_push(idxs.len().try_into().expect("len exceeds i32::MAX"))?;
+    }
+    let flat: Vec<u32> = rows.iter().flat_map(|(_, idxs)| idxs.clone()).collect();
+    let dataset_indices = ListArray::<i32>::new(
+        DataType::List(Box::new(Field::new("item", DataType::UInt32, false))),
+        OffsetsBuffer::from(offsets),
+        Box::new(UInt32Array::from_vec(flat)),
+        None,
+    );
+
+    Ok(Chunk::new(vec![
+        Box::new(color_ids) as Box<dyn Array>,
+        Box::new(dataset_indices) as Box<dyn Array>,
+        Box::new(n_datasets) as Box<dyn Array>,
+    ]))
+}
+
+/// Export a `colors` column family (color_id -> dataset indices) to a Parquet
+/// file, for debugging. The layout is the one color-based exports resolve:
+/// 8-byte little-endian color IDs as keys and serialized `Datasets` as values.
+/// Anything else is an error. The file is written to `<out_path>.tmp` and
+/// renamed once complete, and an existing `out_path` is only replaced with
+/// `force`. Returns the number of colors written.
+pub fn export_colors_to_parquet(
+    db_path: Utf8PathBuf,
+    out_path: Utf8PathBuf,
+    force: bool,
+) -> Result<u64> {
+    if out_path.exists() && !force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("output '{out_path}' already exists; use --force to overwrite it"),
+        )
+        .into());
+    }
+    let opts = rocksdb::Options::default();
+    let cfs = DB::list_cf(&opts, &db_path)?;
+    if !cfs.iter().any(|cf| cf == COLORS_CF) {
+        bail!("'{db_path}' does not have a '{COLORS_CF}' column family");
+    }
+
+    let db = DB::open_cf_for_read_only(&opts, &db_path, cfs, false)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let cf = db
+        .cf_handle(COLORS_CF)
+        .ok_or_else(|| anyhow!("Missing '{COLORS_CF}' CF"))?;
+
+    let schema = create_colors_schema();
+    let options = parquet_write_options();
+    let tmp_path = Utf8PathBuf::from(format!("{out_path}.tmp"));
+    let mut tmp_outputs = TmpOutputs {
+        paths: vec![tmp_path.clone()],
+    };
+    let file = File::create(&tmp_path)?;
+    let mut writer = FileWriter::try_new(file, schema.clone(), options)?;
+
+    let mut write_rows = |rows: &[(u64, Vec<u32>)]| -> Result<()> {
+        let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+        let row_groups = RowGroupIterator::try_new(
+            std::iter::once(Ok(colors_to_chunk(rows)?)),
+            &schema,
+            options,
+            encodings,
+        )?;
+        for group in row_groups {
+            writer.write(group?)?;
+        }
+        Ok(())
+    };
+
+    let mut rows = Vec::with_capacity(COLORS_ROW_GROUP_SIZE);
+    let mut n_colors = 0;
+    for (k, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+    {
+        if k.len() != 8 {
+            bail!(
+                "'{db_path}' has a {}-byte key in '{COLORS_CF}'; expected 8-byte color IDs",
+                k.len()
+            );
+        }
+        let color = LittleEndian::read_u64(&k);
+        let Some(datasets) = Datasets::from_slice(&v) else {
+            bail!("could not parse the dataset list of color {color} in '{db_path}'");
+        };
+
+        rows.push((color, datasets.into_iter().collect::<Vec<u32>>()));
+        n_colors += 1;
+
+        if rows.len() >= COLORS_ROW_GROUP_SIZE {
+            write_rows(&rows)?;
+            rows.clear();
+        }
+    }
+    if !rows.is_empty() {
+        write_rows(&rows)?;
+    }
+
+    writer.end(None)?;
+    std::fs::rename(&tmp_path, &out_path)?;
+    tmp_outputs.keep();
+
+    Ok(n_colors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+
+    #[test]
+    fn test_export_colors_to_parquet() {
+        let tmp = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let db_path = tmp.join(format!("colors-fixture-{}.rocksdb", std::process::id()));
+        let out_path = tmp.join(format!("colors-fixture-{}.parquet", std::process::id()));
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        {
+            let db = DB::open_cf(&opts, &db_path, [COLORS_CF]).unwrap();
+            let cf = db.cf_handle(COLORS_CF).unwrap();
+            for (color, idxs) in [(7u64, vec![0, 2, 5]), (1, vec![3])] {
+                let datasets = Datasets::new(&idxs).as_bytes().unwrap();
+                db.put_cf(&cf, color.to_le_bytes(), datasets).unwrap();
+            }
+        }
+
+        let n_colors = export_colors_to_parquet(db_path.clone(), out_path.clone(), false).unwrap();
+        assert_eq!(n_colors, 2);
+        // an existing output is only replaced with force
+        let err = export_colors_to_parquet(db_path.clone(), out_path.clone(), false).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        export_colors_to_parquet(db_path.clone(), out_path.clone(), true).unwrap();
+        let mut reader = File::open(&out_path).unwrap();
+        let metadata = read_metadata(&mut reader).unwrap();
+        let schema = infer_schema(&metadata).unwrap();
+        let chunks: Vec<_> = FileReader::new(reader, metadata.row_groups, schema, None, None, None)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        std::fs::remove_dir_all(&db_path).unwrap();
+        std::fs::remove_file(&out_path).unwrap();
+
+        // one row per color, in key order
+        assert_eq!(chunks.len(), 1);
+        let columns = chunks[0].arrays();
+        let color_ids = columns[0].as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(color_ids.values().as_slice(), &[1, 7]);
+        let dataset_indices = columns[1]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap();
+        let indices: Vec<Vec<u32>> = dataset_indices
+            .iter()
+            .map(|idxs| {
+                let idxs = idxs.unwrap();
+                let idxs = idxs.as_any().downcast_ref::<UInt32Array>().unwrap();
+                idxs.values().to_vec()
+            })
+            .collect();
+        assert_eq!(indices, vec![vec![3], vec![0, 2, 5]]);
+        let n_datasets = columns[2].as_any().downcast_ref::<UInt32Array>().unwrap();
+        assert_eq!(n_datasets.values().as_slice(), &[1, 3]);
+    }
+    #[test]
+    fn test_export_colors_to_parquet_failure_leaves_no_output() {
+        let tmp = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let db_path = tmp.join(format!("colors-bad-{}.rocksdb", std::process::id()));
+        let out_path = tmp.join(format!("colors-bad-{}.parquet", std::process::id()));
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        {
+            let db = DB::open_cf(&opts, &db_path, [COLORS_CF]).unwrap();
+            let cf = db.cf_handle(COLORS_CF).unwrap();
+            let datasets = Datasets::new(&[0, 1]).as_bytes().unwrap();
+            db.put_cf(&cf, 1u64.to_le_bytes(), &datasets).unwrap();
+            // sorts after the valid color
+            db.put_cf(&cf, [0xff; 4], &datasets).unwrap();
+        }
+
+        let err = export_colors_to_parquet(db_path.clone(), out_path.clone(), false).unwrap_err();
+        std::fs::remove_dir_all(&db_path).unwrap();
+        assert!(err.to_string().contains("4-byte key"));
+        assert!(!out_path.exists());
+        assert!(!Utf8PathBuf::from(format!("{out_path}.tmp")).exists());
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::compute::filter::filter_chunk;
+use arrow2::datatypes::Schema;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use arrow2::io::parquet::write::{Encoding, FileWriter, RowGroupIterator};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+
+use crate::export::{footer_metadata, read_lca_info, write_lca_info, ExportOptions, LCASummary};
+
+/// Row counts from combining several exports.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConcatSummary {
+    pub files: usize,
+    pub rows_read: u64,
+    pub rows_written: u64,
+    /// rows dropped because an earlier input had the same (hash, source)
+    pub duplicates: u64,
+}
+
+/// Combine exports (e.g. the shards of a `--shard` run) into one Parquet
+/// file, keeping the first row for each (hash, source). The inputs must have
+/// the same schema; their row groups are copied in input order, and the
+/// output is marked partial when any input was. The (hash, source) pairs seen
+/// so far are held in memory, about 16 bytes per row.
+///
+/// With `lca_infos`, the per-source LCA summary CSVs of the inputs are summed
+/// and written to `lca_info_path`; the sums are exact when the inputs don't
+/// overlap, as with shards. Compression and `force` are taken from `options`.
+pub fn concat_parquet(
+    inputs: &[Utf8PathBuf],
+    output: &Utf8Path,
+    lca_infos: &[Utf8PathBuf],
+    lca_info_path: Option<&Utf8Path>,
+    options: &ExportOptions,
+) -> Result<ConcatSummary> {
+    options.validate()?;
+    if inputs.is_empty() {
+        anyhow::bail!("no input files to combine");
+    }
+    if output.exists() && !options.force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("output '{output}' already exists; use --force to overwrite it"),
+        )
+        .into());
+    }
+    let write_options = options.writer_settings()?.write_options;
+
+    // write to a temp file and rename on success, as exports do
+    let tmp_path = Utf8PathBuf::from(format!("{output}.tmp"));
+    let mut writer: Option<(Schema, FileWriter<File>)> = None;
+    let mut summary = ConcatSummary {
+        files: inputs.len(),
+        ..Default::default()
+    };
+    let mut partial = false;
+    let mut sources: HashMap<String, u32> = HashMap::new();
+    let mut seen: HashSet<(u64, u32)> = HashSet::new();
+
+    for input in inputs {
+        let mut reader = File::open(input).with_context(|| format!("cannot open '{input}'"))?;
+        let metadata = read_metadata(&mut reader)?;
+        let schema = infer_schema(&metadata)?;
+        partial |= metadata
+            .key_value_metadata
+            .iter()
+            .flatten()
+            .any(|kv| kv.key == "export_partial" && kv.value.as_deref() == Some("true"));
+
+        let (schema, writer) = match writer {
+            Some((ref expected, _)) if expected.fields != schema.fields => {
+                anyhow::bail!("'{input}' has a different schema than '{}'", inputs[0])
+            }
+            Some(ref mut w) => (&w.0, &mut w.1),
+            None => {
+                let file = File::create(&tmp_path)?;
+                let w = writer.insert((
+                    schema.clone(),
+                    FileWriter::try_new(file, schema.clone(), write_options)?,
+                ));
+                (&w.0, &mut w.1)
+            }
+        };
+        let column = |name: &str| {
+            schema
+                .fields
+                .iter()
+                .position(|field| field.name == name)
+                .ok_or_else(|| anyhow!("'{input}' has no '{name}' column"))
+        };
+        let (hash_idx, source_idx) = (column("hash")?, column("source")?);
+
+        for chunk in FileReader::new(
+            reader,
+            metadata.row_groups,
+            schema.clone(),
+            None,
+            None,
+            None,
+        ) {
+            let chunk = chunk?;
+            let arrays = chunk.arrays();
+            let downcast_err = || anyhow!("unexpected column types in '{input}'");
+            let hashes = arrays[hash_idx]
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .ok_or_else(downcast_err)?;
+            let chunk_sources = arrays[source_idx]
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .ok_or_else(downcast_err)?;
+
+            let keep: Vec<bool> = (0..chunk.len())
+                .map(|row| {
+                    let next_id = sources.len() as u32;
+                    let source_id = *sources
+                        .entry(chunk_sources.value(row).to_string())
+                        .or_insert(next_id);
+                    seen.insert((hashes.value(row), source_id))
+                })
+                .collect();
+            let kept = keep.iter().filter(|k| **k).count();
+            summary.rows_read += chunk.len() as u64;
+            summary.rows_written += kept as u64;
+            summary.duplicates += (chunk.len() - kept) as u64;
+            if kept == 0 {
+                continue;
+            }
+
+            let chunk = if kept == chunk.len() {
+                chunk
+            } else {
+                filter_chunk(&chunk, &BooleanArray::from_slice(keep))?
+            };
+            let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+            let row_groups = RowGroupIterator::try_new(
+                std::iter::once(Ok(chunk)),
+                schema,
+                write_options,
+                encodings,
+            )?;
+            for group in row_groups {
+                writer.write(group?)?;
+            }
+        }
+    }
+
+    if let Some((_, mut writer)) = writer {
+        writer.end(Some(footer_metadata(summary.rows_written, partial)))?;
+    }
+    std::fs::rename(&tmp_path, output)?;
+    eprintln!(
+        "Combined {} file(s) into {output}: {} rows, {} duplicates dropped",
+        summary.files, summary.rows_written, summary.duplicates
+    );
+
+    if !lca_infos.is_empty() {
+        let mut summaries: Vec<(String, LCASummary)> = Vec::new();
+        for path in lca_infos {
+            for (source, lca) in read_lca_info(path)? {
+                match summaries.iter_mut().find(|(s, _)| *s == source) {
+                    Some((_, existing)) => existing.merge(&lca),
+                    None => summaries.push((source, lca)),
+                }
+            }
+        }
+        write_lca_info(lca_info_path, &summaries)?;
+    }
+    Ok(summary)
+}
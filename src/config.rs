@@ -0,0 +1,990 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::export::{parse_compression, ExportOptions, LineageFilter, Sampling};
+use crate::hashlist::load_hash_list;
+use crate::incremental::PreviousExport;
+use crate::lineage_table::LineageTable;
+use crate::membership::DEFAULT_BLOOM_FILTER_FPP;
+use crate::naming::{Anonymizer, DatasetLabels, DatasetNaming};
+use crate::patterns::DatasetPatterns;
+use crate::picklist::Picklist;
+use crate::prefetch::Prefetch;
+use crate::python::{python_progress_callback, to_py_err};
+use crate::query_tags::QueryTags;
+use crate::rewrite::Partitioning;
+use crate::sketches::{load_query_hashes, InputKind};
+
+// ksize of the sketches read from sketch collections and queries by default
+const DEFAULT_SKETCH_KSIZE: u32 = 31;
+
+/// Everything needed to run one export, after validation.
+pub(crate) struct ExportJob {
+    pub db_paths: Vec<Utf8PathBuf>,
+    /// `None` to collect the records in memory
+    pub output: Option<Utf8PathBuf>,
+    pub tax_paths: Vec<Utf8PathBuf>,
+    pub lca_info_path: Option<Utf8PathBuf>,
+    pub options: ExportOptions,
+    /// (ksize, moltype) when the inputs are sketch collections rather than
+    /// RevIndexes; such jobs go through `sigs_to_parquet`
+    pub sketch_selection: Option<(u32, String)>,
+}
+
+/// Export settings for Python callers, built up with chainable setters and
+/// passed to `export()`:
+///
+/// ```python
+/// config = ExportConfig(["db.rocksdb"], "db.parquet").taxonomy(["tax.csv"]).compression("zstd:3")
+/// stats = export(config)
+/// ```
+///
+/// Validation happens when the export starts, so setters can be called in any order.
+#[pyclass]
+#[derive(Debug)]
+pub(crate) struct ExportConfig {
+    pub databases: Vec<String>,
+    pub output: Option<String>,
+    pub taxonomy: Vec<String>,
+    pub lca_info: Option<String>,
+    pub rw: bool,
+    pub readahead_size: usize,
+    pub no_fill_cache: bool,
+    pub pin_data: bool,
+    pub use_snapshot: bool,
+    pub secondary: bool,
+    pub secondary_path: Option<String>,
+    pub shard: Option<u64>,
+    pub num_shards: Option<u64>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_rows: usize,
+    pub on_interrupt: String,
+    pub force: bool,
+    pub dry_run: bool,
+    pub lca_summary_only: bool,
+    pub sample_fraction: Option<f64>,
+    pub every_nth: Option<u64>,
+    pub limit: Option<u64>,
+    pub progress_json: Option<String>,
+    pub max_memory: Option<u64>,
+    pub stats_json: Option<String>,
+    pub compression: Option<String>,
+    pub row_group_size: Option<usize>,
+    pub columns: Option<Vec<String>>,
+    pub merge: bool,
+    pub since: Option<String>,
+    pub picklist: Option<String>,
+    pub include_datasets: Vec<String>,
+    pub exclude_datasets: Vec<String>,
+    pub min_datasets: Option<usize>,
+    pub max_datasets: Option<usize>,
+    pub lineage_filter: Option<String>,
+    pub lineage_filter_mode: String,
+    pub min_lca_rank: Option<String>,
+    pub drop_unclassified: bool,
+    pub unclassified_output: Option<String>,
+    pub hash_list: Option<String>,
+    pub query: Option<String>,
+    pub tag_queries: bool,
+    pub prefetch: Option<String>,
+    pub prefetch_threshold_bp: u64,
+    pub allow_mixed_params: bool,
+    pub strict: bool,
+    pub dataset_summary: Option<String>,
+    pub lineage_summary: Option<String>,
+    pub kraken_report: Option<String>,
+    pub krona: Option<String>,
+    pub biom: Option<String>,
+    pub cooccurrence: Option<String>,
+    pub cooccurrence_min_shared: u64,
+    pub top_shared: Option<String>,
+    pub top_shared_n: usize,
+    pub lineage_hll: Option<String>,
+    pub hll_rank: Option<String>,
+    pub deterministic: bool,
+    pub sharded_output: Option<String>,
+    pub dataset_name: Option<String>,
+    pub dataset_labels: Option<String>,
+    pub anonymize: Option<String>,
+    pub anonymize_salt: Option<String>,
+    pub with_abundance: bool,
+    pub lineage_table: Option<String>,
+    pub contamination_rank: Option<String>,
+    pub disagreement_metrics: bool,
+    pub bloom_filter: Option<String>,
+    pub bloom_filter_fpp: f64,
+    pub progress_callback: Option<PyObject>,
+    pub ksize: Option<u32>,
+    pub moltype: String,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            databases: vec![],
+            output: None,
+            taxonomy: vec![],
+            lca_info: None,
+            rw: false,
+            readahead_size: 0,
+            no_fill_cache: false,
+            pin_data: false,
+            use_snapshot: false,
+            secondary: false,
+            secondary_path: None,
+            shard: None,
+            num_shards: None,
+            checkpoint: None,
+            checkpoint_rows: 10_000_000,
+            on_interrupt: "finalize".to_string(),
+            force: false,
+            dry_run: false,
+            lca_summary_only: false,
+            sample_fraction: None,
+            every_nth: None,
+            limit: None,
+            progress_json: None,
+            max_memory: None,
+            stats_json: None,
+            compression: None,
+            row_group_size: None,
+            columns: None,
+            merge: false,
+            since: None,
+            picklist: None,
+            include_datasets: vec![],
+            exclude_datasets: vec![],
+            min_datasets: None,
+            max_datasets: None,
+            lineage_filter: None,
+            lineage_filter_mode: "lca".to_string(),
+            min_lca_rank: None,
+            drop_unclassified: false,
+            unclassified_output: None,
+            hash_list: None,
+            query: None,
+            tag_queries: false,
+            prefetch: None,
+            prefetch_threshold_bp: 50_000,
+            allow_mixed_params: false,
+            strict: false,
+            dataset_summary: None,
+            lineage_summary: None,
+            kraken_report: None,
+            krona: None,
+            biom: None,
+            cooccurrence: None,
+            cooccurrence_min_shared: 1,
+            top_shared: None,
+            top_shared_n: 100,
+            lineage_hll: None,
+            hll_rank: None,
+            deterministic: false,
+            sharded_output: None,
+            dataset_name: None,
+            dataset_labels: None,
+            anonymize: None,
+            anonymize_salt: None,
+            with_abundance: false,
+            lineage_table: None,
+            contamination_rank: None,
+            disagreement_metrics: false,
+            bloom_filter: None,
+            bloom_filter_fpp: DEFAULT_BLOOM_FILTER_FPP,
+            progress_callback: None,
+            ksize: None,
+            moltype: "DNA".to_string(),
+        }
+    }
+}
+
+impl ExportConfig {
+    /// Validate the settings and resolve them into an export job.
+    pub fn build(&self, py: Python, cancel_flag: Arc<AtomicBool>) -> PyResult<ExportJob> {
+        let db_paths: Vec<Utf8PathBuf> = self.databases.iter().map(Utf8PathBuf::from).collect();
+        let mut sketch_inputs = 0;
+        for db in &db_paths {
+            match InputKind::detect(db) {
+                Some(InputKind::RevIndex) => {}
+                Some(_) => sketch_inputs += 1,
+                None => {
+                    return Err(PyValueError::new_err(format!(
+                        "'{db}' is not a RevIndex database or sketch collection"
+                    )))
+                }
+            }
+        }
+        if sketch_inputs > 0 && sketch_inputs < db_paths.len() {
+            return Err(PyValueError::new_err(
+                "RevIndex databases and sketch collections cannot be exported together",
+            ));
+        }
+        let sketch_selection = if sketch_inputs > 0 {
+            self.check_sketch_options()?;
+            Some((
+                self.ksize.unwrap_or(DEFAULT_SKETCH_KSIZE),
+                self.moltype.clone(),
+            ))
+        } else {
+            None
+        };
+
+        let shard = match (self.shard, self.num_shards) {
+            (None, None) => None,
+            (Some(shard), Some(num_shards)) => Some((shard, num_shards)),
+            _ => {
+                return Err(PyValueError::new_err(
+                    "shard and num_shards must be given together",
+                ))
+            }
+        };
+
+        let sampling = match (self.sample_fraction, self.every_nth) {
+            (None, None) => None,
+            (Some(fraction), None) => Some(Sampling::Fraction(fraction)),
+            (None, Some(n)) => Some(Sampling::EveryNth(n)),
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err(
+                    "sample_fraction and every_nth cannot be combined",
+                ))
+            }
+        };
+
+        let value_error = |e: anyhow::Error| PyValueError::new_err(e.to_string());
+        let since = match self.since {
+            Some(ref path) => {
+                let path = Utf8PathBuf::from(path);
+                let previous = py
+                    .allow_threads(|| PreviousExport::load(&path))
+                    .map_err(to_py_err)?;
+                Some(Arc::new(previous))
+            }
+            None => None,
+        };
+        let picklist = match self.picklist {
+            Some(ref arg) => Some(Arc::new(
+                py.allow_threads(|| Picklist::load(arg))
+                    .map_err(to_py_err)?,
+            )),
+            None => None,
+        };
+        let sharded_output = self
+            .sharded_output
+            .as_deref()
+            .map(str::parse::<Partitioning>)
+            .transpose()
+            .map_err(value_error)?;
+        if self.anonymize.is_some() && self.output.is_none() {
+            return Err(PyValueError::new_err(
+                "anonymize cannot be combined with in-memory output",
+            ));
+        }
+        if self.anonymize_salt.is_some() && self.anonymize.is_none() {
+            return Err(PyValueError::new_err("anonymize_salt needs anonymize"));
+        }
+        if self.lineage_table.is_some() && self.output.is_none() {
+            return Err(PyValueError::new_err(
+                "lineage_table cannot be combined with in-memory output",
+            ));
+        }
+        if self.bloom_filter.is_some() && self.output.is_none() {
+            return Err(PyValueError::new_err(
+                "bloom_filter cannot be combined with in-memory output",
+            ));
+        }
+        let anonymize = match self.anonymize {
+            Some(ref mapping) => {
+                let salt = self
+                    .anonymize_salt
+                    .clone()
+                    .unwrap_or_else(Anonymizer::random_salt);
+                Some(Anonymizer::new(&salt, Utf8Path::new(mapping)).map_err(value_error)?)
+            }
+            None => None,
+        };
+        let mut dataset_naming = match self.dataset_name {
+            Some(ref chain) => chain.parse().map_err(value_error)?,
+            None => DatasetNaming::default(),
+        };
+        if let Some(ref path) = self.dataset_labels {
+            let labels = py
+                .allow_threads(|| DatasetLabels::load(Utf8Path::new(path)))
+                .map_err(to_py_err)?;
+            dataset_naming = dataset_naming.with_labels(Arc::new(labels));
+        }
+        let lineage_filter = match self.lineage_filter {
+            Some(ref prefix) => {
+                let mode = self.lineage_filter_mode.parse().map_err(value_error)?;
+                Some(LineageFilter::new(prefix, mode).map_err(value_error)?)
+            }
+            None => None,
+        };
+        // a hash list and a query together export the hashes in both
+        let hash_list = match self.hash_list {
+            Some(ref path) => {
+                let path = Utf8PathBuf::from(path);
+                let hashes = py
+                    .allow_threads(|| load_hash_list(&path))
+                    .map_err(to_py_err)?;
+                Some(hashes)
+            }
+            None => None,
+        };
+        if self.tag_queries && self.query.is_none() {
+            return Err(PyValueError::new_err("tag_queries needs a query"));
+        }
+        let mut query_tags = None;
+        let query = match self.query {
+            Some(ref path) => {
+                let path = Utf8PathBuf::from(path);
+                let ksize = self.ksize.unwrap_or(DEFAULT_SKETCH_KSIZE);
+                let hashes = if self.tag_queries {
+                    let tags = py
+                        .allow_threads(|| QueryTags::load(&path, ksize, &self.moltype))
+                        .map_err(to_py_err)?;
+                    let hashes = tags.hashes();
+                    query_tags = Some(Arc::new(tags));
+                    hashes
+                } else {
+                    py.allow_threads(|| load_query_hashes(&path, ksize, &self.moltype))
+                        .map_err(to_py_err)?
+                };
+                Some(hashes)
+            }
+            None => None,
+        };
+        let prefetch = match self.prefetch {
+            Some(ref path) => {
+                let path = Utf8PathBuf::from(path);
+                let ksize = self.ksize.unwrap_or(DEFAULT_SKETCH_KSIZE);
+                let hashes = py
+                    .allow_threads(|| load_query_hashes(&path, ksize, &self.moltype))
+                    .map_err(to_py_err)?;
+                Some(Prefetch::new(hashes, self.prefetch_threshold_bp))
+            }
+            None => None,
+        };
+        let hashes = match (hash_list, query) {
+            (Some(listed), Some(query)) => Some(listed.intersection(&query).copied().collect()),
+            (listed, query) => listed.or(query),
+        }
+        .map(Arc::new);
+        let dataset_patterns = DatasetPatterns::new(&self.include_datasets, &self.exclude_datasets)
+            .map_err(value_error)?
+            .map(Arc::new);
+        let options = ExportOptions {
+            rw: self.rw,
+            readahead_size: self.readahead_size,
+            no_fill_cache: self.no_fill_cache,
+            pin_data: self.pin_data,
+            use_snapshot: self.use_snapshot,
+            secondary: self.secondary,
+            secondary_path: self.secondary_path.as_ref().map(Utf8PathBuf::from),
+            shard,
+            checkpoint: self.checkpoint.as_ref().map(Utf8PathBuf::from),
+            checkpoint_rows: self.checkpoint_rows,
+            on_interrupt: self.on_interrupt.parse().map_err(value_error)?,
+            force: self.force,
+            dry_run: self.dry_run,
+            lca_summary_only: self.lca_summary_only,
+            sampling,
+            limit: self.limit,
+            progress_json: self.progress_json.as_ref().map(Utf8PathBuf::from),
+            max_memory: self.max_memory,
+            stats_json: self.stats_json.as_ref().map(Utf8PathBuf::from),
+            progress_callback: self
+                .progress_callback
+                .as_ref()
+                .map(|cb| python_progress_callback(cb.clone_ref(py))),
+            cancel_flag: Some(cancel_flag),
+            compression: self
+                .compression
+                .as_deref()
+                .map(parse_compression)
+                .transpose()
+                .map_err(value_error)?,
+            row_group_size: self.row_group_size,
+            columns: self.columns.clone(),
+            merge: self.merge,
+            since,
+            picklist,
+            dataset_patterns,
+            min_datasets: self.min_datasets,
+            max_datasets: self.max_datasets,
+            lineage_filter,
+            min_lca_rank: self.min_lca_rank.clone(),
+            drop_unclassified: self.drop_unclassified,
+            unclassified_output: self.unclassified_output.as_ref().map(Utf8PathBuf::from),
+            hashes,
+            prefetch,
+            query_tags,
+            ksize: self.ksize,
+            allow_mixed_params: self.allow_mixed_params,
+            strict: self.strict,
+            dataset_summary: self.dataset_summary.as_ref().map(Utf8PathBuf::from),
+            lineage_summary: self.lineage_summary.as_ref().map(Utf8PathBuf::from),
+            kraken_report: self.kraken_report.as_ref().map(Utf8PathBuf::from),
+            krona: self.krona.as_ref().map(Utf8PathBuf::from),
+            biom: self.biom.as_ref().map(Utf8PathBuf::from),
+            cooccurrence: self.cooccurrence.as_ref().map(Utf8PathBuf::from),
+            cooccurrence_min_shared: self.cooccurrence_min_shared,
+            top_shared: self.top_shared.as_ref().map(Utf8PathBuf::from),
+            top_shared_n: self.top_shared_n,
+            lineage_hll: self.lineage_hll.as_ref().map(Utf8PathBuf::from),
+            hll_rank: self.hll_rank.clone(),
+            deterministic: self.deterministic,
+            sharded_output,
+            dataset_naming,
+            anonymize,
+            with_abundance: self.with_abundance,
+            lineage_table: self
+                .lineage_table
+                .as_deref()
+                .map(|path| LineageTable::new(Utf8Path::new(path))),
+            contamination_rank: self.contamination_rank.clone(),
+            disagreement_metrics: self.disagreement_metrics,
+            bloom_filter: self
+                .bloom_filter
+                .as_ref()
+                .map(|path| (Utf8PathBuf::from(path), self.bloom_filter_fpp)),
+        };
+        options.validate().map_err(value_error)?;
+        options
+            .check_taxonomy(!self.taxonomy.is_empty())
+            .map_err(value_error)?;
+
+        Ok(ExportJob {
+            db_paths,
+            output: self.output.as_ref().map(Utf8PathBuf::from),
+            tax_paths: self.taxonomy.iter().map(Utf8PathBuf::from).collect(),
+            lca_info_path: self.lca_info.as_ref().map(Utf8PathBuf::from),
+            options,
+            sketch_selection,
+        })
+    }
+
+    /// Sketch collections are exported by `sigs_to_parquet`, which reads no
+    /// RocksDB and writes one file in one pass; reject the options it can't honor.
+    fn check_sketch_options(&self) -> PyResult<()> {
+        let unsupported = [
+            ("rw", self.rw),
+            ("readahead_size", self.readahead_size > 0),
+            ("no_fill_cache", self.no_fill_cache),
+            ("pin_data", self.pin_data),
+            ("use_snapshot", self.use_snapshot),
+            ("secondary", self.secondary),
+            ("shard", self.shard.is_some() || self.num_shards.is_some()),
+            ("checkpoint", self.checkpoint.is_some()),
+            ("dry_run", self.dry_run),
+            ("lca_summary_only", self.lca_summary_only),
+            (
+                "sampling",
+                self.sample_fraction.is_some() || self.every_nth.is_some(),
+            ),
+            ("limit", self.limit.is_some()),
+            ("merge", self.merge),
+            ("since", self.since.is_some()),
+            ("progress_json", self.progress_json.is_some()),
+            ("max_memory", self.max_memory.is_some()),
+            ("progress_callback", self.progress_callback.is_some()),
+            ("unclassified_output", self.unclassified_output.is_some()),
+            ("prefetch", self.prefetch.is_some()),
+            ("allow_mixed_params", self.allow_mixed_params),
+            ("dataset_summary", self.dataset_summary.is_some()),
+            ("lineage_summary", self.lineage_summary.is_some()),
+            ("kraken_report", self.kraken_report.is_some()),
+            ("krona", self.krona.is_some()),
+            ("biom", self.biom.is_some()),
+            ("cooccurrence", self.cooccurrence.is_some()),
+            ("top_shared", self.top_shared.is_some()),
+            ("lineage_hll", self.lineage_hll.is_some()),
+            ("sharded_output", self.sharded_output.is_some()),
+            ("bloom_filter", self.bloom_filter.is_some()),
+            ("in-memory output", self.output.is_none()),
+        ];
+        let names: Vec<&str> = unsupported
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect();
+        if names.is_empty() {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!(
+                "not supported for sketch collections, only for RevIndex databases: {}",
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+#[pymethods]
+impl ExportConfig {
+    #[new]
+    #[pyo3(signature = (databases, output = None))]
+    fn new(databases: Vec<String>, output: Option<String>) -> Self {
+        Self {
+            databases,
+            output,
+            ..Default::default()
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ExportConfig(databases={:?}, output={:?})",
+            self.databases, self.output
+        )
+    }
+
+    /// Output Parquet file; `None` returns the records as a pyarrow Table instead.
+    fn output(mut slf: PyRefMut<'_, Self>, output: Option<String>) -> PyRefMut<'_, Self> {
+        slf.output = output;
+        slf
+    }
+
+    /// Taxonomy CSV files used to annotate datasets.
+    fn taxonomy(mut slf: PyRefMut<'_, Self>, paths: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.taxonomy = paths;
+        slf
+    }
+
+    /// Write LCA summaries to this CSV file.
+    fn lca_info(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.lca_info = Some(path);
+        slf
+    }
+
+    /// Parquet compression, e.g. `zstd`, `zstd:9`, `snappy`, `lz4` or `uncompressed`.
+    fn compression(mut slf: PyRefMut<'_, Self>, compression: String) -> PyRefMut<'_, Self> {
+        slf.compression = Some(compression);
+        slf
+    }
+
+    /// Rows per Parquet row group.
+    fn row_group_size(mut slf: PyRefMut<'_, Self>, rows: usize) -> PyRefMut<'_, Self> {
+        slf.row_group_size = Some(rows);
+        slf
+    }
+
+    /// Only write these columns (always in schema order).
+    fn columns(mut slf: PyRefMut<'_, Self>, columns: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.columns = Some(columns);
+        slf
+    }
+
+    /// Only export hashes where `hash % num_shards == shard`.
+    fn shard(mut slf: PyRefMut<'_, Self>, shard: u64, num_shards: u64) -> PyRefMut<'_, Self> {
+        slf.shard = Some(shard);
+        slf.num_shards = Some(num_shards);
+        slf
+    }
+
+    /// Export a deterministic fraction of hashes.
+    fn sample_fraction(mut slf: PyRefMut<'_, Self>, fraction: f64) -> PyRefMut<'_, Self> {
+        slf.sample_fraction = Some(fraction);
+        slf
+    }
+
+    /// Export every nth hash of each database.
+    fn every_nth(mut slf: PyRefMut<'_, Self>, n: u64) -> PyRefMut<'_, Self> {
+        slf.every_nth = Some(n);
+        slf
+    }
+
+    /// Stop after exporting this many hashes per database.
+    fn limit(mut slf: PyRefMut<'_, Self>, limit: u64) -> PyRefMut<'_, Self> {
+        slf.limit = Some(limit);
+        slf
+    }
+
+    /// RocksDB readahead size in bytes for the scan.
+    fn readahead_size(mut slf: PyRefMut<'_, Self>, bytes: usize) -> PyRefMut<'_, Self> {
+        slf.readahead_size = bytes;
+        slf
+    }
+
+    /// Don't fill the RocksDB block cache during the scan.
+    #[pyo3(signature = (enabled = true))]
+    fn no_fill_cache(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.no_fill_cache = enabled;
+        slf
+    }
+
+    /// Pin data blocks read by the iterator.
+    #[pyo3(signature = (enabled = true))]
+    fn pin_data(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.pin_data = enabled;
+        slf
+    }
+
+    /// Read from a consistent RocksDB snapshot.
+    #[pyo3(signature = (enabled = true))]
+    fn snapshot(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.use_snapshot = enabled;
+        slf
+    }
+
+    /// Open databases as RocksDB secondary instances.
+    #[pyo3(signature = (path = None))]
+    fn secondary(mut slf: PyRefMut<'_, Self>, path: Option<String>) -> PyRefMut<'_, Self> {
+        slf.secondary = true;
+        slf.secondary_path = path;
+        slf
+    }
+
+    /// Resumable export using this checkpoint file.
+    #[pyo3(signature = (path, rows_per_part = None))]
+    fn checkpoint(
+        mut slf: PyRefMut<'_, Self>,
+        path: String,
+        rows_per_part: Option<usize>,
+    ) -> PyRefMut<'_, Self> {
+        slf.checkpoint = Some(path);
+        if let Some(rows) = rows_per_part {
+            slf.checkpoint_rows = rows;
+        }
+        slf
+    }
+
+    /// What to do with partial output when interrupted: keep, finalize or delete.
+    fn on_interrupt(mut slf: PyRefMut<'_, Self>, policy: String) -> PyRefMut<'_, Self> {
+        slf.on_interrupt = policy;
+        slf
+    }
+
+    /// Overwrite an existing output file.
+    #[pyo3(signature = (enabled = true))]
+    fn force(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.force = enabled;
+        slf
+    }
+
+    /// Only estimate the export; write nothing.
+    #[pyo3(signature = (enabled = true))]
+    fn dry_run(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.dry_run = enabled;
+        slf
+    }
+
+    /// Only compute LCA summaries; write no Parquet.
+    #[pyo3(signature = (enabled = true))]
+    fn lca_summary_only(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.lca_summary_only = enabled;
+        slf
+    }
+
+    /// Write one row per hash across all databases, with the union of its datasets.
+    #[pyo3(signature = (enabled = true))]
+    fn merge(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.merge = enabled;
+        slf
+    }
+
+    /// Only export rows that are new or changed since this previous export.
+    fn since(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.since = Some(path);
+        slf
+    }
+
+    /// Only export the datasets selected by a sourmash picklist,
+    /// `file.csv:column:coltype[:include|exclude]`.
+    fn picklist(mut slf: PyRefMut<'_, Self>, picklist: String) -> PyRefMut<'_, Self> {
+        slf.picklist = Some(picklist);
+        slf
+    }
+
+    /// Only export datasets whose names match one of these regexes; `@file`
+    /// reads patterns from a file, one per line.
+    fn include_datasets(mut slf: PyRefMut<'_, Self>, patterns: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.include_datasets = patterns;
+        slf
+    }
+
+    /// Leave out datasets whose names match one of these regexes; `@file`
+    /// reads patterns from a file, one per line.
+    fn exclude_datasets(mut slf: PyRefMut<'_, Self>, patterns: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.exclude_datasets = patterns;
+        slf
+    }
+
+    /// Only export hashes found in this many datasets or more.
+    fn min_datasets(mut slf: PyRefMut<'_, Self>, count: usize) -> PyRefMut<'_, Self> {
+        slf.min_datasets = Some(count);
+        slf
+    }
+
+    /// Only export hashes found in this many datasets or fewer.
+    fn max_datasets(mut slf: PyRefMut<'_, Self>, count: usize) -> PyRefMut<'_, Self> {
+        slf.max_datasets = Some(count);
+        slf
+    }
+
+    /// Only export hashes under this lineage prefix, e.g. `d__Bacteria;p__Proteobacteria`;
+    /// `mode` is `lca` (the LCA lineage) or `any` (any dataset's lineage).
+    #[pyo3(signature = (prefix, mode = "lca".to_string()))]
+    fn lineage_filter(
+        mut slf: PyRefMut<'_, Self>,
+        prefix: String,
+        mode: String,
+    ) -> PyRefMut<'_, Self> {
+        slf.lineage_filter = Some(prefix);
+        slf.lineage_filter_mode = mode;
+        slf
+    }
+
+    /// Only export hashes whose LCA is at this rank or below, e.g. `genus`.
+    fn min_lca_rank(mut slf: PyRefMut<'_, Self>, rank: String) -> PyRefMut<'_, Self> {
+        slf.min_lca_rank = Some(rank);
+        slf
+    }
+
+    /// Leave out hashes none of whose datasets are in the taxonomy.
+    #[pyo3(signature = (enabled = true))]
+    fn drop_unclassified(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.drop_unclassified = enabled;
+        slf
+    }
+
+    /// Write hashes none of whose datasets are in the taxonomy to this Parquet
+    /// file instead of the main output.
+    fn unclassified_output(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.unclassified_output = Some(path);
+        slf
+    }
+
+    /// Only export the hashes listed in this file: a text file with one hash
+    /// per line, or a Parquet file with a `hash` column (or a single column).
+    fn hash_list(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.hash_list = Some(path);
+        slf
+    }
+
+    /// Write JSON-lines progress events to this path (`-` for stderr).
+    fn progress_json(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.progress_json = Some(path);
+        slf
+    }
+
+    /// Soft memory budget in bytes.
+    fn max_memory(mut slf: PyRefMut<'_, Self>, bytes: u64) -> PyRefMut<'_, Self> {
+        slf.max_memory = Some(bytes);
+        slf
+    }
+
+    /// Also write the final run statistics to this JSON file.
+    fn stats_json(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.stats_json = Some(path);
+        slf
+    }
+
+    /// Call `callback(db, processed, total)` periodically during the scan.
+    fn progress_callback(mut slf: PyRefMut<'_, Self>, callback: PyObject) -> PyRefMut<'_, Self> {
+        slf.progress_callback = Some(callback);
+        slf
+    }
+
+    /// Export databases with different ksize, scaled or moltype together,
+    /// instead of failing before the scan.
+    #[pyo3(signature = (enabled = true))]
+    fn allow_mixed_params(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.allow_mixed_params = enabled;
+        slf
+    }
+
+    /// Write a per-dataset summary Parquet: hashes, hashes unique to the
+    /// dataset, classified fraction and LCA rank counts.
+    fn dataset_summary(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.dataset_summary = Some(path);
+        slf
+    }
+
+    /// Write hash counts by lineage at each rank to this CSV.
+    fn lineage_summary(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.lineage_summary = Some(path);
+        slf
+    }
+
+    /// Write a kraken2-style report of the LCA assignments to this path.
+    fn kraken_report(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.kraken_report = Some(path);
+        slf
+    }
+
+    /// Write the LCA distribution as Krona text (for `ktImportText`) to this path.
+    fn krona(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.krona = Some(path);
+        slf
+    }
+
+    /// Write hash counts by LCA lineage per source as a BIOM table to this path.
+    fn biom(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.biom = Some(path);
+        slf
+    }
+
+    /// Write an edge list of dataset pairs weighted by shared hashes to this
+    /// path, keeping pairs that share at least `min_shared` hashes.
+    #[pyo3(signature = (path, min_shared = 1))]
+    fn cooccurrence(
+        mut slf: PyRefMut<'_, Self>,
+        path: String,
+        min_shared: u64,
+    ) -> PyRefMut<'_, Self> {
+        slf.cooccurrence = Some(path);
+        slf.cooccurrence_min_shared = min_shared;
+        slf
+    }
+
+    /// Write the `n` hashes found in the most datasets of each source, with
+    /// their LCA, to this path.
+    #[pyo3(signature = (path, n = 100))]
+    fn top_shared(mut slf: PyRefMut<'_, Self>, path: String, n: usize) -> PyRefMut<'_, Self> {
+        slf.top_shared = Some(path);
+        slf.top_shared_n = n;
+        slf
+    }
+
+    /// Write HyperLogLog estimates of the distinct hashes per lineage at `rank`
+    /// (genus if not given) to this path.
+    #[pyo3(signature = (path, rank = None))]
+    fn lineage_hll(
+        mut slf: PyRefMut<'_, Self>,
+        path: String,
+        rank: Option<String>,
+    ) -> PyRefMut<'_, Self> {
+        slf.lineage_hll = Some(path);
+        slf.hll_rank = rank;
+        slf
+    }
+
+    /// Export the databases one at a time, in order, for byte-identical output.
+    #[pyo3(signature = (enabled = true))]
+    fn deterministic(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.deterministic = enabled;
+        slf
+    }
+
+    /// Write a directory of shard files instead of a single file, split per
+    /// `source` or by `hash:N`, with one writer thread per file.
+    fn sharded_output(mut slf: PyRefMut<'_, Self>, partitioning: String) -> PyRefMut<'_, Self> {
+        slf.sharded_output = Some(partitioning);
+        slf
+    }
+
+    /// Name datasets by the first non-empty manifest field of a comma-separated
+    /// chain of `name`, `filename`, `md5` and `idx` (default `name,filename`).
+    fn dataset_name(mut slf: PyRefMut<'_, Self>, chain: String) -> PyRefMut<'_, Self> {
+        slf.dataset_name = Some(chain);
+        slf
+    }
+
+    /// Name datasets by the labels in this CSV file, matched by their record
+    /// `name` or `md5`; other datasets keep their usual names.
+    fn dataset_labels(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.dataset_labels = Some(path);
+        slf
+    }
+
+    /// Replace dataset names with salted hashes, writing the real names to
+    /// `mapping` (CSV). The same `salt` gives the same names in every export;
+    /// a random one is used if not given.
+    #[pyo3(signature = (mapping, salt = None))]
+    fn anonymize(
+        mut slf: PyRefMut<'_, Self>,
+        mapping: String,
+        salt: Option<String>,
+    ) -> PyRefMut<'_, Self> {
+        slf.anonymize = Some(mapping);
+        slf.anonymize_salt = salt;
+        slf
+    }
+
+    /// Add per-dataset and total abundance columns, from the sketches of the
+    /// datasets that track abundance.
+    fn with_abundance(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.with_abundance = true;
+        slf
+    }
+
+    /// Number the LCA lineages in an `lca_lineage_id` column and write them
+    /// to this sidecar Parquet file.
+    fn lineage_table(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.lineage_table = Some(path);
+        slf
+    }
+
+    /// Add a `contamination_flag` column, set for hashes whose lineages
+    /// disagree above `rank` (domain if not given).
+    #[pyo3(signature = (rank = "domain".to_string()))]
+    fn flag_contamination(mut slf: PyRefMut<'_, Self>, rank: String) -> PyRefMut<'_, Self> {
+        slf.contamination_rank = Some(rank);
+        slf
+    }
+
+    /// Add columns with the distinct genera and species of each hash's
+    /// lineages and their entropy.
+    fn disagreement_metrics(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.disagreement_metrics = true;
+        slf
+    }
+
+    /// Write a bloom filter of the exported hashes to this file, with false
+    /// positive rate `fpp`.
+    #[pyo3(signature = (path, fpp = DEFAULT_BLOOM_FILTER_FPP))]
+    fn bloom_filter(mut slf: PyRefMut<'_, Self>, path: String, fpp: f64) -> PyRefMut<'_, Self> {
+        slf.bloom_filter = Some(path);
+        slf.bloom_filter_fpp = fpp;
+        slf
+    }
+
+    /// Fail on dataset IDs missing from a database's manifest, instead of
+    /// skipping them and listing them in the run statistics.
+    #[pyo3(signature = (enabled = true))]
+    fn strict(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.strict = enabled;
+        slf
+    }
+
+    /// Only export the hashes of the sketches in this query file (with the
+    /// `sketches()` ksize and moltype), e.g. a metagenome's signature.
+    fn query(mut slf: PyRefMut<'_, Self>, path: String) -> PyRefMut<'_, Self> {
+        slf.query = Some(path);
+        slf
+    }
+
+    /// Add a `query_names` column naming the sketches of the `query()` file
+    /// that have each hash, so one pass annotates many queries.
+    #[pyo3(signature = (enabled = true))]
+    fn tag_queries(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.tag_queries = enabled;
+        slf
+    }
+
+    /// Only export the datasets sharing at least `threshold_bp` with the
+    /// sketches in this query file (with the `sketches()` ksize and moltype),
+    /// with all their hashes.
+    #[pyo3(signature = (path, threshold_bp = 50_000))]
+    fn prefetch(
+        mut slf: PyRefMut<'_, Self>,
+        path: String,
+        threshold_bp: u64,
+    ) -> PyRefMut<'_, Self> {
+        slf.prefetch = Some(path);
+        slf.prefetch_threshold_bp = threshold_bp;
+        slf
+    }
+
+    /// Sketches to export: `ksize` selects the datasets of RevIndexes (required
+    /// when one has several ksizes) and, with `moltype`, the sketches of sketch
+    /// collections and queries.
+    #[pyo3(signature = (ksize, moltype = "DNA".to_string()))]
+    fn sketches(mut slf: PyRefMut<'_, Self>, ksize: u32, moltype: String) -> PyRefMut<'_, Self> {
+        slf.ksize = Some(ksize);
+        slf.moltype = moltype;
+        slf
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::sketch::minhash::max_hash_for_scaled;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::gather::rows_at_ksize;
+use crate::query::query_parquet;
+
+/// One dataset sharing hashes with a query of `parquet_containment`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContainmentMatch {
+    pub query: String,
+    pub name: String,
+    /// sources (revindex basenames) the dataset was exported from, `;`-separated
+    pub source: String,
+    /// query hashes in this dataset
+    pub shared_hashes: usize,
+    /// query hashes after downsampling to the export's scaled
+    pub query_hashes: usize,
+    /// fraction of the query contained in this dataset
+    pub containment: f64,
+}
+
+/// Containment of each query in every dataset of an exported Parquet file,
+/// without the RevIndex. `queries` are (name, hashes, scaled); their hashes
+/// are looked up together, so row groups are only read once for all queries.
+/// Each query is downsampled to the coarsest scaled among its matching rows.
+/// Datasets below `min_containment` are left out; the rest are ordered by
+/// query, then by containment and name.
+pub fn parquet_containment(
+    parquet_path: &Utf8Path,
+    queries: &[(String, Vec<u64>, u32)],
+    ksize: u32,
+    min_containment: f64,
+) -> Result<Vec<ContainmentMatch>> {
+    let all_hashes: Vec<u64> = queries
+        .iter()
+        .flat_map(|(_, hashes, _)| hashes.iter().copied())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let query = query_parquet(parquet_path, &all_hashes)?;
+    let rows = rows_at_ksize(&query, ksize)?;
+
+    let mut matches = Vec::new();
+    for (query_name, query_hashes, scaled) in queries {
+        let query_hashes: HashSet<u64> = query_hashes.iter().copied().collect();
+        let query_rows: Vec<_> = rows
+            .iter()
+            .filter(|(hash, ..)| query_hashes.contains(hash))
+            .collect();
+        let scaled = query_rows
+            .iter()
+            .map(|r| r.1)
+            .max()
+            .unwrap_or(0)
+            .max(*scaled);
+        let max_hash = max_hash_for_scaled(scaled as u64);
+        let query_size = query_hashes.iter().filter(|&&h| h <= max_hash).count();
+        if query_size == 0 {
+            continue;
+        }
+
+        let mut datasets: BTreeMap<&str, (BTreeSet<&str>, HashSet<u64>)> = BTreeMap::new();
+        for (hash, _, source, names) in query_rows {
+            if *hash > max_hash {
+                continue;
+            }
+            for &name in names {
+                let entry = datasets.entry(name).or_default();
+                entry.0.insert(*source);
+                entry.1.insert(*hash);
+            }
+        }
+
+        let mut query_matches: Vec<ContainmentMatch> = datasets
+            .into_iter()
+            .map(|(name, (sources, hashes))| ContainmentMatch {
+                query: query_name.clone(),
+                name: name.to_string(),
+                source: sources.into_iter().collect::<Vec<_>>().join(";"),
+                shared_hashes: hashes.len(),
+                query_hashes: query_size,
+                containment: hashes.len() as f64 / query_size as f64,
+            })
+            .filter(|m| m.containment >= min_containment)
+            .collect();
+        // datasets are already in name order, which the stable sort keeps for ties
+        query_matches.sort_by(|a, b| b.containment.total_cmp(&a.containment));
+        matches.extend(query_matches);
+    }
+    Ok(matches)
+}
+
+/// Write containment results as CSV; the header is written even without
+/// matches.
+pub fn write_containment_csv(matches: &[ContainmentMatch], path: &Utf8Path) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_path(path)?;
+    writer.write_record([
+        "query",
+        "name",
+        "source",
+        "shared_hashes",
+        "query_hashes",
+        "containment",
+    ])?;
+    for m in matches {
+        writer.serialize(m)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use csv::Writer;
+use sourmash::collection::CollectionSet;
+use std::collections::{HashMap, HashSet};
+
+use crate::naming::{Anonymizer, DatasetNaming};
+
+/// Shared hash counts for each pair of datasets of one database, for
+/// `--cooccurrence`. Datasets are indexed by their ID in the manifest.
+#[derive(Debug, Clone)]
+pub(crate) struct PairCounts {
+    source: String,
+    names: Vec<String>,
+    /// (lower ID, higher ID) -> hashes found in both
+    pairs: HashMap<(u32, u32), u64>,
+}
+
+impl PairCounts {
+    pub fn new(source: &str, collection: &CollectionSet, naming: &DatasetNaming) -> Self {
+        let names = collection
+            .manifest()
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| naming.name(idx as u32, record))
+            .collect();
+        Self {
+            source: source.to_string(),
+            names,
+            pairs: HashMap::new(),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Replace the names of the datasets in any pair with their anonymized
+    /// names.
+    pub fn anonymize(&mut self, anonymizer: &Anonymizer) {
+        let paired: HashSet<u32> = self.pairs.keys().flat_map(|&(a, b)| [a, b]).collect();
+        for idx in paired {
+            let name = &mut self.names[idx as usize];
+            *name = anonymizer.anonymize(name);
+        }
+    }
+
+    /// Count one exported hash for every pair of its (valid) dataset IDs.
+    pub fn add(&mut self, datasets: &[u32]) {
+        for (i, &a) in datasets.iter().enumerate() {
+            for &b in &datasets[i + 1..] {
+                let pair = if a < b { (a, b) } else { (b, a) };
+                *self.pairs.entry(pair).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Write an edge list of dataset pairs sharing at least `min_shared` hashes:
+/// one row per (source, dataset_a, dataset_b) with the shared hash count,
+/// largest first within each source. Returns the number of rows.
+pub(crate) fn write_cooccurrence(
+    path: &Utf8Path,
+    counts: &[PairCounts],
+    min_shared: u64,
+) -> Result<usize> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record(["source", "dataset_a", "dataset_b", "shared_hashes"])?;
+    let mut n_rows = 0;
+    for source in counts {
+        let mut edges: Vec<(&(u32, u32), &u64)> = source
+            .pairs
+            .iter()
+            .filter(|(_, shared)| **shared >= min_shared)
+            .collect();
+        edges.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for ((a, b), shared) in edges {
+            writer.serialize((
+                &source.source,
+                &source.names[*a as usize],
+                &source.names[*b as usize],
+                shared,
+            ))?;
+            n_rows += 1;
+        }
+    }
+    writer.flush()?;
+    eprintln!("Wrote {n_rows} dataset pairs to {path}");
+    Ok(n_rows)
+}
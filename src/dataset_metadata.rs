@@ -0,0 +1,51 @@
+use anyhow::Result;
+use arrow2::io::parquet::write::{write_metadata_sidecar, KeyValue, ThriftFileMetaData};
+use camino::Utf8Path;
+use std::fs::File;
+use std::io::BufWriter;
+
+/// Name of the file listing the row groups of every file of a directory
+/// output, as written by `pyarrow.parquet.write_metadata`.
+pub const METADATA_FILE: &str = "_metadata";
+/// Name of the file holding only the schema of a directory output.
+pub const COMMON_METADATA_FILE: &str = "_common_metadata";
+
+/// Write `_metadata` and `_common_metadata` into `dir`, so `pyarrow.dataset`
+/// and Dask can plan reads of its files without opening each footer. `parts`
+/// are the footers of the files, by path relative to `dir`; all row groups
+/// are listed in `_metadata`, each pointing at its file, and both get
+/// `key_value_metadata` in place of the per-file footer metadata. Nothing is
+/// written for a directory without files.
+pub(crate) fn write_dataset_metadata(
+    dir: &Utf8Path,
+    parts: Vec<(String, ThriftFileMetaData)>,
+    key_value_metadata: Vec<KeyValue>,
+) -> Result<()> {
+    let Some((_, first)) = parts.first() else {
+        return Ok(());
+    };
+    let mut common = first.clone();
+    common.row_groups = Vec::new();
+    common.num_rows = 0;
+    common.key_value_metadata = Some(key_value_metadata);
+
+    let mut combined = common.clone();
+    for (path, part) in parts {
+        if part.schema != combined.schema {
+            anyhow::bail!("'{path}' has a different schema than the other files in '{dir}'");
+        }
+        combined.num_rows += part.num_rows;
+        for mut row_group in part.row_groups {
+            for column in &mut row_group.columns {
+                column.file_path = Some(path.clone());
+            }
+            combined.row_groups.push(row_group);
+        }
+    }
+
+    for (name, metadata) in [(COMMON_METADATA_FILE, &common), (METADATA_FILE, &combined)] {
+        let mut file = BufWriter::new(File::create(dir.join(name))?);
+        write_metadata_sidecar(&mut file, metadata)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::*;
+use arrow2::io::parquet::write::*;
+use camino::Utf8Path;
+use sourmash::collection::CollectionSet;
+use std::fs::File;
+
+use crate::export::{parquet_write_options, RANKS};
+use crate::naming::{Anonymizer, DatasetNaming};
+
+/// Per-dataset counts over the exported rows of one database, for
+/// `--dataset-summary`. Datasets are indexed by their ID in the manifest.
+#[derive(Debug, Clone)]
+pub(crate) struct DatasetTally {
+    source: String,
+    names: Vec<String>,
+    /// datasets left out by the picklist, name patterns or ksize aren't listed
+    kept: Vec<bool>,
+    hashes: Vec<u64>,
+    /// hashes found in no other dataset of this database
+    unique: Vec<u64>,
+    /// hashes with an LCA, by LCA rank (indexed like `RANKS`)
+    ranks: Vec<[u64; RANKS.len()]>,
+}
+
+impl DatasetTally {
+    pub fn new(
+        source: &str,
+        collection: &CollectionSet,
+        mask: Option<&[bool]>,
+        naming: &DatasetNaming,
+    ) -> Self {
+        let names: Vec<String> = collection
+            .manifest()
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| naming.name(idx as u32, record))
+            .collect();
+        let n = names.len();
+        Self {
+            source: source.to_string(),
+            names,
+            kept: mask.map_or_else(|| vec![true; n], <[bool]>::to_vec),
+            hashes: vec![0; n],
+            unique: vec![0; n],
+            ranks: vec![[0; RANKS.len()]; n],
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Replace the names of the kept datasets with their anonymized names.
+    pub fn anonymize(&mut self, anonymizer: &Anonymizer) {
+        for (name, kept) in self.names.iter_mut().zip(&self.kept) {
+            if *kept {
+                *name = anonymizer.anonymize(name);
+            }
+        }
+    }
+
+    /// Count one exported hash for each of its (valid) dataset IDs.
+    pub fn add(&mut self, datasets: &[u32], lca_rank: Option<&str>) {
+        let rank = lca_rank.and_then(|rank| RANKS.iter().position(|r| *r == rank));
+        for &idx in datasets {
+            let idx = idx as usize;
+            self.hashes[idx] += 1;
+            if datasets.len() == 1 {
+                self.unique[idx] += 1;
+            }
+            if let Some(rank) = rank {
+                self.ranks[idx][rank] += 1;
+            }
+        }
+    }
+}
+
+fn dataset_summary_schema() -> Schema {
+    let mut fields = vec![
+        Field::new("source", DataType::Utf8, false),
+        Field::new("dataset", DataType::Utf8, false),
+        Field::new("hashes", DataType::UInt64, false),
+        Field::new("unique_hashes", DataType::UInt64, false),
+        Field::new("classified_fraction", DataType::Float64, false),
+    ];
+    fields.extend(
+        RANKS
+            .iter()
+            .map(|rank| Field::new(format!("lca_{rank}"), DataType::UInt64, false)),
+    );
+    Schema::from(fields)
+}
+
+/// Write one row per dataset: its source, name, exported hashes, hashes
+/// unique to it, the fraction of its hashes with an LCA, and how many of its
+/// hashes have their LCA at each rank (`lca_domain` ... `lca_species`).
+/// Returns the number of rows.
+pub(crate) fn write_dataset_summary(path: &Utf8Path, tallies: &[DatasetTally]) -> Result<usize> {
+    let rows = || {
+        tallies.iter().flat_map(|tally| {
+            (0..tally.names.len())
+                .filter(move |idx| tally.kept[*idx])
+                .map(move |idx| (tally, idx))
+        })
+    };
+    let classified_fraction = |(tally, idx): (&DatasetTally, usize)| match tally.hashes[idx] {
+        0 => 0.0,
+        total => tally.ranks[idx].iter().sum::<u64>() as f64 / total as f64,
+    };
+
+    let mut columns: Vec<Box<dyn Array>> = vec![
+        Box::new(Utf8Array::<i32>::from_iter_values(
+            rows().map(|(tally, _)| tally.source.as_str()),
+        )),
+        Box::new(Utf8Array::<i32>::from_iter_values(
+            rows().map(|(tally, idx)| tally.names[idx].as_str()),
+        )),
+        Box::new(UInt64Array::from_vec(
+            rows().map(|(tally, idx)| tally.hashes[idx]).collect(),
+        )),
+        Box::new(UInt64Array::from_vec(
+            rows().map(|(tally, idx)| tally.unique[idx]).collect(),
+        )),
+        Box::new(Float64Array::from_vec(
+            rows().map(classified_fraction).collect(),
+        )),
+    ];
+    for rank in 0..RANKS.len() {
+        columns.push(Box::new(UInt64Array::from_vec(
+            rows().map(|(tally, idx)| tally.ranks[idx][rank]).collect(),
+        )));
+    }
+    let n_rows = rows().count();
+
+    let schema = dataset_summary_schema();
+    let options = parquet_write_options();
+    let mut writer = FileWriter::try_new(File::create(path)?, schema.clone(), options)?;
+    let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+    let row_groups = RowGroupIterator::try_new(
+        std::iter::once(Ok(Chunk::new(columns))),
+        &schema,
+        options,
+        encodings,
+    )?;
+    for group in row_groups {
+        writer.write(group?)?;
+    }
+    writer.end(None)?;
+    eprintln!("Wrote the summary of {n_rows} datasets to {path}");
+    Ok(n_rows)
+}
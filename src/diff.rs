@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use arrow2::io::parquet::write::{Encoding, FileWriter, RowGroupIterator};
+use byteorder::{ByteOrder, LittleEndian};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+
+use crate::export::{
+    check_db_compatibility, dataset_names, parquet_write_options, string_list_array,
+    DatasetResolver, HASHES_CF,
+};
+use crate::is_revindex_database;
+use crate::naming::DatasetNaming;
+
+// rows per row group in the diff output
+const DIFF_BATCH_SIZE: usize = 100_000;
+
+/// Hash counts from comparing two databases (or exports), A and B.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiffSummary {
+    pub a_only: u64,
+    pub b_only: u64,
+    pub shared: u64,
+    /// shared hashes whose dataset names differ between A and B
+    pub shared_changed: u64,
+}
+
+/// Dataset names (sorted) for every hash in a RevIndex.
+fn revindex_hashes(db_path: &Utf8Path) -> Result<HashMap<u64, Vec<String>>> {
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    let mut hashes = HashMap::new();
+    for (k, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+        .filter(|(k, _)| k.len() == 8)
+    {
+        let Some(datasets) = resolver.resolve(&v)? else {
+            continue;
+        };
+        let mut names = dataset_names(collection, datasets, &DatasetNaming::default());
+        names.sort_unstable();
+        hashes.insert(LittleEndian::read_u64(&k), names);
+    }
+    Ok(hashes)
+}
+
+/// Dataset names (sorted, deduplicated) for every hash in an export; rows for
+/// the same hash from several sources are combined.
+fn parquet_hashes(path: &Utf8Path) -> Result<HashMap<u64, Vec<String>>> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?
+        .filter(|_, field| field.name == "hash" || field.name == "dataset_names");
+    let column = |name: &str| {
+        schema
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| anyhow!("'{path}' has no '{name}' column"))
+    };
+    let (hash_idx, names_idx) = (column("hash")?, column("dataset_names")?);
+
+    let mut hashes: HashMap<u64, Vec<String>> = HashMap::new();
+    for chunk in FileReader::new(
+        reader,
+        metadata.row_groups,
+        schema.clone(),
+        None,
+        None,
+        None,
+    ) {
+        let chunk = chunk?;
+        let arrays = chunk.arrays();
+        let downcast_err = || anyhow!("unexpected column types in '{path}'");
+        let chunk_hashes = arrays[hash_idx]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(downcast_err)?;
+        let names = arrays[names_idx]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .ok_or_else(downcast_err)?;
+        let name_values = names
+            .values()
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+
+        for row in 0..chunk.len() {
+            let (start, end) = names.offsets().start_end(row);
+            hashes
+                .entry(chunk_hashes.value(row))
+                .or_default()
+                .extend((start..end).map(|j| name_values.value(j).to_string()));
+        }
+    }
+
+    for names in hashes.values_mut() {
+        names.sort_unstable();
+        names.dedup();
+    }
+    Ok(hashes)
+}
+
+/// Dataset names for every hash in `path`, a RevIndex or an exported Parquet file.
+fn load_hashes(path: &Utf8Path) -> Result<HashMap<u64, Vec<String>>> {
+    if is_revindex_database(&Utf8PathBuf::from(path)) {
+        revindex_hashes(path)
+    } else {
+        parquet_hashes(path)
+    }
+}
+
+fn diff_schema() -> Schema {
+    let names = || DataType::List(Box::new(Field::new("item", DataType::Utf8, true)));
+    Schema::from(vec![
+        Field::new("hash", DataType::UInt64, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("membership_changed", DataType::Boolean, false),
+        Field::new("datasets_a", names(), false),
+        Field::new("datasets_b", names(), false),
+    ])
+}
+
+type DiffRow = (u64, &'static str, Vec<String>, Vec<String>);
+
+fn write_diff_rows(writer: &mut FileWriter<File>, schema: &Schema, rows: &[DiffRow]) -> Result<()> {
+    let hashes = UInt64Array::from_slice(rows.iter().map(|r| r.0).collect::<Vec<_>>());
+    let status = Utf8Array::<i32>::from_slice(rows.iter().map(|r| r.1).collect::<Vec<_>>());
+    let changed = BooleanArray::from_slice(
+        rows.iter()
+            .map(|r| r.1 == "shared" && r.2 != r.3)
+            .collect::<Vec<_>>(),
+    );
+    let datasets_a = string_list_array(&rows.iter().map(|r| r.2.clone()).collect::<Vec<_>>())?;
+    let datasets_b = string_list_array(&rows.iter().map(|r| r.3.clone()).collect::<Vec<_>>())?;
+    let chunk = Chunk::new(vec![
+        Box::new(hashes) as Box<dyn Array>,
+        Box::new(status) as Box<dyn Array>,
+        Box::new(changed) as Box<dyn Array>,
+        Box::new(datasets_a) as Box<dyn Array>,
+        Box::new(datasets_b) as Box<dyn Array>,
+    ]);
+
+    let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+    let row_groups = RowGroupIterator::try_new(
+        std::iter::once(Ok(chunk)),
+        schema,
+        parquet_write_options(),
+        encodings,
+    )?;
+    for group in row_groups {
+        writer.write(group?)?;
+    }
+    Ok(())
+}
+
+/// Compare the hashes of `a` and `b` (each a RevIndex or an exported Parquet
+/// file) and count the hashes only in A, only in B, and in both. With `output`,
+/// also write every hash, sorted, to Parquet with its `status` (`a_only`,
+/// `b_only` or `shared`) and its dataset names in each input, to audit what
+/// changed between database releases. Both inputs are held in memory.
+pub fn revindex_diff(a: &Utf8Path, b: &Utf8Path, output: Option<&Utf8Path>) -> Result<DiffSummary> {
+    let mut a_hashes = load_hashes(a)?;
+    let mut b_hashes = load_hashes(b)?;
+
+    let mut a_keys: Vec<u64> = a_hashes.keys().copied().collect();
+    let mut b_keys: Vec<u64> = b_hashes.keys().copied().collect();
+    a_keys.sort_unstable();
+    b_keys.sort_unstable();
+
+    let schema = diff_schema();
+    let mut writer = output
+        .map(|path| -> Result<_> {
+            let file = File::create(path).with_context(|| format!("cannot create '{path}'"))?;
+            Ok(FileWriter::try_new(
+                file,
+                schema.clone(),
+                parquet_write_options(),
+            )?)
+        })
+        .transpose()?;
+
+    let mut summary = DiffSummary::default();
+    let mut rows: Vec<DiffRow> = Vec::with_capacity(DIFF_BATCH_SIZE);
+    let (mut i, mut j) = (0, 0);
+    while i < a_keys.len() || j < b_keys.len() {
+        let order = match (a_keys.get(i), b_keys.get(j)) {
+            (Some(x), Some(y)) => x.cmp(y),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        let row = match order {
+            Ordering::Less => {
+                let hash = a_keys[i];
+                i += 1;
+                summary.a_only += 1;
+                (
+                    hash,
+                    "a_only",
+                    a_hashes.remove(&hash).unwrap_or_default(),
+                    vec![],
+                )
+            }
+            Ordering::Greater => {
+                let hash = b_keys[j];
+                j += 1;
+                summary.b_only += 1;
+                (
+                    hash,
+                    "b_only",
+                    vec![],
+                    b_hashes.remove(&hash).unwrap_or_default(),
+                )
+            }
+            Ordering::Equal => {
+                let hash = a_keys[i];
+                i += 1;
+                j += 1;
+                let in_a = a_hashes.remove(&hash).unwrap_or_default();
+                let in_b = b_hashes.remove(&hash).unwrap_or_default();
+                summary.shared += 1;
+                if in_a != in_b {
+                    summary.shared_changed += 1;
+                }
+                (hash, "shared", in_a, in_b)
+            }
+        };
+
+        if let Some(writer) = writer.as_mut() {
+            rows.push(row);
+            if rows.len() >= DIFF_BATCH_SIZE {
+                write_diff_rows(writer, &schema, &rows)?;
+                rows.clear();
+            }
+        }
+    }
+
+    if let Some(mut writer) = writer {
+        if !rows.is_empty() {
+            write_diff_rows(&mut writer, &schema, &rows)?;
+        }
+        writer.end(None)?;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,279 @@
+use anyhow::{anyhow, Result};
+use arrow2::io::parquet::write::FileWriter;
+use byteorder::{ByteOrder, LittleEndian};
+use camino::Utf8PathBuf;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::time::Instant;
+
+use crate::export::{
+    annotate_taxonomy, check_db_compatibility, dataset_names, parse_compression, select_ksize,
+    strip_accession_version, write_records, ArrowRecord, DatasetResolver, ExportOptions, Sampling,
+    SketchParams, WriterSettings, HASHES_CF,
+};
+
+// number of hashes sampled from the start of each database by default
+const DRY_RUN_SAMPLE_SIZE: usize = 10_000;
+
+// codecs compared when projecting the output size
+const ESTIMATE_CODECS: [&str; 6] = ["zstd", "snappy", "lz4", "gzip", "brotli", "uncompressed"];
+
+/// Projected export of a single database.
+#[derive(Debug, Clone, Serialize)]
+pub struct DbEstimate {
+    pub source: String,
+    pub ksize: u32,
+    pub scaled: u32,
+    pub n_datasets: usize,
+    /// RocksDB's estimate of the number of hashes
+    pub estimated_hashes: u64,
+    /// rows expected in the output, after sharding, sampling and `limit`
+    pub projected_rows: u64,
+    pub sampled_hashes: usize,
+    /// datasets with a taxonomy entry; unset without a taxonomy
+    pub taxonomy_matches: Option<usize>,
+    /// sampled hashes with an LCA
+    pub classified: usize,
+    /// projected output size with the configured compression
+    pub projected_bytes: f64,
+    /// projected output size for each supported codec
+    pub bytes_per_codec: BTreeMap<String, f64>,
+    /// projected single-threaded export time
+    pub projected_secs: f64,
+}
+
+/// Projected size and run time of an export, extrapolated from a sample of
+/// each database.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportEstimate {
+    pub databases: Vec<DbEstimate>,
+    pub estimated_hashes: u64,
+    pub projected_rows: u64,
+    pub projected_bytes: f64,
+    pub bytes_per_codec: BTreeMap<String, f64>,
+    /// projected wall time, exporting databases in parallel
+    pub projected_secs: f64,
+    pub threads: usize,
+}
+
+/// Rows an export of `estimated` hashes should produce with these options.
+fn projected_rows(estimated: u64, options: &ExportOptions) -> u64 {
+    let mut rows = estimated as f64;
+    if let Some((_, num_shards)) = options.shard {
+        rows /= num_shards as f64;
+    }
+    match options.sampling {
+        Some(Sampling::Fraction(fraction)) => rows *= fraction,
+        Some(Sampling::EveryNth(n)) => rows /= n as f64,
+        None => {}
+    }
+    let rows = rows.round() as u64;
+    options.limit.map_or(rows, |limit| rows.min(limit))
+}
+
+/// Size in bytes of `sample` encoded as a complete Parquet file.
+fn encoded_size(settings: &WriterSettings, sample: &[ArrowRecord]) -> Result<usize> {
+    let mut writer = FileWriter::try_new(
+        Cursor::new(Vec::new()),
+        settings.schema(),
+        settings.write_options,
+    )?;
+    if !sample.is_empty() {
+        write_records(&mut writer, settings, sample)?;
+    }
+    writer.end(None)?;
+    Ok(writer.into_inner().into_inner().len())
+}
+
+/// Open each database, sample hashes from the start of it, and extrapolate the
+/// output rows, size (for the configured and every other codec) and run time of
+/// a full export with `options`. `sample_fraction` sets the share of each
+/// database's estimated hashes to sample; by default 10,000 hashes are sampled.
+pub fn estimate_export(
+    db_paths: &[Utf8PathBuf],
+    taxonomy_map: Option<&HashMap<String, String>>,
+    options: &ExportOptions,
+    sample_fraction: Option<f64>,
+) -> Result<ExportEstimate> {
+    options.validate()?;
+    options.check_taxonomy(taxonomy_map.is_some())?;
+    if let Some(fraction) = sample_fraction {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            anyhow::bail!("sample fraction must be in (0, 1] (got {fraction})");
+        }
+    }
+    let settings = options.writer_settings()?;
+    let mut estimate = ExportEstimate {
+        threads: rayon::current_num_threads(),
+        ..Default::default()
+    };
+
+    for db_path in db_paths {
+        check_db_compatibility(db_path)?;
+        let revindex = RevIndex::open(db_path, true, None)
+            .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+        let revindex = match revindex {
+            RevIndex::Plain(revindex) => revindex,
+        };
+        let collection = revindex.collection();
+        let SketchParams { ksize, scaled, .. } =
+            select_ksize(collection.manifest(), db_path, options.ksize)?;
+
+        let db = revindex.db.as_ref();
+        let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+        let estimated = db
+            .property_int_value_cf(&cf, "rocksdb.estimate-num-keys")?
+            .unwrap_or(0);
+
+        // how many datasets have a taxonomy entry?
+        let taxonomy_matches = taxonomy_map.map(|tax_map| {
+            collection
+                .manifest()
+                .iter()
+                .filter(|record| {
+                    let ident = record.name().split_whitespace().next().unwrap_or("");
+                    tax_map.contains_key(strip_accession_version(ident))
+                })
+                .count()
+        });
+
+        let sample_size = match sample_fraction {
+            Some(fraction) => ((estimated as f64 * fraction).ceil() as usize).max(1),
+            None => DRY_RUN_SAMPLE_SIZE,
+        };
+
+        // sample hashes from the start of the hashes CF
+        let source = db_path.file_name().unwrap_or(db_path.as_str()).to_string();
+        let mut resolver = DatasetResolver::new(db)?;
+        let mut sample: Vec<ArrowRecord> = Vec::with_capacity(sample_size);
+        let start = Instant::now();
+        for (k, v) in db
+            .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+            .filter_map(Result::ok)
+            .filter(|(k, _)| k.len() == 8)
+            .take(sample_size)
+        {
+            let Some(datasets) = resolver.resolve(&v)? else {
+                continue;
+            };
+            let names = dataset_names(collection, datasets, &options.dataset_naming);
+            let (taxonomy_list, lca_lineage, lca_rank) = annotate_taxonomy(&names, taxonomy_map);
+            sample.push(ArrowRecord {
+                hash: LittleEndian::read_u64(&k),
+                dataset_names: names,
+                taxonomy_list,
+                lca_lineage,
+                lca_rank,
+                ksize,
+                scaled,
+                source: source.clone(),
+                abundances: None,
+            });
+        }
+
+        // encode the sample to measure bytes per row
+        let encoded_bytes = encoded_size(&settings, &sample)?;
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let rows = projected_rows(estimated, options);
+        let n = sample.len().max(1) as f64;
+        let per_row = |bytes: usize| bytes as f64 / n * rows as f64;
+
+        let mut bytes_per_codec = BTreeMap::new();
+        for codec in ESTIMATE_CODECS {
+            let mut codec_settings = settings.clone();
+            codec_settings.write_options.compression = parse_compression(codec)?;
+            let bytes = per_row(encoded_size(&codec_settings, &sample)?);
+            *estimate
+                .bytes_per_codec
+                .entry(codec.to_string())
+                .or_default() += bytes;
+            bytes_per_codec.insert(codec.to_string(), bytes);
+        }
+
+        let db_estimate = DbEstimate {
+            source,
+            ksize,
+            scaled,
+            n_datasets: collection.len(),
+            estimated_hashes: estimated,
+            projected_rows: rows,
+            sampled_hashes: sample.len(),
+            taxonomy_matches,
+            classified: sample.iter().filter(|r| r.lca_rank.is_some()).count(),
+            projected_bytes: per_row(encoded_bytes),
+            bytes_per_codec,
+            projected_secs: elapsed / n * rows as f64,
+        };
+        estimate.estimated_hashes += db_estimate.estimated_hashes;
+        estimate.projected_rows += db_estimate.projected_rows;
+        estimate.projected_bytes += db_estimate.projected_bytes;
+        estimate.projected_secs += db_estimate.projected_secs;
+        estimate.databases.push(db_estimate);
+    }
+
+    estimate.projected_secs /= estimate.threads.min(db_paths.len()).max(1) as f64;
+    Ok(estimate)
+}
+
+/// Open each database, sample its first hashes, and report what a full export
+/// would look like (hash counts, ksize/scaled, taxonomy coverage, projected
+/// output size and time) without writing any Parquet.
+pub(crate) fn dry_run(
+    db_paths: &[Utf8PathBuf],
+    taxonomy_map: Option<&HashMap<String, String>>,
+    options: &ExportOptions,
+) -> Result<()> {
+    let estimate = estimate_export(db_paths, taxonomy_map, options, None)?;
+
+    for db in &estimate.databases {
+        eprintln!("{}:", db.source);
+        eprintln!("  ksize: {}, scaled: {}", db.ksize, db.scaled);
+        eprintln!("  datasets: {}", db.n_datasets);
+        eprintln!("  estimated hashes: {}", db.estimated_hashes);
+        if let Some(matches) = db.taxonomy_matches {
+            eprintln!(
+                "  datasets with taxonomy: {matches} ({:.1}%)",
+                matches as f64 / db.n_datasets.max(1) as f64 * 100.0
+            );
+            eprintln!(
+                "  sampled hashes with an LCA: {} of {} ({:.1}%)",
+                db.classified,
+                db.sampled_hashes,
+                db.classified as f64 / db.sampled_hashes.max(1) as f64 * 100.0
+            );
+        }
+        eprintln!(
+            "  projected output size: {:.1} MB",
+            db.projected_bytes / 1_000_000.0
+        );
+        eprintln!(
+            "  projected time (single thread): {:.0}s",
+            db.projected_secs
+        );
+    }
+
+    eprintln!("--- Dry run summary ---");
+    eprintln!("Estimated total hashes: {}", estimate.estimated_hashes);
+    eprintln!("Projected rows: {}", estimate.projected_rows);
+    eprintln!(
+        "Projected output size: {:.1} MB",
+        estimate.projected_bytes / 1_000_000.0
+    );
+    let codecs: Vec<String> = estimate
+        .bytes_per_codec
+        .iter()
+        .map(|(codec, bytes)| format!("{codec} {:.1} MB", bytes / 1_000_000.0))
+        .collect();
+    eprintln!("  by compression: {}", codecs.join(", "));
+    let single_threaded: f64 = estimate.databases.iter().map(|db| db.projected_secs).sum();
+    eprintln!(
+        "Projected time: {:.0}s single-threaded, ~{:.0}s with {} threads",
+        single_threaded, estimate.projected_secs, estimate.threads
+    );
+    eprintln!("No output was written (--dry-run).");
+
+    Ok(())
+}
@@ -10,23 +10,52 @@ use byteorder::{ByteOrder, LittleEndian};
 use camino::{Utf8Path, Utf8PathBuf};
 use csv::Writer;
 use rayon::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sourmash::collection::CollectionSet;
 use sourmash::index::revindex::{Datasets, RevIndex, RevIndexOps};
-use std::collections::{BTreeMap, HashMap};
+use sourmash::manifest::{Manifest, Record};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
-
+use std::time::Instant;
+
+use crate::abundance::DatasetAbundances;
+use crate::checkpoint::{Checkpoint, SourceProgress};
+use crate::cooccurrence::{write_cooccurrence, PairCounts};
+use crate::dataset_summary::{write_dataset_summary, DatasetTally};
+use crate::dry_run::dry_run;
+use crate::incremental::PreviousExport;
+use crate::lineage_hll::{write_lineage_hll, LineageSketches, DEFAULT_HLL_RANK};
+use crate::lineage_table::LineageTable;
+use crate::lineages::{
+    write_biom, write_kraken_report, write_krona_text, write_lineage_summary, LineageCounts,
+};
+use crate::membership::write_bloom_filter;
+use crate::memory::MemoryMonitor;
+use crate::merge::{join_merge_thread, start_merge_thread};
+use crate::naming::{Anonymizer, DatasetNaming};
+use crate::patterns::DatasetPatterns;
+use crate::picklist::Picklist;
+use crate::prefetch::Prefetch;
+use crate::progress::{ProgressCallback, Reporter};
+use crate::query_tags::QueryTags;
+use crate::rewrite::Partitioning;
+use crate::sharded::{start_sharded_writer_thread, ShardedExport};
+use crate::stats::{InvalidDatasets, RunStats, StageTimer};
+use crate::top_shared::{write_top_shared, TopShared};
+
+// handles SIGINT, SIGTERM and SIGHUP (via the ctrlc "termination" feature)
 fn setup_ctrlc_handler(cancel_flag: Arc<AtomicBool>) -> Result<()> {
     if std::env::var("PYTEST_RUNNING").is_err() {
         let cancel_flag = cancel_flag.clone();
         ctrlc::set_handler(move || {
-            eprintln!("Received Ctrl-C! Will terminate after current item...");
+            eprintln!("Received interrupt signal! Will terminate after current item...");
             cancel_flag.store(true, Ordering::SeqCst);
         })?;
     } else {
@@ -35,21 +64,911 @@ fn setup_ctrlc_handler(cancel_flag: Arc<AtomicBool>) -> Result<()> {
     Ok(())
 }
 
-// Record struct for parquet file output
+/// What to do with partial output when an export is interrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InterruptPolicy {
+    /// stop immediately; leave finished checkpoint parts (and any in-progress output) as-is
+    Keep,
+    /// flush buffered records and write a valid footer marked as partial
+    #[default]
+    Finalize,
+    /// remove the partial output (and any checkpoint parts)
+    Delete,
+}
+
+impl std::str::FromStr for InterruptPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "finalize" => Ok(Self::Finalize),
+            "delete" => Ok(Self::Delete),
+            _ => anyhow::bail!("unknown interrupt policy '{s}'; expected keep, finalize or delete"),
+        }
+    }
+}
+
+/// Error returned when an export stops early because of an interrupt signal
+/// (or a cancellation requested by the caller).
 #[derive(Debug)]
-struct ArrowRecord {
-    hash: u64,
-    dataset_names: Vec<String>,
-    taxonomy_list: Option<Vec<String>>,
-    lca_lineage: Option<String>,
-    lca_rank: Option<String>,
-    ksize: u32,
-    scaled: u32,
-    source: String, // basename of revindex
+pub struct ExportInterrupted {
+    pub rows_written: u64,
+    pub policy: InterruptPolicy,
+}
+
+impl fmt::Display for ExportInterrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.policy {
+            InterruptPolicy::Finalize => write!(
+                f,
+                "export was interrupted after writing {} hashes; output and LCA summaries cover only those hashes",
+                self.rows_written
+            ),
+            InterruptPolicy::Keep => write!(
+                f,
+                "export was interrupted; partial output was left as-is"
+            ),
+            InterruptPolicy::Delete => {
+                write!(f, "export was interrupted; partial output was removed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportInterrupted {}
+
+/// Temporary outputs of an export (`<output>.tmp`, a file or a sharded
+/// directory, and the unclassified `.tmp`), removed when dropped unless the
+/// export got far enough to rename or deliberately keep them, so a failed run
+/// doesn't leave them behind. Other writers use it for their own `.tmp` files.
+pub(crate) struct TmpOutputs {
+    pub(crate) paths: Vec<Utf8PathBuf>,
+}
+
+impl TmpOutputs {
+    /// Keep the temporary outputs from here on.
+    pub(crate) fn keep(&mut self) {
+        self.paths.clear();
+    }
+}
+
+impl Drop for TmpOutputs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let removed = if path.is_dir() {
+                std::fs::remove_dir_all(path)
+            } else if path.exists() {
+                std::fs::remove_file(path)
+            } else {
+                continue;
+            };
+            match removed {
+                Ok(()) => eprintln!("Removed temporary output {path}"),
+                Err(e) => eprintln!("Warning: could not remove temporary output {path}: {e}"),
+            }
+        }
+    }
+}
+
+/// Error returned by producers that stop because the writer (or merge)
+/// thread failed; the thread's own error is reported instead once joined.
+#[derive(Debug)]
+pub(crate) struct WriterFailed;
+
+impl fmt::Display for WriterFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the writer thread stopped with an error")
+    }
+}
+
+impl std::error::Error for WriterFailed {}
+
+/// Run a consumer of record batches (writer or merge thread) on a new thread.
+/// If it fails, `memory` is flagged so producers stop sending right away
+/// rather than scanning their databases to the end.
+pub(crate) fn spawn_consumer<T: Send + 'static>(
+    memory: Arc<MemoryMonitor>,
+    body: impl FnOnce() -> Result<T> + Send + 'static,
+) -> thread::JoinHandle<Result<T>> {
+    thread::spawn(move || {
+        let result = body();
+        if result.is_err() {
+            memory.set_writer_failed();
+        }
+        result
+    })
+}
+
+// same thread mode as the sourmash RevIndex, so our handles are interchangeable
+pub(crate) type DB = rocksdb::DBWithThreadMode<rocksdb::MultiThreaded>;
+
+/// Deterministic subsampling of hashes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// keep roughly this fraction of hashes, chosen by hash value
+    Fraction(f64),
+    /// keep every Nth hash in iteration order
+    EveryNth(u64),
+}
+
+impl Sampling {
+    fn keep(&self, hash: u64, position: u64) -> bool {
+        match *self {
+            // mix the hash first: FracMinHash values are bounded by max_hash,
+            // so comparing them directly would depend on scaled
+            Sampling::Fraction(fraction) => {
+                let mixed = hash.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(32);
+                (mixed as f64) < fraction * u64::MAX as f64
+            }
+            Sampling::EveryNth(n) => position % n == 0,
+        }
+    }
+}
+
+/// Which lineages of a hash a `LineageFilter` checks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineageMatch {
+    /// the LCA lineage must fall under the prefix
+    #[default]
+    Lca,
+    /// any of the datasets' lineages may fall under the prefix
+    Any,
+}
+
+impl std::str::FromStr for LineageMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lca" => Ok(Self::Lca),
+            "any" => Ok(Self::Any),
+            _ => anyhow::bail!("unknown lineage filter mode '{s}'; expected lca or any"),
+        }
+    }
+}
+
+/// Keep only hashes whose lineage falls under a prefix such as
+/// `d__Bacteria;p__Proteobacteria`; the prefix matches whole ranks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineageFilter {
+    prefix: String,
+    mode: LineageMatch,
+}
+
+impl LineageFilter {
+    pub fn new(prefix: &str, mode: LineageMatch) -> Result<Self> {
+        let prefix = prefix.trim().trim_end_matches(';');
+        if prefix.is_empty() {
+            anyhow::bail!("lineage filter must not be empty");
+        }
+        Ok(Self {
+            prefix: prefix.to_string(),
+            mode,
+        })
+    }
+
+    fn under(&self, lineage: &str) -> bool {
+        lineage
+            .strip_prefix(self.prefix.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(';'))
+    }
+
+    /// Whether a hash with these annotations is kept; unclassified hashes never are.
+    pub(crate) fn keeps(
+        &self,
+        taxonomy_list: Option<&Vec<String>>,
+        lca_lineage: Option<&str>,
+    ) -> bool {
+        match self.mode {
+            LineageMatch::Lca => lca_lineage.is_some_and(|lineage| self.under(lineage)),
+            LineageMatch::Any => {
+                taxonomy_list.is_some_and(|list| list.iter().any(|lineage| self.under(lineage)))
+            }
+        }
+    }
+}
+
+/// Options controlling how each RevIndex is opened and scanned.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// open the RocksDB in read-write mode
+    pub rw: bool,
+    /// readahead size (bytes) for the `hashes` iterator; 0 keeps the RocksDB default
+    pub readahead_size: usize,
+    /// skip populating the block cache during the scan
+    pub no_fill_cache: bool,
+    /// pin iterator keys/values in memory while the iterator is alive
+    pub pin_data: bool,
+    /// iterate over a consistent snapshot of the database
+    pub use_snapshot: bool,
+    /// scan the `hashes` CF through a RocksDB secondary instance
+    pub secondary: bool,
+    /// directory for secondary instance files; defaults to a per-process temp dir
+    pub secondary_path: Option<Utf8PathBuf>,
+    /// only export hashes where `hash % num_shards == shard`, as (shard, num_shards)
+    pub shard: Option<(u64, u64)>,
+    /// checkpoint file for resumable exports
+    pub checkpoint: Option<Utf8PathBuf>,
+    /// rows per part file when checkpointing
+    pub checkpoint_rows: usize,
+    /// what to do with partial output on SIGINT/SIGTERM/SIGHUP
+    pub on_interrupt: InterruptPolicy,
+    /// overwrite an existing output file
+    pub force: bool,
+    /// report what an export would do, without writing output
+    pub dry_run: bool,
+    /// only compute the LCA summary; no Parquet output
+    pub lca_summary_only: bool,
+    /// export only a deterministic subsample of hashes
+    pub sampling: Option<Sampling>,
+    /// stop after exporting this many hashes per database
+    pub limit: Option<u64>,
+    /// write JSON-lines progress events here (`-` for stderr) instead of progress bars
+    pub progress_json: Option<Utf8PathBuf>,
+    /// soft memory budget in bytes; flush early and apply backpressure near it
+    pub max_memory: Option<u64>,
+    /// also write the final run statistics to this JSON file
+    pub stats_json: Option<Utf8PathBuf>,
+    /// called with (db, processed, total) as each database is scanned
+    pub progress_callback: Option<ProgressCallback>,
+    /// externally controlled cancellation; replaces the signal handler when set
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+    /// Parquet compression; zstd when unset
+    pub compression: Option<CompressionOptions>,
+    /// rows per Parquet row group; `DEFAULT_ROW_GROUP_SIZE` when unset
+    pub row_group_size: Option<usize>,
+    /// only write these columns, in schema order; all columns when unset
+    pub columns: Option<Vec<String>>,
+    /// write one row per hash across all databases, with the union of its
+    /// datasets; held in memory until every database has been scanned
+    pub merge: bool,
+    /// skip hashes a previous export already has with the same datasets
+    pub since: Option<Arc<PreviousExport>>,
+    /// only export these datasets; hashes left without datasets are dropped
+    pub picklist: Option<Arc<Picklist>>,
+    /// regex filters on dataset names, applied like the picklist
+    pub dataset_patterns: Option<Arc<DatasetPatterns>>,
+    /// drop hashes with fewer datasets than this (after dataset filters)
+    pub min_datasets: Option<usize>,
+    /// drop hashes with more datasets than this (after dataset filters)
+    pub max_datasets: Option<usize>,
+    /// only export hashes under this lineage; needs a taxonomy
+    pub lineage_filter: Option<LineageFilter>,
+    /// only export hashes whose LCA is at this rank (one of `RANKS`) or below
+    pub min_lca_rank: Option<String>,
+    /// drop hashes none of whose datasets have a lineage
+    pub drop_unclassified: bool,
+    /// write hashes none of whose datasets have a lineage to this Parquet
+    /// file instead of the main output
+    pub unclassified_output: Option<Utf8PathBuf>,
+    /// only export these hashes (from a hash list and/or query sketch); the
+    /// databases are still scanned in full
+    pub hashes: Option<Arc<HashSet<u64>>>,
+    /// only export the datasets overlapping a query sketch by at least a
+    /// threshold, with all their hashes
+    pub prefetch: Option<Prefetch>,
+    /// add a `query_names` column naming the queries each hash is in
+    pub query_tags: Option<Arc<QueryTags>>,
+    /// write a bloom filter of the exported hashes here once the output is
+    /// complete, as (path, false positive rate)
+    pub bloom_filter: Option<(Utf8PathBuf, f64)>,
+    /// export the datasets with this ksize; required for databases with
+    /// several ksizes
+    pub ksize: Option<u32>,
+    /// export databases with different ksize, scaled or moltype together
+    pub allow_mixed_params: bool,
+    /// fail on dataset IDs that aren't in a database's manifest, instead of
+    /// skipping and reporting them
+    pub strict: bool,
+    /// write per-dataset hash counts, unique hashes and LCA ranks here
+    pub dataset_summary: Option<Utf8PathBuf>,
+    /// write hash counts by lineage at each rank here (CSV)
+    pub lineage_summary: Option<Utf8PathBuf>,
+    /// write a kraken2-style report of the LCA assignments here
+    pub kraken_report: Option<Utf8PathBuf>,
+    /// write the LCA distribution here as Krona text (for `ktImportText`)
+    pub krona: Option<Utf8PathBuf>,
+    /// write hash counts by LCA lineage per source here as a BIOM table
+    pub biom: Option<Utf8PathBuf>,
+    /// write an edge list of dataset pairs weighted by shared hashes here (CSV)
+    pub cooccurrence: Option<Utf8PathBuf>,
+    /// only list dataset pairs sharing at least this many hashes
+    pub cooccurrence_min_shared: u64,
+    /// write the most widely shared hashes of each source here (CSV)
+    pub top_shared: Option<Utf8PathBuf>,
+    /// how many hashes per source `top_shared` lists
+    pub top_shared_n: usize,
+    /// write estimated distinct hashes per lineage (HyperLogLog) here (CSV)
+    pub lineage_hll: Option<Utf8PathBuf>,
+    /// rank of the lineages in `lineage_hll`; genus if unset
+    pub hll_rank: Option<String>,
+    /// export the databases one at a time, in the order given, so the same
+    /// inputs always produce byte-identical output
+    pub deterministic: bool,
+    /// write a directory of shard files, one writer thread each, split per
+    /// source, per ksize or by `hash % N`, instead of a single file
+    pub sharded_output: Option<Partitioning>,
+    /// which manifest fields name the datasets, in order of preference
+    pub dataset_naming: DatasetNaming,
+    /// replace dataset names with salted hashes in the output and tables,
+    /// writing the real names to the anonymizer's mapping file
+    pub anonymize: Option<Anonymizer>,
+    /// add per-dataset and total abundance columns, read from the sketches
+    /// of datasets that track abundance
+    pub with_abundance: bool,
+    /// number LCA lineages in an `lca_lineage_id` column, and write the
+    /// numbered lineages to the table's sidecar Parquet file
+    pub lineage_table: Option<LineageTable>,
+    /// add a `contamination_flag` column, set for hashes whose lineages
+    /// disagree above this rank
+    pub contamination_rank: Option<String>,
+    /// add columns counting the distinct genera and species of each hash's
+    /// lineages and the entropy of its lineages
+    pub disagreement_metrics: bool,
+}
+
+impl ExportOptions {
+    /// Resolve compression, row group size and column selection for the writer.
+    pub(crate) fn writer_settings(&self) -> Result<WriterSettings> {
+        let mut settings = WriterSettings::default();
+        if let Some(compression) = self.compression {
+            settings.write_options.compression = compression;
+        }
+        if let Some(size) = self.row_group_size {
+            if size == 0 {
+                anyhow::bail!("row group size must be at least 1");
+            }
+            settings.row_group_size = size;
+        }
+        if let Some(ref names) = self.columns {
+            let schema = create_schema();
+            let mut columns = names
+                .iter()
+                .map(|name| {
+                    schema
+                        .fields
+                        .iter()
+                        .position(|f| &f.name == name)
+                        .ok_or_else(|| {
+                            let known: Vec<&str> =
+                                schema.fields.iter().map(|f| f.name.as_str()).collect();
+                            anyhow!(
+                                "unknown column '{name}'; expected one of {}",
+                                known.join(", ")
+                            )
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            columns.sort_unstable();
+            columns.dedup();
+            if columns.is_empty() {
+                anyhow::bail!("at least one output column must be selected");
+            }
+            settings.columns = Some(columns);
+        }
+        settings.anonymize = self.anonymize.clone();
+        settings.with_abundance = self.with_abundance;
+        settings.lineage_table = self.lineage_table.clone();
+        if let Some(ref rank) = self.contamination_rank {
+            let idx = RANKS.iter().position(|r| r == rank).ok_or_else(|| {
+                anyhow!(
+                    "unknown rank '{rank}'; expected one of {}",
+                    RANKS.join(", ")
+                )
+            })?;
+            settings.contamination_rank = Some(idx);
+        }
+        settings.disagreement_metrics = self.disagreement_metrics;
+        settings.query_tags = self.query_tags.clone();
+        Ok(settings)
+    }
+
+    /// Check the options for values and combinations an export can't honor;
+    /// every export entry point calls this before opening anything.
+    ///
+    /// # Errors
+    ///
+    /// Fails on an empty shard count or a shard outside it, a sampling
+    /// fraction outside (0, 1] or a step of 0, `min_datasets` above
+    /// `max_datasets`, unknown rank names, a bloom filter false positive rate
+    /// outside (0, 1), and options that can't be combined, as well as on the
+    /// writer settings `writer_settings` rejects.
+    pub fn validate(&self) -> Result<()> {
+        if self.rw && self.secondary {
+            anyhow::bail!("secondary mode cannot be combined with rw mode");
+        }
+        if let Some((shard, num_shards)) = self.shard {
+            if num_shards == 0 || shard >= num_shards {
+                anyhow::bail!(
+                    "shard must be between 0 and num_shards - 1 (got shard {shard} of {num_shards})"
+                );
+            }
+        }
+        match self.sampling {
+            Some(Sampling::Fraction(fraction)) if !(fraction > 0.0 && fraction <= 1.0) => {
+                anyhow::bail!("sample fraction must be in (0, 1] (got {fraction})");
+            }
+            Some(Sampling::EveryNth(0)) => anyhow::bail!("every_nth must be at least 1"),
+            _ => {}
+        }
+        if let (Some(min), Some(max)) = (self.min_datasets, self.max_datasets) {
+            if min > max {
+                anyhow::bail!("min_datasets ({min}) cannot be larger than max_datasets ({max})");
+            }
+        }
+        for rank in [&self.min_lca_rank, &self.hll_rank, &self.contamination_rank]
+            .into_iter()
+            .flatten()
+        {
+            if !RANKS.contains(&rank.as_str()) {
+                anyhow::bail!(
+                    "unknown rank '{rank}'; expected one of {}",
+                    RANKS.join(", ")
+                );
+            }
+        }
+        let tables = [
+            ("dataset_summary", self.dataset_summary.is_some()),
+            ("lineage_summary", self.lineage_summary.is_some()),
+            ("kraken_report", self.kraken_report.is_some()),
+            ("krona", self.krona.is_some()),
+            ("biom", self.biom.is_some()),
+            ("cooccurrence", self.cooccurrence.is_some()),
+            ("top_shared", self.top_shared.is_some()),
+            ("lineage_hll", self.lineage_hll.is_some()),
+        ];
+        if let Some((name, _)) = tables.iter().find(|(_, set)| *set) {
+            if self.merge || self.checkpoint.is_some() {
+                anyhow::bail!("{name} cannot be combined with merge or checkpoint");
+            }
+        }
+        if self.merge && self.checkpoint.is_some() {
+            anyhow::bail!("--merge cannot be combined with --checkpoint");
+        }
+        if self.merge && self.allow_mixed_params {
+            anyhow::bail!(
+                "merge needs matching sketch parameters and cannot be combined with allow_mixed_params"
+            );
+        }
+        if self.deterministic && (self.max_memory.is_some() || self.checkpoint.is_some()) {
+            anyhow::bail!("deterministic cannot be combined with max_memory or checkpoint");
+        }
+        if self.drop_unclassified && self.unclassified_output.is_some() {
+            anyhow::bail!("drop_unclassified and unclassified_output cannot be combined");
+        }
+        if self.unclassified_output.is_some() && self.checkpoint.is_some() {
+            anyhow::bail!("--unclassified-output cannot be combined with --checkpoint");
+        }
+        if self.sharded_output.is_some()
+            && (self.checkpoint.is_some() || self.unclassified_output.is_some())
+        {
+            anyhow::bail!(
+                "--sharded-output cannot be combined with --checkpoint or --unclassified-output"
+            );
+        }
+        if self.anonymize.is_some() && (self.since.is_some() || self.checkpoint.is_some()) {
+            anyhow::bail!("--anonymize cannot be combined with --since or --checkpoint");
+        }
+        if self.lineage_table.is_some() && self.checkpoint.is_some() {
+            anyhow::bail!("--lineage-table cannot be combined with --checkpoint");
+        }
+        if self.with_abundance && self.merge {
+            anyhow::bail!("--with-abundance cannot be combined with --merge");
+        }
+        if let Some((_, fpp)) = self.bloom_filter {
+            if !(fpp > 0.0 && fpp < 1.0) {
+                anyhow::bail!("bloom filter false positive rate must be in (0, 1) (got {fpp})");
+            }
+        }
+        self.writer_settings()?;
+        Ok(())
+    }
+
+    /// Check that the options needing lineages have a taxonomy to read them from.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `has_taxonomy` is false and a taxonomy filter or table is set.
+    pub fn check_taxonomy(&self, has_taxonomy: bool) -> Result<()> {
+        if has_taxonomy {
+            return Ok(());
+        }
+        let taxonomy_options = [
+            ("a lineage filter", self.lineage_filter.is_some()),
+            ("min_lca_rank", self.min_lca_rank.is_some()),
+            ("drop_unclassified", self.drop_unclassified),
+            ("unclassified_output", self.unclassified_output.is_some()),
+            ("lineage_summary", self.lineage_summary.is_some()),
+            ("kraken_report", self.kraken_report.is_some()),
+            ("krona", self.krona.is_some()),
+            ("biom", self.biom.is_some()),
+            ("lineage_hll", self.lineage_hll.is_some()),
+            ("lineage_table", self.lineage_table.is_some()),
+            ("contamination_rank", self.contamination_rank.is_some()),
+            ("disagreement_metrics", self.disagreement_metrics),
+        ];
+        match taxonomy_options.iter().find(|(_, set)| *set) {
+            Some((name, _)) => anyhow::bail!("{name} needs a taxonomy to annotate hashes with"),
+            None => Ok(()),
+        }
+    }
+
+    // `validate` rejects a `num_shards` of 0
+    fn in_shard(&self, hash: u64) -> bool {
+        match self.shard {
+            Some((shard, num_shards)) => hash % num_shards == shard,
+            None => true,
+        }
+    }
+
+    pub(crate) fn in_hash_list(&self, hash: u64) -> bool {
+        self.hashes
+            .as_ref()
+            .map_or(true, |hashes| hashes.contains(&hash))
+    }
+
+    /// `position` is the 0-based index of the hash in iteration order.
+    fn in_sample(&self, hash: u64, position: u64) -> bool {
+        self.sampling.map_or(true, |s| s.keep(hash, position))
+    }
+
+    /// Whether a hash with `count` datasets is within `--min/--max-datasets`.
+    pub(crate) fn in_dataset_range(&self, count: usize) -> bool {
+        self.min_datasets.map_or(true, |min| count >= min)
+            && self.max_datasets.map_or(true, |max| count <= max)
+    }
+
+    /// Whether a hash with these annotations passes the taxonomy filters.
+    pub(crate) fn keeps_annotation(
+        &self,
+        taxonomy_list: Option<&Vec<String>>,
+        lca_lineage: Option<&str>,
+        lca_rank: Option<&str>,
+    ) -> bool {
+        if self.drop_unclassified && taxonomy_list.map_or(true, Vec::is_empty) {
+            return false;
+        }
+        if let Some(ref min_rank) = self.min_lca_rank {
+            let depth = |rank: &str| RANKS.iter().position(|r| *r == rank);
+            if lca_rank.and_then(depth) < depth(min_rank) {
+                return false;
+            }
+        }
+        self.lineage_filter
+            .as_ref()
+            .map_or(true, |filter| filter.keeps(taxonomy_list, lca_lineage))
+    }
+
+    /// Whether the dataset `idx` of `record` has the selected ksize and passes
+    /// the picklist and name patterns (matched against its display name).
+    pub(crate) fn keeps_dataset(&self, idx: u32, record: &Record) -> bool {
+        let name = self.dataset_naming.name(idx, record);
+        self.ksize.map_or(true, |ksize| record.ksize() == ksize)
+            && self.picklist.as_ref().map_or(true, |p| p.matches(record))
+            && self
+                .dataset_patterns
+                .as_ref()
+                .map_or(true, |p| p.matches(&name))
+    }
+
+    /// Whether the scan counts hashes by lineage, for the lineage tables.
+    pub(crate) fn counts_lineages(&self) -> bool {
+        self.lineage_summary.is_some()
+            || self.kraken_report.is_some()
+            || self.krona.is_some()
+            || self.biom.is_some()
+    }
+
+    /// Which dataset IDs of `collection` are kept, or `None` to keep them all.
+    pub(crate) fn dataset_mask(&self, collection: &CollectionSet) -> Option<Vec<bool>> {
+        if self.ksize.is_none() && self.picklist.is_none() && self.dataset_patterns.is_none() {
+            return None;
+        }
+        Some(
+            collection
+                .manifest()
+                .iter()
+                .enumerate()
+                .map(|(idx, record)| self.keeps_dataset(idx as u32, record))
+                .collect(),
+        )
+    }
+
+    fn read_options(&self) -> rocksdb::ReadOptions {
+        let mut readopts = rocksdb::ReadOptions::default();
+        if self.readahead_size > 0 {
+            readopts.set_readahead_size(self.readahead_size);
+        }
+        readopts.fill_cache(!self.no_fill_cache);
+        readopts.set_pin_data(self.pin_data);
+        readopts
+    }
+}
+
+/// Open `db_path` as a RocksDB secondary instance and catch up with the primary,
+/// so we can scan a database that another process still holds open for writing.
+fn open_secondary(db_path: &Utf8Path, secondary_path: &Utf8Path) -> Result<DB> {
+    let mut opts = rocksdb::Options::default();
+    // secondary instances must keep all files open to follow the primary
+    opts.set_max_open_files(-1);
+
+    let cfs = DB::list_cf(&opts, db_path)?;
+    let db = DB::open_cf_as_secondary(&opts, db_path, secondary_path, cfs)
+        .map_err(|e| anyhow!("cannot open '{db_path}' as a secondary instance: {e}"))?;
+    db.try_catch_up_with_primary()?;
+    Ok(db)
+}
+
+pub(crate) const COLORS_CF: &str = "colors";
+pub(crate) const HASHES_CF: &str = "hashes";
+const METADATA_CF: &str = "metadata";
+const VERSION_KEY: &str = "version";
+
+// RevIndex on-disk format versions this plugin knows how to read
+const SUPPORTED_DB_VERSIONS: [u8; 1] = [1];
+
+// comma-separated values for error messages
+fn join_values<T: fmt::Display>(values: impl IntoIterator<Item = T>) -> String {
+    values
+        .into_iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The sketch parameters of the datasets exported from one database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SketchParams {
+    pub ksize: u32,
+    pub scaled: u32,
+    pub moltype: String,
 }
 
-// schema for parquet file
-fn create_schema() -> Schema {
+/// The sketch parameters to export from a database's manifest: `requested` if
+/// the manifest has it, or its only ksize. Errors list the available ksizes.
+/// Every record with the selected ksize must share its scaled and moltype,
+/// since those are written to each row (or decide what a hash means); a
+/// manifest that mixes them is an error rather than a mislabeled export.
+pub(crate) fn select_ksize(
+    manifest: &Manifest,
+    db_path: &Utf8Path,
+    requested: Option<u32>,
+) -> Result<SketchParams> {
+    let available: BTreeSet<u32> = manifest.iter().map(|record| record.ksize()).collect();
+    let ksize = match requested {
+        Some(ksize) if available.contains(&ksize) => ksize,
+        Some(ksize) => anyhow::bail!(
+            "'{db_path}' has no sketches with ksize {ksize}; available ksizes: {}",
+            join_values(&available)
+        ),
+        None if available.len() > 1 => anyhow::bail!(
+            "'{db_path}' has sketches with several ksizes ({}); choose one with --ksize",
+            join_values(&available)
+        ),
+        None => *available
+            .first()
+            .ok_or_else(|| anyhow!("No records in manifest"))?,
+    };
+
+    let selected = || manifest.iter().filter(|record| record.ksize() == ksize);
+    let scaled: BTreeSet<u32> = selected().map(|record| *record.scaled()).collect();
+    if scaled.len() > 1 {
+        anyhow::bail!(
+            "'{db_path}' has ksize {ksize} sketches with different scaled values ({}); \
+             its rows can't be labeled with a single scaled",
+            join_values(&scaled)
+        );
+    }
+    let moltypes: BTreeSet<String> = selected()
+        .map(|record| record.moltype().to_string())
+        .collect();
+    if moltypes.len() > 1 {
+        anyhow::bail!(
+            "'{db_path}' has ksize {ksize} sketches with different moltypes ({}); \
+             their hashes can't be told apart",
+            join_values(&moltypes)
+        );
+    }
+    Ok(SketchParams {
+        ksize,
+        scaled: scaled.first().copied().unwrap_or_default(),
+        moltype: moltypes.into_iter().next().unwrap_or_default(),
+    })
+}
+
+/// The sketch parameters each database of an export is exported with.
+fn database_params(db_paths: &[Utf8PathBuf], ksize: Option<u32>) -> Result<Vec<SketchParams>> {
+    let mut params = Vec::with_capacity(db_paths.len());
+    for db_path in db_paths {
+        let revindex = RevIndex::open(db_path, true, None)
+            .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+        let revindex = match revindex {
+            RevIndex::Plain(revindex) => revindex,
+        };
+        params.push(select_ksize(
+            revindex.collection().manifest(),
+            db_path,
+            ksize,
+        )?);
+    }
+    Ok(params)
+}
+
+/// Check that the databases of one export share ksize, scaled and moltype,
+/// so the output isn't a mix users only notice later. On a mismatch the error
+/// has a table of every database's parameters.
+pub(crate) fn check_params_consistency(db_paths: &[Utf8PathBuf], ksize: Option<u32>) -> Result<()> {
+    let params = database_params(db_paths, ksize)?;
+    if params.windows(2).all(|pair| pair[0] == pair[1]) {
+        return Ok(());
+    }
+
+    let width = db_paths
+        .iter()
+        .map(|path| path.as_str().len())
+        .max()
+        .unwrap_or_default()
+        .max("database".len());
+    let mut table = format!("  {:width$}  ksize  scaled  moltype\n", "database");
+    for (db_path, p) in db_paths.iter().zip(&params) {
+        table += &format!(
+            "  {:width$}  {:<5}  {:<6}  {}\n",
+            db_path.as_str(),
+            p.ksize,
+            p.scaled,
+            p.moltype
+        );
+    }
+    anyhow::bail!(
+        "the databases have different sketch parameters:\n{table}\
+         use --allow-mixed-params to export them together anyway"
+    )
+}
+
+/// Check that `db_path` looks like a RevIndex we can export, and report a
+/// clear error if it was built with an unsupported format version.
+/// Returns the format version, if recorded.
+pub(crate) fn check_db_compatibility(db_path: &Utf8Path) -> Result<Option<u8>> {
+    let opts = rocksdb::Options::default();
+    let cfs = DB::list_cf(&opts, db_path)
+        .map_err(|e| anyhow!("cannot read column families of '{db_path}': {e}"))?;
+
+    for required in [HASHES_CF, METADATA_CF] {
+        if !cfs.iter().any(|cf| cf == required) {
+            anyhow::bail!(
+                "'{db_path}' is missing the '{required}' column family; is it a sourmash RevIndex?"
+            );
+        }
+    }
+
+    let db = DB::open_cf_for_read_only(&opts, db_path, &cfs, false)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let cf = db
+        .cf_handle(METADATA_CF)
+        .ok_or_else(|| anyhow!("Missing '{METADATA_CF}' CF"))?;
+
+    let version = match db.get_pinned_cf(&cf, VERSION_KEY)? {
+        Some(raw) if raw.len() == 1 => raw[0],
+        Some(raw) => anyhow::bail!(
+            "'{db_path}' has an unreadable RevIndex format version ({} bytes)",
+            raw.len()
+        ),
+        None => {
+            eprintln!("Warning: '{db_path}' does not record a RevIndex format version");
+            return Ok(None);
+        }
+    };
+
+    if !SUPPORTED_DB_VERSIONS.contains(&version) {
+        anyhow::bail!(
+            "'{db_path}' uses RevIndex format version {version}, but sourmash_plugin_export {} supports format version(s) {:?}",
+            env!("CARGO_PKG_VERSION"),
+            SUPPORTED_DB_VERSIONS
+        );
+    }
+
+    Ok(Some(version))
+}
+
+// dataset sets of color-based RevIndexes kept in memory per scan; the cache
+// is cleared when full, which is cheap next to reading the colors back
+const COLOR_CACHE_SIZE: usize = 1_000_000;
+
+/// Resolves values stored in the `hashes` CF to dataset indices.
+/// Plain RevIndexes store a serialized `Datasets` per hash; color-based
+/// RevIndexes store an 8-byte little-endian color ID whose serialized
+/// `Datasets` lives in the `colors` CF, under the same 8 bytes.
+pub(crate) enum DatasetResolver<'a> {
+    Plain,
+    Colors {
+        db: &'a DB,
+        cf: Arc<rocksdb::BoundColumnFamily<'a>>,
+        cache: HashMap<u64, Datasets>,
+    },
+}
+
+impl<'a> DatasetResolver<'a> {
+    /// Fails if the database has a `colors` CF that `db` wasn't opened with,
+    /// since its `hashes` values couldn't be read as dataset lists.
+    pub(crate) fn new(db: &'a DB) -> Result<Self> {
+        if let Some(cf) = db.cf_handle(COLORS_CF) {
+            return Ok(Self::Colors {
+                db,
+                cf,
+                cache: HashMap::new(),
+            });
+        }
+        let cfs = DB::list_cf(&rocksdb::Options::default(), db.path())?;
+        if cfs.iter().any(|cf| cf == COLORS_CF) {
+            anyhow::bail!(
+                "'{}' has a '{COLORS_CF}' column family, but it was opened without it",
+                db.path().display()
+            );
+        }
+        Ok(Self::Plain)
+    }
+
+    /// `None` for a plain value that doesn't parse; callers skip those. A
+    /// color ID that is malformed, missing or doesn't parse is an error.
+    pub(crate) fn resolve(&mut self, value: &[u8]) -> Result<Option<Datasets>> {
+        match self {
+            Self::Plain => Ok(Datasets::from_slice(value)),
+            Self::Colors { db, cf, cache } => {
+                if value.len() != 8 {
+                    anyhow::bail!(
+                        "found a {}-byte value in '{HASHES_CF}'; expected an 8-byte color ID",
+                        value.len()
+                    );
+                }
+                let color = LittleEndian::read_u64(value);
+                if let Some(datasets) = cache.get(&color) {
+                    return Ok(Some(datasets.clone()));
+                }
+
+                let raw = db
+                    .get_pinned_cf(cf, value)?
+                    .ok_or_else(|| anyhow!("color {color} is not in '{COLORS_CF}'"))?;
+                let datasets = Datasets::from_slice(&raw).ok_or_else(|| {
+                    anyhow!("could not parse the dataset list of color {color} in '{COLORS_CF}'")
+                })?;
+                if cache.len() >= COLOR_CACHE_SIZE {
+                    cache.clear();
+                }
+                cache.insert(color, datasets.clone());
+                Ok(Some(datasets))
+            }
+        }
+    }
+}
+
+/// One exported hash: one row of the Parquet output.
+#[derive(Debug)]
+pub struct ArrowRecord {
+    pub hash: u64,
+    pub dataset_names: Vec<String>,
+    pub taxonomy_list: Option<Vec<String>>,
+    pub lca_lineage: Option<String>,
+    pub lca_rank: Option<String>,
+    pub ksize: u32,
+    pub scaled: u32,
+    pub source: String, // basename of revindex
+    /// abundance in each of `dataset_names`, with `--with-abundance`; `None`
+    /// unless all of them track abundance
+    pub abundances: Option<Vec<u64>>,
+}
+
+/// Version of the export schema, recorded as `export_schema_version` in the
+/// footer metadata. It covers the base columns of `create_schema`: it is
+/// bumped whenever one of them is added, removed, renamed or changes type, and
+/// `get_schema` keeps every version. Opt-in columns (`abundance_fields`,
+/// `lineage_id_field`, `contamination_field`, `disagreement_fields`,
+/// `query_names_field`) are not versioned; they are appended after the base
+/// columns with fixed names and types, and readers look them up by name.
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Arrow schema of the exported Parquet file.
+pub fn create_schema() -> Schema {
     Schema::from(vec![
         Field::new("hash", DataType::UInt64, false),
         Field::new(
@@ -70,7 +989,176 @@ fn create_schema() -> Schema {
     ])
 }
 
-fn string_list_array(values: &[Vec<String>]) -> Result<ListArray<i32>, arrow2::error::Error> {
+/// Columns added after the export columns by `--with-abundance`: the
+/// abundance of the hash in each of its datasets, and their sum. Both are
+/// null for hashes in datasets without abundance tracking.
+pub fn abundance_fields() -> Vec<Field> {
+    vec![
+        Field::new(
+            "abundances",
+            DataType::List(Box::new(Field::new("item", DataType::UInt64, false))),
+            true,
+        ),
+        Field::new("total_abundance", DataType::UInt64, true),
+    ]
+}
+
+/// Column added after the export (and abundance) columns by
+/// `--lineage-table`: the ID of the hash's LCA lineage in the lineage table,
+/// or null without an LCA.
+pub fn lineage_id_field() -> Field {
+    Field::new("lca_lineage_id", DataType::UInt32, true)
+}
+
+/// Column added last by `--flag-contamination`: whether the lineages of the
+/// hash's datasets disagree above the flag rank (so lineages from different
+/// domains always do), or null for hashes without lineages.
+pub fn contamination_field() -> Field {
+    Field::new("contamination_flag", DataType::Boolean, true)
+}
+
+/// The `contamination_field` value of a record, for the rank at index `rank`
+/// of `RANKS`.
+fn contamination_flag(record: &ArrowRecord, rank: usize) -> Option<bool> {
+    if is_unclassified(record) {
+        return None;
+    }
+    let lca = record
+        .lca_rank
+        .as_deref()
+        .and_then(|lca| RANKS.iter().position(|r| *r == lca));
+    Some(lca.map_or(true, |lca| lca < rank))
+}
+
+/// Columns added last by `--disagreement-metrics`: the number of distinct
+/// genera and species among the lineages of the hash's datasets, and the
+/// Shannon entropy (in bits) of those lineages, 0 when they all agree. All
+/// are null for hashes without lineages.
+pub fn disagreement_fields() -> Vec<Field> {
+    vec![
+        Field::new("distinct_genera", DataType::UInt32, true),
+        Field::new("distinct_species", DataType::UInt32, true),
+        Field::new("lineage_entropy", DataType::Float64, true),
+    ]
+}
+
+/// Column added last by `--tag-queries`: the names of the query sketches
+/// that have the hash, in the order of the query collection.
+pub fn query_names_field() -> Field {
+    Field::new(
+        "query_names",
+        DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+        false,
+    )
+}
+
+/// The number of distinct lineages truncated at the rank at index `rank` of
+/// `RANKS`, among those that have that rank. Names are at their rank's
+/// position, with blank ranks left empty (see `load_taxonomy_map`).
+fn distinct_at_rank(lineages: &[String], rank: usize) -> u32 {
+    lineages
+        .iter()
+        .filter_map(|lineage| {
+            let parts: Vec<&str> = lineage.split(';').collect();
+            parts
+                .get(rank)
+                .filter(|name| !name.is_empty())
+                .map(|_| parts[..=rank].join(";"))
+        })
+        .collect::<HashSet<_>>()
+        .len() as u32
+}
+
+/// Shannon entropy, in bits, of the distribution of lineages.
+fn lineage_entropy(lineages: &[String]) -> f64 {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for lineage in lineages {
+        *counts.entry(lineage.as_str()).or_default() += 1;
+    }
+    let total = lineages.len() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum::<f64>()
+        .max(0.0)
+}
+
+/// The `--disagreement-metrics` columns of these records.
+fn disagreement_arrays(records: &[ArrowRecord]) -> Vec<Box<dyn Array>> {
+    let genus = RANKS.iter().position(|r| *r == "genus").unwrap();
+    let species = RANKS.len() - 1;
+    let lineages = || {
+        records.iter().map(|r| {
+            r.taxonomy_list
+                .as_deref()
+                .filter(|lineages| !lineages.is_empty())
+        })
+    };
+    vec![
+        Box::new(UInt32Array::from(
+            lineages()
+                .map(|l| l.map(|l| distinct_at_rank(l, genus)))
+                .collect::<Vec<_>>(),
+        )),
+        Box::new(UInt32Array::from(
+            lineages()
+                .map(|l| l.map(|l| distinct_at_rank(l, species)))
+                .collect::<Vec<_>>(),
+        )),
+        Box::new(Float64Array::from(
+            lineages()
+                .map(|l| l.map(lineage_entropy))
+                .collect::<Vec<_>>(),
+        )),
+    ]
+}
+
+/// The `--with-abundance` columns of these records.
+fn abundance_arrays(records: &[ArrowRecord]) -> ArrowResult<Vec<Box<dyn Array>>> {
+    let mut abundances = MutableListArray::<i32, MutablePrimitiveArray<u64>>::new_with_field(
+        MutablePrimitiveArray::new(),
+        "item",
+        false,
+    );
+    for record in records {
+        abundances.try_push(
+            record
+                .abundances
+                .as_ref()
+                .map(|a| a.iter().copied().map(Some)),
+        )?;
+    }
+    let totals = UInt64Array::from(
+        records
+            .iter()
+            .map(|r| r.abundances.as_ref().map(|a| a.iter().sum::<u64>()))
+            .collect::<Vec<_>>(),
+    );
+    Ok(vec![
+        Box::new(ListArray::<i32>::from(abundances)) as Box<dyn Array>,
+        Box::new(totals) as Box<dyn Array>,
+    ])
+}
+
+/// Arrow base schema of a given version of the export schema, so readers can
+/// check an export against the version in its footer. Opt-in columns aren't
+/// included, and `--columns` may leave some base columns out.
+pub fn get_schema(version: u32) -> Result<Schema> {
+    match version {
+        1 => Ok(create_schema()),
+        _ => anyhow::bail!(
+            "unknown export schema version {version}; known versions are 1 to \
+             {EXPORT_SCHEMA_VERSION}"
+        ),
+    }
+}
+
+pub(crate) fn string_list_array(
+    values: &[Vec<String>],
+) -> Result<ListArray<i32>, arrow2::error::Error> {
     let flat: Vec<&str> = values.iter().flatten().map(String::as_str).collect();
 
     let mut offsets = Offsets::<i32>::new();
@@ -90,7 +1178,7 @@ fn string_list_array(values: &[Vec<String>]) -> Result<ListArray<i32>, arrow2::e
 }
 
 /// Returns: schema and chunk (i.e., row group)
-fn convert_to_batch(records: &[ArrowRecord]) -> ArrowResult<(Schema, Chunk<Box<dyn Array>>)> {
+pub fn convert_to_batch(records: &[ArrowRecord]) -> ArrowResult<(Schema, Chunk<Box<dyn Array>>)> {
     let hashes = UInt64Array::from_slice(records.iter().map(|r| r.hash).collect::<Vec<_>>());
     let ksizes = UInt32Array::from_slice(records.iter().map(|r| r.ksize).collect::<Vec<_>>());
     let scaleds = UInt32Array::from_slice(records.iter().map(|r| r.scaled).collect::<Vec<_>>());
@@ -140,75 +1228,566 @@ fn convert_to_batch(records: &[ArrowRecord]) -> ArrowResult<(Schema, Chunk<Box<d
     Ok((create_schema(), chunk))
 }
 
-/// Start an MPSC writer thread that receives ArrowRecords and writes batches to a Parquet file.
+pub(crate) fn parquet_write_options() -> WriteOptions {
+    WriteOptions {
+        write_statistics: true,
+        compression: CompressionOptions::Zstd(None),
+        version: Version::V2,
+        data_pagesize_limit: None,
+    }
+}
+
+/// Parse a compression name: `zstd`, `zstd:LEVEL`, `snappy`, `lz4`, `gzip`,
+/// `gzip:LEVEL`, `brotli` or `uncompressed`.
+pub fn parse_compression(value: &str) -> Result<CompressionOptions> {
+    let (name, level) = match value.split_once(':') {
+        Some((name, level)) => (name, Some(level)),
+        None => (value, None),
+    };
+    let codec = name.to_ascii_lowercase();
+    let bad_level = || anyhow!("invalid compression level in '{value}'");
+
+    let compression = match codec.as_str() {
+        "zstd" => CompressionOptions::Zstd(
+            level
+                .map(|l| l.parse().map_err(|_| bad_level()))
+                .transpose()?
+                .map(ZstdLevel::try_new)
+                .transpose()?,
+        ),
+        "gzip" => CompressionOptions::Gzip(
+            level
+                .map(|l| l.parse().map_err(|_| bad_level()))
+                .transpose()?
+                .map(GzipLevel::try_new)
+                .transpose()?,
+        ),
+        "snappy" => CompressionOptions::Snappy,
+        "lz4" => CompressionOptions::Lz4Raw,
+        "brotli" => CompressionOptions::Brotli(None),
+        "uncompressed" | "none" => CompressionOptions::Uncompressed,
+        _ => anyhow::bail!(
+            "unknown compression '{value}'; expected zstd, snappy, lz4, gzip, brotli or uncompressed"
+        ),
+    };
+    if level.is_some() && !matches!(codec.as_str(), "zstd" | "gzip") {
+        anyhow::bail!("compression '{name}' does not take a level");
+    }
+    Ok(compression)
+}
+
+/// Default number of rows per Parquet row group.
+pub(crate) const DEFAULT_ROW_GROUP_SIZE: usize = 100_000;
+
+/// How records are encoded in the Parquet output.
+#[derive(Debug, Clone)]
+pub(crate) struct WriterSettings {
+    pub write_options: WriteOptions,
+    pub row_group_size: usize,
+    /// indices of the output columns in the full schema; `None` for all columns
+    pub columns: Option<Vec<usize>>,
+    /// dataset names are replaced as records are encoded
+    pub anonymize: Option<Anonymizer>,
+    /// append the `abundance_fields` columns
+    pub with_abundance: bool,
+    /// append the `lineage_id_field` column, numbering lineages here
+    pub lineage_table: Option<LineageTable>,
+    /// append the `contamination_field` column, flagging lineages that
+    /// disagree above the rank at this index of `RANKS`
+    pub contamination_rank: Option<usize>,
+    /// append the `disagreement_fields` columns
+    pub disagreement_metrics: bool,
+    /// append the `query_names_field` column, naming the queries of each hash
+    pub query_tags: Option<Arc<QueryTags>>,
+}
+
+impl Default for WriterSettings {
+    fn default() -> Self {
+        Self {
+            write_options: parquet_write_options(),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            columns: None,
+            anonymize: None,
+            with_abundance: false,
+            lineage_table: None,
+            contamination_rank: None,
+            disagreement_metrics: false,
+            query_tags: None,
+        }
+    }
+}
+
+impl WriterSettings {
+    /// Output schema, restricted to the selected columns.
+    pub fn schema(&self) -> Schema {
+        let schema = create_schema();
+        let mut fields = match self.columns {
+            Some(ref columns) => columns
+                .iter()
+                .map(|&i| schema.fields[i].clone())
+                .collect::<Vec<_>>(),
+            None => schema.fields,
+        };
+        if self.with_abundance {
+            fields.extend(abundance_fields());
+        }
+        if self.lineage_table.is_some() {
+            fields.push(lineage_id_field());
+        }
+        if self.contamination_rank.is_some() {
+            fields.push(contamination_field());
+        }
+        if self.disagreement_metrics {
+            fields.extend(disagreement_fields());
+        }
+        if self.query_tags.is_some() {
+            fields.push(query_names_field());
+        }
+        Schema::from(fields)
+    }
+
+    /// Convert records into a chunk with only the selected columns.
+    pub fn chunk(&self, records: &[ArrowRecord]) -> Result<Chunk<Box<dyn Array>>> {
+        let (_, chunk) = match self.anonymize {
+            Some(ref anonymizer) => {
+                let records: Vec<ArrowRecord> = records
+                    .iter()
+                    .map(|record| ArrowRecord {
+                        dataset_names: record
+                            .dataset_names
+                            .iter()
+                            .map(|name| anonymizer.anonymize(name))
+                            .collect(),
+                        taxonomy_list: record.taxonomy_list.clone(),
+                        lca_lineage: record.lca_lineage.clone(),
+                        lca_rank: record.lca_rank.clone(),
+                        source: record.source.clone(),
+                        abundances: record.abundances.clone(),
+                        ..*record
+                    })
+                    .collect();
+                convert_to_batch(&records)?
+            }
+            None => convert_to_batch(records)?,
+        };
+        let mut arrays = match self.columns {
+            Some(ref columns) => {
+                let arrays = chunk.into_arrays();
+                columns.iter().map(|&i| arrays[i].clone()).collect()
+            }
+            None => chunk.into_arrays(),
+        };
+        if self.with_abundance {
+            arrays.extend(abundance_arrays(records)?);
+        }
+        if let Some(ref table) = self.lineage_table {
+            arrays.push(Box::new(UInt32Array::from(
+                records
+                    .iter()
+                    .map(|r| table.id(r.lca_lineage.as_deref()))
+                    .collect::<Vec<_>>(),
+            )));
+        }
+        if let Some(rank) = self.contamination_rank {
+            arrays.push(Box::new(BooleanArray::from(
+                records
+                    .iter()
+                    .map(|r| contamination_flag(r, rank))
+                    .collect::<Vec<_>>(),
+            )));
+        }
+        if self.disagreement_metrics {
+            arrays.extend(disagreement_arrays(records));
+        }
+        if let Some(ref tags) = self.query_tags {
+            let names: Vec<Vec<String>> = records
+                .iter()
+                .map(|r| tags.names(r.hash).into_iter().map(String::from).collect())
+                .collect();
+            arrays.push(Box::new(string_list_array(&names)?));
+        }
+        Ok(Chunk::new(arrays))
+    }
+}
+
+// number of records a producer accumulates before sending them to the writer thread
+pub(crate) const SEND_BATCH_SIZE: usize = 10_000;
+
+/// A batch of records from a single source, sent from a producer to the writer thread.
+pub(crate) struct WriteBatch {
+    source: String,
+    /// last hash iterated in this source (may be filtered out of `records`)
+    last_hash: Option<u64>,
+    pub records: Vec<ArrowRecord>,
+    /// LCA summary of just these records
+    summary: LCASummary,
+    /// this is the final batch for `source`
+    done: bool,
+}
+
+impl WriteBatch {
+    /// A complete batch of records that no checkpoint needs to track.
+    pub(crate) fn complete(source: String, records: Vec<ArrowRecord>, summary: LCASummary) -> Self {
+        Self {
+            source,
+            last_hash: records.last().map(|r| r.hash),
+            records,
+            summary,
+            done: true,
+        }
+    }
+}
+
+/// Checkpointing setup for the writer thread.
+struct CheckpointConfig {
+    path: Utf8PathBuf,
+    state: Checkpoint,
+    rows_per_part: usize,
+}
+
+impl CheckpointConfig {
+    fn open_part(
+        &self,
+        schema: &Schema,
+        options: WriteOptions,
+    ) -> Result<(Utf8PathBuf, FileWriter<File>)> {
+        let path = self.state.part_path(self.state.parts.len());
+        let writer = FileWriter::try_new(File::create(&path)?, schema.clone(), options)?;
+        Ok((path, writer))
+    }
+
+    /// Close the current part (if any), fold pending progress into the
+    /// checkpoint, and save it.
+    fn finalize_part(
+        &mut self,
+        part: Option<(Utf8PathBuf, FileWriter<File>)>,
+        part_rows: usize,
+        pending: &mut BTreeMap<String, SourceProgress>,
+    ) -> Result<()> {
+        if let Some((path, mut writer)) = part {
+            writer.end(None)?;
+            self.state.parts.push(path.to_string());
+            self.state.rows_written += part_rows as u64;
+        }
+        for (source, progress) in std::mem::take(pending) {
+            let saved = self.state.sources.entry(source).or_default();
+            saved.summary.ksize = progress.summary.ksize;
+            saved.summary.scaled = progress.summary.scaled;
+            saved.last_hash = progress.last_hash.or(saved.last_hash);
+            saved.complete |= progress.complete;
+            saved.summary.merge(&progress.summary);
+        }
+        self.state.save(&self.path)
+    }
+}
+
+pub(crate) fn write_records<W: std::io::Write>(
+    writer: &mut FileWriter<W>,
+    settings: &WriterSettings,
+    records: &[ArrowRecord],
+) -> Result<()> {
+    let schema = settings.schema();
+    let chunk = settings.chunk(records)?;
+    let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+    let row_groups = RowGroupIterator::try_new(
+        std::iter::once(Ok(chunk)),
+        &schema,
+        settings.write_options,
+        encodings,
+    )?;
+
+    for group in row_groups {
+        writer.write(group?)?;
+    }
+    Ok(())
+}
+
+/// Key-value metadata stored in the Parquet footer.
+pub(crate) fn footer_metadata(rows_written: u64, partial: bool) -> Vec<KeyValue> {
+    vec![
+        KeyValue {
+            key: "export_schema_version".to_string(),
+            value: Some(EXPORT_SCHEMA_VERSION.to_string()),
+        },
+        KeyValue {
+            key: "export_partial".to_string(),
+            value: Some(partial.to_string()),
+        },
+        KeyValue {
+            key: "hashes_written".to_string(),
+            value: Some(rows_written.to_string()),
+        },
+    ]
+}
+
+/// Whether none of a record's datasets have a lineage.
+fn is_unclassified(record: &ArrowRecord) -> bool {
+    record.taxonomy_list.as_ref().map_or(true, Vec::is_empty)
+}
+
+/// What the writer thread reports back once the channel is closed.
+pub(crate) struct WriterOutput {
+    pub rows_written: u64,
+    /// rows written to the unclassified output, if there is one
+    pub unclassified_rows: u64,
+    /// final checkpoint state, when checkpointing
+    pub checkpoint: Option<Checkpoint>,
+}
+
+/// Start an MPSC writer thread that receives batches of ArrowRecords and writes them to a Parquet file.
 /// Returns a Sender that can be cloned for use with Rayon threads.
+///
+/// If `cancel_flag` is set by the time the channel closes, partial output is handled
+/// according to `on_interrupt`: by default the file is still finalized (buffer
+/// flushed, footer written) but marked as partial in the footer metadata.
+///
+/// With a checkpoint, records are written to numbered part files instead; each time a
+/// part is finalized the checkpoint is saved, and the final state is returned on join.
+///
+/// With `unclassified_path` (not supported with a checkpoint), records without
+/// a lineage are written to that file instead.
+///
+/// The buffer is flushed before `flush_threshold` when `memory` is near its budget.
 fn start_arrow_writer_thread(
     parquet_path: Utf8PathBuf,
-    flush_threshold: usize,
-) -> Result<(Sender<ArrowRecord>, thread::JoinHandle<Result<()>>)> {
-    let (sender, receiver): (Sender<ArrowRecord>, Receiver<ArrowRecord>) = mpsc::channel();
-
-    let parquet_path = parquet_path.to_string();
-
-    let handle = thread::spawn(move || -> Result<()> {
-        let file = File::create(&parquet_path)?;
-        let options = WriteOptions {
-            write_statistics: true,
-            compression: CompressionOptions::Zstd(None),
-            version: Version::V2,
-            data_pagesize_limit: None,
-        };
-
+    unclassified_path: Option<Utf8PathBuf>,
+    settings: WriterSettings,
+    checkpoint: Option<CheckpointConfig>,
+    cancel_flag: Arc<AtomicBool>,
+    on_interrupt: InterruptPolicy,
+    memory: Arc<MemoryMonitor>,
+) -> Result<(Sender<WriteBatch>, thread::JoinHandle<Result<WriterOutput>>)> {
+    let (sender, receiver): (Sender<WriteBatch>, Receiver<WriteBatch>) = mpsc::channel();
+
+    let handle = spawn_consumer(memory.clone(), move || -> Result<WriterOutput> {
+        let options = settings.write_options;
+        let flush_threshold = settings.row_group_size;
         let mut buffer = Vec::with_capacity(flush_threshold);
+        let schema = settings.schema();
+
+        let Some(mut checkpoint) = checkpoint else {
+            let file = File::create(&parquet_path)?;
+            let mut writer = FileWriter::try_new(file, schema.clone(), options)?;
+            let mut rows_written = 0;
+            let mut unclassified = unclassified_path
+                .as_ref()
+                .map(|path| -> Result<_> {
+                    let file = File::create(path)?;
+                    Ok(FileWriter::try_new(file, schema.clone(), options)?)
+                })
+                .transpose()?;
+            let mut unclassified_buffer = Vec::new();
+            let mut unclassified_rows = 0;
+
+            for batch in receiver {
+                memory.batch_received();
+                memory.maybe_report();
+                match unclassified {
+                    Some(_) => {
+                        for record in batch.records {
+                            if is_unclassified(&record) {
+                                unclassified_buffer.push(record);
+                            } else {
+                                buffer.push(record);
+                            }
+                        }
+                    }
+                    None => buffer.extend(batch.records),
+                }
+
+                if buffer.len() >= flush_threshold || memory.near_limit() {
+                    write_records(&mut writer, &settings, &buffer)?;
+                    rows_written += buffer.len() as u64;
+                    buffer.clear();
+                }
+                if let Some(ref mut writer) = unclassified {
+                    if unclassified_buffer.len() >= flush_threshold || memory.near_limit() {
+                        write_records(writer, &settings, &unclassified_buffer)?;
+                        unclassified_rows += unclassified_buffer.len() as u64;
+                        unclassified_buffer.clear();
+                    }
+                }
+            }
+
+            if cancel_flag.load(Ordering::SeqCst) && on_interrupt != InterruptPolicy::Finalize {
+                drop(writer);
+                drop(unclassified);
+                if on_interrupt == InterruptPolicy::Delete {
+                    std::fs::remove_file(&parquet_path)?;
+                    if let Some(ref path) = unclassified_path {
+                        std::fs::remove_file(path)?;
+                    }
+                    eprintln!("Removed partial output {parquet_path}");
+                }
+                return Ok(WriterOutput {
+                    rows_written,
+                    unclassified_rows,
+                    checkpoint: None,
+                });
+            }
 
-        // Prime schema from empty batch
-        let (schema, _) = convert_to_batch(&[])?;
-        let mut writer = FileWriter::try_new(file, schema.clone(), options)?;
-
-        for record in receiver {
-            buffer.push(record);
-
-            if buffer.len() >= flush_threshold {
-                let (_, chunk) = convert_to_batch(&buffer)?;
-                let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
-                let row_groups = RowGroupIterator::try_new(
-                    std::iter::once(Ok(chunk)),
-                    &schema,
-                    options,
-                    encodings,
-                )?;
-
-                for group in row_groups {
-                    writer.write(group?)?;
+            // Flush remaining records
+            if !buffer.is_empty() {
+                write_records(&mut writer, &settings, &buffer)?;
+                rows_written += buffer.len() as u64;
+            }
+
+            let partial = cancel_flag.load(Ordering::SeqCst);
+            writer.end(Some(footer_metadata(rows_written, partial)))?;
+            if let Some(mut writer) = unclassified {
+                if !unclassified_buffer.is_empty() {
+                    write_records(&mut writer, &settings, &unclassified_buffer)?;
+                    unclassified_rows += unclassified_buffer.len() as u64;
                 }
+                writer.end(Some(footer_metadata(unclassified_rows, partial)))?;
+            }
+            return Ok(WriterOutput {
+                rows_written,
+                unclassified_rows,
+                checkpoint: None,
+            });
+        };
 
+        // progress received since the last finalized part
+        let mut pending: BTreeMap<String, SourceProgress> = BTreeMap::new();
+        let mut part: Option<(Utf8PathBuf, FileWriter<File>)> = None;
+        let mut part_rows = 0;
+
+        for batch in receiver {
+            memory.batch_received();
+            memory.maybe_report();
+            let progress = pending.entry(batch.source).or_default();
+            progress.summary.ksize = batch.summary.ksize;
+            progress.summary.scaled = batch.summary.scaled;
+            progress.last_hash = batch.last_hash.or(progress.last_hash);
+            progress.complete |= batch.done;
+            progress.summary.merge(&batch.summary);
+            buffer.extend(batch.records);
+
+            if buffer.len() >= flush_threshold || memory.near_limit() {
+                if part.is_none() {
+                    part = Some(checkpoint.open_part(&schema, options)?);
+                }
+                let (_, writer) = part.as_mut().expect("part writer is open");
+                write_records(writer, &settings, &buffer)?;
+                part_rows += buffer.len();
                 buffer.clear();
+
+                if part_rows >= checkpoint.rows_per_part {
+                    checkpoint.finalize_part(part.take(), part_rows, &mut pending)?;
+                    part_rows = 0;
+                }
+            }
+        }
+
+        if cancel_flag.load(Ordering::SeqCst) && on_interrupt != InterruptPolicy::Finalize {
+            // the in-progress part was never recorded, so resuming will overwrite it
+            if let Some((path, writer)) = part.take() {
+                drop(writer);
+                std::fs::remove_file(path)?;
+            }
+            if on_interrupt == InterruptPolicy::Delete {
+                for part in &checkpoint.state.parts {
+                    std::fs::remove_file(part)?;
+                }
+                if checkpoint.path.exists() {
+                    std::fs::remove_file(&checkpoint.path)?;
+                }
+                eprintln!("Removed checkpoint and partial parts for {parquet_path}");
             }
+            return Ok(WriterOutput {
+                rows_written: checkpoint.state.rows_written,
+                unclassified_rows: 0,
+                checkpoint: None,
+            });
         }
 
-        // Flush remaining records
+        // Flush remaining records into a last part
         if !buffer.is_empty() {
+            if part.is_none() {
+                part = Some(checkpoint.open_part(&schema, options)?);
+            }
+            let (_, writer) = part.as_mut().expect("part writer is open");
+            write_records(writer, &settings, &buffer)?;
+            part_rows += buffer.len();
+        }
+        checkpoint.finalize_part(part.take(), part_rows, &mut pending)?;
+
+        eprintln!(
+            "Finished writing {} Parquet part(s) for {parquet_path}",
+            checkpoint.state.parts.len()
+        );
+        Ok(WriterOutput {
+            rows_written: checkpoint.state.rows_written,
+            unclassified_rows: 0,
+            checkpoint: Some(checkpoint.state),
+        })
+    });
+
+    Ok((sender, handle))
+}
+
+/// Concatenate the row groups of several Parquet files (with identical schemas) into one file.
+pub(crate) fn concat_parquet_files(
+    inputs: &[Utf8PathBuf],
+    output: &Utf8Path,
+    settings: &WriterSettings,
+) -> Result<()> {
+    let options = settings.write_options;
+    let mut writer: Option<(Schema, FileWriter<File>)> = None;
+
+    for input in inputs {
+        let mut reader = File::open(input)?;
+        let metadata = arrow2::io::parquet::read::read_metadata(&mut reader)?;
+        let schema = arrow2::io::parquet::read::infer_schema(&metadata)?;
+        let chunks = arrow2::io::parquet::read::FileReader::new(
+            reader,
+            metadata.row_groups,
+            schema.clone(),
+            None,
+            None,
+            None,
+        );
+
+        let (schema, writer) = match writer {
+            Some(ref mut w) => (&w.0, &mut w.1),
+            None => {
+                let file = File::create(output)?;
+                let w =
+                    writer.insert((schema.clone(), FileWriter::try_new(file, schema, options)?));
+                (&w.0, &mut w.1)
+            }
+        };
+
+        for chunk in chunks {
             let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
-            let (_, chunk) = convert_to_batch(&buffer)?;
             let row_groups =
-                RowGroupIterator::try_new(std::iter::once(Ok(chunk)), &schema, options, encodings)?;
-
+                RowGroupIterator::try_new(std::iter::once(chunk), &*schema, options, encodings)?;
             for group in row_groups {
                 writer.write(group?)?;
             }
         }
+    }
 
-        writer.end(None)?;
-        eprintln!("Finished writing Parquet to {parquet_path}");
-        Ok(())
-    });
-
-    Ok((sender, handle))
+    match writer {
+        Some((_, mut writer)) => {
+            writer.end(None)?;
+        }
+        None => {
+            // no inputs: still produce a valid, empty file
+            let mut writer =
+                FileWriter::try_new(File::create(output)?, settings.schema(), options)?;
+            writer.end(None)?;
+        }
+    }
+    Ok(())
 }
 
 // LCA and Taxonomy Utils
-#[derive(Default, Clone)]
-struct LCASummary {
+
+/// Per-source counts of hashes by LCA rank, plus unclassified and no-LCA hashes.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LCASummary {
     rank_counts: HashMap<String, usize>,
     no_lca_count: usize,
     unclassified_count: usize,
@@ -226,7 +1805,8 @@ impl LCASummary {
         }
     }
 
-    fn add_rank(&mut self, taxonomy_list: Option<&Vec<String>>, lca_rank: Option<&str>) {
+    /// Count one hash, given its dataset taxonomies and LCA rank.
+    pub fn add_rank(&mut self, taxonomy_list: Option<&Vec<String>>, lca_rank: Option<&str>) {
         self.total += 1;
 
         match taxonomy_list {
@@ -246,7 +1826,23 @@ impl LCASummary {
         }
     }
 
-    fn merge(&mut self, other: &LCASummary) {
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn rank_counts(&self) -> &HashMap<String, usize> {
+        &self.rank_counts
+    }
+
+    pub fn no_lca_count(&self) -> usize {
+        self.no_lca_count
+    }
+
+    pub fn unclassified_count(&self) -> usize {
+        self.unclassified_count
+    }
+
+    pub fn merge(&mut self, other: &LCASummary) {
         for (rank, count) in &other.rank_counts {
             *self.rank_counts.entry(rank.clone()).or_default() += count;
         }
@@ -255,64 +1851,142 @@ impl LCASummary {
         self.total += other.total;
     }
 
-    fn to_csv_rows(&self, source: &str) -> Vec<(String, u32, u32, String, usize, f64)> {
-        let mut rows = self
-            .rank_counts
+    /// Hashes with an LCA at some rank, the base of the percent-of-classified
+    /// columns.
+    pub fn classified_count(&self) -> usize {
+        self.rank_counts.values().sum()
+    }
+
+    /// Hashes whose LCA is at `rank` or a more specific rank.
+    pub fn cumulative_count(&self, rank: &str) -> usize {
+        let Some(index) = RANKS.iter().position(|r| *r == rank) else {
+            return self.rank_counts.get(rank).copied().unwrap_or_default();
+        };
+        RANKS[index..]
             .iter()
-            .map(|(rank, count)| {
-                (
-                    rank.clone(),
-                    *count,
-                    (*count as f64 / self.total as f64) * 100.0,
-                )
-            })
-            .collect::<Vec<_>>();
+            .filter_map(|r| self.rank_counts.get(*r))
+            .sum()
+    }
 
-        if self.no_lca_count > 0 {
-            rows.push((
-                "no_lca".into(),
-                self.no_lca_count,
-                (self.no_lca_count as f64 / self.total as f64) * 100.0,
-            ));
-        }
+    /// One CSV row per LCA rank (most general first), then `no_lca` and
+    /// `unclassified`. The percent-of-classified and cumulative columns are
+    /// only set for LCA ranks.
+    fn to_csv_rows(&self, source: &str) -> Vec<LcaInfoRow> {
+        let percent = |count: usize, base: usize| match base {
+            0 => 0.0,
+            base => count as f64 / base as f64 * 100.0,
+        };
+        let classified = self.classified_count();
 
-        if self.unclassified_count > 0 {
-            rows.push((
-                "unclassified".into(),
-                self.unclassified_count,
-                (self.unclassified_count as f64 / self.total as f64) * 100.0,
-            ));
-        }
-
-        rows.into_iter()
-            .map(|(rank, count, pct)| {
-                (
-                    source.to_string(),
-                    self.ksize,
-                    self.scaled,
-                    rank,
+        let mut ranks: Vec<&String> = self.rank_counts.keys().collect();
+        ranks.sort_by_key(|rank| {
+            let depth = RANKS.iter().position(|r| *r == rank.as_str());
+            (depth.unwrap_or(RANKS.len()), *rank)
+        });
+        let mut rows: Vec<LcaInfoRow> = ranks
+            .into_iter()
+            .map(|rank| {
+                let count = self.rank_counts[rank];
+                let cumulative = self.cumulative_count(rank);
+                LcaInfoRow {
+                    lca_rank: rank.clone(),
                     count,
-                    pct,
-                )
+                    percent: percent(count, self.total),
+                    percent_classified: Some(percent(count, classified)),
+                    cumulative_count: Some(cumulative),
+                    cumulative_percent: Some(percent(cumulative, self.total)),
+                    ..LcaInfoRow::new(source, self)
+                }
             })
-            .collect()
+            .collect();
+
+        for (rank, count) in [
+            ("no_lca", self.no_lca_count),
+            ("unclassified", self.unclassified_count),
+        ] {
+            if count > 0 {
+                rows.push(LcaInfoRow {
+                    lca_rank: rank.into(),
+                    count,
+                    percent: percent(count, self.total),
+                    ..LcaInfoRow::new(source, self)
+                });
+            }
+        }
+        rows
     }
 
-    pub const CSV_HEADER: [&'static str; 6] =
-        ["source", "ksize", "scaled", "lca_rank", "count", "percent"];
+    pub const CSV_HEADER: [&'static str; 9] = [
+        "source",
+        "ksize",
+        "scaled",
+        "lca_rank",
+        "count",
+        "percent",
+        "percent_classified",
+        "cumulative_count",
+        "cumulative_percent",
+    ];
 
     pub fn write_csv<W: std::io::Write>(
         &self,
         writer: &mut csv::Writer<W>,
         source: &str,
     ) -> Result<()> {
-        for (src, ksize, scaled, rank, count, pct) in self.to_csv_rows(source) {
-            writer.serialize((src, ksize, scaled, rank, count, format!("{:.2}", pct)))?;
+        let two_places = |pct: f64| format!("{pct:.2}");
+        for row in self.to_csv_rows(source) {
+            writer.serialize((
+                row.source,
+                row.ksize,
+                row.scaled,
+                row.lca_rank,
+                row.count,
+                two_places(row.percent),
+                row.percent_classified.map(two_places),
+                row.cumulative_count,
+                row.cumulative_percent.map(two_places),
+            ))?;
         }
         Ok(())
     }
 }
 
+/// One row of an LCA summary CSV (`--lca-info`). Files written before the
+/// percent-of-classified and cumulative columns were added still read.
+#[derive(Debug, Clone, Deserialize)]
+struct LcaInfoRow {
+    source: String,
+    ksize: u32,
+    scaled: u32,
+    lca_rank: String,
+    count: usize,
+    percent: f64,
+    /// percent of the hashes with an LCA
+    #[serde(default)]
+    percent_classified: Option<f64>,
+    /// hashes with their LCA at this rank or a more specific one
+    #[serde(default)]
+    cumulative_count: Option<usize>,
+    #[serde(default)]
+    cumulative_percent: Option<f64>,
+}
+
+impl LcaInfoRow {
+    fn new(source: &str, summary: &LCASummary) -> Self {
+        Self {
+            source: source.to_string(),
+            ksize: summary.ksize,
+            scaled: summary.scaled,
+            lca_rank: String::new(),
+            count: 0,
+            percent: 0.0,
+            percent_classified: None,
+            cumulative_count: None,
+            cumulative_percent: None,
+        }
+    }
+}
+
 impl fmt::Display for LCASummary {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "--- LCA Summary ---")?;
@@ -329,10 +2003,15 @@ impl fmt::Display for LCASummary {
             _ => 7,
         });
 
+        let classified = self.classified_count();
         for rank in rank_keys {
             let count = self.rank_counts[&*rank];
             let pct = (count as f64 / self.total as f64) * 100.0;
-            writeln!(f, "{rank}: {count} ({pct:.1}%)")?;
+            let pct_classified = (count as f64 / classified as f64) * 100.0;
+            writeln!(
+                f,
+                "{rank}: {count} ({pct:.1}%; {pct_classified:.1}% of classified)"
+            )?;
         }
 
         if self.no_lca_count > 0 {
@@ -350,7 +2029,10 @@ impl fmt::Display for LCASummary {
     }
 }
 
-fn write_lca_info(path: Option<&Utf8Path>, all_summaries: &[(String, LCASummary)]) -> Result<()> {
+pub(crate) fn write_lca_info(
+    path: Option<&Utf8Path>,
+    all_summaries: &[(String, LCASummary)],
+) -> Result<()> {
     if all_summaries.is_empty() {
         return Ok(()); // no summaries to print or write
     }
@@ -409,7 +2091,44 @@ fn write_lca_info(path: Option<&Utf8Path>, all_summaries: &[(String, LCASummary)
     Ok(())
 }
 
-fn strip_accession_version(s: &str) -> &str {
+/// Read per-source summaries back from an LCA summary CSV written by
+/// `write_lca_info`; the `combined_k*` rows are skipped, since they are
+/// recomputed on write.
+pub(crate) fn read_lca_info(path: &Utf8Path) -> Result<Vec<(String, LCASummary)>> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    let mut summaries: Vec<(String, LCASummary)> = Vec::new();
+    for row in rdr.deserialize() {
+        let LcaInfoRow {
+            source,
+            ksize,
+            scaled,
+            lca_rank: rank,
+            count,
+            ..
+        } = row?;
+        if source.starts_with("combined_k") {
+            continue;
+        }
+        let index = match summaries.iter().position(|(s, _)| *s == source) {
+            Some(index) => index,
+            None => {
+                summaries.push((source.clone(), LCASummary::new(ksize, scaled)));
+                summaries.len() - 1
+            }
+        };
+        let summary = &mut summaries[index].1;
+        match rank.as_str() {
+            "no_lca" => summary.no_lca_count += count,
+            "unclassified" => summary.unclassified_count += count,
+            _ => *summary.rank_counts.entry(rank).or_default() += count,
+        }
+        summary.total += count;
+    }
+    Ok(summaries)
+}
+
+/// Drop the version suffix from an accession (`GCF_000005845.2` -> `GCF_000005845`).
+pub fn strip_accession_version(s: &str) -> &str {
     s.split('.').next().unwrap_or(s)
 }
 
@@ -429,15 +2148,17 @@ struct TaxonomyRow {
     species: Option<String>,
 }
 
-fn compute_lca_strs(taxonomies: &[String]) -> (String, Option<&'static str>) {
+// ranks of an LCA lineage, in lineage order
+pub(crate) const RANKS: [&str; 7] = [
+    "domain", "phylum", "class", "order", "family", "genus", "species",
+];
+
+/// Lowest common ancestor of `;`-separated lineages, and the rank it resolves to.
+pub fn compute_lca_strs(taxonomies: &[String]) -> (String, Option<&'static str>) {
     if taxonomies.is_empty() {
         return (String::new(), None);
     }
 
-    let rank_names = [
-        "domain", "phylum", "class", "order", "family", "genus", "species",
-    ];
-
     let split_taxonomies: Vec<Vec<&str>> =
         taxonomies.iter().map(|s| s.split(';').collect()).collect();
 
@@ -451,19 +2172,88 @@ fn compute_lca_strs(taxonomies: &[String]) -> (String, Option<&'static str>) {
             .all(|parts| parts.get(i) == Some(val))
         {
             lca.push(*val);
-            lca_rank = rank_names.get(i).copied();
+            // a rank left blank in all lineages doesn't resolve the LCA
+            if !val.is_empty() {
+                lca_rank = RANKS.get(i).copied();
+            }
         } else {
             break;
         }
     }
+    while lca.last() == Some(&"") {
+        lca.pop();
+    }
 
     (lca.join(";"), lca_rank)
 }
 
-fn load_taxonomy_map(path: Utf8PathBuf) -> Result<HashMap<String, String>> {
-    let file = File::open(&path)?;
-    let reader = BufReader::new(file);
-    let mut rdr = csv::Reader::from_reader(reader);
+/// Load and merge taxonomy CSVs; `None` if there are none (or they are empty).
+pub(crate) fn load_taxonomies(
+    tax_paths: Vec<Utf8PathBuf>,
+) -> Result<Option<HashMap<String, String>>> {
+    Ok(load_ranked_taxonomies(tax_paths)?.map(|tax_map| {
+        tax_map
+            .into_iter()
+            .map(|(ident, ranks)| (ident, join_ranks(ranks)))
+            .collect()
+    }))
+}
+
+/// Load and merge taxonomy CSVs, keeping each lineage's names by rank (in
+/// `RANKS` order, `None` for the ranks a row leaves blank); `None` if there
+/// are none (or they are empty).
+pub(crate) fn load_ranked_taxonomies(
+    tax_paths: Vec<Utf8PathBuf>,
+) -> Result<Option<HashMap<String, RankedLineage>>> {
+    if tax_paths.iter().filter(|path| path.as_str() == "-").count() > 1 {
+        anyhow::bail!("the taxonomy can only be read from stdin ('-') once");
+    }
+    let mut full_tax_map = HashMap::new();
+    for path in tax_paths {
+        full_tax_map.extend(load_ranked_taxonomy_map(path)?);
+    }
+
+    Ok(if full_tax_map.is_empty() {
+        None
+    } else {
+        Some(full_tax_map)
+    })
+}
+
+/// Lineage names by rank, in `RANKS` order.
+pub(crate) type RankedLineage = [Option<String>; 7];
+
+// the `;`-separated lineage, as sourmash displays it: a blank rank keeps its
+// place as an empty name, so each name's position is its rank, and trailing
+// blank ranks are dropped
+fn join_ranks(ranks: RankedLineage) -> String {
+    let depth = ranks.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+    ranks[..depth]
+        .iter()
+        .map(|name| name.as_deref().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Load a sourmash taxonomy CSV into a map of accession (without version) -> lineage.
+/// Lineages are `;`-separated names from domain down, with an empty name for
+/// each blank rank before the last named one. A path of `-` reads the CSV from stdin.
+pub fn load_taxonomy_map(path: Utf8PathBuf) -> Result<HashMap<String, String>> {
+    Ok(load_ranked_taxonomy_map(path)?
+        .into_iter()
+        .map(|(ident, ranks)| (ident, join_ranks(ranks)))
+        .collect())
+}
+
+// `load_taxonomy_map`, keeping the names by rank
+fn load_ranked_taxonomy_map(path: Utf8PathBuf) -> Result<HashMap<String, RankedLineage>> {
+    let reader: Box<dyn std::io::Read> = if path.as_str() == "-" {
+        eprintln!("Reading taxonomy from stdin");
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(File::open(&path)?)
+    };
+    let mut rdr = csv::Reader::from_reader(BufReader::new(reader));
 
     let mut tax_map = HashMap::new();
     let mut total_rows = 0;
@@ -501,11 +2291,7 @@ fn load_taxonomy_map(path: Utf8PathBuf) -> Result<HashMap<String, String>> {
                     row.family,
                     row.genus,
                     row.species,
-                ]
-                .into_iter()
-                .flatten()
-                .collect::<Vec<_>>()
-                .join(";");
+                ];
                 let ident = strip_accession_version(&row.ident);
                 tax_map.insert(ident.to_string(), taxonomy);
             }
@@ -515,29 +2301,158 @@ fn load_taxonomy_map(path: Utf8PathBuf) -> Result<HashMap<String, String>> {
             }
         }
     }
-
-    if tax_map.is_empty() {
-        anyhow::bail!(
-            "Provided taxonomy file '{}' is empty or failed to parse.",
-            path
-        );
-    }
-
-    eprintln!(
-        "Loaded {} taxonomy entries ({} rows failed to parse).",
-        tax_map.len(),
-        failed_rows
-    );
-
-    Ok(tax_map)
+
+    if tax_map.is_empty() {
+        anyhow::bail!(
+            "Provided taxonomy file '{}' is empty or failed to parse.",
+            path
+        );
+    }
+
+    eprintln!(
+        "Loaded {} taxonomy entries ({} rows failed to parse).",
+        tax_map.len(),
+        failed_rows
+    );
+
+    Ok(tax_map)
+}
+
+/// Look up display names for dataset indices with `naming`.
+pub(crate) fn dataset_names(
+    collection: &CollectionSet,
+    datasets: impl IntoIterator<Item = u32>,
+    naming: &DatasetNaming,
+) -> Vec<String> {
+    datasets
+        .into_iter()
+        .filter_map(|idx| {
+            if (idx as usize) >= collection.len() {
+                eprintln!("Skipping invalid dataset ID: {}", idx);
+                return None;
+            }
+            let record = collection.record_for_dataset(idx).ok()?;
+            Some(naming.name(idx, record))
+        })
+        .collect()
+}
+
+/// Returns: (taxonomy_list, lca_lineage, lca_rank) for a hash's datasets;
+/// all None without a taxonomy.
+pub(crate) fn annotate_taxonomy(
+    dataset_names: &[String],
+    taxonomy_map: Option<&HashMap<String, String>>,
+) -> (Option<Vec<String>>, Option<String>, Option<String>) {
+    let Some(tax_map) = taxonomy_map else {
+        return (None, None, None);
+    };
+
+    let taxonomy_list: Vec<String> = dataset_names
+        .iter()
+        .filter_map(|name| name.split_whitespace().next())
+        .map(strip_accession_version)
+        .filter_map(|accession| tax_map.get(accession))
+        .cloned()
+        .collect();
+
+    let (lineage, rank) = compute_lca_strs(&taxonomy_list);
+    (
+        Some(taxonomy_list),
+        Some(lineage),
+        rank.map(|r| r.to_string()),
+    )
+}
+
+// update progress every this many hashes
+const PROGRESS_UPDATE_INTERVAL: u64 = 10_000;
+
+/// Totals shared by the scans of all databases of one export.
+#[derive(Debug, Default)]
+pub(crate) struct ScanTotals {
+    /// keys that were not 8-byte hashes or whose dataset list could not be parsed
+    pub skipped_keys: AtomicU64,
+    /// per-source dataset IDs that aren't in the manifest
+    pub invalid_datasets: Mutex<BTreeMap<String, InvalidDatasets>>,
+    /// per-dataset counts, with `--dataset-summary`
+    pub dataset_tallies: Mutex<Vec<DatasetTally>>,
+    /// per-source counts by LCA lineage, with `--lineage-summary`,
+    /// `--kraken-report`, `--krona` or `--biom`
+    pub lineage_counts: Mutex<Vec<LineageCounts>>,
+    /// per-source shared hashes between datasets, with `--cooccurrence`
+    pub pair_counts: Mutex<Vec<PairCounts>>,
+    /// distinct hashes per lineage over all sources, with `--lineage-hll`
+    pub lineage_sketches: Mutex<Option<LineageSketches>>,
+    /// per-source most widely shared hashes, with `--top-shared`
+    pub top_shared: Mutex<Vec<TopShared>>,
+}
+
+impl ScanTotals {
+    /// Write the summary tables requested in `options`, sorted by source.
+    fn write_tables(&self, options: &ExportOptions) -> Result<()> {
+        if let Some(path) = options.dataset_summary.as_deref() {
+            let mut tallies = std::mem::take(&mut *self.dataset_tallies.lock().unwrap());
+            tallies.sort_by(|a, b| a.source().cmp(b.source()));
+            if let Some(ref anonymizer) = options.anonymize {
+                for tally in &mut tallies {
+                    tally.anonymize(anonymizer);
+                }
+            }
+            write_dataset_summary(path, &tallies)?;
+        }
+        let mut counts = std::mem::take(&mut *self.lineage_counts.lock().unwrap());
+        counts.sort_by(|a, b| a.source().cmp(b.source()));
+        if let Some(path) = options.lineage_summary.as_deref() {
+            write_lineage_summary(path, &counts)?;
+        }
+        if let Some(path) = options.kraken_report.as_deref() {
+            write_kraken_report(path, &counts)?;
+        }
+        if let Some(path) = options.krona.as_deref() {
+            write_krona_text(path, &counts)?;
+        }
+        if let Some(path) = options.biom.as_deref() {
+            write_biom(path, &counts)?;
+        }
+        if let Some(path) = options.cooccurrence.as_deref() {
+            let mut pairs = std::mem::take(&mut *self.pair_counts.lock().unwrap());
+            pairs.sort_by(|a, b| a.source().cmp(b.source()));
+            if let Some(ref anonymizer) = options.anonymize {
+                for counts in &mut pairs {
+                    counts.anonymize(anonymizer);
+                }
+            }
+            write_cooccurrence(path, &pairs, options.cooccurrence_min_shared)?;
+        }
+        if let Some(path) = options.top_shared.as_deref() {
+            let mut top = std::mem::take(&mut *self.top_shared.lock().unwrap());
+            top.sort_by(|a, b| a.source().cmp(b.source()));
+            write_top_shared(path, &top)?;
+        }
+        if let Some(path) = options.lineage_hll.as_deref() {
+            if let Some(sketches) = self.lineage_sketches.lock().unwrap().take() {
+                write_lineage_hll(path, &sketches)?;
+            }
+        }
+        if let Some(ref anonymizer) = options.anonymize {
+            anonymizer.write_mapping()?;
+        }
+        if let Some(ref table) = options.lineage_table {
+            table.write()?;
+        }
+        Ok(())
+    }
 }
 
 // process single revindex
-fn process_revindex(
+pub(crate) fn process_revindex(
     db_path: &Utf8Path,
-    sender: &Sender<ArrowRecord>,
+    sender: Option<&Sender<WriteBatch>>,
     taxonomy_map: Option<&HashMap<String, String>>,
-    rw: bool,
+    options: &ExportOptions,
+    resume: Option<&SourceProgress>,
+    reporter: &Reporter,
+    memory: &MemoryMonitor,
+    totals: &ScanTotals,
     cancel_flag: Arc<AtomicBool>,
 ) -> Result<LCASummary> {
     // get basename of revindex directory for us to write later
@@ -545,24 +2460,45 @@ fn process_revindex(
         .file_name()
         .ok_or_else(|| anyhow!("Cannot get basename of path: {}", db_path))?
         .to_string();
-    println!("Opening DB (rw mode? {})", rw);
-    let revindex = RevIndex::open(db_path, !rw, None)
+
+    if let Some(progress) = resume.filter(|p| p.complete) {
+        eprintln!("Skipping {db_basename}: already exported according to checkpoint");
+        return Ok(progress.summary.clone());
+    }
+    println!("Opening DB (rw mode? {})", options.rw);
+    // a secondary instance only needs the collection from a read-only open
+    let read_only = !options.rw || options.secondary;
+    let revindex = RevIndex::open(db_path, read_only, None)
         .map_err(|e| anyhow::anyhow!("cannot open RocksDB database. Error is: {e}"))?;
 
-    let RevIndex::Plain(revindex) = revindex;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
     eprintln!("DB opened");
 
-    // get ksize, scaled from the first record
-    // (assuming all records in a RocksDB have the same ksize and scaled)
-    let manifest = revindex.collection().manifest();
-    let (ksize, scaled) = manifest
-        .iter()
-        .next()
-        .map(|record| (record.ksize(), record.scaled()))
-        .ok_or_else(|| anyhow!("No records in manifest"))?;
+    let SketchParams { ksize, scaled, .. } =
+        select_ksize(revindex.collection().manifest(), db_path, options.ksize)?;
 
-    let db = &revindex.db;
-    let cf = db.cf_handle("hashes").expect("Missing 'hashes' CF");
+    let secondary_path = options.secondary.then(|| {
+        options.secondary_path.clone().unwrap_or_else(|| {
+            let tmp = Utf8PathBuf::try_from(std::env::temp_dir())
+                .unwrap_or_else(|_| Utf8PathBuf::from("."));
+            tmp.join(format!("{db_basename}.secondary.{}", std::process::id()))
+        })
+    });
+    let secondary_db = secondary_path
+        .as_deref()
+        .map(|path| {
+            eprintln!("Opening secondary instance at '{path}'");
+            open_secondary(db_path, path)
+        })
+        .transpose()?;
+
+    let db: &DB = match secondary_db {
+        Some(ref db) => db,
+        None => revindex.db.as_ref(),
+    };
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
 
     // estimate total hashes to process
     // this is not exact, but should be close enough
@@ -575,79 +2511,228 @@ fn process_revindex(
         db_path, total_hashes
     );
 
-    let mut lca_summary = LCASummary::new(ksize, *scaled);
+    // datasets left out by a picklist, name patterns or the prefetch
+    let mut mask = options.dataset_mask(revindex.collection());
+    if let Some(ref prefetch) = options.prefetch {
+        let overlapping = prefetch.mask(db, revindex.collection().len(), scaled)?;
+        mask = Some(match mask {
+            Some(mask) => mask.iter().zip(overlapping).map(|(a, b)| *a && b).collect(),
+            None => overlapping,
+        });
+    }
+    if let Some(ref mask) = mask {
+        let kept = mask.iter().filter(|keep| **keep).count();
+        eprintln!("Keeping {kept} of {} datasets in {db_basename}", mask.len());
+    }
+
+    let abundances = options
+        .with_abundance
+        .then(|| DatasetAbundances::load(revindex.collection(), mask.as_deref()))
+        .transpose()?;
+
+    let mut lca_summary = LCASummary::new(ksize, scaled);
+    let mut batch_summary = LCASummary::new(ksize, scaled);
+    let mut batch = Vec::with_capacity(SEND_BATCH_SIZE);
+    let mut last_hash = None;
     let mut processed = 0;
-    let mut next_percent = 1;
+    let mut exported = 0;
     eprintln!("Iterating across hashes...");
 
+    let progress_bar = reporter.add_bar(&db_basename, total_hashes);
+    let started = Instant::now();
+    reporter.event("scan", Some(&db_basename), 0, Some(total_hashes), started);
+
+    // plain or color-based hash values
+    let mut resolver = DatasetResolver::new(db)?;
+
+    // snapshot must outlive the iterator that reads from it
+    let snapshot = options.use_snapshot.then(|| db.snapshot());
+    let mut readopts = options.read_options();
+    if let Some(ref snapshot) = snapshot {
+        readopts.set_snapshot(snapshot);
+    }
+
+    // resume after the last hash recorded in the checkpoint, if any
+    let resume_hash = resume.and_then(|p| p.last_hash);
+    let resume_key = resume_hash.map(u64::to_le_bytes);
+    let mode = match resume_key {
+        Some(ref key) => {
+            eprintln!("Resuming {db_basename} after hash {}", resume_hash.unwrap());
+            rocksdb::IteratorMode::From(key, rocksdb::Direction::Forward)
+        }
+        None => rocksdb::IteratorMode::Start,
+    };
+
+    let mut db_invalid = InvalidDatasets::default();
+    let mut tally = options.dataset_summary.is_some().then(|| {
+        DatasetTally::new(
+            &db_basename,
+            revindex.collection(),
+            mask.as_deref(),
+            &options.dataset_naming,
+        )
+    });
+    let mut lineages = options
+        .counts_lineages()
+        .then(|| LineageCounts::new(&db_basename));
+    let mut pairs = options
+        .cooccurrence
+        .is_some()
+        .then(|| PairCounts::new(&db_basename, revindex.collection(), &options.dataset_naming));
+    let mut top_shared = options
+        .top_shared
+        .is_some()
+        .then(|| TopShared::new(&db_basename, options.top_shared_n));
+    let mut sketches = options
+        .lineage_hll
+        .as_ref()
+        .map(|_| {
+            let rank = options.hll_rank.as_deref().unwrap_or(DEFAULT_HLL_RANK);
+            LineageSketches::new(rank, ksize)
+        })
+        .transpose()?;
+    let mut cancelled = false;
     for (k, v) in db
-        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .iterator_cf_opt(&cf, readopts, mode)
         .filter_map(Result::ok)
     {
+        if memory.writer_failed() {
+            return Err(WriterFailed.into());
+        }
         // Check for cancellation
         if cancel_flag.load(Ordering::SeqCst) {
             eprintln!("Cancellation detected! Stopping iteration.");
+            cancelled = true;
+            break;
+        }
+
+        if options.limit.is_some_and(|limit| exported >= limit) {
+            eprintln!("Reached limit of {exported} hashes for {db_basename}.");
             break;
         }
 
+        if resume_key.as_ref().is_some_and(|key| k.as_ref() == key) {
+            continue;
+        }
         if k.len() != 8 {
+            totals.skipped_keys.fetch_add(1, Ordering::Relaxed);
             continue;
         }
 
         processed += 1;
-        let current_percent = processed * 100 / total_hashes;
-        if current_percent >= next_percent {
-            eprintln!("Processed {}% of {} hashes", current_percent, db_path);
-            next_percent = current_percent + 1;
+        if processed % PROGRESS_UPDATE_INTERVAL == 0 {
+            progress_bar.set_position(processed);
+            reporter.scanned(&db_basename, processed, total_hashes);
+            reporter.event(
+                "scan",
+                Some(&db_basename),
+                processed,
+                Some(total_hashes),
+                started,
+            );
         }
 
         let hash = LittleEndian::read_u64(&k);
+        last_hash = Some(hash);
+        if !options.in_shard(hash)
+            || !options.in_hash_list(hash)
+            || !options.in_sample(hash, processed - 1)
+        {
+            continue;
+        }
 
-        let datasets = match Datasets::from_slice(&v) {
+        let datasets = match resolver.resolve(&v)? {
             Some(d) => d,
             None => {
                 eprintln!("Warning: could not parse dataset list");
+                totals.skipped_keys.fetch_add(1, Ordering::Relaxed);
                 continue;
             }
         };
 
-        let dataset_names: Vec<String> = datasets
+        // IDs beyond the manifest are reported rather than warned about one by one
+        let (mut datasets, invalid): (Vec<u32>, Vec<u32>) = datasets
             .into_iter()
-            .filter_map(|idx| {
-                if (idx as usize) >= revindex.collection().len() {
-                    eprintln!("Skipping invalid dataset ID: {}", idx);
-                    return None;
-                }
-                let record = revindex.collection().record_for_dataset(idx).ok()?;
-                let name = record.name();
-                if !name.is_empty() {
-                    Some(name.to_string())
-                } else {
-                    Some(record.filename().to_string())
-                }
-            })
-            .collect();
-
-        let (taxonomy_list, lca_lineage, lca_rank) = if let Some(tax_map) = taxonomy_map {
-            let taxonomy_list: Vec<String> = dataset_names
-                .iter()
-                .filter_map(|name| name.split_whitespace().next())
-                .map(strip_accession_version)
-                .filter_map(|accession| tax_map.get(accession))
-                .cloned()
-                .collect();
+            .partition(|idx| (*idx as usize) < revindex.collection().len());
+        if !invalid.is_empty() {
+            if options.strict {
+                anyhow::bail!(
+                    "hash {hash} in {db_basename} refers to dataset IDs not in its manifest ({}); \
+                     rerun without --strict to skip them",
+                    invalid
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            db_invalid.record(hash, &invalid);
+        }
 
-            let (lineage, rank) = compute_lca_strs(&taxonomy_list);
-            (
-                Some(taxonomy_list),
-                Some(lineage),
-                rank.map(|r| r.to_string()),
+        if let Some(ref mask) = mask {
+            datasets.retain(|idx| mask.get(*idx as usize).copied().unwrap_or(false));
+        }
+        let dataset_names = dataset_names(
+            revindex.collection(),
+            datasets.iter().copied(),
+            &options.dataset_naming,
+        );
+        if dataset_names.is_empty() && mask.is_some() {
+            continue;
+        }
+        // merged rows are counted once they're combined
+        if !options.merge && !options.in_dataset_range(dataset_names.len()) {
+            continue;
+        }
+        // merged rows are compared once they're combined (see HashMerger::send)
+        if !options.merge
+            && options
+                .since
+                .as_ref()
+                .is_some_and(|previous| previous.contains(hash, &dataset_names))
+        {
+            continue;
+        }
+        let (taxonomy_list, lca_lineage, lca_rank) =
+            annotate_taxonomy(&dataset_names, taxonomy_map);
+        // merged rows are annotated again once they're combined
+        if !options.merge
+            && !options.keeps_annotation(
+                taxonomy_list.as_ref(),
+                lca_lineage.as_deref(),
+                lca_rank.as_deref(),
             )
-        } else {
-            (None, None, None)
-        };
+        {
+            continue;
+        }
+
+        batch_summary.add_rank(taxonomy_list.as_ref(), lca_rank.as_deref());
+        if let Some(ref mut tally) = tally {
+            tally.add(&datasets, lca_rank.as_deref());
+        }
+        if let Some(ref mut lineages) = lineages {
+            lineages.add(lca_lineage.as_deref(), lca_rank.as_deref());
+        }
+        if let Some(ref mut pairs) = pairs {
+            pairs.add(&datasets);
+        }
+        if let Some(ref mut top_shared) = top_shared {
+            top_shared.add(
+                hash,
+                dataset_names.len(),
+                lca_lineage.as_deref(),
+                lca_rank.as_deref(),
+            );
+        }
+        if let Some(ref mut sketches) = sketches {
+            sketches.add(hash, taxonomy_list.as_ref())?;
+        }
+        exported += 1;
 
-        lca_summary.add_rank(taxonomy_list.as_ref(), lca_rank.as_deref());
+        // summary-only mode: no records to build or send
+        let Some(sender) = sender else {
+            continue;
+        };
 
         let record = ArrowRecord {
             hash,
@@ -656,79 +2741,680 @@ fn process_revindex(
             lca_lineage,
             lca_rank,
             ksize,
-            scaled: *scaled,
+            scaled,
             source: db_basename.clone(),
+            abundances: abundances
+                .as_ref()
+                .and_then(|abundances| abundances.abundances(hash, &datasets)),
         };
 
-        sender.send(record)?;
+        batch.push(record);
+        if batch.len() >= SEND_BATCH_SIZE {
+            lca_summary.merge(&batch_summary);
+            memory.wait_for_writer();
+            memory.batch_sent();
+            sender
+                .send(WriteBatch {
+                    source: db_basename.clone(),
+                    last_hash,
+                    records: std::mem::replace(&mut batch, Vec::with_capacity(SEND_BATCH_SIZE)),
+                    summary: std::mem::replace(&mut batch_summary, LCASummary::new(ksize, scaled)),
+                    done: false,
+                })
+                .map_err(|_| WriterFailed)?;
+        }
+    }
+
+    progress_bar.set_position(processed);
+    if cancelled {
+        progress_bar.abandon();
+    } else {
+        progress_bar.finish();
+        reporter.scanned(&db_basename, processed, processed);
+        reporter.event(
+            "scan_done",
+            Some(&db_basename),
+            processed,
+            Some(processed),
+            started,
+        );
+    }
+
+    // send any remaining records, marking this source as done unless cancelled
+    lca_summary.merge(&batch_summary);
+    if let Some(sender) = sender {
+        memory.batch_sent();
+        sender
+            .send(WriteBatch {
+                source: db_basename.clone(),
+                last_hash,
+                records: batch,
+                summary: batch_summary,
+                done: !cancelled,
+            })
+            .map_err(|_| WriterFailed)?;
+    }
+
+    // include anything exported by a previous run
+    if let Some(progress) = resume {
+        lca_summary.merge(&progress.summary);
+    }
+
+    if db_invalid.hashes > 0 {
+        eprintln!(
+            "Warning: {} hashes in {db_basename} refer to {} dataset IDs not in its manifest; \
+             they were skipped (see the run statistics)",
+            db_invalid.hashes,
+            db_invalid.ids.len()
+        );
+        totals
+            .invalid_datasets
+            .lock()
+            .unwrap()
+            .insert(db_basename.clone(), db_invalid);
+    }
+    if let Some(tally) = tally {
+        totals.dataset_tallies.lock().unwrap().push(tally);
+    }
+    if let Some(lineages) = lineages {
+        totals.lineage_counts.lock().unwrap().push(lineages);
+    }
+    if let Some(pairs) = pairs {
+        totals.pair_counts.lock().unwrap().push(pairs);
+    }
+    if let Some(top_shared) = top_shared {
+        totals.top_shared.lock().unwrap().push(top_shared);
+    }
+    if let Some(sketches) = sketches {
+        let mut all = totals.lineage_sketches.lock().unwrap();
+        match all.as_mut() {
+            Some(all) => all.merge(sketches)?,
+            None => *all = Some(sketches),
+        }
+    }
+
+    // clean up secondary instance files we created ourselves
+    drop(secondary_db);
+    if let (Some(path), None) = (secondary_path, &options.secondary_path) {
+        let _ = std::fs::remove_dir_all(path);
     }
     Ok(lca_summary)
 }
 
-// main function
+/// Compute and write only the LCA summaries, skipping all Arrow conversion and Parquet output.
+fn summarize_lca_only(
+    db_paths: &[Utf8PathBuf],
+    tax_map: Option<&HashMap<String, String>>,
+    lca_info_path: Option<&Utf8Path>,
+    options: &ExportOptions,
+    cancel_flag: Arc<AtomicBool>,
+) -> Result<Vec<(String, LCASummary)>> {
+    if tax_map.is_none() {
+        eprintln!("Warning: no taxonomy provided; all hashes will be unclassified.");
+    }
+
+    let reporter = Reporter::new(
+        options.progress_json.as_deref(),
+        options.progress_callback.clone(),
+    )?;
+    let memory = MemoryMonitor::new(options.max_memory);
+    let totals = ScanTotals::default();
+    let summaries = db_paths
+        .par_iter()
+        .map(|db_path| -> Result<(String, LCASummary)> {
+            let summary = process_revindex(
+                db_path,
+                None,
+                tax_map,
+                options,
+                None,
+                &reporter,
+                &memory,
+                &totals,
+                cancel_flag.clone(),
+            )?;
+            Ok((db_path.file_name().unwrap().to_string(), summary))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    write_lca_info(lca_info_path, &summaries)?;
+    totals.write_tables(options)?;
+
+    if cancel_flag.load(Ordering::SeqCst) {
+        anyhow::bail!("LCA summary was interrupted; summaries cover only the hashes processed");
+    }
+    Ok(summaries)
+}
+
+/// Write the `--bloom-filter` of a finished temporary output (a file or a
+/// sharded directory) to `<filter>.tmp`, which `tmp_outputs` removes unless
+/// `publish_bloom_filter` renames it.
+fn bloom_filter_tmp(
+    tmp_path: &Utf8Path,
+    options: &ExportOptions,
+    stats: &mut RunStats,
+    tmp_outputs: &mut TmpOutputs,
+) -> Result<Option<Utf8PathBuf>> {
+    let Some((ref path, fpp)) = options.bloom_filter else {
+        return Ok(None);
+    };
+    let bloom_timer = StageTimer::start("bloom_filter");
+    let parquet_paths = if tmp_path.is_dir() {
+        ShardedExport::load(tmp_path)?.paths(tmp_path)
+    } else {
+        vec![tmp_path.to_path_buf()]
+    };
+    let bloom_tmp = Utf8PathBuf::from(format!("{path}.tmp"));
+    tmp_outputs.paths.push(bloom_tmp.clone());
+    write_bloom_filter(&parquet_paths, &bloom_tmp, fpp)?;
+    stats.stages.push(bloom_timer.finish());
+    Ok(Some(bloom_tmp))
+}
+
+// give the bloom filter written by `bloom_filter_tmp` its final name
+fn publish_bloom_filter(bloom_tmp: Option<Utf8PathBuf>, options: &ExportOptions) -> Result<()> {
+    if let (Some(tmp), Some((path, _))) = (bloom_tmp, &options.bloom_filter) {
+        std::fs::rename(tmp, path)?;
+    }
+    Ok(())
+}
+
+/// Rename a finished temporary output to its final path. Whatever is there
+/// (only with `--force`) is removed first, since a rename can't replace a
+/// directory or put a directory in place of a file.
+fn replace_output(tmp_path: &Utf8Path, out_path: &Utf8Path) -> Result<()> {
+    if out_path.is_dir() {
+        std::fs::remove_dir_all(out_path)?;
+    } else if out_path.exists() && tmp_path.is_dir() {
+        std::fs::remove_file(out_path)?;
+    }
+    std::fs::rename(tmp_path, out_path)?;
+    Ok(())
+}
+
+/// Export RevIndex databases to a single Parquet file (optionally annotated with
+/// taxonomy, with LCA summaries written to `lca_info_path`), returning statistics
+/// about the finished run.
+///
+/// # Errors
+///
+/// Fails before reading anything if `options` don't pass
+/// `ExportOptions::validate`, or need a taxonomy and `tax_paths` is empty.
 pub fn export_revindex_to_parquet(
     db_paths: Vec<Utf8PathBuf>,
     out_path: Utf8PathBuf,
     tax_paths: Vec<Utf8PathBuf>,
     lca_info_path: Option<Utf8PathBuf>,
-    rw: bool,
-) -> Result<()> {
-    // set up ctrl-c signal handler
-    let cancel_flag = Arc::new(AtomicBool::new(false));
-    setup_ctrlc_handler(cancel_flag.clone())?;
+    options: ExportOptions,
+) -> Result<RunStats> {
+    let run_timer = Instant::now();
+    options.validate()?;
+    options.check_taxonomy(!tax_paths.is_empty())?;
+
+    // set up ctrl-c signal handler, unless the caller brought its own cancellation flag
+    let cancel_flag = match options.cancel_flag.clone() {
+        Some(flag) => flag,
+        None => {
+            let flag = Arc::new(AtomicBool::new(false));
+            setup_ctrlc_handler(flag.clone())?;
+            flag
+        }
+    };
 
     // load taxonomy if we have it
-    let mut full_tax_map = HashMap::new();
+    let tax_map = load_taxonomies(tax_paths)?;
 
-    for path in tax_paths {
-        let map = load_taxonomy_map(path)?;
-        full_tax_map.extend(map);
+    if options.dry_run {
+        dry_run(&db_paths, tax_map.as_ref(), &options)?;
+        return Ok(RunStats {
+            duration_secs: run_timer.elapsed().as_secs_f64(),
+            ..Default::default()
+        });
     }
 
-    let tax_map = if full_tax_map.is_empty() {
-        None
-    } else {
-        Some(full_tax_map)
-    };
+    if options.lca_summary_only {
+        for db_path in &db_paths {
+            check_db_compatibility(db_path)?;
+        }
+        let summaries = summarize_lca_only(
+            &db_paths,
+            tax_map.as_ref(),
+            lca_info_path.as_deref(),
+            &options,
+            cancel_flag,
+        )?;
+        return Ok(RunStats {
+            sources: summaries.into_iter().collect(),
+            duration_secs: run_timer.elapsed().as_secs_f64(),
+            ..Default::default()
+        });
+    }
+
+    // refuse to clobber existing output
+    if out_path.exists() && !options.force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("output '{out_path}' already exists; use --force to overwrite it"),
+        )
+        .into());
+    }
+    // write to a temp file and rename on success, so a failed run never
+    // leaves a truncated file with the final name
+    let tmp_path = Utf8PathBuf::from(format!("{out_path}.tmp"));
+
+    // check all databases before we start writing anything
+    for db_path in &db_paths {
+        check_db_compatibility(db_path)?;
+    }
+    // databases with different ksizes are written to one partition per
+    // ksize, rather than mixed in one file
+    let mut sharded_output = options.sharded_output;
+    if db_paths.len() > 1 && !options.allow_mixed_params {
+        check_params_consistency(&db_paths, options.ksize)?;
+    } else if db_paths.len() > 1 && sharded_output.is_none() && !options.merge {
+        let ksizes: BTreeSet<u32> = database_params(&db_paths, options.ksize)?
+            .into_iter()
+            .map(|params| params.ksize)
+            .collect();
+        if ksizes.len() > 1 {
+            if options.checkpoint.is_some() || options.unclassified_output.is_some() {
+                anyhow::bail!(
+                    "the databases have different ksizes ({}), which are written to one \
+                     partition per ksize; that can't be combined with --checkpoint or \
+                     --unclassified-output",
+                    join_values(&ksizes)
+                );
+            }
+            eprintln!(
+                "The databases have different ksizes ({}); writing one partition per ksize to {out_path}/ksize=<k>/",
+                join_values(&ksizes)
+            );
+            sharded_output = Some(Partitioning::Ksize);
+        }
+    }
+    let unclassified_path = options.unclassified_output.as_deref();
+    if let Some(path) = unclassified_path {
+        if path == out_path {
+            anyhow::bail!("--unclassified-output must differ from the output file");
+        }
+        if path.exists() && !options.force {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("output '{path}' already exists; use --force to overwrite it"),
+            )
+            .into());
+        }
+    }
+    let unclassified_tmp = unclassified_path.map(|path| Utf8PathBuf::from(format!("{path}.tmp")));
+
+    // load or start a checkpoint, if requested
+    let checkpoint = options
+        .checkpoint
+        .as_ref()
+        .map(|path| -> Result<CheckpointConfig> {
+            Ok(CheckpointConfig {
+                path: path.clone(),
+                state: Checkpoint::load_or_new(path, &out_path)?,
+                rows_per_part: options.checkpoint_rows,
+            })
+        })
+        .transpose()?;
+    let resume_state = checkpoint.as_ref().map(|c| c.state.clone());
+
+    let mut stats = RunStats::default();
+    let totals = ScanTotals::default();
 
     // start arrow writer thread
-    let (sender, handle) = start_arrow_writer_thread(out_path, 100_000)?;
+    let export_timer = StageTimer::start("export");
+    let memory = Arc::new(MemoryMonitor::new(options.max_memory));
+    let settings = options.writer_settings()?;
+    let mut tmp_outputs = TmpOutputs {
+        paths: std::iter::once(tmp_path.clone())
+            .chain(unclassified_tmp.clone())
+            .collect(),
+    };
+    let (sender, handle) = match sharded_output {
+        Some(partitioning) => start_sharded_writer_thread(
+            tmp_path.clone(),
+            partitioning,
+            settings.clone(),
+            cancel_flag.clone(),
+            options.on_interrupt,
+            memory.clone(),
+        )?,
+        None => start_arrow_writer_thread(
+            tmp_path.clone(),
+            unclassified_tmp.clone(),
+            settings.clone(),
+            checkpoint,
+            cancel_flag.clone(),
+            options.on_interrupt,
+            memory.clone(),
+        )?,
+    };
+
+    // one progress bar per database, or JSON progress events
+    let reporter = Reporter::new(
+        options.progress_json.as_deref(),
+        options.progress_callback.clone(),
+    )?;
 
     // init LCA summary
     let all_summaries = Arc::new(Mutex::new(Vec::new()));
 
-    // parallelize across all input revindex files
-    db_paths
-        .par_iter()
-        .try_for_each::<_, Result<()>>(|db_path| {
-            let lca_summary =
-                process_revindex(db_path, &sender, tax_map.as_ref(), rw, cancel_flag.clone())?;
-            {
-                let mut all = all_summaries.lock().unwrap();
-                all.push((db_path, lca_summary));
-            }
-            Ok(())
-        })?;
+    // when merging, databases send their records to a merge thread instead
+    let merging = options.merge.then(|| start_merge_thread(memory.clone()));
+    let db_sender = merging
+        .as_ref()
+        .map_or(&sender, |(merge_sender, _)| merge_sender);
+
+    // parallelize across all input revindex files, unless rows must come out
+    // in a fixed order
+    let export_db = |db_path: &Utf8PathBuf| -> Result<()> {
+        let resume = resume_state
+            .as_ref()
+            .and_then(|c| c.progress(db_path.file_name().unwrap_or(db_path.as_str())));
+        let lca_summary = process_revindex(
+            db_path,
+            Some(db_sender),
+            tax_map.as_ref(),
+            &options,
+            resume,
+            &reporter,
+            &memory,
+            &totals,
+            cancel_flag.clone(),
+        )?;
+        {
+            let mut all = all_summaries.lock().unwrap();
+            all.push((db_path, lca_summary));
+        }
+        Ok(())
+    };
+    let scanned = if options.deterministic {
+        db_paths.iter().try_for_each(export_db)
+    } else {
+        db_paths.par_iter().try_for_each(export_db)
+    };
+    if let Err(e) = scanned {
+        // stop the consumers, so nothing writes to the temporary outputs
+        // while they are removed
+        let merge_error = merging.and_then(|(merge_sender, merge_handle)| {
+            drop(merge_sender);
+            join_merge_thread(merge_handle).err()
+        });
+        drop(sender);
+        let writer_error = handle.join().unwrap().err();
+        if !e.is::<WriterFailed>() {
+            return Err(e);
+        }
+        // the producers stopped because a consumer failed: report its error
+        return Err(merge_error.or(writer_error).unwrap_or(e));
+    }
+
+    let merged_summary = match merging {
+        Some((merge_sender, merge_handle)) => {
+            drop(merge_sender);
+            let merger = join_merge_thread(merge_handle)?;
+            if cancel_flag.load(Ordering::SeqCst) {
+                None
+            } else {
+                Some(merger.send(&sender, tax_map.as_ref(), &options, &memory)?)
+            }
+        }
+        None => None,
+    };
 
     drop(sender); // Close the channel
-    handle.join().unwrap()?; // Wait for writer to finish
+    let writer_output = handle.join().unwrap()?; // Wait for writer to finish
+    let interrupted = cancel_flag.load(Ordering::SeqCst);
+    if interrupted && options.on_interrupt == InterruptPolicy::Keep {
+        tmp_outputs.keep();
+    }
+    stats.stages.push(export_timer.finish());
+    stats.rows_written = writer_output.rows_written;
+    stats.unclassified_rows = writer_output.unclassified_rows;
+    stats.skipped_keys = totals.skipped_keys.load(Ordering::Relaxed);
+    stats.invalid_datasets = std::mem::take(&mut *totals.invalid_datasets.lock().unwrap());
+
+    if let Some(path) = options.checkpoint.as_ref() {
+        // combine part files once every source has been fully exported
+        if let Some(state) = writer_output.checkpoint {
+            let sources = db_paths.iter().map(|p| p.file_name().unwrap_or(p.as_str()));
+            if state.is_complete(sources) {
+                let parts: Vec<Utf8PathBuf> = state.parts.iter().map(Utf8PathBuf::from).collect();
+                reporter.event("combine", None, 0, Some(parts.len() as u64), Instant::now());
+                let combine_timer = StageTimer::start("combine");
+                concat_parquet_files(&parts, &tmp_path, &settings)?;
+                stats.stages.push(combine_timer.finish());
+                let bloom_tmp =
+                    bloom_filter_tmp(&tmp_path, &options, &mut stats, &mut tmp_outputs)?;
+                replace_output(&tmp_path, &out_path)?;
+                publish_bloom_filter(bloom_tmp, &options)?;
+                for part in &parts {
+                    std::fs::remove_file(part)?;
+                }
+                std::fs::remove_file(path)?;
+                eprintln!("Combined {} part file(s) into {out_path}", parts.len());
+            } else {
+                eprintln!("Export incomplete; rerun with checkpoint '{path}' to resume.");
+            }
+        }
+    } else if !interrupted || options.on_interrupt == InterruptPolicy::Finalize {
+        // only a finished (or deliberately finalized) file gets the final name,
+        // and its bloom filter is written first, so they are published together
+        let bloom_tmp = bloom_filter_tmp(&tmp_path, &options, &mut stats, &mut tmp_outputs)?;
+        replace_output(&tmp_path, &out_path)?;
+        publish_bloom_filter(bloom_tmp, &options)?;
+        if interrupted {
+            eprintln!(
+                "Finished writing partial Parquet to {out_path} ({} hashes)",
+                writer_output.rows_written
+            );
+        } else {
+            eprintln!("Finished writing Parquet to {out_path}");
+        }
+        if let (Some(tmp), Some(path)) = (&unclassified_tmp, unclassified_path) {
+            std::fs::rename(tmp, path)?;
+            eprintln!(
+                "Wrote {} unclassified hashes to {path}",
+                writer_output.unclassified_rows
+            );
+        }
+    }
+    // everything is renamed (or removed by the writer) by now
+    tmp_outputs.keep();
 
     // write LCA summaries to CSV
     let all_summaries_guard = all_summaries.lock().unwrap();
-    let summaries: Vec<(String, LCASummary)> = all_summaries_guard
+    let mut summaries: Vec<(String, LCASummary)> = all_summaries_guard
         .iter()
         .map(|(p, s)| (p.file_name().unwrap().to_string(), s.clone()))
         .collect();
+    if let Some(summary) = merged_summary {
+        summaries.push(("merged".to_string(), summary));
+    }
 
-    write_lca_info(lca_info_path.as_deref(), &summaries)?;
+    if !interrupted || options.on_interrupt == InterruptPolicy::Finalize {
+        let lca_timer = StageTimer::start("lca_summary");
+        write_lca_info(lca_info_path.as_deref(), &summaries)?;
+        totals.write_tables(&options)?;
+        stats.stages.push(lca_timer.finish());
+    }
+    // report on the final output, if there is one
+    if out_path.is_dir() {
+        let sharded = ShardedExport::load(&out_path)?;
+        stats.read_parquet_files(&sharded.paths(&out_path))?;
+        stats.output = Some(out_path.to_string());
+    } else if out_path.exists() {
+        stats.read_parquet(&out_path)?;
+        stats.output = Some(out_path.to_string());
+    }
+    stats.sources = summaries.into_iter().collect();
+    stats.duration_secs = run_timer.elapsed().as_secs_f64();
+    eprint!("{stats}");
+    if let Some(path) = options.stats_json.as_deref() {
+        stats.write_json(path)?;
+    }
 
-    Ok(())
+    let stage = if interrupted { "interrupted" } else { "done" };
+    reporter.event(
+        stage,
+        None,
+        writer_output.rows_written,
+        None,
+        Instant::now(),
+    );
+
+    if interrupted {
+        return Err(ExportInterrupted {
+            rows_written: writer_output.rows_written,
+            policy: options.on_interrupt,
+        }
+        .into());
+    }
+
+    Ok(stats)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_compression() {
+        assert!(matches!(
+            parse_compression("zstd").unwrap(),
+            CompressionOptions::Zstd(None)
+        ));
+        assert!(matches!(
+            parse_compression("ZSTD:3").unwrap(),
+            CompressionOptions::Zstd(Some(_))
+        ));
+        assert!(matches!(
+            parse_compression("snappy").unwrap(),
+            CompressionOptions::Snappy
+        ));
+        assert!(parse_compression("snappy:3").is_err());
+        assert!(parse_compression("zstd:fast").is_err());
+        assert!(parse_compression("bzip2").is_err());
+    }
+
+    #[test]
+    fn test_writer_settings_columns() {
+        let options = ExportOptions {
+            columns: Some(vec!["source".into(), "hash".into()]),
+            ..Default::default()
+        };
+        let settings = options.writer_settings().unwrap();
+        let names: Vec<_> = settings
+            .schema()
+            .fields
+            .iter()
+            .map(|f| f.name.clone())
+            .collect();
+        // columns keep schema order
+        assert_eq!(names, vec!["hash", "source"]);
+
+        let options = ExportOptions {
+            columns: Some(vec!["nope".into()]),
+            ..Default::default()
+        };
+        assert!(options.writer_settings().is_err());
+    }
+
+    #[test]
+    fn test_lca_summary_cumulative() {
+        let mut summary = LCASummary::new(31, 1000);
+        let tax = vec!["d__Bacteria".to_string()];
+        for rank in ["species", "species", "genus", "phylum"] {
+            summary.add_rank(Some(&tax), Some(rank));
+        }
+        summary.add_rank(None, None);
+        assert_eq!(summary.classified_count(), 4);
+        assert_eq!(summary.cumulative_count("species"), 2);
+        assert_eq!(summary.cumulative_count("family"), 3);
+        assert_eq!(summary.cumulative_count("domain"), 4);
+
+        let rows = summary.to_csv_rows("db");
+        let ranks: Vec<_> = rows.iter().map(|row| row.lca_rank.as_str()).collect();
+        assert_eq!(ranks, ["phylum", "genus", "species", "unclassified"]);
+        assert_eq!(rows[1].percent_classified, Some(25.0));
+        assert_eq!(rows[1].cumulative_count, Some(3));
+        assert_eq!(rows[3].percent_classified, None);
+    }
+
+    #[test]
+    fn test_in_shard() {
+        let options = ExportOptions {
+            shard: Some((1, 3)),
+            ..Default::default()
+        };
+        assert!(options.in_shard(4));
+        assert!(!options.in_shard(5));
+        assert!(ExportOptions::default().in_shard(5));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let all = ExportOptions {
+            sampling: Some(Sampling::Fraction(1.0)),
+            ..Default::default()
+        };
+        let none = ExportOptions {
+            sampling: Some(Sampling::Fraction(0.0)),
+            ..Default::default()
+        };
+        let nth = ExportOptions {
+            sampling: Some(Sampling::EveryNth(10)),
+            ..Default::default()
+        };
+        assert!((0..1000).all(|h| all.in_sample(h, h)));
+        assert!((0..1000).all(|h| !none.in_sample(h, h)));
+        assert_eq!((0..1000).filter(|&h| nth.in_sample(h, h)).count(), 100);
+        assert!(ExportOptions::default().in_sample(5, 5));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ExportOptions::default().validate().is_ok());
+        let invalid = [
+            ExportOptions {
+                shard: Some((0, 0)),
+                ..Default::default()
+            },
+            ExportOptions {
+                shard: Some((3, 3)),
+                ..Default::default()
+            },
+            ExportOptions {
+                sampling: Some(Sampling::EveryNth(0)),
+                ..Default::default()
+            },
+            ExportOptions {
+                min_datasets: Some(5),
+                max_datasets: Some(2),
+                ..Default::default()
+            },
+            ExportOptions {
+                min_lca_rank: Some("kingdom".to_string()),
+                ..Default::default()
+            },
+        ];
+        for options in invalid {
+            assert!(options.validate().is_err(), "{options:?}");
+        }
+
+        let ranked = ExportOptions {
+            min_lca_rank: Some("genus".to_string()),
+            ..Default::default()
+        };
+        assert!(ranked.validate().is_ok());
+        assert!(ranked.check_taxonomy(true).is_ok());
+        let err = ranked.check_taxonomy(false).unwrap_err();
+        assert!(err.to_string().contains("needs a taxonomy"));
+    }
+
     #[test]
     fn test_identical_lineages() {
         let input = vec![
@@ -780,4 +3466,114 @@ mod tests {
         assert_eq!(lca, "");
         assert_eq!(rank, None);
     }
+
+    #[test]
+    fn test_disagreement_metrics() {
+        let input = vec![
+            "d__Bacteria;p__Firmicutes;c__Bacilli;o__Lactobacillales;f__Lactobacillaceae;g__Lactobacillus;s__Lactobacillus acidophilus".to_string(),
+            "d__Bacteria;p__Firmicutes;c__Bacilli;o__Lactobacillales;f__Lactobacillaceae;g__Lactobacillus;s__Lactobacillus gasseri".to_string(),
+            "d__Bacteria;p__Firmicutes;c__Bacilli;o__Lactobacillales;f__Lactobacillaceae;g__Lactobacillus".to_string(),
+            "d__Bacteria;p__Firmicutes;c__Bacilli;o__Lactobacillales;f__Lactobacillaceae;g__Lactobacillus".to_string(),
+        ];
+        assert_eq!(distinct_at_rank(&input, 5), 1);
+        assert_eq!(distinct_at_rank(&input, 6), 2);
+        // two singletons and a pair: 2 * 1/4 * 2 + 1/2 * 1 bits
+        assert!((lineage_entropy(&input) - 1.5).abs() < 1e-12);
+        assert_eq!(lineage_entropy(&input[2..]), 0.0);
+    }
+
+    #[test]
+    fn test_blank_intermediate_rank() {
+        let ranks = |names: [&str; 7]| -> RankedLineage {
+            names.map(|name| (!name.is_empty()).then(|| name.to_string()))
+        };
+        // no family for the first lineage, and no species for either
+        let input = vec![
+            join_ranks(ranks(["d__B", "p__F", "c__B", "o__L", "", "g__A", ""])),
+            join_ranks(ranks(["d__B", "p__F", "c__B", "o__L", "f__L", "g__L", ""])),
+        ];
+        assert_eq!(input[0], "d__B;p__F;c__B;o__L;;g__A");
+
+        // the genus is still at the genus position, and there are no species
+        assert_eq!(distinct_at_rank(&input, 5), 2);
+        assert_eq!(distinct_at_rank(&input, 6), 0);
+        assert_eq!(distinct_at_rank(&input[..1], 5), 1);
+        assert_eq!(distinct_at_rank(&input[..1], 4), 0);
+
+        let (lca, rank) = compute_lca_strs(&input);
+        assert_eq!(lca, "d__B;p__F;c__B;o__L");
+        assert_eq!(rank, Some("order"));
+        // a rank blank in every lineage doesn't end the LCA
+        let (lca, rank) = compute_lca_strs(&input[..1]);
+        assert_eq!(lca, input[0]);
+        assert_eq!(rank, Some("genus"));
+    }
+
+    #[test]
+    fn test_dataset_resolver_colors() {
+        let path = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("resolver-colors-{}.rocksdb", std::process::id()));
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts, &path, [HASHES_CF, COLORS_CF]).unwrap();
+        {
+            let cf = db.cf_handle(COLORS_CF).unwrap();
+            for (color, idxs) in [(7u64, vec![0, 2, 5]), (1, vec![3])] {
+                let datasets = Datasets::new(&idxs).as_bytes().unwrap();
+                db.put_cf(&cf, color.to_le_bytes(), datasets).unwrap();
+            }
+            db.put_cf(&cf, 9u64.to_le_bytes(), b"not datasets").unwrap();
+        }
+
+        let mut resolver = DatasetResolver::new(&db).unwrap();
+        let resolve = |resolver: &mut DatasetResolver, color: u64| {
+            resolver
+                .resolve(&color.to_le_bytes())
+                .map(|datasets| datasets.unwrap().into_iter().collect::<Vec<u32>>())
+        };
+        assert_eq!(resolve(&mut resolver, 7).unwrap(), vec![0, 2, 5]);
+        assert_eq!(resolve(&mut resolver, 1).unwrap(), vec![3]);
+        // cached
+        assert_eq!(resolve(&mut resolver, 7).unwrap(), vec![0, 2, 5]);
+
+        // missing colors, malformed IDs and unparsable dataset lists are errors
+        let missing = resolve(&mut resolver, 2).unwrap_err();
+        assert!(missing.to_string().contains("color 2 is not in"));
+        assert!(resolver.resolve(&[1, 2, 3]).is_err());
+        assert!(resolve(&mut resolver, 9).is_err());
+        drop(resolver);
+        drop(db);
+
+        // a database opened without its colors CF can't be read as plain
+        let db = DB::open_cf_for_read_only(&opts, &path, [HASHES_CF], false).unwrap();
+        let err = DatasetResolver::new(&db).err().unwrap();
+        assert!(err.to_string().contains("opened without it"));
+        drop(db);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dataset_resolver_plain() {
+        let path = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("resolver-plain-{}.rocksdb", std::process::id()));
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts, &path, [HASHES_CF]).unwrap();
+
+        let mut resolver = DatasetResolver::new(&db).unwrap();
+        let value = Datasets::new(&[4, 1]).as_bytes().unwrap();
+        let datasets: Vec<u32> = resolver
+            .resolve(&value)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(datasets, vec![1, 4]);
+        drop(db);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
 }
@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Result};
+use arrow2::array::*;
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::sketch::minhash::max_hash_for_scaled;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::query::{query_parquet, HashQuery};
+
+/// One dataset picked by `parquet_gather`, in the order it was picked.
+#[derive(Debug, Clone, Serialize)]
+pub struct GatherMatch {
+    pub rank: usize,
+    pub name: String,
+    /// sources (revindex basenames) the dataset was exported from, `;`-separated
+    pub source: String,
+    /// query hashes in this dataset
+    pub shared_hashes: usize,
+    /// query hashes assigned to this dataset and no earlier match
+    pub unique_hashes: usize,
+    /// fraction of the query contained in this dataset
+    pub containment: f64,
+    pub f_unique_to_query: f64,
+}
+
+/// Downcast column `name` of every chunk in `query`.
+fn columns<'a, T: 'static>(query: &'a HashQuery, name: &str) -> Result<Vec<&'a T>> {
+    let idx = query
+        .schema
+        .fields
+        .iter()
+        .position(|field| field.name == name)
+        .ok_or_else(|| anyhow!("Parquet file has no '{name}' column"))?;
+    query
+        .chunks
+        .iter()
+        .map(|chunk| {
+            chunk.arrays()[idx]
+                .as_any()
+                .downcast_ref::<T>()
+                .ok_or_else(|| anyhow!("unexpected type for column '{name}'"))
+        })
+        .collect()
+}
+
+/// A matching row of an export, as (hash, scaled, source, dataset names).
+pub(crate) type MatchRow<'a> = (u64, u32, &'a str, Vec<&'a str>);
+
+/// The rows of `query` at `ksize`.
+pub(crate) fn rows_at_ksize(query: &HashQuery, ksize: u32) -> Result<Vec<MatchRow<'_>>> {
+    let hashes = columns::<UInt64Array>(query, "hash")?;
+    let names = columns::<ListArray<i32>>(query, "dataset_names")?;
+    let ksizes = columns::<UInt32Array>(query, "ksize")?;
+    let scaleds = columns::<UInt32Array>(query, "scaled")?;
+    let sources = columns::<Utf8Array<i32>>(query, "source")?;
+
+    let mut rows = Vec::new();
+    for (i, chunk_hashes) in hashes.iter().enumerate() {
+        let dataset_names = names[i]
+            .values()
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(|| anyhow!("unexpected type for column 'dataset_names'"))?;
+        for row in 0..chunk_hashes.len() {
+            if ksizes[i].value(row) != ksize {
+                continue;
+            }
+            let (start, end) = names[i].offsets().start_end(row);
+            rows.push((
+                chunk_hashes.value(row),
+                scaleds[i].value(row),
+                sources[i].value(row),
+                (start..end)
+                    .map(|j| dataset_names.value(j))
+                    .collect::<Vec<_>>(),
+            ));
+        }
+    }
+    Ok(rows)
+}
+
+/// Gather-style search of a query sketch against an exported Parquet file,
+/// without the RevIndex: find the datasets sharing hashes with the query, then
+/// repeatedly pick the dataset covering the most not-yet-assigned query hashes.
+/// Query hashes are downsampled to the coarsest scaled among the matching rows;
+/// matches below `threshold_bp` (at that scaled) are not reported.
+pub fn parquet_gather(
+    parquet_path: &Utf8Path,
+    query_hashes: &[u64],
+    ksize: u32,
+    scaled: u32,
+    threshold_bp: u64,
+) -> Result<Vec<GatherMatch>> {
+    let query = query_parquet(parquet_path, query_hashes)?;
+    let rows = rows_at_ksize(&query, ksize)?;
+
+    let scaled = rows.iter().map(|r| r.1).max().unwrap_or(0).max(scaled);
+    let max_hash = max_hash_for_scaled(scaled as u64);
+    let query_set: HashSet<u64> = query_hashes
+        .iter()
+        .copied()
+        .filter(|&h| h <= max_hash)
+        .collect();
+    if query_set.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut datasets: BTreeMap<&str, (BTreeSet<&str>, HashSet<u64>)> = BTreeMap::new();
+    for (hash, _, source, names) in &rows {
+        if *hash > max_hash {
+            continue;
+        }
+        for &name in names {
+            let entry = datasets.entry(name).or_default();
+            entry.0.insert(*source);
+            entry.1.insert(*hash);
+        }
+    }
+
+    let threshold = threshold_bp.div_ceil(scaled.max(1) as u64) as usize;
+    let query_size = query_set.len() as f64;
+    let mut remaining = query_set;
+    let mut matches = Vec::new();
+    loop {
+        // ties go to the first dataset by name, so results are reproducible
+        let best = datasets
+            .iter()
+            .map(|(name, (_, hashes))| (hashes.intersection(&remaining).count(), *name))
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(a.1)));
+        let Some((unique, name)) = best else {
+            break;
+        };
+        if unique == 0 || unique < threshold {
+            break;
+        }
+
+        let (sources, hashes) = datasets.remove(name).expect("picked from datasets");
+        remaining.retain(|h| !hashes.contains(h));
+        matches.push(GatherMatch {
+            rank: matches.len(),
+            name: name.to_string(),
+            source: sources.into_iter().collect::<Vec<_>>().join(";"),
+            shared_hashes: hashes.len(),
+            unique_hashes: unique,
+            containment: hashes.len() as f64 / query_size,
+            f_unique_to_query: unique as f64 / query_size,
+        });
+    }
+    Ok(matches)
+}
+
+/// Write gather results as CSV; the header is written even without matches.
+pub fn write_gather_csv(matches: &[GatherMatch], path: &Utf8Path) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_path(path)?;
+    writer.write_record([
+        "rank",
+        "name",
+        "source",
+        "shared_hashes",
+        "unique_hashes",
+        "containment",
+        "f_unique_to_query",
+    ])?;
+    for m in matches {
+        writer.serialize(m)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::Utf8Path;
+use std::collections::HashSet;
+use std::fs::File;
+
+/// Read the hashes in a Parquet file: its `hash` column, or its only column.
+fn load_parquet_hashes(path: &Utf8Path) -> Result<HashSet<u64>> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?;
+    let schema = match schema.fields.len() {
+        1 => schema,
+        _ => schema.filter(|_, field| field.name == "hash"),
+    };
+    if schema.fields.is_empty() {
+        anyhow::bail!("'{path}' has no 'hash' column and more than one column");
+    }
+
+    let mut hashes = HashSet::new();
+    for chunk in FileReader::new(
+        reader,
+        metadata.row_groups,
+        schema.clone(),
+        None,
+        None,
+        None,
+    ) {
+        let chunk = chunk?;
+        let values = chunk.arrays()[0]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(|| anyhow!("the hash column of '{path}' is not uint64"))?;
+        hashes.extend(values.iter().flatten().copied());
+    }
+    Ok(hashes)
+}
+
+/// Load the hashes to export from a `.parquet` file, or a text file with one
+/// hash per line (blank lines and lines starting with '#' are skipped).
+pub fn load_hash_list(path: &Utf8Path) -> Result<HashSet<u64>> {
+    let hashes = if path.extension() == Some("parquet") {
+        load_parquet_hashes(path)?
+    } else {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("cannot read '{path}'"))?;
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse()
+                    .map_err(|_| anyhow!("invalid hash '{line}' in '{path}'"))
+            })
+            .collect::<Result<_>>()?
+    };
+    if hashes.is_empty() {
+        anyhow::bail!("no hashes in '{path}'");
+    }
+    eprintln!("Loaded {} hashes from '{path}'", hashes.len());
+    Ok(hashes)
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use sourmash::collection::{Collection, CollectionSet};
+use sourmash::encodings::HashFunctions;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use sourmash::signature::Signature;
+use sourmash::sketch::minhash::KmerMinHash;
+use sourmash::sketch::Sketch;
+use std::collections::BTreeMap;
+use std::fs::File;
+
+// exports don't record the hash seed; sourmash sketches use 42 unless told otherwise
+const DEFAULT_SEED: u64 = 42;
+
+/// Summary of a RevIndex rebuilt from Parquet.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub ksize: u32,
+    pub scaled: u32,
+    pub n_datasets: usize,
+    /// distinct (dataset, hash) pairs written
+    pub n_hashes: u64,
+}
+
+/// A signature holding one scaled sketch, named `name`.
+pub(crate) fn build_signature(
+    name: &str,
+    ksize: u32,
+    scaled: u32,
+    moltype: &str,
+    hashes: &[u64],
+) -> Result<Signature> {
+    let hash_function = HashFunctions::try_from(moltype)
+        .map_err(|e| anyhow!("unknown moltype '{moltype}': {e}"))?;
+    // sourmash stores protein-family ksizes in nucleotides
+    let mh_ksize = if hash_function.dna() {
+        ksize
+    } else {
+        ksize * 3
+    };
+    let mut mh = KmerMinHash::new(scaled, mh_ksize, hash_function, DEFAULT_SEED, false, 0);
+    mh.add_many(hashes)?;
+
+    let mut sig = Signature::default();
+    sig.set_name(name);
+    sig.push(Sketch::MinHash(mh));
+    Ok(sig)
+}
+
+/// Group the rows of exported Parquet files by dataset name; returns the hashes
+/// of each dataset and the (single) ksize and scaled of the rows.
+fn read_datasets(parquet_paths: &[Utf8PathBuf]) -> Result<(BTreeMap<String, Vec<u64>>, u32, u32)> {
+    let mut datasets: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    let mut params: Option<(u32, u32)> = None;
+
+    for path in parquet_paths {
+        let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+        let metadata = read_metadata(&mut reader)?;
+        let wanted = ["hash", "dataset_names", "ksize", "scaled"];
+        let schema =
+            infer_schema(&metadata)?.filter(|_, field| wanted.contains(&field.name.as_str()));
+        let column = |name: &str| {
+            schema
+                .fields
+                .iter()
+                .position(|field| field.name == name)
+                .ok_or_else(|| anyhow!("'{path}' has no '{name}' column"))
+        };
+        let (hash_idx, names_idx, ksize_idx, scaled_idx) = (
+            column("hash")?,
+            column("dataset_names")?,
+            column("ksize")?,
+            column("scaled")?,
+        );
+
+        for chunk in FileReader::new(
+            reader,
+            metadata.row_groups,
+            schema.clone(),
+            None,
+            None,
+            None,
+        ) {
+            let chunk = chunk?;
+            let arrays = chunk.arrays();
+            let downcast_err = || anyhow!("unexpected column types in '{path}'");
+            let hashes = arrays[hash_idx]
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .ok_or_else(downcast_err)?;
+            let names = arrays[names_idx]
+                .as_any()
+                .downcast_ref::<ListArray<i32>>()
+                .ok_or_else(downcast_err)?;
+            let name_values = names
+                .values()
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .ok_or_else(downcast_err)?;
+            let ksizes = arrays[ksize_idx]
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .ok_or_else(downcast_err)?;
+            let scaleds = arrays[scaled_idx]
+                .as_any()
+                .downcast_ref::<UInt32Array>()
+                .ok_or_else(downcast_err)?;
+
+            for row in 0..chunk.len() {
+                let row_params = (ksizes.value(row), scaleds.value(row));
+                match params {
+                    None => params = Some(row_params),
+                    Some(p) if p != row_params => anyhow::bail!(
+                        "a RevIndex holds a single ksize and scaled, but the input has rows \
+                         with ksize {}/scaled {} and ksize {}/scaled {}",
+                        p.0,
+                        p.1,
+                        row_params.0,
+                        row_params.1
+                    ),
+                    Some(_) => {}
+                }
+                let (start, end) = names.offsets().start_end(row);
+                for j in start..end {
+                    datasets
+                        .entry(name_values.value(j).to_string())
+                        .or_default()
+                        .push(hashes.value(row));
+                }
+            }
+        }
+    }
+
+    let (ksize, scaled) = params.ok_or_else(|| anyhow!("no rows in the input"))?;
+    Ok((datasets, ksize, scaled))
+}
+
+/// Rebuild a RevIndex from one or more exports: the rows are grouped back into
+/// one sketch per dataset name, and the sketches indexed into a new database at
+/// `output`, with the sketches stored inside it. Exports don't record moltype,
+/// seed, abundances or the original md5s/locations, so the rebuilt datasets are
+/// `moltype` sketches with seed 42, and datasets are identified by name only
+/// (datasets sharing a name are merged).
+pub fn parquet_to_revindex(
+    parquet_paths: &[Utf8PathBuf],
+    output: &Utf8Path,
+    moltype: &str,
+) -> Result<ImportSummary> {
+    if output.exists() {
+        anyhow::bail!("'{output}' already exists; refusing to overwrite it");
+    }
+
+    let (datasets, ksize, scaled) = read_datasets(parquet_paths)?;
+    let mut n_hashes = 0;
+    let sigs = datasets
+        .into_iter()
+        .map(|(name, mut hashes)| {
+            hashes.sort_unstable();
+            hashes.dedup();
+            n_hashes += hashes.len() as u64;
+            build_signature(&name, ksize, scaled, moltype, &hashes)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let n_datasets = sigs.len();
+
+    let collection: CollectionSet = Collection::from_sigs(sigs)?.try_into()?;
+    let mut revindex = RevIndex::create(output, collection, false)
+        .map_err(|e| anyhow!("cannot create RocksDB database at '{output}'. Error is: {e}"))?;
+    revindex.internalize_storage()?;
+
+    Ok(ImportSummary {
+        ksize,
+        scaled,
+        n_datasets,
+        n_hashes,
+    })
+}
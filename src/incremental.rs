@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::Utf8Path;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+
+/// The (hash, dataset names) rows of a previous export, for incremental
+/// exports (`--since`): a row is skipped when the previous export has a row
+/// for the same hash with the same dataset names. Sources are ignored, so a
+/// database can be renamed between releases. Dataset names are kept as a
+/// 64-bit fingerprint, so memory use is about 16 bytes per previous row.
+pub struct PreviousExport {
+    rows: HashSet<(u64, u64)>,
+}
+
+impl fmt::Debug for PreviousExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreviousExport")
+            .field("rows", &self.rows.len())
+            .finish()
+    }
+}
+
+/// Order-independent fingerprint of a set of dataset names.
+fn names_fingerprint<S: AsRef<str>>(names: &[S]) -> u64 {
+    let mut sorted: Vec<&str> = names.iter().map(AsRef::as_ref).collect();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut hasher = DefaultHasher::new();
+    sorted.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl PreviousExport {
+    /// Read the `hash` and `dataset_names` columns of an exported Parquet file.
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+        let metadata = read_metadata(&mut reader)?;
+        let schema = infer_schema(&metadata)?
+            .filter(|_, field| field.name == "hash" || field.name == "dataset_names");
+        let column = |name: &str| {
+            schema
+                .fields
+                .iter()
+                .position(|field| field.name == name)
+                .ok_or_else(|| anyhow!("'{path}' has no '{name}' column"))
+        };
+        let (hash_idx, names_idx) = (column("hash")?, column("dataset_names")?);
+
+        let mut rows = HashSet::new();
+        for chunk in FileReader::new(
+            reader,
+            metadata.row_groups,
+            schema.clone(),
+            None,
+            None,
+            None,
+        ) {
+            let chunk = chunk?;
+            let arrays = chunk.arrays();
+            let downcast_err = || anyhow!("unexpected column types in '{path}'");
+            let hashes = arrays[hash_idx]
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .ok_or_else(downcast_err)?;
+            let names = arrays[names_idx]
+                .as_any()
+                .downcast_ref::<ListArray<i32>>()
+                .ok_or_else(downcast_err)?;
+            let name_values = names
+                .values()
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .ok_or_else(downcast_err)?;
+
+            for row in 0..chunk.len() {
+                let (start, end) = names.offsets().start_end(row);
+                let row_names: Vec<&str> = (start..end).map(|j| name_values.value(j)).collect();
+                rows.insert((hashes.value(row), names_fingerprint(&row_names)));
+            }
+        }
+        Ok(Self { rows })
+    }
+
+    /// Rows in the previous export.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Did the previous export have this hash with exactly these datasets?
+    pub fn contains(&self, hash: u64, dataset_names: &[String]) -> bool {
+        self.rows
+            .contains(&(hash, names_fingerprint(dataset_names)))
+    }
+}
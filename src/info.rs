@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Result};
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+
+use crate::export::{check_db_compatibility, COLORS_CF, DB, HASHES_CF};
+
+/// Basic facts about a RevIndex database, for validating inputs before an export.
+#[derive(Debug, Clone, Serialize)]
+pub struct DbInfo {
+    pub ksize: u32,
+    pub scaled: u32,
+    pub moltype: String,
+    pub n_datasets: usize,
+    /// RocksDB's estimate of the number of hashes; not exact
+    pub estimated_hashes: u64,
+    /// RevIndex format version, if the database records one
+    pub format_version: Option<u8>,
+    /// color-based database, with a `colors` column family
+    pub has_colors: bool,
+}
+
+/// Read ksize, scaled, moltype, dataset count, estimated hash count and
+/// format version from a RevIndex database, without scanning it.
+pub fn db_info(db_path: &Utf8Path) -> Result<DbInfo> {
+    let format_version = check_db_compatibility(db_path)?;
+    let has_colors = DB::list_cf(&rocksdb::Options::default(), db_path)
+        .map_err(|e| anyhow!("cannot read column families of '{db_path}': {e}"))?
+        .iter()
+        .any(|cf| cf == COLORS_CF);
+
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+
+    let manifest = revindex.collection().manifest();
+    let record = manifest
+        .iter()
+        .next()
+        .ok_or_else(|| anyhow!("No records in manifest"))?;
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let estimated_hashes = db
+        .property_int_value_cf(&cf, "rocksdb.estimate-num-keys")?
+        .unwrap_or(0);
+
+    Ok(DbInfo {
+        ksize: record.ksize(),
+        scaled: *record.scaled(),
+        moltype: record.moltype().to_string(),
+        n_datasets: manifest.len(),
+        estimated_hashes,
+        format_version,
+        has_colors,
+    })
+}
+
+/// One dataset (signature) in a RevIndex collection.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetInfo {
+    pub name: String,
+    pub filename: String,
+    pub md5: String,
+}
+
+/// List the datasets in a RevIndex, in dataset-ID order.
+pub fn list_datasets(db_path: &Utf8Path) -> Result<Vec<DatasetInfo>> {
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+
+    Ok(revindex
+        .collection()
+        .manifest()
+        .iter()
+        .map(|record| DatasetInfo {
+            name: record.name().to_string(),
+            filename: record.filename().to_string(),
+            md5: record.md5().to_string(),
+        })
+        .collect())
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::datatypes::DataType;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::Utf8Path;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+
+/// Sizes of one row group.
+#[derive(Debug, Clone, Serialize)]
+pub struct RowGroupInfo {
+    pub num_rows: usize,
+    pub compressed_bytes: u64,
+    pub uncompressed_bytes: u64,
+}
+
+/// Sizes of one column, summed over all row groups.
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    /// dotted path of the leaf column, e.g. `dataset_names.list.item`
+    pub name: String,
+    pub compression: String,
+    pub compressed_bytes: u64,
+    pub uncompressed_bytes: u64,
+}
+
+/// Layout and contents of a Parquet file, for finding out where the bytes of
+/// an export went.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParquetInspection {
+    pub bytes_on_disk: u64,
+    pub num_rows: usize,
+    pub created_by: Option<String>,
+    /// (field name, type) for each top-level field
+    pub schema: Vec<(String, String)>,
+    pub row_groups: Vec<RowGroupInfo>,
+    pub columns: Vec<ColumnInfo>,
+    /// key-value metadata from the footer, e.g. `export_partial`
+    pub metadata: BTreeMap<String, Option<String>>,
+    /// rows per value of the `source` column; empty when there is none
+    pub source_rows: BTreeMap<String, u64>,
+}
+
+/// Short type name, e.g. `list<utf8>` rather than arrow2's Debug output.
+pub(crate) fn type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::List(field) | DataType::LargeList(field) => {
+            format!("list<{}>", type_name(field.data_type()))
+        }
+        other => format!("{other:?}").to_lowercase(),
+    }
+}
+
+/// Count rows per source; only the `source` column is read.
+pub(crate) fn count_source_rows(path: &Utf8Path) -> Result<BTreeMap<String, u64>> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?.filter(|_, field| field.name == "source");
+    let mut counts = BTreeMap::new();
+    if schema.fields.is_empty() {
+        return Ok(counts);
+    }
+
+    for chunk in FileReader::new(
+        reader,
+        metadata.row_groups,
+        schema.clone(),
+        None,
+        None,
+        None,
+    ) {
+        let chunk = chunk?;
+        let sources = chunk.arrays()[0]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(|| anyhow!("unexpected type for the 'source' column in '{path}'"))?;
+        for source in sources.iter().flatten() {
+            *counts.entry(source.to_string()).or_insert(0) += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Describe a Parquet file: schema, row count, row-group layout, per-column
+/// compressed sizes, footer metadata and per-source row counts. Everything but
+/// the source counts comes from the footer.
+pub fn parquet_inspect(path: &Utf8Path) -> Result<ParquetInspection> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?;
+
+    let row_groups = metadata
+        .row_groups
+        .iter()
+        .map(|rg| RowGroupInfo {
+            num_rows: rg.num_rows(),
+            compressed_bytes: rg.compressed_size() as u64,
+            uncompressed_bytes: rg.total_byte_size() as u64,
+        })
+        .collect();
+
+    let mut columns: Vec<ColumnInfo> = Vec::new();
+    for rg in &metadata.row_groups {
+        for column in rg.columns() {
+            let name = column.descriptor().path_in_schema.join(".");
+            let idx = match columns.iter().position(|c| c.name == name) {
+                Some(idx) => idx,
+                None => {
+                    columns.push(ColumnInfo {
+                        name,
+                        compression: format!("{:?}", column.compression()).to_lowercase(),
+                        compressed_bytes: 0,
+                        uncompressed_bytes: 0,
+                    });
+                    columns.len() - 1
+                }
+            };
+            let info = &mut columns[idx];
+            info.compressed_bytes += column.compressed_size() as u64;
+            info.uncompressed_bytes += column.uncompressed_size() as u64;
+        }
+    }
+
+    let footer = metadata
+        .key_value_metadata
+        .iter()
+        .flatten()
+        .map(|kv| (kv.key.clone(), kv.value.clone()))
+        .collect();
+
+    Ok(ParquetInspection {
+        bytes_on_disk: std::fs::metadata(path)?.len(),
+        num_rows: metadata.num_rows,
+        created_by: metadata.created_by.clone(),
+        schema: schema
+            .fields
+            .iter()
+            .map(|field| (field.name.clone(), type_name(field.data_type())))
+            .collect(),
+        row_groups,
+        columns,
+        metadata: footer,
+        source_rows: count_source_rows(path)?,
+    })
+}
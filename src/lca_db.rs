@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use camino::{Utf8Path, Utf8PathBuf};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::json;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::export::{
+    check_db_compatibility, load_ranked_taxonomies, select_ksize, strip_accession_version,
+    DatasetResolver, RankedLineage, SketchParams, HASHES_CF,
+};
+
+/// Ranks of a sourmash LCA database lineage, in order; the export's `domain`
+/// is sourmash's `superkingdom`.
+const LCA_DB_RANKS: [&str; 7] = [
+    "superkingdom",
+    "phylum",
+    "class",
+    "order",
+    "family",
+    "genus",
+    "species",
+];
+
+/// Counts from `revindex_to_lca_json`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LcaDbSummary {
+    pub ksize: u32,
+    pub scaled: u32,
+    pub datasets: usize,
+    /// datasets without a lineage in the taxonomy; their hashes are kept
+    pub unassigned: usize,
+    pub lineages: usize,
+    pub hashes: u64,
+}
+
+// the database file, gzipped or not
+enum LcaOutput {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+impl LcaOutput {
+    fn finish(self) -> io::Result<()> {
+        match self {
+            LcaOutput::Plain(_) => Ok(()),
+            LcaOutput::Gzip(encoder) => encoder.finish().map(|_| ()),
+        }
+    }
+}
+
+impl Write for LcaOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            LcaOutput::Plain(file) => file.write(buf),
+            LcaOutput::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            LcaOutput::Plain(file) => file.flush(),
+            LcaOutput::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// `(rank, name)` pairs of a lineage as `sourmash lca index` stores them: a
+// blank rank keeps its place with an empty name, and trailing blanks are dropped
+fn lineage_pairs(ranks: &RankedLineage) -> Vec<(&'static str, &str)> {
+    let depth = ranks.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+    LCA_DB_RANKS
+        .iter()
+        .copied()
+        .zip(
+            ranks[..depth]
+                .iter()
+                .map(|name| name.as_deref().unwrap_or("")),
+        )
+        .collect()
+}
+
+/// Write a RevIndex and its taxonomy as a sourmash LCA database (the JSON
+/// format of `sourmash lca index`, version 2.1), so tools that load LCA
+/// databases can use it. Datasets are identified by the first word of their
+/// name, as `sourmash lca index` does, and their lineages are looked up by
+/// that identifier without its version. Only the datasets with `ksize` (or
+/// the database's single ksize) are included. The JSON is gzipped if
+/// `output` ends in `.gz`, and the hashes are streamed to it in one scan.
+pub fn revindex_to_lca_json(
+    db_path: &Utf8Path,
+    tax_paths: Vec<Utf8PathBuf>,
+    output: &Utf8Path,
+    ksize: Option<u32>,
+) -> Result<LcaDbSummary> {
+    let taxonomy = load_ranked_taxonomies(tax_paths)?
+        .ok_or_else(|| anyhow!("an LCA database needs a taxonomy with at least one lineage"))?;
+
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+    let SketchParams {
+        ksize,
+        scaled,
+        moltype,
+    } = select_ksize(collection.manifest(), db_path, ksize)?;
+
+    // LCA index of each dataset ID, for the datasets with the selected ksize
+    let mut lca_idx: Vec<Option<u32>> = vec![None; collection.len()];
+    let mut ident_to_name = BTreeMap::new();
+    let mut ident_to_idx = BTreeMap::new();
+    let mut idx_to_lid = BTreeMap::new();
+    let mut lineage_to_lid: HashMap<&RankedLineage, u32> = HashMap::new();
+    let mut summary = LcaDbSummary {
+        ksize,
+        scaled,
+        ..Default::default()
+    };
+    for (idx, record) in collection.manifest().iter().enumerate() {
+        if record.ksize() != ksize {
+            continue;
+        }
+        let name = record.name().to_string();
+        let ident = name
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        if ident_to_idx.contains_key(&ident) {
+            anyhow::bail!("'{db_path}' has several datasets with the identifier '{ident}'");
+        }
+        let this_idx = summary.datasets as u32;
+        summary.datasets += 1;
+        lca_idx[idx] = Some(this_idx);
+        match taxonomy.get(strip_accession_version(&ident)) {
+            Some(lineage) => {
+                let next_lid = lineage_to_lid.len() as u32;
+                let lid = *lineage_to_lid.entry(lineage).or_insert(next_lid);
+                idx_to_lid.insert(this_idx.to_string(), lid);
+            }
+            None => summary.unassigned += 1,
+        }
+        ident_to_idx.insert(ident.clone(), this_idx);
+        ident_to_name.insert(ident, name);
+    }
+    summary.lineages = lineage_to_lid.len();
+    let lid_to_lineage: BTreeMap<String, Vec<(&str, &str)>> = lineage_to_lid
+        .iter()
+        .map(|(lineage, lid)| (lid.to_string(), lineage_pairs(lineage)))
+        .collect();
+
+    let file = File::create(output).with_context(|| format!("cannot create '{output}'"))?;
+    let file = if output.as_str().ends_with(".gz") {
+        LcaOutput::Gzip(GzEncoder::new(file, Compression::default()))
+    } else {
+        LcaOutput::Plain(file)
+    };
+    let mut out = BufWriter::new(file);
+    let header = json!({
+        "version": "2.1",
+        "type": "sourmash_lca",
+        "license": "CC0",
+        "ksize": ksize,
+        "scaled": scaled,
+        "moltype": moltype,
+        "lid_to_lineage": lid_to_lineage,
+        "ident_to_name": ident_to_name,
+        "ident_to_idx": ident_to_idx,
+        "idx_to_lid": idx_to_lid,
+    });
+    // the header object, left open for the hashes
+    let header = serde_json::to_string(&header)?;
+    out.write_all(header[..header.len() - 1].as_bytes())?;
+    out.write_all(b",\"hashval_to_idx\":{")?;
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    for (k, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+        .filter(|(k, _)| k.len() == 8)
+    {
+        let Some(datasets) = resolver.resolve(&v)? else {
+            continue;
+        };
+        let mut idxs: Vec<u32> = datasets
+            .into_iter()
+            .filter_map(|idx| lca_idx.get(idx as usize).copied().flatten())
+            .collect();
+        if idxs.is_empty() {
+            continue;
+        }
+        idxs.sort_unstable();
+        idxs.dedup();
+        if summary.hashes > 0 {
+            out.write_all(b",")?;
+        }
+        // JSON has no 64-bit integers, so hashes are keys as strings
+        write!(out, "\"{}\":", LittleEndian::read_u64(&k))?;
+        serde_json::to_writer(&mut out, &idxs)?;
+        summary.hashes += 1;
+    }
+    out.write_all(b"}}")?;
+    out.into_inner().map_err(|e| e.into_error())?.finish()?;
+    eprintln!(
+        "Wrote {} hashes of {} datasets ({} lineages) to {output}",
+        summary.hashes, summary.datasets, summary.lineages
+    );
+    Ok(summary)
+}
@@ -1,29 +1,123 @@
-use camino::Utf8PathBuf;
-
-use pyo3::prelude::*;
-use pyo3::wrap_pyfunction;
+//! Export sourmash RevIndex (RocksDB) databases to Parquet, annotated with
+//! taxonomy and LCA information.
+//!
+//! The Python plugin is built on top of this crate (with the default `python`
+//! feature); other Rust tools can embed the exporter by depending on it with
+//! `default-features = false`:
+//!
+//! ```no_run
+//! use camino::Utf8PathBuf;
+//! use sourmash_plugin_export::{export_revindex_to_parquet, ExportOptions};
+//!
+//! let stats = export_revindex_to_parquet(
+//!     vec![Utf8PathBuf::from("gtdb.rocksdb")],
+//!     Utf8PathBuf::from("gtdb.parquet"),
+//!     vec![Utf8PathBuf::from("gtdb.lineages.csv")],
+//!     None,
+//!     ExportOptions::default(),
+//! )?;
+//! println!("{} hashes written", stats.rows_written);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
 
-use anyhow::bail;
+use camino::Utf8PathBuf;
 
+mod abundance;
+mod check;
+mod checkpoint;
+mod classify;
+mod clickhouse;
+mod colors;
+mod concat;
+#[cfg(feature = "python")]
+mod config;
+mod containment;
+mod cooccurrence;
+mod dataset_metadata;
+mod dataset_summary;
+mod diff;
+mod dry_run;
 mod export;
-use export::export_revindex_to_parquet;
+mod gather;
+mod hashlist;
+mod import;
+mod incremental;
+mod info;
+mod inspect;
+mod lca_db;
+mod lineage_hll;
+mod lineage_table;
+mod lineages;
+mod manifest;
+mod membership;
+mod memory;
+mod merge;
+mod naming;
+mod pairwise;
+mod patterns;
+mod pgcopy;
+mod picklist;
+mod prefetch;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
+mod query;
+mod query_tags;
+mod reannotate;
+mod rewrite;
+mod sharded;
+mod sigs;
+mod sketches;
+mod stats;
+mod stream;
+mod summary;
+mod top_shared;
+mod verify;
 
-#[pyfunction]
-fn set_global_thread_pool(num_threads: usize) -> PyResult<usize> {
-    if std::panic::catch_unwind(|| {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(num_threads)
-            .build_global()
-    })
-    .is_ok()
-    {
-        Ok(rayon::current_num_threads())
-    } else {
-        Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
-            "Could not set the number of threads. Global thread pool might already be initialized.",
-        ))
-    }
-}
+pub use check::{check_revindex, CheckReport};
+pub use classify::{classify_hashes, Classification, LcaSource, RankAssignment};
+pub use clickhouse::{clickhouse_create_table_sql, clickhouse_insert_sql, encode_row_binary};
+pub use colors::export_colors_to_parquet;
+pub use concat::{concat_parquet, ConcatSummary};
+pub use containment::{parquet_containment, write_containment_csv, ContainmentMatch};
+pub use dataset_metadata::{COMMON_METADATA_FILE, METADATA_FILE};
+pub use diff::{revindex_diff, DiffSummary};
+pub use dry_run::{estimate_export, DbEstimate, ExportEstimate};
+pub use export::{
+    abundance_fields, compute_lca_strs, contamination_field, convert_to_batch, create_schema,
+    disagreement_fields, export_revindex_to_parquet, get_schema, lineage_id_field,
+    load_taxonomy_map, parse_compression, query_names_field, strip_accession_version, ArrowRecord,
+    ExportInterrupted, ExportOptions, InterruptPolicy, LCASummary, LineageFilter, LineageMatch,
+    Sampling, EXPORT_SCHEMA_VERSION,
+};
+pub use gather::{parquet_gather, write_gather_csv, GatherMatch};
+pub use hashlist::load_hash_list;
+pub use import::{parquet_to_revindex, ImportSummary};
+pub use incremental::PreviousExport;
+pub use info::{db_info, list_datasets, DatasetInfo, DbInfo};
+pub use inspect::{parquet_inspect, ColumnInfo, ParquetInspection, RowGroupInfo};
+pub use lca_db::{revindex_to_lca_json, LcaDbSummary};
+pub use lineage_table::LineageTable;
+pub use manifest::manifest_to_parquet;
+pub use membership::{bloom_filter_contains, DEFAULT_BLOOM_FILTER_FPP};
+pub use naming::{Anonymizer, DatasetLabels, DatasetNaming, NameField};
+pub use pairwise::{revindex_pairwise, PairwiseMetric, PairwiseSummary};
+pub use patterns::DatasetPatterns;
+pub use pgcopy::{encode_pgcopy, pg_copy_sql, pg_create_table_sql, PgCopyFormat};
+pub use picklist::{PickColumn, Picklist};
+pub use prefetch::Prefetch;
+pub use progress::ProgressCallback;
+pub use query::{query_parquet, HashQuery};
+pub use query_tags::QueryTags;
+pub use reannotate::reannotate_parquet;
+pub use rewrite::{rewrite_parquet, Partitioning, RewriteSummary};
+pub use sharded::{ShardFile, ShardedExport, SHARDED_METADATA_FILE};
+pub use sigs::{revindex_to_sigs, DatasetSketch};
+pub use sketches::{load_query_hashes, sigs_to_parquet, InputKind};
+pub use stats::{InvalidDatasets, RunStats, StageTiming};
+pub use stream::ExportStream;
+pub use summary::parquet_lca_summary;
+pub use verify::{verify_export, VerifyReport};
 
 pub fn is_revindex_database(path: &Utf8PathBuf) -> bool {
     // quick file check for Revindex database:
@@ -35,43 +129,3 @@ pub fn is_revindex_database(path: &Utf8PathBuf) -> bool {
         false
     }
 }
-
-#[pyfunction]
-#[pyo3(signature = (db_path_list, output, tax_path_list = None, lca_info_path= None, rw = false))]
-fn do_export_to_parquet(
-    db_path_list: Vec<String>,
-    output: String,
-    tax_path_list: Option<Vec<String>>,
-    lca_info_path: Option<String>,
-    rw: bool,
-) -> anyhow::Result<u8> {
-    let db_paths: Vec<Utf8PathBuf> = db_path_list.into_iter().map(Utf8PathBuf::from).collect();
-    let tax_paths: Vec<Utf8PathBuf> = tax_path_list
-        .unwrap_or_default()
-        .into_iter()
-        .map(Utf8PathBuf::from)
-        .collect();
-    let output_path = Utf8PathBuf::from(output);
-    let lca_info_path = lca_info_path.map(Utf8PathBuf::from);
-
-    for db in &db_paths {
-        if !is_revindex_database(db) {
-            bail!("'{db}' is not a valid RevIndex database");
-        }
-    }
-
-    match export_revindex_to_parquet(db_paths, output_path, tax_paths, lca_info_path, rw) {
-        Ok(_) => Ok(0),
-        Err(e) => {
-            eprintln!("Error: {e}");
-            Ok(1)
-        }
-    }
-}
-
-#[pymodule]
-fn sourmash_plugin_export(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(do_export_to_parquet, m)?)?;
-    m.add_function(wrap_pyfunction!(set_global_thread_pool, m)?)?;
-    Ok(())
-}
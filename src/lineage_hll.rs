@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use camino::Utf8Path;
+use csv::Writer;
+use sourmash::signature::SigsTrait;
+use sourmash::sketch::hyperloglog::HyperLogLog;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::export::RANKS;
+
+/// Rank of `--lineage-hll` when none is given.
+pub const DEFAULT_HLL_RANK: &str = "genus";
+
+// relative error of each lineage's cardinality estimate; 4 KiB per lineage
+const HLL_ERROR_RATE: f64 = 0.02;
+
+/// HyperLogLog counters of the distinct hashes found in each lineage at one
+/// rank, for `--lineage-hll`. A hash counts for the lineages of all its
+/// datasets, so the estimate is the richness of the taxon's genomes; counters
+/// of several databases merge into the distinct hashes across all of them.
+#[derive(Debug, Clone)]
+pub(crate) struct LineageSketches {
+    ksize: u32,
+    /// index of the rank in `RANKS`
+    rank: usize,
+    /// lineage truncated to the rank -> (HLL, exported hashes counted)
+    sketches: BTreeMap<String, (HyperLogLog, u64)>,
+}
+
+impl LineageSketches {
+    pub fn new(rank: &str, ksize: u32) -> Result<Self> {
+        let rank = RANKS
+            .iter()
+            .position(|r| *r == rank)
+            .ok_or_else(|| anyhow!("unknown rank '{rank}'"))?;
+        Ok(Self {
+            ksize,
+            rank,
+            sketches: BTreeMap::new(),
+        })
+    }
+
+    /// Count one exported hash for the lineages of its datasets at the rank;
+    /// lineages that don't resolve to the rank are left out.
+    pub fn add(&mut self, hash: u64, taxonomy_list: Option<&Vec<String>>) -> Result<()> {
+        let Some(lineages) = taxonomy_list else {
+            return Ok(());
+        };
+        let mut seen = HashSet::new();
+        for lineage in lineages {
+            let Some(truncated) = truncate_lineage(lineage, self.rank) else {
+                continue;
+            };
+            if !seen.insert(truncated) {
+                continue;
+            }
+            if !self.sketches.contains_key(truncated) {
+                let hll = new_hll(self.ksize)?;
+                self.sketches.insert(truncated.to_string(), (hll, 0));
+            }
+            let (hll, hashes) = self.sketches.get_mut(truncated).unwrap();
+            hll.add_hash(hash);
+            *hashes += 1;
+        }
+        Ok(())
+    }
+
+    /// Merge the counters of another database into these.
+    pub fn merge(&mut self, other: LineageSketches) -> Result<()> {
+        for (lineage, (hll, hashes)) in other.sketches {
+            match self.sketches.get_mut(&lineage) {
+                Some((mine, mine_hashes)) => {
+                    mine.merge(&hll)
+                        .map_err(|e| anyhow!("cannot merge the HLL of '{lineage}': {e}"))?;
+                    *mine_hashes += hashes;
+                }
+                None => {
+                    self.sketches.insert(lineage, (hll, hashes));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn new_hll(ksize: u32) -> Result<HyperLogLog> {
+    HyperLogLog::with_error_rate(HLL_ERROR_RATE, ksize as usize)
+        .map_err(|e| anyhow!("cannot create a HyperLogLog: {e}"))
+}
+
+/// The first `rank + 1` parts of a lineage, or `None` if it has fewer or the
+/// part at the rank is empty.
+fn truncate_lineage(lineage: &str, rank: usize) -> Option<&str> {
+    let mut end = 0;
+    for (i, part) in lineage.split(';').enumerate() {
+        end += part.len();
+        if i == rank {
+            return (!part.is_empty()).then(|| &lineage[..end]);
+        }
+        end += 1; // the ';'
+    }
+    None
+}
+
+/// Write the estimated distinct hashes of each lineage, with the exported
+/// hashes counted for it (which counts a hash once per database it is in).
+/// Returns the number of lineages.
+pub(crate) fn write_lineage_hll(path: &Utf8Path, sketches: &LineageSketches) -> Result<usize> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record(["rank", "lineage", "distinct_hashes", "hashes"])?;
+    let rank = RANKS[sketches.rank];
+    for (lineage, (hll, hashes)) in &sketches.sketches {
+        writer.serialize((rank, lineage, hll.cardinality(), hashes))?;
+    }
+    writer.flush()?;
+    let n_rows = sketches.sketches.len();
+    eprintln!("Wrote distinct hash estimates for {n_rows} lineages to {path}");
+    Ok(n_rows)
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::*;
+use arrow2::io::parquet::write::*;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+
+use crate::export::{parquet_write_options, RANKS};
+
+/// Numbers the LCA lineages of an export for `--lineage-table`, so the main
+/// table can reference a sidecar table of lineages by `lca_lineage_id`
+/// instead of repeating lineage strings. IDs start at 1, in the order the
+/// lineages are first written; clones share them.
+#[derive(Debug, Clone)]
+pub struct LineageTable {
+    path: Utf8PathBuf,
+    ids: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl LineageTable {
+    /// Number lineages, writing the table to `path` once the export is done.
+    pub fn new(path: &Utf8Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            ids: Arc::default(),
+        }
+    }
+
+    /// The ID of an LCA lineage, or `None` for hashes without one.
+    pub fn id(&self, lineage: Option<&str>) -> Option<u32> {
+        let lineage = lineage.filter(|lineage| !lineage.is_empty())?;
+        let mut ids = self.ids.lock().unwrap();
+        if let Some(&id) = ids.get(lineage) {
+            return Some(id);
+        }
+        let id = ids.len() as u32 + 1;
+        ids.insert(lineage.to_string(), id);
+        Some(id)
+    }
+
+    /// Write every lineage numbered so far: its `lineage_id`, one column per
+    /// rank (null below the lineage's rank) and the full `lineage` string, in
+    /// ID order.
+    pub(crate) fn write(&self) -> Result<()> {
+        let ids = self.ids.lock().unwrap();
+        let mut lineages: Vec<(&str, u32)> = ids.iter().map(|(l, id)| (l.as_str(), *id)).collect();
+        lineages.sort_by_key(|(_, id)| *id);
+
+        let mut fields = vec![Field::new("lineage_id", DataType::UInt32, false)];
+        fields.extend(
+            RANKS
+                .iter()
+                .map(|rank| Field::new(*rank, DataType::Utf8, true)),
+        );
+        fields.push(Field::new("lineage", DataType::Utf8, false));
+        let schema = Schema::from(fields);
+
+        let mut columns: Vec<Box<dyn Array>> = vec![Box::new(UInt32Array::from_vec(
+            lineages.iter().map(|(_, id)| *id).collect(),
+        ))];
+        for rank in 0..RANKS.len() {
+            columns.push(Box::new(Utf8Array::<i32>::from(
+                lineages
+                    .iter()
+                    .map(|(lineage, _)| {
+                        lineage.split(';').nth(rank).filter(|name| !name.is_empty())
+                    })
+                    .collect::<Vec<_>>(),
+            )));
+        }
+        columns.push(Box::new(Utf8Array::<i32>::from_iter_values(
+            lineages.iter().map(|(lineage, _)| *lineage),
+        )));
+
+        let options = parquet_write_options();
+        let mut writer = FileWriter::try_new(File::create(&self.path)?, schema.clone(), options)?;
+        let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+        let row_groups = RowGroupIterator::try_new(
+            std::iter::once(Ok(Chunk::new(columns))),
+            &schema,
+            options,
+            encodings,
+        )?;
+        for group in row_groups {
+            writer.write(group?)?;
+        }
+        writer.end(None)?;
+        eprintln!("Wrote {} lineages to {}", lineages.len(), self.path);
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use csv::Writer;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::export::RANKS;
+
+/// Hash counts by LCA lineage over the exported rows of one source, for
+/// `--lineage-summary`, `--kraken-report`, `--krona` and `--biom`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LineageCounts {
+    source: String,
+    /// hashes whose LCA is exactly this lineage
+    direct: HashMap<String, u64>,
+    /// hashes without an LCA: no taxonomy for their datasets, or none shared
+    unassigned: u64,
+    total: u64,
+}
+
+impl LineageCounts {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Count one exported hash, given its LCA lineage and rank.
+    pub fn add(&mut self, lca_lineage: Option<&str>, lca_rank: Option<&str>) {
+        self.total += 1;
+        match (lca_lineage, lca_rank) {
+            (Some(lineage), Some(_)) if !lineage.is_empty() => {
+                *self.direct.entry(lineage.to_string()).or_default() += 1;
+            }
+            _ => self.unassigned += 1,
+        }
+    }
+
+    /// Add the counts of another source.
+    pub fn merge(&mut self, other: &LineageCounts) {
+        for (lineage, count) in &other.direct {
+            *self.direct.entry(lineage.clone()).or_default() += count;
+        }
+        self.unassigned += other.unassigned;
+        self.total += other.total;
+    }
+
+    /// Hashes at or below each lineage (the lineage and all its descendants),
+    /// keyed by lineage; every ancestor of an LCA lineage is included.
+    pub fn clade_counts(&self) -> BTreeMap<String, u64> {
+        let mut clades: BTreeMap<String, u64> = BTreeMap::new();
+        for (lineage, count) in &self.direct {
+            let mut end = 0;
+            for part in lineage.split(';') {
+                end += part.len();
+                *clades.entry(lineage[..end].to_string()).or_default() += count;
+                end += 1; // the ';'
+            }
+        }
+        clades
+    }
+}
+
+/// Rank of a lineage string, from its number of ranks.
+pub(crate) fn lineage_rank(lineage: &str) -> Option<&'static str> {
+    RANKS.get(lineage.split(';').count() - 1).copied()
+}
+
+/// Write hash counts by lineage at each rank, like a metagenome summary of the
+/// database's k-mers: one row per (source, rank, lineage) with the hashes at or
+/// below that lineage and their fraction of the source's hashes, largest first,
+/// plus an `unclassified` row per rank for hashes not assigned at that rank.
+pub(crate) fn write_lineage_summary(path: &Utf8Path, counts: &[LineageCounts]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record(["source", "rank", "lineage", "hashes", "fraction"])?;
+    for source in counts {
+        let clades = source.clade_counts();
+        let fraction = |hashes: u64| match source.total {
+            0 => 0.0,
+            total => hashes as f64 / total as f64,
+        };
+        for rank in RANKS {
+            let mut rows: Vec<(&String, u64)> = clades
+                .iter()
+                .filter(|(lineage, _)| lineage_rank(lineage) == Some(rank))
+                .map(|(lineage, count)| (lineage, *count))
+                .collect();
+            rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let assigned: u64 = rows.iter().map(|(_, count)| count).sum();
+            for (lineage, hashes) in rows {
+                writer.serialize((&source.source, rank, lineage, hashes, fraction(hashes)))?;
+            }
+            let unclassified = source.total - assigned;
+            if unclassified > 0 {
+                writer.serialize((
+                    &source.source,
+                    rank,
+                    "unclassified",
+                    unclassified,
+                    fraction(unclassified),
+                ))?;
+            }
+        }
+    }
+    writer.flush()?;
+    eprintln!("Wrote lineage summary to {path}");
+    Ok(())
+}
+
+/// A lineage part without its `g__`-style rank prefix.
+fn taxon_name(part: &str) -> &str {
+    match part.split_once("__") {
+        Some((prefix, rest)) if prefix.len() == 1 => rest,
+        _ => part,
+    }
+}
+
+// kraken2 rank codes, indexed like `RANKS`
+const KRAKEN_RANK_CODES: [&str; 7] = ["D", "P", "C", "O", "F", "G", "S"];
+
+/// Write a kraken2-style report of the LCA assignments of all sources together:
+/// tab-separated percent of hashes, hashes in the clade, hashes assigned
+/// directly to the taxon, rank code, taxid and the taxon name indented by two
+/// spaces per rank. Taxonomies here have no taxids, so they are 0 (1 for root);
+/// names have their `g__`-style prefixes removed. Children are listed under
+/// their parent, largest clade first.
+pub(crate) fn write_kraken_report(path: &Utf8Path, counts: &[LineageCounts]) -> Result<()> {
+    let mut all = LineageCounts::default();
+    for source in counts {
+        all.merge(source);
+    }
+    let clades = all.clade_counts();
+    let mut children: BTreeMap<&str, Vec<(&str, u64)>> = BTreeMap::new();
+    for (lineage, count) in &clades {
+        let parent = lineage.rsplit_once(';').map_or("", |(parent, _)| parent);
+        children.entry(parent).or_default().push((lineage, *count));
+    }
+    for list in children.values_mut() {
+        list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    let percent = |hashes: u64| match all.total {
+        0 => 0.0,
+        total => hashes as f64 * 100.0 / total as f64,
+    };
+    if all.unassigned > 0 {
+        writeln!(
+            out,
+            "{:6.2}\t{}\t{}\tU\t0\tunclassified",
+            percent(all.unassigned),
+            all.unassigned,
+            all.unassigned
+        )?;
+    }
+    let classified = all.total - all.unassigned;
+    writeln!(
+        out,
+        "{:6.2}\t{classified}\t0\tR\t1\troot",
+        percent(classified)
+    )?;
+
+    // depth-first from the domains
+    let mut stack: Vec<(&str, u64)> = children.get("").cloned().unwrap_or_default();
+    stack.reverse();
+    while let Some((lineage, clade)) = stack.pop() {
+        let depth = lineage.split(';').count();
+        let code = KRAKEN_RANK_CODES.get(depth - 1).copied().unwrap_or("-");
+        let name = taxon_name(lineage.rsplit(';').next().unwrap_or(lineage));
+        writeln!(
+            out,
+            "{:6.2}\t{clade}\t{}\t{code}\t0\t{}{name}",
+            percent(clade),
+            all.direct.get(lineage).copied().unwrap_or_default(),
+            "  ".repeat(depth)
+        )?;
+        if let Some(list) = children.get(lineage) {
+            stack.extend(list.iter().rev());
+        }
+    }
+    out.flush()?;
+    eprintln!("Wrote Kraken-style report to {path}");
+    Ok(())
+}
+
+/// Write the LCA distribution of all sources together as Krona text, the input
+/// of `ktImportText`: one line per LCA lineage with its hash count and then
+/// its taxon names, tab-separated, from domain down. Hashes without an LCA are
+/// counted on an `unclassified` line.
+pub(crate) fn write_krona_text(path: &Utf8Path, counts: &[LineageCounts]) -> Result<()> {
+    let mut all = LineageCounts::default();
+    for source in counts {
+        all.merge(source);
+    }
+    let mut lineages: Vec<(&String, &u64)> = all.direct.iter().collect();
+    lineages.sort();
+
+    let mut out = BufWriter::new(File::create(path)?);
+    for (lineage, count) in lineages {
+        let names: Vec<&str> = lineage.split(';').map(taxon_name).collect();
+        writeln!(out, "{count}\t{}", names.join("\t"))?;
+    }
+    if all.unassigned > 0 {
+        writeln!(out, "{}\tunclassified", all.unassigned)?;
+    }
+    out.flush()?;
+    eprintln!("Wrote Krona text to {path}");
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct BiomEntry {
+    id: String,
+    metadata: Option<BiomTaxonomy>,
+}
+
+#[derive(Serialize)]
+struct BiomTaxonomy {
+    taxonomy: Vec<String>,
+}
+
+/// A BIOM 1.0 table, in its JSON format.
+#[derive(Serialize)]
+struct BiomTable {
+    id: Option<String>,
+    format: &'static str,
+    format_url: &'static str,
+    #[serde(rename = "type")]
+    table_type: &'static str,
+    generated_by: String,
+    date: String,
+    rows: Vec<BiomEntry>,
+    columns: Vec<BiomEntry>,
+    matrix_type: &'static str,
+    matrix_element_type: &'static str,
+    shape: [usize; 2],
+    data: Vec<[u64; 3]>,
+}
+
+/// Current UTC time as `YYYY-MM-DDTHH:MM:SS`.
+fn utc_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+/// Write hash counts by LCA lineage per source as a sparse BIOM 1.0 (JSON)
+/// table, for QIIME 2, phyloseq and other tools built around BIOM: one
+/// observation per LCA lineage, with its ranks as `taxonomy` metadata, and one
+/// sample per source. Hashes without an LCA are counted as `unclassified`.
+pub(crate) fn write_biom(path: &Utf8Path, counts: &[LineageCounts]) -> Result<()> {
+    let mut lineages: BTreeSet<&str> = counts
+        .iter()
+        .flat_map(|source| source.direct.keys().map(String::as_str))
+        .collect();
+    let unassigned = counts.iter().any(|source| source.unassigned > 0);
+    if unassigned {
+        lineages.insert("unclassified");
+    }
+    let lineages: Vec<&str> = lineages.into_iter().collect();
+
+    let mut data = Vec::new();
+    for (row, lineage) in lineages.iter().enumerate() {
+        for (col, source) in counts.iter().enumerate() {
+            let count = match *lineage {
+                "unclassified" => source.unassigned,
+                lineage => source.direct.get(lineage).copied().unwrap_or_default(),
+            };
+            if count > 0 {
+                data.push([row as u64, col as u64, count]);
+            }
+        }
+    }
+
+    let table = BiomTable {
+        id: None,
+        format: "Biological Observation Matrix 1.0.0",
+        format_url: "http://biom-format.org",
+        table_type: "Taxon table",
+        generated_by: format!("sourmash_plugin_export {}", env!("CARGO_PKG_VERSION")),
+        date: utc_timestamp(),
+        rows: lineages
+            .iter()
+            .map(|lineage| BiomEntry {
+                id: lineage.to_string(),
+                metadata: Some(BiomTaxonomy {
+                    taxonomy: lineage.split(';').map(str::to_string).collect(),
+                }),
+            })
+            .collect(),
+        columns: counts
+            .iter()
+            .map(|source| BiomEntry {
+                id: source.source.clone(),
+                metadata: None,
+            })
+            .collect(),
+        matrix_type: "sparse",
+        matrix_element_type: "int",
+        shape: [lineages.len(), counts.len()],
+        data,
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut out, &table)?;
+    out.flush()?;
+    eprintln!("Wrote BIOM table of {} lineages to {path}", lineages.len());
+    Ok(())
+}
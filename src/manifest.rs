@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::*;
+use arrow2::io::parquet::write::*;
+use camino::Utf8Path;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::fs::File;
+
+use crate::export::{check_db_compatibility, parquet_write_options};
+
+// schema for the manifest parquet file
+fn create_manifest_schema() -> Schema {
+    Schema::from(vec![
+        Field::new("idx", DataType::UInt32, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("md5", DataType::Utf8, false),
+        Field::new("ksize", DataType::UInt32, false),
+        Field::new("scaled", DataType::UInt32, false),
+        Field::new("moltype", DataType::Utf8, false),
+        Field::new("n_hashes", DataType::UInt64, false),
+        Field::new("filename", DataType::Utf8, false),
+    ])
+}
+
+/// Export the collection manifest of a RevIndex to a Parquet file, one row per
+/// dataset: `idx` is the dataset ID used in the `hashes` CF, so the table can be
+/// joined with the dataset names of a hash export. Returns the number of datasets.
+pub fn manifest_to_parquet(db_path: &Utf8Path, out_path: &Utf8Path) -> Result<usize> {
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let manifest = revindex.collection().manifest();
+
+    let records: Vec<_> = manifest.iter().collect();
+    let chunk = Chunk::new(vec![
+        Box::new(UInt32Array::from_vec((0..records.len() as u32).collect())) as Box<dyn Array>,
+        Box::new(Utf8Array::<i32>::from_iter_values(
+            records.iter().map(|r| r.name().as_str()),
+        )),
+        Box::new(Utf8Array::<i32>::from_iter_values(
+            records.iter().map(|r| r.md5().as_str()),
+        )),
+        Box::new(UInt32Array::from_vec(
+            records.iter().map(|r| r.ksize()).collect(),
+        )),
+        Box::new(UInt32Array::from_vec(
+            records.iter().map(|r| *r.scaled()).collect(),
+        )),
+        Box::new(Utf8Array::<i32>::from_iter_values(
+            records.iter().map(|r| r.moltype().to_string()),
+        )),
+        Box::new(UInt64Array::from_vec(
+            records.iter().map(|r| *r.n_hashes() as u64).collect(),
+        )),
+        Box::new(Utf8Array::<i32>::from_iter_values(
+            records.iter().map(|r| r.filename().as_str()),
+        )),
+    ]);
+
+    let schema = create_manifest_schema();
+    let options = parquet_write_options();
+    let mut writer = FileWriter::try_new(File::create(out_path)?, schema.clone(), options)?;
+    let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+    let row_groups =
+        RowGroupIterator::try_new(std::iter::once(Ok(chunk)), &schema, options, encodings)?;
+    for group in row_groups {
+        writer.write(group?)?;
+    }
+    writer.end(None)?;
+    eprintln!("Wrote {} manifest records to {out_path}", records.len());
+
+    Ok(records.len())
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::bloom_filter;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::fs::File;
+
+/// Default false positive rate of `--bloom-filter`.
+pub const DEFAULT_BLOOM_FILTER_FPP: f64 = 0.01;
+
+// bytes per block of a split-block bloom filter
+const BLOCK_BYTES: usize = 32;
+
+/// Bytes of a split-block bloom filter holding `n` values at false positive
+/// rate `fpp`, as Parquet writers size them, in whole blocks.
+fn bloom_filter_bytes(n: u64, fpp: f64) -> usize {
+    let bits = -8.0 * n as f64 / (1.0 - fpp.powf(1.0 / 8.0)).ln();
+    let blocks = (bits / 8.0 / BLOCK_BYTES as f64).ceil() as usize;
+    blocks.max(1) * BLOCK_BYTES
+}
+
+/// Write a standalone bloom filter over the hashes of an export's Parquet
+/// files, for `--bloom-filter`, so services can check whether a hash is in
+/// the database without the Parquet or the RocksDB. The file is the bitset of
+/// a Parquet split-block bloom filter (32-byte blocks, values hashed with
+/// xxHash64 of their 8 little-endian bytes, seed 0), sized for the number of
+/// rows at false positive rate `fpp`. Returns the number of hashes added.
+pub(crate) fn write_bloom_filter(
+    parquet_paths: &[Utf8PathBuf],
+    path: &Utf8Path,
+    fpp: f64,
+) -> Result<u64> {
+    let mut files = Vec::with_capacity(parquet_paths.len());
+    let mut rows = 0;
+    for parquet_path in parquet_paths {
+        let mut reader =
+            File::open(parquet_path).with_context(|| format!("cannot open '{parquet_path}'"))?;
+        let metadata = read_metadata(&mut reader)?;
+        rows += metadata.num_rows as u64;
+        files.push((parquet_path, reader, metadata));
+    }
+
+    let mut bitset = vec![0; bloom_filter_bytes(rows, fpp)];
+    let mut added = 0;
+    for (parquet_path, reader, metadata) in files {
+        let schema = infer_schema(&metadata)?.filter(|_, field| field.name == "hash");
+        if schema.fields.is_empty() {
+            anyhow::bail!("'{parquet_path}' has no 'hash' column");
+        }
+        for chunk in FileReader::new(reader, metadata.row_groups, schema, None, None, None) {
+            let chunk = chunk?;
+            let hashes = chunk.arrays()[0]
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .ok_or_else(|| anyhow!("'hash' column of '{parquet_path}' is not UInt64"))?;
+            for &hash in hashes.values().iter() {
+                bloom_filter::insert(&mut bitset, bloom_filter::hash_native(hash as i64));
+            }
+            added += hashes.len() as u64;
+        }
+    }
+    std::fs::write(path, &bitset).with_context(|| format!("cannot write '{path}'"))?;
+    eprintln!(
+        "Wrote a {} byte bloom filter of {added} hashes to {path}",
+        bitset.len()
+    );
+    Ok(added)
+}
+
+/// Check `hashes` against a bloom filter written by `--bloom-filter`: `false`
+/// means a hash is certainly not in the export, `true` that it probably is.
+pub fn bloom_filter_contains(path: &Utf8Path, hashes: &[u64]) -> Result<Vec<bool>> {
+    let bitset = std::fs::read(path).with_context(|| format!("cannot read '{path}'"))?;
+    if bitset.is_empty() || bitset.len() % BLOCK_BYTES != 0 {
+        anyhow::bail!("'{path}' is not a split-block bloom filter");
+    }
+    Ok(hashes
+        .iter()
+        .map(|&hash| bloom_filter::is_in_set(&bitset, bloom_filter::hash_native(hash as i64)))
+        .collect())
+}
@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// how often to log the process memory use
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// flush early once RSS reaches this fraction of `--max-memory`
+const FLUSH_FRACTION: f64 = 0.9;
+
+/// Resident set size of this process in bytes, if the platform exposes it.
+pub(crate) fn current_rss() -> Option<u64> {
+    // VmRSS is reported in kB; only available on Linux
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Periodic memory reporting plus an optional soft memory budget.
+///
+/// Near the budget the writer flushes its buffer early, and producers wait for
+/// the writer to drain queued batches before sending more (backpressure). It
+/// also records whether the writer failed, so producers stop at once.
+pub(crate) struct MemoryMonitor {
+    max_bytes: Option<u64>,
+    last_report: Mutex<Instant>,
+    /// batches sent to the writer but not yet received
+    queued: AtomicUsize,
+    /// also wait once this many batches are queued, whatever the memory use
+    max_queued: Option<usize>,
+    /// set when the writer stopped with an error
+    writer_failed: AtomicBool,
+}
+
+impl MemoryMonitor {
+    pub fn new(max_bytes: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            last_report: Mutex::new(Instant::now()),
+            queued: AtomicUsize::new(0),
+            max_queued: None,
+            writer_failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Bound the number of queued batches, for consumers that may fall behind.
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = Some(max_queued);
+        self
+    }
+
+    /// Record that the writer (or merge) thread stopped with an error.
+    pub fn set_writer_failed(&self) {
+        self.writer_failed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn writer_failed(&self) -> bool {
+        self.writer_failed.load(Ordering::SeqCst)
+    }
+
+    pub fn batch_sent(&self) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn batch_received(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Block while the writer still has queued batches and we are either near the
+    /// budget or at `max_queued`. Once the queue is empty, or the writer has
+    /// failed, we carry on regardless, so this can never deadlock.
+    pub fn wait_for_writer(&self) {
+        loop {
+            let queued = self.queued.load(Ordering::SeqCst);
+            let full = self.max_queued.is_some_and(|max| queued >= max);
+            if queued == 0 || !(full || self.near_limit()) || self.writer_failed() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Log RSS if the last report is older than `REPORT_INTERVAL`.
+    pub fn maybe_report(&self) {
+        let mut last = self.last_report.lock().unwrap();
+        if last.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        *last = Instant::now();
+
+        if let Some(rss) = current_rss() {
+            match self.max_bytes {
+                Some(max) => eprintln!(
+                    "Memory: RSS {} of {} budget",
+                    format_bytes(rss),
+                    format_bytes(max)
+                ),
+                None => eprintln!("Memory: RSS {}", format_bytes(rss)),
+            }
+        }
+    }
+
+    /// Is the process close to its memory budget? Always false without `--max-memory`
+    /// or on platforms where RSS is unavailable.
+    pub fn near_limit(&self) -> bool {
+        match (self.max_bytes, current_rss()) {
+            (Some(max), Some(rss)) => rss as f64 >= max as f64 * FLUSH_FRACTION,
+            _ => false,
+        }
+    }
+}
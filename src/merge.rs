@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::export::{
+    annotate_taxonomy, spawn_consumer, ArrowRecord, ExportOptions, LCASummary, WriteBatch,
+    WriterFailed, SEND_BATCH_SIZE,
+};
+use crate::memory::MemoryMonitor;
+
+/// Source name for the batches of a merged export.
+const MERGED_SOURCE: &str = "merged";
+
+#[derive(Default)]
+struct MergedHash {
+    dataset_names: BTreeSet<String>,
+    sources: BTreeSet<String>,
+}
+
+/// Collects the records exported from several databases, for `--merge`: one
+/// entry per hash, with the union of its datasets and sources.
+#[derive(Default)]
+pub(crate) struct HashMerger {
+    hashes: HashMap<u64, MergedHash>,
+    /// (ksize, scaled) shared by every record
+    params: Option<(u32, u32)>,
+}
+
+impl HashMerger {
+    fn add(&mut self, records: Vec<ArrowRecord>) -> Result<()> {
+        for record in records {
+            let params = (record.ksize, record.scaled);
+            match self.params {
+                None => self.params = Some(params),
+                Some(p) if p != params => anyhow::bail!(
+                    "--merge needs databases with the same ksize and scaled, but found \
+                     ksize {}/scaled {} and ksize {}/scaled {} ('{}')",
+                    p.0,
+                    p.1,
+                    params.0,
+                    params.1,
+                    record.source
+                ),
+                Some(_) => {}
+            }
+            let entry = self.hashes.entry(record.hash).or_default();
+            entry.dataset_names.extend(record.dataset_names);
+            entry.sources.insert(record.source);
+        }
+        Ok(())
+    }
+
+    /// Send one record per hash, in hash order, with the LCA recomputed over
+    /// the union of its datasets and `source` listing every database it came
+    /// from (`;`-separated). Hashes whose combined datasets are outside
+    /// `--min/--max-datasets` or whose combined annotation fails the taxonomy
+    /// filters, or that the `--since` export already has with the same
+    /// datasets, are skipped. Returns the LCA summary of the records
+    /// sent.
+    pub(crate) fn send(
+        self,
+        sender: &Sender<WriteBatch>,
+        taxonomy_map: Option<&HashMap<String, String>>,
+        options: &ExportOptions,
+        memory: &MemoryMonitor,
+    ) -> Result<LCASummary> {
+        let previous = options.since.as_deref();
+        let (ksize, scaled) = self.params.unwrap_or_default();
+        let mut hashes: Vec<(u64, Vec<String>, BTreeSet<String>)> = self
+            .hashes
+            .into_iter()
+            .map(|(hash, merged)| {
+                let names: Vec<String> = merged.dataset_names.into_iter().collect();
+                (hash, names, merged.sources)
+            })
+            .filter(|(_, names, _)| options.in_dataset_range(names.len()))
+            .filter(|(hash, names, _)| !previous.is_some_and(|p| p.contains(*hash, names)))
+            .collect();
+        hashes.sort_unstable_by_key(|(hash, _, _)| *hash);
+
+        let mut summary = LCASummary::new(ksize, scaled);
+        let mut hashes = hashes.into_iter().peekable();
+        while hashes.peek().is_some() {
+            let mut batch_summary = LCASummary::new(ksize, scaled);
+            let records: Vec<ArrowRecord> = hashes
+                .by_ref()
+                .take(SEND_BATCH_SIZE)
+                .filter_map(|(hash, dataset_names, sources)| {
+                    let (taxonomy_list, lca_lineage, lca_rank) =
+                        annotate_taxonomy(&dataset_names, taxonomy_map);
+                    if !options.keeps_annotation(
+                        taxonomy_list.as_ref(),
+                        lca_lineage.as_deref(),
+                        lca_rank.as_deref(),
+                    ) {
+                        return None;
+                    }
+                    batch_summary.add_rank(taxonomy_list.as_ref(), lca_rank.as_deref());
+                    Some(ArrowRecord {
+                        hash,
+                        dataset_names,
+                        taxonomy_list,
+                        lca_lineage,
+                        lca_rank,
+                        ksize,
+                        scaled,
+                        source: sources.into_iter().collect::<Vec<_>>().join(";"),
+                        abundances: None,
+                    })
+                })
+                .collect();
+
+            summary.merge(&batch_summary);
+            memory.wait_for_writer();
+            memory.batch_sent();
+            sender
+                .send(WriteBatch::complete(
+                    MERGED_SOURCE.to_string(),
+                    records,
+                    batch_summary,
+                ))
+                .map_err(|_| WriterFailed)?;
+        }
+        Ok(summary)
+    }
+}
+
+/// Start a thread that collects the batches sent to the returned sender into a
+/// `HashMerger`, returned on join once every sender is dropped.
+pub(crate) fn start_merge_thread(
+    memory: Arc<MemoryMonitor>,
+) -> (Sender<WriteBatch>, thread::JoinHandle<Result<HashMerger>>) {
+    let (sender, receiver) = mpsc::channel::<WriteBatch>();
+    let handle = spawn_consumer(memory.clone(), move || -> Result<HashMerger> {
+        let mut merger = HashMerger::default();
+        for batch in receiver {
+            memory.batch_received();
+            merger.add(batch.records)?;
+        }
+        Ok(merger)
+    });
+    (sender, handle)
+}
+
+/// Wait for a merge thread and return its merger.
+pub(crate) fn join_merge_thread(
+    handle: thread::JoinHandle<Result<HashMerger>>,
+) -> Result<HashMerger> {
+    handle
+        .join()
+        .map_err(|_| anyhow!("merge thread panicked"))?
+}
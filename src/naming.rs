@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use csv::Writer;
+use sourmash::manifest::Record;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+
+/// A manifest field a dataset's display name can come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameField {
+    Name,
+    Filename,
+    Md5,
+    /// the dataset's ID in the manifest
+    Index,
+}
+
+impl std::str::FromStr for NameField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Self::Name),
+            "filename" => Ok(Self::Filename),
+            "md5" => Ok(Self::Md5),
+            "idx" => Ok(Self::Index),
+            _ => anyhow::bail!(
+                "unknown dataset name field '{s}'; expected name, filename, md5 or idx"
+            ),
+        }
+    }
+}
+
+/// Display labels for datasets, by record name or md5, read from a CSV file
+/// with a `label` column and a `name` and/or `md5` column (`--dataset-labels`).
+pub struct DatasetLabels {
+    by_name: HashMap<String, String>,
+    by_md5: HashMap<String, String>,
+}
+
+impl fmt::Debug for DatasetLabels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatasetLabels")
+            .field("by_name", &self.by_name.len())
+            .field("by_md5", &self.by_md5.len())
+            .finish()
+    }
+}
+
+impl DatasetLabels {
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("cannot open dataset labels '{path}'"))?;
+        let mut rdr = csv::Reader::from_reader(BufReader::new(file));
+        let headers = rdr.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let label_idx = column("label")
+            .ok_or_else(|| anyhow!("dataset labels '{path}' have no 'label' column"))?;
+        let (name_idx, md5_idx) = (column("name"), column("md5"));
+        if name_idx.is_none() && md5_idx.is_none() {
+            anyhow::bail!("dataset labels '{path}' need a 'name' or 'md5' column");
+        }
+
+        let mut labels = Self {
+            by_name: HashMap::new(),
+            by_md5: HashMap::new(),
+        };
+        for row in rdr.records() {
+            let row = row?;
+            let field = |idx: Option<usize>| {
+                idx.and_then(|idx| row.get(idx))
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+            };
+            let Some(label) = field(Some(label_idx)) else {
+                continue;
+            };
+            if let Some(name) = field(name_idx) {
+                labels.by_name.insert(name.to_string(), label.to_string());
+            }
+            if let Some(md5) = field(md5_idx) {
+                labels.by_md5.insert(md5.to_string(), label.to_string());
+            }
+        }
+        if labels.by_name.is_empty() && labels.by_md5.is_empty() {
+            anyhow::bail!("no dataset labels in '{path}'");
+        }
+        eprintln!(
+            "Loaded {} dataset labels from '{path}'",
+            labels.by_name.len() + labels.by_md5.len()
+        );
+        Ok(labels)
+    }
+
+    /// The label of the dataset of `record`, by md5 first, then by name.
+    pub fn label(&self, record: &Record) -> Option<&str> {
+        self.by_md5
+            .get(record.md5().as_str())
+            .or_else(|| self.by_name.get(record.name().as_str()))
+            .map(String::as_str)
+    }
+}
+
+/// How datasets are named in the export: their label if they have one
+/// (`--dataset-labels`), else the first non-empty field of the chain
+/// (`--dataset-name`), and the dataset ID if they are all empty. The default
+/// chain is the record name, then the filename.
+#[derive(Debug, Clone)]
+pub struct DatasetNaming {
+    fields: Vec<NameField>,
+    labels: Option<Arc<DatasetLabels>>,
+}
+
+impl Default for DatasetNaming {
+    fn default() -> Self {
+        Self {
+            fields: vec![NameField::Name, NameField::Filename],
+            labels: None,
+        }
+    }
+}
+
+impl std::str::FromStr for DatasetNaming {
+    type Err = anyhow::Error;
+
+    /// Parse a comma-separated chain, e.g. `name,md5`.
+    fn from_str(s: &str) -> Result<Self> {
+        let fields = s
+            .split(',')
+            .map(|field| field.trim().parse())
+            .collect::<Result<Vec<NameField>>>()?;
+        Ok(Self {
+            fields,
+            labels: None,
+        })
+    }
+}
+
+impl DatasetNaming {
+    /// Name datasets with these labels where they have one.
+    pub fn with_labels(mut self, labels: Arc<DatasetLabels>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    /// Display name of the dataset with ID `idx` and manifest record `record`.
+    pub fn name(&self, idx: u32, record: &Record) -> String {
+        if let Some(label) = self.labels.as_ref().and_then(|l| l.label(record)) {
+            return label.to_string();
+        }
+        self.fields
+            .iter()
+            .find_map(|field| {
+                let value = match field {
+                    NameField::Name => record.name().to_string(),
+                    NameField::Filename => record.filename().to_string(),
+                    NameField::Md5 => record.md5().to_string(),
+                    NameField::Index => idx.to_string(),
+                };
+                (!value.is_empty()).then_some(value)
+            })
+            .unwrap_or_else(|| idx.to_string())
+    }
+}
+
+/// Replaces dataset names with salted hashes (`--anonymize`), so the same
+/// name and salt always give the same anonymized name. Every name replaced is
+/// remembered for the private mapping file; clones share it.
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    salt: String,
+    mapping: Utf8PathBuf,
+    names: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl Anonymizer {
+    /// Anonymize with `salt`, writing the mapping back to the real names to
+    /// `mapping` once the export is done.
+    pub fn new(salt: &str, mapping: &Utf8Path) -> Result<Self> {
+        if salt.is_empty() {
+            anyhow::bail!("the anonymization salt must not be empty");
+        }
+        Ok(Self {
+            salt: salt.to_string(),
+            mapping: mapping.to_path_buf(),
+            names: Arc::default(),
+        })
+    }
+
+    /// A new random salt, for exports whose names needn't match later ones.
+    pub fn random_salt() -> String {
+        let random = |n: u64| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(n);
+            hasher.finish()
+        };
+        format!("{:016x}{:016x}", random(0), random(1))
+    }
+
+    /// The anonymized name of `name`: `ds_` and 128 hash bits in hex.
+    pub fn anonymize(&self, name: &str) -> String {
+        let mut names = self.names.lock().unwrap();
+        if let Some(anonymized) = names.get(name) {
+            return anonymized.clone();
+        }
+        let key = format!("{}\0{name}", self.salt);
+        let anonymized = format!(
+            "ds_{:016x}{:016x}",
+            sourmash::_hash_murmur(key.as_bytes(), 0),
+            sourmash::_hash_murmur(key.as_bytes(), 1)
+        );
+        names.insert(name.to_string(), anonymized.clone());
+        anonymized
+    }
+
+    /// Write every name anonymized so far, and its anonymized name, as CSV.
+    pub(crate) fn write_mapping(&self) -> Result<()> {
+        let names = self.names.lock().unwrap();
+        let mut writer = Writer::from_path(&self.mapping)?;
+        writer.write_record(["dataset", "anonymized"])?;
+        for (name, anonymized) in names.iter() {
+            writer.write_record([name, anonymized])?;
+        }
+        writer.flush()?;
+        eprintln!(
+            "Wrote the names of {} anonymized datasets to {}",
+            names.len(),
+            self.mapping
+        );
+        Ok(())
+    }
+}
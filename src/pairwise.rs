@@ -0,0 +1,290 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+use arrow2::io::parquet::write::{Encoding, FileWriter, RowGroupIterator};
+use camino::Utf8Path;
+use csv::Writer;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::collections::HashMap;
+use std::fs::File;
+
+use crate::export::{check_db_compatibility, parquet_write_options, DatasetResolver, HASHES_CF};
+use crate::naming::DatasetNaming;
+
+// rows per row group in the long-form output
+const PAIRWISE_BATCH_SIZE: usize = 100_000;
+
+/// Similarity written to the `revindex_pairwise` matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairwiseMetric {
+    Jaccard,
+    /// containment of the row dataset in the column dataset
+    Containment,
+    MaxContainment,
+}
+
+impl std::str::FromStr for PairwiseMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jaccard" => Ok(Self::Jaccard),
+            "containment" => Ok(Self::Containment),
+            "max_containment" => Ok(Self::MaxContainment),
+            _ => anyhow::bail!(
+                "unknown metric '{s}'; expected jaccard, containment or max_containment"
+            ),
+        }
+    }
+}
+
+/// Counts from `revindex_pairwise`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PairwiseSummary {
+    pub datasets: usize,
+    pub hashes: u64,
+    /// dataset pairs sharing at least one hash
+    pub pairs: usize,
+    /// rows written to the long-form output
+    pub rows_written: usize,
+}
+
+/// Hashes per dataset and shared hashes per pair of datasets of a RevIndex.
+struct SharedCounts {
+    names: Vec<String>,
+    sizes: Vec<u64>,
+    /// (lower ID, higher ID) -> hashes found in both
+    pairs: HashMap<(u32, u32), u64>,
+    hashes: u64,
+}
+
+impl SharedCounts {
+    fn shared(&self, a: usize, b: usize) -> u64 {
+        if a == b {
+            return self.sizes[a];
+        }
+        let pair = if a < b { (a, b) } else { (b, a) };
+        self.pairs
+            .get(&(pair.0 as u32, pair.1 as u32))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Containment of `a` in `b`, Jaccard and max containment; 0 for empty
+    /// datasets.
+    fn similarity(&self, a: usize, b: usize, metric: PairwiseMetric) -> f64 {
+        let shared = self.shared(a, b) as f64;
+        let (size_a, size_b) = (self.sizes[a] as f64, self.sizes[b] as f64);
+        let ratio = |denominator: f64| {
+            if denominator > 0.0 {
+                shared / denominator
+            } else {
+                0.0
+            }
+        };
+        match metric {
+            PairwiseMetric::Jaccard => ratio(size_a + size_b - shared),
+            PairwiseMetric::Containment => ratio(size_a),
+            PairwiseMetric::MaxContainment => ratio(size_a.min(size_b)),
+        }
+    }
+}
+
+/// Count the hashes of every dataset and the hashes shared by every pair of
+/// datasets in one scan of the `hashes` column family.
+fn count_shared(db_path: &Utf8Path) -> Result<SharedCounts> {
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+    let naming = DatasetNaming::default();
+    let names: Vec<String> = collection
+        .manifest()
+        .iter()
+        .enumerate()
+        .map(|(idx, record)| naming.name(idx as u32, record))
+        .collect();
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    let mut sizes = vec![0; names.len()];
+    let mut pairs: HashMap<(u32, u32), u64> = HashMap::new();
+    let mut hashes = 0;
+    for (_, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+        .filter(|(k, _)| k.len() == 8)
+    {
+        let Some(datasets) = resolver.resolve(&v)? else {
+            continue;
+        };
+        let mut datasets: Vec<u32> = datasets
+            .into_iter()
+            .filter(|&idx| (idx as usize) < names.len())
+            .collect();
+        datasets.sort_unstable();
+        datasets.dedup();
+        hashes += 1;
+        for (i, &a) in datasets.iter().enumerate() {
+            sizes[a as usize] += 1;
+            for &b in &datasets[i + 1..] {
+                *pairs.entry((a, b)).or_default() += 1;
+            }
+        }
+    }
+    Ok(SharedCounts {
+        names,
+        sizes,
+        pairs,
+        hashes,
+    })
+}
+
+fn pairwise_schema() -> Schema {
+    Schema::from(vec![
+        Field::new("dataset_a", DataType::Utf8, false),
+        Field::new("dataset_b", DataType::Utf8, false),
+        Field::new("hashes_a", DataType::UInt64, false),
+        Field::new("hashes_b", DataType::UInt64, false),
+        Field::new("shared_hashes", DataType::UInt64, false),
+        Field::new("containment_a_in_b", DataType::Float64, false),
+        Field::new("containment_b_in_a", DataType::Float64, false),
+        Field::new("max_containment", DataType::Float64, false),
+        Field::new("jaccard", DataType::Float64, false),
+    ])
+}
+
+fn write_pairwise_rows(
+    writer: &mut FileWriter<File>,
+    schema: &Schema,
+    counts: &SharedCounts,
+    rows: &[(usize, usize)],
+) -> Result<()> {
+    let names = |side: fn(&(usize, usize)) -> usize| {
+        Utf8Array::<i32>::from_iter_values(rows.iter().map(|r| counts.names[side(r)].as_str()))
+    };
+    let sizes = |side: fn(&(usize, usize)) -> usize| {
+        UInt64Array::from_vec(rows.iter().map(|r| counts.sizes[side(r)]).collect())
+    };
+    let metric = |metric: PairwiseMetric, swap: bool| {
+        Float64Array::from_vec(
+            rows.iter()
+                .map(|&(a, b)| {
+                    if swap {
+                        counts.similarity(b, a, metric)
+                    } else {
+                        counts.similarity(a, b, metric)
+                    }
+                })
+                .collect(),
+        )
+    };
+    let chunk = Chunk::new(vec![
+        Box::new(names(|r| r.0)) as Box<dyn Array>,
+        Box::new(names(|r| r.1)),
+        Box::new(sizes(|r| r.0)),
+        Box::new(sizes(|r| r.1)),
+        Box::new(UInt64Array::from_vec(
+            rows.iter().map(|&(a, b)| counts.shared(a, b)).collect(),
+        )),
+        Box::new(metric(PairwiseMetric::Containment, false)),
+        Box::new(metric(PairwiseMetric::Containment, true)),
+        Box::new(metric(PairwiseMetric::MaxContainment, false)),
+        Box::new(metric(PairwiseMetric::Jaccard, false)),
+    ]);
+
+    let encodings = vec![vec![Encoding::Plain]; schema.fields.len()];
+    let row_groups = RowGroupIterator::try_new(
+        std::iter::once(Ok(chunk)),
+        schema,
+        parquet_write_options(),
+        encodings,
+    )?;
+    for group in row_groups {
+        writer.write(group?)?;
+    }
+    Ok(())
+}
+
+/// Write every pair of datasets sharing at least `min_shared` hashes, most
+/// shared first, with their sizes, shared hashes, containments and Jaccard.
+fn write_long_form(path: &Utf8Path, counts: &SharedCounts, min_shared: u64) -> Result<usize> {
+    let mut pairs: Vec<(&(u32, u32), &u64)> = counts
+        .pairs
+        .iter()
+        .filter(|(_, shared)| **shared >= min_shared)
+        .collect();
+    pairs.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let rows: Vec<(usize, usize)> = pairs
+        .into_iter()
+        .map(|(&(a, b), _)| (a as usize, b as usize))
+        .collect();
+
+    let schema = pairwise_schema();
+    let file = File::create(path).with_context(|| format!("cannot create '{path}'"))?;
+    let mut writer = FileWriter::try_new(file, schema.clone(), parquet_write_options())?;
+    for batch in rows.chunks(PAIRWISE_BATCH_SIZE) {
+        write_pairwise_rows(&mut writer, &schema, counts, batch)?;
+    }
+    writer.end(None)?;
+    Ok(rows.len())
+}
+
+/// Write a square matrix of `metric` between all datasets, laid out like
+/// `sourmash compare --csv`: a header of dataset names, then one row per
+/// dataset in the same order.
+fn write_matrix(path: &Utf8Path, counts: &SharedCounts, metric: PairwiseMetric) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record(&counts.names)?;
+    for a in 0..counts.names.len() {
+        writer.serialize(
+            (0..counts.names.len())
+                .map(|b| counts.similarity(a, b, metric))
+                .collect::<Vec<_>>(),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Compare all datasets of a RevIndex with each other from its `hashes`
+/// column family alone: every hash adds one to each pair of its datasets, so
+/// one scan gives the shared hashes, containments and Jaccard of all pairs
+/// without loading the original sketches. With `output`, pairs sharing at
+/// least `min_shared` hashes are written to Parquet in long form; with
+/// `matrix`, an all-vs-all `metric` matrix is written as CSV. Estimates are
+/// at the database's scaled. Pair counts are held in memory, and a hash in
+/// `n` datasets adds `n*(n-1)/2` of them.
+pub fn revindex_pairwise(
+    db_path: &Utf8Path,
+    output: Option<&Utf8Path>,
+    matrix: Option<&Utf8Path>,
+    metric: PairwiseMetric,
+    min_shared: u64,
+) -> Result<PairwiseSummary> {
+    if output.is_none() && matrix.is_none() {
+        anyhow::bail!("nothing to write; give an output, a matrix or both");
+    }
+    let counts = count_shared(db_path)?;
+    let mut summary = PairwiseSummary {
+        datasets: counts.names.len(),
+        hashes: counts.hashes,
+        pairs: counts.pairs.len(),
+        rows_written: 0,
+    };
+    if let Some(path) = output {
+        summary.rows_written = write_long_form(path, &counts, min_shared)?;
+        eprintln!("Wrote {} dataset pairs to {path}", summary.rows_written);
+    }
+    if let Some(path) = matrix {
+        write_matrix(path, &counts, metric)?;
+        eprintln!("Wrote a {n}x{n} matrix to {path}", n = counts.names.len());
+    }
+    Ok(summary)
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use regex::RegexSet;
+
+/// Regex filters on dataset names (`--include-dataset` / `--exclude-dataset`):
+/// a dataset is kept when it matches any include pattern (or there are none)
+/// and no exclude pattern. Patterns match anywhere in the name unless anchored.
+#[derive(Debug, Clone)]
+pub struct DatasetPatterns {
+    include: Option<RegexSet>,
+    exclude: Option<RegexSet>,
+}
+
+/// Expand `@file` arguments into the patterns listed in the file, one per
+/// line; blank lines and lines starting with '#' are skipped.
+fn expand_patterns(args: &[String]) -> Result<Vec<String>> {
+    let mut patterns = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('@') {
+            Some(path) => {
+                let path = Utf8Path::new(path);
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("cannot read patterns from '{path}'"))?;
+                patterns.extend(
+                    text.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(str::to_string),
+                );
+            }
+            None => patterns.push(arg.clone()),
+        }
+    }
+    Ok(patterns)
+}
+
+fn regex_set(args: &[String]) -> Result<Option<RegexSet>> {
+    let patterns = expand_patterns(args)?;
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        RegexSet::new(&patterns).context("invalid dataset pattern")?,
+    ))
+}
+
+impl DatasetPatterns {
+    /// Compile the patterns; arguments of the form `@file` are read from
+    /// `file`. `None` when there are no patterns at all.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Option<Self>> {
+        let (include, exclude) = (regex_set(include)?, regex_set(exclude)?);
+        if include.is_none() && exclude.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self { include, exclude }))
+    }
+
+    /// Whether a dataset with this name is kept.
+    pub fn matches(&self, name: &str) -> bool {
+        self.include.as_ref().map_or(true, |set| set.is_match(name))
+            && !self.exclude.as_ref().is_some_and(|set| set.is_match(name))
+    }
+}
@@ -0,0 +1,385 @@
+use anyhow::{anyhow, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+
+// oids of the `text` and `numeric` types, for the element type of binary arrays
+const TEXT_OID: i32 = 25;
+const NUMERIC_OID: i32 = 1700;
+
+/// Format of a PostgreSQL `COPY ... FROM STDIN` stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgCopyFormat {
+    Binary,
+    Csv,
+}
+
+impl std::str::FromStr for PgCopyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "binary" => Ok(Self::Binary),
+            "csv" => Ok(Self::Csv),
+            _ => anyhow::bail!("unknown COPY format '{s}'; expected binary or csv"),
+        }
+    }
+}
+
+impl PgCopyFormat {
+    /// Bytes that start a COPY stream.
+    pub fn header(&self) -> Vec<u8> {
+        match self {
+            // signature, flags and header extension length
+            Self::Binary => {
+                let mut out = b"PGCOPY\n\xff\r\n\0".to_vec();
+                out.extend(0i32.to_be_bytes());
+                out.extend(0i32.to_be_bytes());
+                out
+            }
+            Self::Csv => Vec::new(),
+        }
+    }
+
+    /// Bytes that end a COPY stream.
+    pub fn trailer(&self) -> Vec<u8> {
+        match self {
+            Self::Binary => (-1i16).to_be_bytes().to_vec(),
+            Self::Csv => Vec::new(),
+        }
+    }
+}
+
+/// PostgreSQL type of an export column. Hashes don't fit a signed `bigint`,
+/// so they're `numeric(20)` (and abundances `numeric(20)[]`); the `uint32`
+/// columns are `bigint`.
+fn postgres_type(field: &Field) -> Result<&'static str> {
+    match field.data_type() {
+        DataType::UInt64 => Ok("numeric(20)"),
+        DataType::UInt32 => Ok("bigint"),
+        DataType::Float64 => Ok("double precision"),
+        DataType::Boolean => Ok("boolean"),
+        DataType::Utf8 => Ok("text"),
+        DataType::List(item) if item.data_type() == &DataType::Utf8 => Ok("text[]"),
+        DataType::List(item) if item.data_type() == &DataType::UInt64 => Ok("numeric(20)[]"),
+        other => anyhow::bail!("column '{}' has unsupported type {other:?}", field.name),
+    }
+}
+
+/// PostgreSQL `CREATE TABLE IF NOT EXISTS` statement for these export columns.
+pub fn pg_create_table_sql(table: &str, schema: &Schema) -> Result<String> {
+    let columns = schema
+        .fields
+        .iter()
+        .map(|field| {
+            let not_null = if field.is_nullable { "" } else { " NOT NULL" };
+            Ok(format!(
+                "{} {}{not_null}",
+                field.name,
+                postgres_type(field)?
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format!(
+        "CREATE TABLE IF NOT EXISTS {table} ({});",
+        columns.join(", ")
+    ))
+}
+
+/// PostgreSQL `COPY ... FROM STDIN` statement for a stream of these columns.
+pub fn pg_copy_sql(table: &str, schema: &Schema, format: PgCopyFormat) -> String {
+    let names: Vec<&str> = schema.fields.iter().map(|f| f.name.as_str()).collect();
+    let format = match format {
+        PgCopyFormat::Binary => "binary",
+        PgCopyFormat::Csv => "csv",
+    };
+    format!(
+        "COPY {table} ({}) FROM STDIN (FORMAT {format});",
+        names.join(", ")
+    )
+}
+
+/// The binary `numeric` representation of an integer: base-10000 digits, most
+/// significant first, without trailing zero digits.
+fn numeric_binary(value: u64) -> Vec<u8> {
+    let mut digits = Vec::new();
+    let mut rest = value;
+    while rest > 0 {
+        digits.push((rest % 10_000) as i16);
+        rest /= 10_000;
+    }
+    digits.reverse();
+    let weight = digits.len().saturating_sub(1) as i16;
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+
+    let mut out = Vec::with_capacity(8 + 2 * digits.len());
+    out.extend((digits.len() as i16).to_be_bytes());
+    out.extend(weight.to_be_bytes());
+    out.extend(0i16.to_be_bytes()); // sign: positive
+    out.extend(0i16.to_be_bytes()); // display scale
+    for digit in digits {
+        out.extend(digit.to_be_bytes());
+    }
+    out
+}
+
+fn write_binary_field(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend((value.len() as i32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+/// A one-dimensional binary array of `element_oid` items, each given in its
+/// binary representation.
+fn array_binary(element_oid: i32, items: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let len = items.len() as i32;
+    let has_null = items.iter().any(Option::is_none) as i32;
+    let mut out = Vec::new();
+    out.extend(i32::from(len > 0).to_be_bytes()); // dimensions
+    out.extend(has_null.to_be_bytes());
+    out.extend(element_oid.to_be_bytes());
+    if len > 0 {
+        out.extend(len.to_be_bytes());
+        out.extend(1i32.to_be_bytes()); // lower bound
+    }
+    for item in items {
+        match item {
+            Some(item) => write_binary_field(&mut out, item),
+            None => out.extend((-1i32).to_be_bytes()),
+        }
+    }
+    out
+}
+
+/// A one-dimensional binary `text[]`.
+fn text_array_binary(items: &[Option<&str>]) -> Vec<u8> {
+    let items: Vec<Option<Vec<u8>>> = items
+        .iter()
+        .map(|item| item.map(|item| item.as_bytes().to_vec()))
+        .collect();
+    array_binary(TEXT_OID, &items)
+}
+
+/// A one-dimensional binary `numeric[]` of integers.
+fn numeric_array_binary(values: &[u64]) -> Vec<u8> {
+    let items: Vec<Option<Vec<u8>>> = values.iter().map(|&v| Some(numeric_binary(v))).collect();
+    array_binary(NUMERIC_OID, &items)
+}
+
+/// A quoted CSV field; nulls are written as unquoted empty fields instead.
+fn write_csv_text(out: &mut Vec<u8>, value: &str) {
+    out.push(b'"');
+    out.extend(value.replace('"', "\"\"").bytes());
+    out.push(b'"');
+}
+
+/// A `text[]` literal, e.g. `{"a","b"}`, with `NULL` for null items.
+fn text_array_literal(items: &[Option<&str>]) -> String {
+    let items: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            Some(item) => format!("\"{}\"", item.replace('\\', "\\\\").replace('"', "\\\"")),
+            None => "NULL".to_string(),
+        })
+        .collect();
+    format!("{{{}}}", items.join(","))
+}
+
+/// Append the rows of an export chunk to `out` as `COPY` rows in `format`,
+/// with the column types of `pg_create_table_sql`.
+pub fn encode_pgcopy(
+    schema: &Schema,
+    chunk: &Chunk<Box<dyn Array>>,
+    format: PgCopyFormat,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let downcast_err = |field: &Field| anyhow!("unexpected array type for column '{}'", field.name);
+    let columns: Vec<(&Field, &dyn Array)> = schema
+        .fields
+        .iter()
+        .zip(chunk.arrays().iter().map(|array| array.as_ref()))
+        .collect();
+    for row in 0..chunk.len() {
+        if format == PgCopyFormat::Binary {
+            out.extend((columns.len() as i16).to_be_bytes());
+        }
+        for (i, &(field, array)) in columns.iter().enumerate() {
+            if format == PgCopyFormat::Csv && i > 0 {
+                out.push(b',');
+            }
+            if array.is_null(row) {
+                if format == PgCopyFormat::Binary {
+                    out.extend((-1i32).to_be_bytes());
+                }
+                continue;
+            }
+            match field.data_type() {
+                DataType::UInt64 => {
+                    let value = array
+                        .as_any()
+                        .downcast_ref::<UInt64Array>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    match format {
+                        PgCopyFormat::Binary => write_binary_field(out, &numeric_binary(value)),
+                        PgCopyFormat::Csv => out.extend(value.to_string().bytes()),
+                    }
+                }
+                DataType::UInt32 => {
+                    let value = array
+                        .as_any()
+                        .downcast_ref::<UInt32Array>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    match format {
+                        PgCopyFormat::Binary => {
+                            write_binary_field(out, &i64::from(value).to_be_bytes())
+                        }
+                        PgCopyFormat::Csv => out.extend(value.to_string().bytes()),
+                    }
+                }
+                DataType::Float64 => {
+                    let value = array
+                        .as_any()
+                        .downcast_ref::<Float64Array>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    match format {
+                        PgCopyFormat::Binary => write_binary_field(out, &value.to_be_bytes()),
+                        PgCopyFormat::Csv => out.extend(value.to_string().bytes()),
+                    }
+                }
+                DataType::Boolean => {
+                    let value = array
+                        .as_any()
+                        .downcast_ref::<BooleanArray>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    match format {
+                        PgCopyFormat::Binary => write_binary_field(out, &[value as u8]),
+                        PgCopyFormat::Csv => out.push(if value { b't' } else { b'f' }),
+                    }
+                }
+                DataType::Utf8 => {
+                    let value = array
+                        .as_any()
+                        .downcast_ref::<Utf8Array<i32>>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    match format {
+                        PgCopyFormat::Binary => write_binary_field(out, value.as_bytes()),
+                        PgCopyFormat::Csv => write_csv_text(out, value),
+                    }
+                }
+                DataType::List(item) if item.data_type() == &DataType::UInt64 => {
+                    let list = array
+                        .as_any()
+                        .downcast_ref::<ListArray<i32>>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    let values = list
+                        .as_any()
+                        .downcast_ref::<UInt64Array>()
+                        .ok_or_else(|| downcast_err(field))?;
+                    match format {
+                        PgCopyFormat::Binary => {
+                            write_binary_field(out, &numeric_array_binary(values.values()))
+                        }
+                        PgCopyFormat::Csv => {
+                            let values: Vec<String> =
+                                values.values().iter().map(u64::to_string).collect();
+                            out.extend(format!("{{{}}}", values.join(",")).bytes());
+                        }
+                    }
+                }
+                DataType::List(_) => {
+                    let list = array
+                        .as_any()
+                        .downcast_ref::<ListArray<i32>>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .value(row);
+                    let items: Vec<Option<&str>> = list
+                        .as_any()
+                        .downcast_ref::<Utf8Array<i32>>()
+                        .ok_or_else(|| downcast_err(field))?
+                        .iter()
+                        .collect();
+                    match format {
+                        PgCopyFormat::Binary => write_binary_field(out, &text_array_binary(&items)),
+                        PgCopyFormat::Csv => write_csv_text(out, &text_array_literal(&items)),
+                    }
+                }
+                other => anyhow::bail!("column '{}' has unsupported type {other:?}", field.name),
+            }
+        }
+        if format == PgCopyFormat::Csv {
+            out.push(b'\n');
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ndigits, weight, sign and display scale, then the digits
+    fn numeric(header: [i16; 4], digits: &[i16]) -> Vec<u8> {
+        header
+            .iter()
+            .chain(digits)
+            .flat_map(|v| v.to_be_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_numeric_binary() {
+        assert_eq!(numeric_binary(0), numeric([0, 0, 0, 0], &[]));
+        assert_eq!(numeric_binary(10_000), numeric([1, 1, 0, 0], &[1]));
+        assert_eq!(numeric_binary(1234), numeric([1, 0, 0, 0], &[1234]));
+        // 18446744073709551615 is 1844 6744 0737 0955 1615 in base 10000
+        assert_eq!(
+            numeric_binary(u64::MAX),
+            numeric([5, 4, 0, 0], &[1844, 6744, 737, 955, 1615])
+        );
+        // 12 0000 0000: trailing zero digits are dropped, the weight is kept
+        assert_eq!(numeric_binary(1_200_000_000), numeric([1, 2, 0, 0], &[12]));
+        assert_eq!(
+            numeric_binary(1_200_050_000),
+            numeric([2, 2, 0, 0], &[12, 5])
+        );
+    }
+
+    #[test]
+    fn test_text_array_binary() {
+        // no dimensions, no nulls, element type text
+        let mut expected = Vec::new();
+        for v in [0i32, 0, TEXT_OID] {
+            expected.extend(v.to_be_bytes());
+        }
+        assert_eq!(text_array_binary(&[]), expected);
+
+        let mut expected = Vec::new();
+        // one dimension, has nulls, element type text, 2 items from 1
+        for v in [1i32, 1, TEXT_OID, 2, 1] {
+            expected.extend(v.to_be_bytes());
+        }
+        expected.extend(2i32.to_be_bytes());
+        expected.extend(b"ab");
+        expected.extend((-1i32).to_be_bytes());
+        assert_eq!(text_array_binary(&[Some("ab"), None]), expected);
+    }
+
+    #[test]
+    fn test_numeric_array_binary() {
+        let mut expected = Vec::new();
+        for v in [1i32, 0, NUMERIC_OID, 1, 1] {
+            expected.extend(v.to_be_bytes());
+        }
+        let three = numeric_binary(3);
+        expected.extend((three.len() as i32).to_be_bytes());
+        expected.extend(three);
+        assert_eq!(numeric_array_binary(&[3]), expected);
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8Path;
+use sourmash::manifest::Record;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+
+/// The record field a picklist value is compared with, as in sourmash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickColumn {
+    /// the full name
+    Name,
+    /// the first word of the name, e.g. `GCF_000005845.2`
+    Ident,
+    /// the identifier without its version, e.g. `GCF_000005845`
+    IdentPrefix,
+    Md5,
+    /// the first 8 characters of the md5 (`md5prefix8` or `md5short`)
+    Md5Prefix8,
+}
+
+impl std::str::FromStr for PickColumn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Self::Name),
+            "ident" => Ok(Self::Ident),
+            "identprefix" => Ok(Self::IdentPrefix),
+            "md5" => Ok(Self::Md5),
+            "md5prefix8" | "md5short" => Ok(Self::Md5Prefix8),
+            _ => Err(anyhow!(
+                "unknown picklist column type '{s}'; expected name, ident, identprefix, md5, md5prefix8 or md5short"
+            )),
+        }
+    }
+}
+
+impl PickColumn {
+    fn value<'a>(&self, record: &'a Record) -> &'a str {
+        let name = record.name().as_str();
+        let md5 = record.md5().as_str();
+        match self {
+            Self::Name => name,
+            Self::Ident => name.split_whitespace().next().unwrap_or_default(),
+            Self::IdentPrefix => {
+                let ident = name.split_whitespace().next().unwrap_or_default();
+                ident.split('.').next().unwrap_or_default()
+            }
+            Self::Md5 => md5,
+            Self::Md5Prefix8 => md5.get(..8).unwrap_or(md5),
+        }
+    }
+}
+
+/// A sourmash-style picklist, `file.csv:column:coltype[:include|exclude]`,
+/// selecting datasets by the values in one column of a CSV file.
+pub struct Picklist {
+    column: PickColumn,
+    exclude: bool,
+    values: HashSet<String>,
+}
+
+impl fmt::Debug for Picklist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Picklist")
+            .field("column", &self.column)
+            .field("exclude", &self.exclude)
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+impl Picklist {
+    /// Parse a picklist argument and read the values from its CSV file.
+    pub fn load(arg: &str) -> Result<Self> {
+        let parts: Vec<&str> = arg.split(':').collect();
+        let (path, colname, coltype, style) = match parts[..] {
+            [path, colname, coltype] => (path, colname, coltype, "include"),
+            [path, colname, coltype, style] => (path, colname, coltype, style),
+            _ => anyhow::bail!(
+                "invalid picklist '{arg}'; expected file.csv:column:coltype[:include|exclude]"
+            ),
+        };
+        let column = coltype.parse()?;
+        let exclude = match style {
+            "include" => false,
+            "exclude" => true,
+            _ => anyhow::bail!("unknown picklist style '{style}'; expected include or exclude"),
+        };
+
+        let path = Utf8Path::new(path);
+        let file = File::open(path).with_context(|| format!("cannot open picklist '{path}'"))?;
+        let mut rdr = csv::Reader::from_reader(BufReader::new(file));
+        let idx = rdr
+            .headers()?
+            .iter()
+            .position(|h| h == colname)
+            .ok_or_else(|| anyhow!("picklist '{path}' has no column '{colname}'"))?;
+        let mut values = HashSet::new();
+        for row in rdr.records() {
+            let value = row?.get(idx).unwrap_or_default().trim().to_string();
+            if !value.is_empty() {
+                values.insert(value);
+            }
+        }
+        if values.is_empty() {
+            anyhow::bail!("picklist '{path}' has no values in column '{colname}'");
+        }
+        eprintln!(
+            "Loaded {} picklist values from '{path}' ({})",
+            values.len(),
+            if exclude { "excluding" } else { "including" }
+        );
+        Ok(Self {
+            column,
+            exclude,
+            values,
+        })
+    }
+
+    /// Whether the dataset of `record` is kept.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.values.contains(self.column.value(record)) != self.exclude
+    }
+}
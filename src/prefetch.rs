@@ -0,0 +1,60 @@
+use anyhow::Result;
+use rocksdb::DB;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::export::{DatasetResolver, HASHES_CF};
+
+/// A prefetch step for `--prefetch`: only the datasets of each database
+/// sharing at least `threshold_bp` with a query are exported, with all their
+/// hashes, which gives a small export focused on the query for gather-style
+/// analysis downstream.
+#[derive(Debug, Clone)]
+pub struct Prefetch {
+    query: Arc<HashSet<u64>>,
+    threshold_bp: u64,
+}
+
+impl Prefetch {
+    pub fn new(query: HashSet<u64>, threshold_bp: u64) -> Self {
+        Self {
+            query: Arc::new(query),
+            threshold_bp,
+        }
+    }
+
+    /// Query hashes shared with each of `n_datasets` datasets, by dataset ID,
+    /// from point lookups of the query hashes in the `hashes` column family.
+    /// Query hashes above the database's max hash are simply not found.
+    fn overlaps(&self, db: &DB, n_datasets: usize) -> Result<Vec<u64>> {
+        let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+        let mut resolver = DatasetResolver::new(db)?;
+        let mut overlaps = vec![0; n_datasets];
+        for &hash in self.query.iter() {
+            let Some(value) = db.get_pinned_cf(&cf, hash.to_le_bytes())? else {
+                continue;
+            };
+            let Some(datasets) = resolver.resolve(&value)? else {
+                continue;
+            };
+            for idx in datasets {
+                if let Some(overlap) = overlaps.get_mut(idx as usize) {
+                    *overlap += 1;
+                }
+            }
+        }
+        Ok(overlaps)
+    }
+
+    /// Which of the `n_datasets` datasets of a database at `scaled` overlap
+    /// the query by at least the threshold, estimated as shared hashes times
+    /// `scaled` (as `sourmash prefetch` does).
+    pub(crate) fn mask(&self, db: &DB, n_datasets: usize, scaled: u32) -> Result<Vec<bool>> {
+        let threshold = self.threshold_bp.div_ceil(scaled.max(1) as u64).max(1);
+        Ok(self
+            .overlaps(db, n_datasets)?
+            .into_iter()
+            .map(|overlap| overlap >= threshold)
+            .collect())
+    }
+}
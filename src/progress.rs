@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::Serialize;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A single machine-readable progress event, written as one JSON line.
+#[derive(Debug, Serialize)]
+pub(crate) struct ProgressEvent<'a> {
+    pub stage: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db: Option<&'a str>,
+    pub processed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<f64>,
+    pub elapsed_secs: f64,
+}
+
+/// Called periodically with (db, processed, total) while scanning each database.
+#[derive(Clone)]
+pub struct ProgressCallback(pub Arc<dyn Fn(&str, u64, u64) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Reports progress either as interactive progress bars, or as JSON lines
+/// (`--progress-json`) on stderr or a file/FIFO for workflow managers,
+/// plus an optional callback for library callers.
+pub(crate) struct Reporter {
+    bars: MultiProgress,
+    events: Option<Mutex<Box<dyn Write + Send>>>,
+    callback: Option<ProgressCallback>,
+    start: Instant,
+}
+
+impl Reporter {
+    /// `json` is `None` for progress bars, `-` for JSON lines on stderr,
+    /// or a path (e.g. a FIFO) to write JSON lines to.
+    pub fn new(json: Option<&Utf8Path>, callback: Option<ProgressCallback>) -> Result<Self> {
+        let events: Option<Box<dyn Write + Send>> = match json {
+            None => None,
+            Some(path) if path.as_str() == "-" => Some(Box::new(std::io::stderr())),
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("cannot open progress output '{path}'"))?;
+                Some(Box::new(file))
+            }
+        };
+
+        // don't mix progress bars into machine-readable output
+        let bars = if events.is_some() {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
+
+        Ok(Self {
+            bars,
+            events: events.map(Mutex::new),
+            callback,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn add_bar(&self, prefix: &str, total: u64) -> ProgressBar {
+        let bar = self.bars.add(ProgressBar::new(total));
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{prefix} [{elapsed_precise}] {wide_bar} {human_pos}/{human_len} hashes ({per_sec}, ETA {eta})",
+            )
+            .expect("valid progress template"),
+        );
+        bar.set_prefix(prefix.to_string());
+        bar
+    }
+
+    /// Pass scan progress for `db` to the callback, if any.
+    pub fn scanned(&self, db: &str, processed: u64, total: u64) {
+        if let Some(ProgressCallback(ref callback)) = self.callback {
+            callback(db, processed, total);
+        }
+    }
+
+    /// Emit a JSON progress event; a no-op unless `--progress-json` was given.
+    /// `started` is when this stage began, used to estimate the time remaining.
+    pub fn event(
+        &self,
+        stage: &str,
+        db: Option<&str>,
+        processed: u64,
+        total: Option<u64>,
+        started: Instant,
+    ) {
+        let Some(ref events) = self.events else {
+            return;
+        };
+
+        let stage_secs = started.elapsed().as_secs_f64();
+        let eta_secs = total
+            .filter(|&t| processed > 0 && t >= processed)
+            .map(|t| stage_secs * (t - processed) as f64 / processed as f64);
+        let event = ProgressEvent {
+            stage,
+            db,
+            processed,
+            total,
+            eta_secs,
+            elapsed_secs: self.start.elapsed().as_secs_f64(),
+        };
+
+        // progress reporting must never fail an export
+        if let Ok(line) = serde_json::to_string(&event) {
+            let mut out = events.lock().unwrap();
+            let _ = writeln!(out, "{line}");
+            let _ = out.flush();
+        }
+    }
+}
@@ -0,0 +1,1606 @@
+//! Python bindings (the `python` feature).
+
+use arrow2::array::{Array, StructArray};
+use arrow2::datatypes::{DataType, Field};
+use arrow2::ffi::ArrowArrayStream;
+use camino::Utf8PathBuf;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyIOError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyCapsule, PyDict};
+use pyo3::wrap_pyfunction;
+
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::check::check_revindex;
+use crate::classify::{classify_hashes, LcaSource, RankAssignment};
+use crate::clickhouse::{clickhouse_create_table_sql, clickhouse_insert_sql, encode_row_binary};
+use crate::colors::export_colors_to_parquet;
+use crate::concat::concat_parquet;
+use crate::config::ExportConfig;
+use crate::containment::{parquet_containment, write_containment_csv};
+use crate::diff::revindex_diff;
+use crate::dry_run::{estimate_export, ExportEstimate};
+use crate::export::{
+    export_revindex_to_parquet, get_schema as export_schema, load_taxonomies, parse_compression,
+    ExportInterrupted, ExportOptions, LCASummary, EXPORT_SCHEMA_VERSION,
+};
+use crate::gather::{parquet_gather, write_gather_csv};
+use crate::import::parquet_to_revindex;
+use crate::info::{db_info, list_datasets};
+use crate::inspect::{parquet_inspect, type_name};
+use crate::is_revindex_database;
+use crate::lca_db::revindex_to_lca_json;
+use crate::manifest::manifest_to_parquet;
+use crate::membership::bloom_filter_contains;
+use crate::pairwise::{revindex_pairwise, PairwiseMetric};
+use crate::pgcopy::{encode_pgcopy, pg_copy_sql, pg_create_table_sql, PgCopyFormat};
+use crate::progress::ProgressCallback;
+use crate::query::query_parquet;
+use crate::reannotate::reannotate_parquet;
+use crate::rewrite::{rewrite_parquet, Partitioning};
+use crate::sigs::revindex_to_sigs;
+use crate::sketches::sigs_to_parquet;
+use crate::stats::RunStats;
+use crate::stream::ExportStream;
+use crate::summary::parquet_lca_summary;
+use crate::verify::verify_export;
+
+create_exception!(
+    sourmash_plugin_export,
+    ExportInterruptedError,
+    PyRuntimeError,
+    "The export was stopped by SIGINT/SIGTERM/SIGHUP."
+);
+
+// how often a running export checks for KeyboardInterrupt
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Map an export error onto the closest Python exception:
+/// IOError for filesystem problems, ExportInterruptedError for signals,
+/// RuntimeError for everything else.
+pub(crate) fn to_py_err(e: anyhow::Error) -> PyErr {
+    let msg = e.to_string();
+    if e.is::<ExportInterrupted>() {
+        ExportInterruptedError::new_err(msg)
+    } else if e.chain().any(|cause| cause.is::<std::io::Error>()) {
+        PyIOError::new_err(msg)
+    } else {
+        PyRuntimeError::new_err(msg)
+    }
+}
+
+#[pyfunction]
+fn set_global_thread_pool(num_threads: usize) -> PyResult<usize> {
+    if std::panic::catch_unwind(|| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()
+    })
+    .is_ok()
+    {
+        Ok(rayon::current_num_threads())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+            "Could not set the number of threads. Global thread pool might already be initialized.",
+        ))
+    }
+}
+
+/// Cancellation token for exports started from Python: pass it as `handle=`
+/// and call `cancel()` from another thread to stop the export cleanly, as if
+/// it had received SIGINT. A cancelled handle stays cancelled.
+#[pyclass]
+#[derive(Default)]
+struct ExportHandle {
+    cancel_flag: Arc<AtomicBool>,
+}
+
+#[pymethods]
+impl ExportHandle {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn cancel(&self) {
+        self.cancel_flag.store(true, Ordering::SeqCst);
+    }
+
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.cancel_flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Run an export on a worker thread without holding the GIL, so other Python
+/// threads (and progress callbacks) keep running. The calling thread polls for
+/// Python signals: a KeyboardInterrupt sets `cancel_flag`, the export winds down
+/// according to its interrupt policy, and the resulting error is raised with the
+/// KeyboardInterrupt as its cause.
+fn run_interruptible<T, F>(py: Python, cancel_flag: Arc<AtomicBool>, export: F) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce() -> anyhow::Result<T> + Send,
+{
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(export);
+        let mut interrupt = None;
+        while !worker.is_finished() {
+            py.allow_threads(|| std::thread::sleep(SIGNAL_POLL_INTERVAL));
+            if let Err(e) = py.check_signals() {
+                if interrupt.is_none() {
+                    eprintln!("Received interrupt signal! Will terminate after current item...");
+                }
+                cancel_flag.store(true, Ordering::SeqCst);
+                interrupt.get_or_insert(e);
+            }
+        }
+
+        let result = worker
+            .join()
+            .map_err(|_| PyRuntimeError::new_err("export thread panicked"))?;
+        result.map_err(|e| {
+            let err = to_py_err(e);
+            if let Some(interrupt) = interrupt {
+                err.set_cause(py, Some(interrupt));
+            }
+            err
+        })
+    })
+}
+
+/// Wrap a Python callable as a progress callback. Exceptions raised by the
+/// callable are printed and otherwise ignored, so they can't abort an export.
+pub(crate) fn python_progress_callback(callback: PyObject) -> ProgressCallback {
+    ProgressCallback(Arc::new(move |db: &str, processed: u64, total: u64| {
+        Python::with_gil(|py| {
+            if let Err(e) = callback.call1(py, (db, processed, total)) {
+                e.print(py);
+            }
+        })
+    }))
+}
+
+/// Convert run statistics into a Python dict for scripted callers.
+fn run_stats_to_dict<'py>(py: Python<'py>, stats: &RunStats) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("output", stats.output.as_deref())?;
+    dict.set_item("rows_written", stats.rows_written)?;
+    dict.set_item("unclassified_rows", stats.unclassified_rows)?;
+    dict.set_item("row_groups", stats.row_groups)?;
+    dict.set_item("bytes_on_disk", stats.bytes_on_disk)?;
+    dict.set_item("skipped_keys", stats.skipped_keys)?;
+    let invalid_datasets = PyDict::new(py);
+    for (name, invalid) in &stats.invalid_datasets {
+        let entry = PyDict::new(py);
+        entry.set_item("hashes", invalid.hashes)?;
+        entry.set_item("ids", invalid.ids.iter().copied().collect::<Vec<_>>())?;
+        entry.set_item("example_hashes", invalid.example_hashes.clone())?;
+        invalid_datasets.set_item(name, entry)?;
+    }
+    dict.set_item("invalid_datasets", invalid_datasets)?;
+    dict.set_item("duration_secs", stats.duration_secs)?;
+
+    let sources = PyDict::new(py);
+    for (name, summary) in &stats.sources {
+        sources.set_item(name, lca_summary_to_dict(py, summary)?)?;
+    }
+    dict.set_item("sources", sources)?;
+    Ok(dict)
+}
+
+fn lca_summary_to_dict<'py>(py: Python<'py>, summary: &LCASummary) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("count", summary.total())?;
+    dict.set_item("rank_counts", summary.rank_counts().clone())?;
+    dict.set_item("no_lca", summary.no_lca_count())?;
+    dict.set_item("unclassified", summary.unclassified_count())?;
+    Ok(dict)
+}
+
+#[pyfunction]
+#[pyo3(signature = (db_path_list, output, tax_path_list = None, lca_info_path= None, rw = false, readahead_size = 0, no_fill_cache = false, pin_data = false, use_snapshot = false, secondary = false, secondary_path = None, shard = None, num_shards = None, checkpoint = None, checkpoint_rows = 10_000_000, on_interrupt = "finalize".to_string(), force = false, dry_run = false, lca_summary_only = false, sample_fraction = None, every_nth = None, limit = None, progress_json = None, max_memory = None, stats_json = None, progress_callback = None, handle = None, compression = None, row_group_size = None, columns = None, merge = false, since = None, ksize = None, moltype = "DNA".to_string(), picklist = None, include_datasets = None, exclude_datasets = None, min_datasets = None, max_datasets = None, lineage_filter = None, lineage_filter_mode = "lca".to_string(), min_lca_rank = None, drop_unclassified = false, unclassified_output = None, hash_list = None, query = None, tag_queries = false, prefetch = None, prefetch_threshold_bp = 50_000, allow_mixed_params = false, strict = false, dataset_summary = None, lineage_summary = None, kraken_report = None, krona = None, biom = None, cooccurrence = None, cooccurrence_min_shared = 1, top_shared = None, top_shared_n = 100, lineage_hll = None, hll_rank = None, deterministic = false, sharded_output = None, dataset_name = None, dataset_labels = None, anonymize = None, anonymize_salt = None, with_abundance = false, lineage_table = None, contamination_rank = None, disagreement_metrics = false, bloom_filter = None, bloom_filter_fpp = 0.01))]
+#[allow(clippy::too_many_arguments)]
+fn do_export_to_parquet<'py>(
+    py: Python<'py>,
+    db_path_list: Vec<String>,
+    output: Option<String>,
+    tax_path_list: Option<Vec<String>>,
+    lca_info_path: Option<String>,
+    rw: bool,
+    readahead_size: usize,
+    no_fill_cache: bool,
+    pin_data: bool,
+    use_snapshot: bool,
+    secondary: bool,
+    secondary_path: Option<String>,
+    shard: Option<u64>,
+    num_shards: Option<u64>,
+    checkpoint: Option<String>,
+    checkpoint_rows: usize,
+    on_interrupt: String,
+    force: bool,
+    dry_run: bool,
+    lca_summary_only: bool,
+    sample_fraction: Option<f64>,
+    every_nth: Option<u64>,
+    limit: Option<u64>,
+    progress_json: Option<String>,
+    max_memory: Option<u64>,
+    stats_json: Option<String>,
+    progress_callback: Option<PyObject>,
+    handle: Option<PyRef<'py, ExportHandle>>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    columns: Option<Vec<String>>,
+    merge: bool,
+    since: Option<String>,
+    ksize: Option<u32>,
+    moltype: String,
+    picklist: Option<String>,
+    include_datasets: Option<Vec<String>>,
+    exclude_datasets: Option<Vec<String>>,
+    min_datasets: Option<usize>,
+    max_datasets: Option<usize>,
+    lineage_filter: Option<String>,
+    lineage_filter_mode: String,
+    min_lca_rank: Option<String>,
+    drop_unclassified: bool,
+    unclassified_output: Option<String>,
+    hash_list: Option<String>,
+    query: Option<String>,
+    tag_queries: bool,
+    prefetch: Option<String>,
+    prefetch_threshold_bp: u64,
+    allow_mixed_params: bool,
+    strict: bool,
+    dataset_summary: Option<String>,
+    lineage_summary: Option<String>,
+    kraken_report: Option<String>,
+    krona: Option<String>,
+    biom: Option<String>,
+    cooccurrence: Option<String>,
+    cooccurrence_min_shared: u64,
+    top_shared: Option<String>,
+    top_shared_n: usize,
+    lineage_hll: Option<String>,
+    hll_rank: Option<String>,
+    deterministic: bool,
+    sharded_output: Option<String>,
+    dataset_name: Option<String>,
+    dataset_labels: Option<String>,
+    anonymize: Option<String>,
+    anonymize_salt: Option<String>,
+    with_abundance: bool,
+    lineage_table: Option<String>,
+    contamination_rank: Option<String>,
+    disagreement_metrics: bool,
+    bloom_filter: Option<String>,
+    bloom_filter_fpp: f64,
+) -> PyResult<PyObject> {
+    let config = ExportConfig {
+        databases: db_path_list,
+        output,
+        taxonomy: tax_path_list.unwrap_or_default(),
+        lca_info: lca_info_path,
+        rw,
+        readahead_size,
+        no_fill_cache,
+        pin_data,
+        use_snapshot,
+        secondary,
+        secondary_path,
+        shard,
+        num_shards,
+        checkpoint,
+        checkpoint_rows,
+        on_interrupt,
+        force,
+        dry_run,
+        lca_summary_only,
+        sample_fraction,
+        every_nth,
+        limit,
+        progress_json,
+        max_memory,
+        stats_json,
+        progress_callback,
+        compression,
+        row_group_size,
+        columns,
+        merge,
+        since,
+        ksize,
+        moltype,
+        picklist,
+        include_datasets: include_datasets.unwrap_or_default(),
+        exclude_datasets: exclude_datasets.unwrap_or_default(),
+        min_datasets,
+        max_datasets,
+        lineage_filter,
+        lineage_filter_mode,
+        min_lca_rank,
+        drop_unclassified,
+        unclassified_output,
+        hash_list,
+        query,
+        tag_queries,
+        prefetch,
+        prefetch_threshold_bp,
+        allow_mixed_params,
+        strict,
+        dataset_summary,
+        lineage_summary,
+        kraken_report,
+        krona,
+        biom,
+        cooccurrence,
+        cooccurrence_min_shared,
+        top_shared,
+        top_shared_n,
+        lineage_hll,
+        hll_rank,
+        deterministic,
+        sharded_output,
+        dataset_name,
+        dataset_labels,
+        anonymize,
+        anonymize_salt,
+        with_abundance,
+        lineage_table,
+        contamination_rank,
+        disagreement_metrics,
+        bloom_filter,
+        bloom_filter_fpp,
+    };
+    run_export(py, &config, handle)
+}
+
+/// Run an export described by an `ExportConfig`; returns the run statistics dict,
+/// or a pyarrow Table of the records when the config has no output.
+#[pyfunction]
+#[pyo3(name = "export", signature = (config, handle = None))]
+fn do_export<'py>(
+    py: Python<'py>,
+    config: PyRef<'py, ExportConfig>,
+    handle: Option<PyRef<'py, ExportHandle>>,
+) -> PyResult<PyObject> {
+    run_export(py, &config, handle)
+}
+
+fn run_export<'py>(
+    py: Python<'py>,
+    config: &ExportConfig,
+    handle: Option<PyRef<'py, ExportHandle>>,
+) -> PyResult<PyObject> {
+    // cancelled by the handle, or by KeyboardInterrupt (see run_interruptible)
+    let cancel_flag = handle.map_or_else(Default::default, |h| h.cancel_flag.clone());
+    let job = config.build(py, cancel_flag.clone())?;
+
+    if let Some((ksize, moltype)) = job.sketch_selection {
+        let output = job.output.expect("sketch exports always have an output");
+        let stats = py
+            .allow_threads(|| {
+                sigs_to_parquet(
+                    &job.db_paths,
+                    &output,
+                    ksize,
+                    &moltype,
+                    job.tax_paths,
+                    job.lca_info_path.as_deref(),
+                    &job.options,
+                )
+            })
+            .map_err(to_py_err)?;
+        return Ok(run_stats_to_dict(py, &stats)?.into_any().unbind());
+    }
+
+    // without an output file, collect the records in memory
+    let writes_records = !(job.options.dry_run || job.options.lca_summary_only);
+    let output = match job.output {
+        Some(output) => output,
+        None if writes_records => {
+            let ipc = run_interruptible(py, cancel_flag, || {
+                ExportStream::start(job.db_paths, job.tax_paths, job.options)?.into_ipc()
+            })?;
+            let reader = py
+                .import("pyarrow.ipc")?
+                .call_method1("open_stream", (PyBytes::new(py, &ipc),))?;
+            return Ok(reader.call_method0("read_all")?.unbind());
+        }
+        None => Utf8PathBuf::new(),
+    };
+
+    let stats = run_interruptible(py, cancel_flag, || {
+        export_revindex_to_parquet(
+            job.db_paths,
+            output,
+            job.tax_paths,
+            job.lca_info_path,
+            job.options,
+        )
+    })?;
+    Ok(run_stats_to_dict(py, &stats)?.into_any().unbind())
+}
+
+/// An `ArrowArrayStream` handed to Python inside a capsule.
+#[repr(transparent)]
+struct CapsuleStream(ArrowArrayStream);
+
+// SAFETY: the stream is only used by whichever thread the consumer reads it
+// from; its batches are produced by `ExportStream`, which is `Send`.
+unsafe impl Send for CapsuleStream {}
+
+/// Export records streamed as Arrow record batches through the Arrow PyCapsule
+/// interface, e.g. `pyarrow.RecordBatchReader.from_stream(stream)` or
+/// `polars.from_arrow(...)`. The stream can be consumed once.
+#[pyclass(name = "ExportStream")]
+struct PyExportStream {
+    stream: Mutex<Option<ExportStream>>,
+}
+
+#[pymethods]
+impl PyExportStream {
+    #[pyo3(signature = (requested_schema = None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyCapsule>> {
+        // the schema is fixed by the export's column selection
+        let _ = requested_schema;
+        let stream = self
+            .stream
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| PyRuntimeError::new_err("export stream was already consumed"))?;
+
+        let fields = stream.schema().fields;
+        let field = Field::new("", DataType::Struct(fields.clone()), false);
+        let batches = stream.map(move |chunk| -> arrow2::error::Result<Box<dyn Array>> {
+            let chunk = chunk
+                .map_err(|e| arrow2::error::Error::External("export".to_string(), e.into()))?;
+            Ok(
+                StructArray::new(DataType::Struct(fields.clone()), chunk.into_arrays(), None)
+                    .boxed(),
+            )
+        });
+        let ffi_stream = arrow2::ffi::export_iterator(Box::new(batches), field);
+
+        PyCapsule::new(
+            py,
+            CapsuleStream(ffi_stream),
+            Some(CString::new("arrow_array_stream").unwrap()),
+        )
+    }
+}
+
+/// Start the streaming export of an `ExportConfig`, for the stream functions.
+fn start_stream(
+    py: Python<'_>,
+    config: &ExportConfig,
+    handle: Option<PyRef<'_, ExportHandle>>,
+) -> PyResult<ExportStream> {
+    let cancel_flag = handle.map_or_else(Default::default, |h| h.cancel_flag.clone());
+    let job = config.build(py, cancel_flag)?;
+    if job.sketch_selection.is_some() {
+        return Err(PyValueError::new_err(
+            "streaming exports need RevIndex databases, not sketch collections",
+        ));
+    }
+    ExportStream::start(job.db_paths, job.tax_paths, job.options).map_err(to_py_err)
+}
+
+/// Start an export described by an `ExportConfig`, streaming records instead of
+/// writing Parquet; the config's output, checkpoint and compression are ignored.
+#[pyfunction]
+#[pyo3(signature = (config, handle = None))]
+fn export_stream<'py>(
+    py: Python<'py>,
+    config: PyRef<'py, ExportConfig>,
+    handle: Option<PyRef<'py, ExportHandle>>,
+) -> PyResult<PyExportStream> {
+    let stream = start_stream(py, &config, handle)?;
+    Ok(PyExportStream {
+        stream: Mutex::new(Some(stream)),
+    })
+}
+
+/// Export records streamed as ClickHouse RowBinary, one `bytes` object per
+/// batch of rows, with the statements to create the table and insert them.
+#[pyclass(name = "ClickHouseStream")]
+struct PyClickHouseStream {
+    stream: Mutex<ExportStream>,
+}
+
+#[pymethods]
+impl PyClickHouseStream {
+    /// `CREATE TABLE IF NOT EXISTS` statement for the streamed columns.
+    fn create_table_sql(&self, table: &str) -> PyResult<String> {
+        let schema = self.stream.lock().unwrap().schema();
+        clickhouse_create_table_sql(table, &schema)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// `INSERT ... FORMAT RowBinary` statement for the streamed columns.
+    fn insert_sql(&self, table: &str) -> String {
+        clickhouse_insert_sql(table, &self.stream.lock().unwrap().schema())
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Py<PyBytes>>> {
+        let encoded = py.allow_threads(|| -> anyhow::Result<Option<Vec<u8>>> {
+            let mut stream = self.stream.lock().unwrap();
+            let schema = stream.schema();
+            let Some(chunk) = stream.next().transpose()? else {
+                return Ok(None);
+            };
+            let mut out = Vec::new();
+            encode_row_binary(&schema, &chunk, &mut out)?;
+            Ok(Some(out))
+        });
+        Ok(encoded
+            .map_err(to_py_err)?
+            .map(|bytes| PyBytes::new(py, &bytes).unbind()))
+    }
+}
+
+/// Start an export described by an `ExportConfig`, streaming records as
+/// ClickHouse RowBinary; the config's output, checkpoint and compression are
+/// ignored.
+#[pyfunction]
+#[pyo3(signature = (config, handle = None))]
+fn clickhouse_stream<'py>(
+    py: Python<'py>,
+    config: PyRef<'py, ExportConfig>,
+    handle: Option<PyRef<'py, ExportHandle>>,
+) -> PyResult<PyClickHouseStream> {
+    let stream = start_stream(py, &config, handle)?;
+    Ok(PyClickHouseStream {
+        stream: Mutex::new(stream),
+    })
+}
+
+/// Export records streamed as a PostgreSQL `COPY ... FROM STDIN` stream in
+/// binary or CSV format, one `bytes` object per batch of rows (the first and
+/// last also carry the stream's header and trailer), with the statements to
+/// create the table and start the copy.
+#[pyclass(name = "PgCopyStream")]
+struct PyPgCopyStream {
+    stream: Mutex<ExportStream>,
+    format: PgCopyFormat,
+    started: AtomicBool,
+    finished: AtomicBool,
+}
+
+#[pymethods]
+impl PyPgCopyStream {
+    /// `CREATE TABLE IF NOT EXISTS` statement for the streamed columns.
+    fn create_table_sql(&self, table: &str) -> PyResult<String> {
+        let schema = self.stream.lock().unwrap().schema();
+        pg_create_table_sql(table, &schema).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// `COPY ... FROM STDIN` statement for the streamed columns and format.
+    fn copy_sql(&self, table: &str) -> String {
+        pg_copy_sql(table, &self.stream.lock().unwrap().schema(), self.format)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Py<PyBytes>>> {
+        let encoded = py.allow_threads(|| -> anyhow::Result<Option<Vec<u8>>> {
+            let mut stream = self.stream.lock().unwrap();
+            if self.finished.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            let mut out = Vec::new();
+            if !self.started.swap(true, Ordering::SeqCst) {
+                out.extend(self.format.header());
+            }
+            let schema = stream.schema();
+            match stream.next().transpose()? {
+                Some(chunk) => encode_pgcopy(&schema, &chunk, self.format, &mut out)?,
+                None => {
+                    self.finished.store(true, Ordering::SeqCst);
+                    out.extend(self.format.trailer());
+                    if out.is_empty() {
+                        return Ok(None);
+                    }
+                }
+            }
+            Ok(Some(out))
+        });
+        Ok(encoded
+            .map_err(to_py_err)?
+            .map(|bytes| PyBytes::new(py, &bytes).unbind()))
+    }
+}
+
+/// Start an export described by an `ExportConfig`, streaming records for
+/// PostgreSQL `COPY` in `format` (`binary` or `csv`); the config's output,
+/// checkpoint and compression are ignored.
+#[pyfunction]
+#[pyo3(signature = (config, format = "binary".to_string(), handle = None))]
+fn pgcopy_stream<'py>(
+    py: Python<'py>,
+    config: PyRef<'py, ExportConfig>,
+    format: String,
+    handle: Option<PyRef<'py, ExportHandle>>,
+) -> PyResult<PyPgCopyStream> {
+    let format = format
+        .parse()
+        .map_err(|e: anyhow::Error| PyValueError::new_err(e.to_string()))?;
+    let stream = start_stream(py, &config, handle)?;
+    Ok(PyPgCopyStream {
+        stream: Mutex::new(stream),
+        format,
+        started: AtomicBool::new(false),
+        finished: AtomicBool::new(false),
+    })
+}
+
+/// Look up `hashes` in an exported Parquet file, reading only the row groups
+/// that may contain them. Returns the matching rows as a pyarrow Table, or
+/// writes them to `output` and returns a dict of counts.
+#[pyfunction]
+#[pyo3(name = "query_parquet", signature = (parquet_path, hashes, output = None))]
+fn do_query_parquet(
+    py: Python,
+    parquet_path: String,
+    hashes: Vec<u64>,
+    output: Option<String>,
+) -> PyResult<PyObject> {
+    let parquet_path = Utf8PathBuf::from(parquet_path);
+    let query = py
+        .allow_threads(|| query_parquet(&parquet_path, &hashes))
+        .map_err(to_py_err)?;
+
+    let Some(output) = output else {
+        let ipc = query.to_ipc().map_err(to_py_err)?;
+        let reader = py
+            .import("pyarrow.ipc")?
+            .call_method1("open_stream", (PyBytes::new(py, &ipc),))?;
+        return Ok(reader.call_method0("read_all")?.unbind());
+    };
+
+    py.allow_threads(|| query.write_parquet(Utf8PathBuf::from(output).as_path()))
+        .map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("rows", query.num_rows())?;
+    dict.set_item("row_groups_read", query.row_groups_read)?;
+    dict.set_item("row_groups_total", query.row_groups_total)?;
+    Ok(dict.into_any().unbind())
+}
+
+/// Gather-style search of a query's hashes against an exported Parquet file.
+/// Returns the matches as a list of dicts, and also writes them as CSV to `output`.
+#[pyfunction]
+#[pyo3(name = "parquet_gather", signature = (parquet_path, query_hashes, ksize, scaled, threshold_bp = 50_000, output = None))]
+fn do_parquet_gather<'py>(
+    py: Python<'py>,
+    parquet_path: String,
+    query_hashes: Vec<u64>,
+    ksize: u32,
+    scaled: u32,
+    threshold_bp: u64,
+    output: Option<String>,
+) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    if scaled == 0 {
+        return Err(PyValueError::new_err("query must be a scaled sketch"));
+    }
+    let parquet_path = Utf8PathBuf::from(parquet_path);
+    let matches = py
+        .allow_threads(|| {
+            let matches =
+                parquet_gather(&parquet_path, &query_hashes, ksize, scaled, threshold_bp)?;
+            if let Some(output) = output {
+                write_gather_csv(&matches, Utf8PathBuf::from(output).as_path())?;
+            }
+            Ok(matches)
+        })
+        .map_err(to_py_err)?;
+
+    matches
+        .iter()
+        .map(|m| {
+            let row = PyDict::new(py);
+            row.set_item("rank", m.rank)?;
+            row.set_item("name", &m.name)?;
+            row.set_item("source", &m.source)?;
+            row.set_item("shared_hashes", m.shared_hashes)?;
+            row.set_item("unique_hashes", m.unique_hashes)?;
+            row.set_item("containment", m.containment)?;
+            row.set_item("f_unique_to_query", m.f_unique_to_query)?;
+            Ok(row)
+        })
+        .collect()
+}
+
+/// Containment of each query, given as (name, hashes, scaled), in the datasets
+/// of an exported Parquet file. Returns the matches as a list of dicts, and also
+/// writes them as CSV to `output`.
+#[pyfunction]
+#[pyo3(name = "parquet_containment", signature = (parquet_path, queries, ksize, min_containment = 0.0, output = None))]
+fn do_parquet_containment<'py>(
+    py: Python<'py>,
+    parquet_path: String,
+    queries: Vec<(String, Vec<u64>, u32)>,
+    ksize: u32,
+    min_containment: f64,
+    output: Option<String>,
+) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    if queries.iter().any(|(_, _, scaled)| *scaled == 0) {
+        return Err(PyValueError::new_err("queries must be scaled sketches"));
+    }
+    let parquet_path = Utf8PathBuf::from(parquet_path);
+    let matches = py
+        .allow_threads(|| {
+            let matches = parquet_containment(&parquet_path, &queries, ksize, min_containment)?;
+            if let Some(output) = output {
+                write_containment_csv(&matches, Utf8PathBuf::from(output).as_path())?;
+            }
+            Ok(matches)
+        })
+        .map_err(to_py_err)?;
+
+    matches
+        .iter()
+        .map(|m| {
+            let row = PyDict::new(py);
+            row.set_item("query", &m.query)?;
+            row.set_item("name", &m.name)?;
+            row.set_item("source", &m.source)?;
+            row.set_item("shared_hashes", m.shared_hashes)?;
+            row.set_item("query_hashes", m.query_hashes)?;
+            row.set_item("containment", m.containment)?;
+            Ok(row)
+        })
+        .collect()
+}
+
+fn rank_assignment_to_dict<'py>(
+    py: Python<'py>,
+    assignment: &RankAssignment,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("rank", &assignment.rank)?;
+    dict.set_item("lineage", &assignment.lineage)?;
+    dict.set_item("hashes", assignment.hashes)?;
+    dict.set_item("fraction", assignment.fraction)?;
+    Ok(dict)
+}
+
+/// Check hashes against a bloom filter written by `--bloom-filter`: `False`
+/// means a hash is certainly not in the export, `True` that it probably is.
+#[pyfunction]
+#[pyo3(name = "bloom_filter_contains")]
+fn do_bloom_filter_contains(py: Python<'_>, path: String, hashes: Vec<u64>) -> PyResult<Vec<bool>> {
+    let path = Utf8PathBuf::from(path);
+    py.allow_threads(|| bloom_filter_contains(&path, &hashes))
+        .map_err(to_py_err)
+}
+
+/// LCA classification of a query sketch's hashes, looked up in an export
+/// (`parquet`) or in a RevIndex with taxonomy (`db` and `taxonomy`).
+#[pyfunction]
+#[pyo3(signature = (query_hashes, ksize, scaled, parquet = None, db = None, taxonomy = None, threshold = 5))]
+#[allow(clippy::too_many_arguments)]
+fn classify_sig<'py>(
+    py: Python<'py>,
+    query_hashes: Vec<u64>,
+    ksize: u32,
+    scaled: u32,
+    parquet: Option<String>,
+    db: Option<String>,
+    taxonomy: Option<Vec<String>>,
+    threshold: usize,
+) -> PyResult<Bound<'py, PyDict>> {
+    let classification = match (parquet, db) {
+        (Some(parquet), None) => {
+            let parquet = Utf8PathBuf::from(parquet);
+            py.allow_threads(|| {
+                classify_hashes(
+                    &LcaSource::Parquet(&parquet),
+                    &query_hashes,
+                    ksize,
+                    scaled,
+                    threshold,
+                )
+            })
+        }
+        (None, Some(db)) => {
+            let db_path = Utf8PathBuf::from(db);
+            if !is_revindex_database(&db_path) {
+                return Err(PyValueError::new_err(format!(
+                    "'{db_path}' is not a valid RevIndex database"
+                )));
+            }
+            let tax_paths: Vec<Utf8PathBuf> = taxonomy
+                .unwrap_or_default()
+                .into_iter()
+                .map(Utf8PathBuf::from)
+                .collect();
+            py.allow_threads(|| {
+                let taxonomy = load_taxonomies(tax_paths)?.ok_or_else(|| {
+                    anyhow::anyhow!("classifying against a database needs a taxonomy")
+                })?;
+                classify_hashes(
+                    &LcaSource::RevIndex {
+                        db_path: &db_path,
+                        taxonomy: &taxonomy,
+                    },
+                    &query_hashes,
+                    ksize,
+                    scaled,
+                    threshold,
+                )
+            })
+        }
+        _ => return Err(PyValueError::new_err("give exactly one of parquet or db")),
+    }
+    .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("query_hashes", classification.query_hashes)?;
+    dict.set_item("classified_hashes", classification.classified_hashes)?;
+    let ranks = classification
+        .ranks
+        .iter()
+        .map(|r| rank_assignment_to_dict(py, r))
+        .collect::<PyResult<Vec<_>>>()?;
+    dict.set_item("ranks", ranks)?;
+    let assignment = classification
+        .assignment
+        .as_ref()
+        .map(|a| rank_assignment_to_dict(py, a))
+        .transpose()?;
+    dict.set_item("assignment", assignment)?;
+    Ok(dict)
+}
+
+/// Rebuild a RevIndex at `output` from one or more exported Parquet files.
+/// Returns a dict with `ksize`, `scaled`, `n_datasets` and `n_hashes`.
+#[pyfunction]
+#[pyo3(name = "parquet_to_revindex", signature = (parquet_paths, output, moltype = "DNA".to_string()))]
+fn do_parquet_to_revindex<'py>(
+    py: Python<'py>,
+    parquet_paths: Vec<String>,
+    output: String,
+    moltype: String,
+) -> PyResult<Bound<'py, PyDict>> {
+    let parquet_paths: Vec<Utf8PathBuf> =
+        parquet_paths.into_iter().map(Utf8PathBuf::from).collect();
+    let output = Utf8PathBuf::from(output);
+    let summary = py
+        .allow_threads(|| parquet_to_revindex(&parquet_paths, &output, &moltype))
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("ksize", summary.ksize)?;
+    dict.set_item("scaled", summary.scaled)?;
+    dict.set_item("n_datasets", summary.n_datasets)?;
+    dict.set_item("n_hashes", summary.n_hashes)?;
+    Ok(dict)
+}
+
+/// Recompute the taxonomy columns of an existing export with a new taxonomy;
+/// returns the run statistics dict.
+#[pyfunction]
+#[pyo3(name = "reannotate_parquet", signature = (input, output, tax_path_list, lca_info_path = None, force = false, compression = None, row_group_size = None, columns = None))]
+#[allow(clippy::too_many_arguments)]
+fn do_reannotate_parquet(
+    py: Python,
+    input: String,
+    output: String,
+    tax_path_list: Vec<String>,
+    lca_info_path: Option<String>,
+    force: bool,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    columns: Option<Vec<String>>,
+) -> PyResult<PyObject> {
+    let options = ExportOptions {
+        force,
+        compression: compression
+            .as_deref()
+            .map(parse_compression)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        row_group_size,
+        columns,
+        ..Default::default()
+    };
+    let (input, output) = (Utf8PathBuf::from(input), Utf8PathBuf::from(output));
+    let tax_paths = tax_path_list.into_iter().map(Utf8PathBuf::from).collect();
+    let lca_info_path = lca_info_path.map(Utf8PathBuf::from);
+
+    let stats = py
+        .allow_threads(|| {
+            reannotate_parquet(
+                &input,
+                &output,
+                tax_paths,
+                lca_info_path.as_deref(),
+                &options,
+            )
+        })
+        .map_err(to_py_err)?;
+    Ok(run_stats_to_dict(py, &stats)?.into_any().unbind())
+}
+
+/// Rebuild the LCA summary of an existing export; returns a dict of summaries
+/// keyed by source (or `all_k{ksize}_sc{scaled}` without `by_source`), and
+/// writes them as CSV to `lca_info_path` when given.
+#[pyfunction]
+#[pyo3(name = "parquet_lca_summary", signature = (parquet_path, lca_info_path = None, by_source = true))]
+fn do_parquet_lca_summary(
+    py: Python,
+    parquet_path: String,
+    lca_info_path: Option<String>,
+    by_source: bool,
+) -> PyResult<PyObject> {
+    let parquet_path = Utf8PathBuf::from(parquet_path);
+    let lca_info_path = lca_info_path.map(Utf8PathBuf::from);
+    let summaries = py
+        .allow_threads(|| parquet_lca_summary(&parquet_path, lca_info_path.as_deref(), by_source))
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    for (label, summary) in &summaries {
+        dict.set_item(label, lca_summary_to_dict(py, summary)?)?;
+    }
+    Ok(dict.into_any().unbind())
+}
+
+/// Describe an exported Parquet file; returns a dict with `num_rows`,
+/// `bytes_on_disk`, `created_by`, `schema`, `row_groups`, `columns`,
+/// `metadata` and `source_rows`.
+#[pyfunction]
+#[pyo3(name = "parquet_inspect")]
+fn do_parquet_inspect(py: Python, path: String) -> PyResult<PyObject> {
+    let path = Utf8PathBuf::from(path);
+    let inspection = py
+        .allow_threads(|| parquet_inspect(&path))
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("num_rows", inspection.num_rows)?;
+    dict.set_item("bytes_on_disk", inspection.bytes_on_disk)?;
+    dict.set_item("created_by", inspection.created_by)?;
+    dict.set_item("schema", inspection.schema)?;
+    let row_groups = inspection
+        .row_groups
+        .iter()
+        .map(|rg| {
+            let group = PyDict::new(py);
+            group.set_item("num_rows", rg.num_rows)?;
+            group.set_item("compressed_bytes", rg.compressed_bytes)?;
+            group.set_item("uncompressed_bytes", rg.uncompressed_bytes)?;
+            Ok(group)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    dict.set_item("row_groups", row_groups)?;
+    let columns = inspection
+        .columns
+        .iter()
+        .map(|c| {
+            let column = PyDict::new(py);
+            column.set_item("name", &c.name)?;
+            column.set_item("compression", &c.compression)?;
+            column.set_item("compressed_bytes", c.compressed_bytes)?;
+            column.set_item("uncompressed_bytes", c.uncompressed_bytes)?;
+            Ok(column)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    dict.set_item("columns", columns)?;
+    dict.set_item("metadata", inspection.metadata)?;
+    dict.set_item("source_rows", inspection.source_rows)?;
+    Ok(dict.into_any().unbind())
+}
+
+/// Cross-check an export against one of its source databases. Returns a dict
+/// with `source`, `db_hashes`, `parquet_rows`, `sampled` and `problems`, a list
+/// of every divergence found (empty when the export matches).
+#[pyfunction]
+#[pyo3(name = "verify_export", signature = (parquet_path, db_path, sample_size = 1000))]
+fn do_verify_export(
+    py: Python,
+    parquet_path: String,
+    db_path: String,
+    sample_size: usize,
+) -> PyResult<PyObject> {
+    let parquet_path = Utf8PathBuf::from(parquet_path);
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    let report = py
+        .allow_threads(|| verify_export(&parquet_path, &db_path, sample_size))
+        .map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("source", report.source)?;
+    dict.set_item("db_hashes", report.db_hashes)?;
+    dict.set_item("parquet_rows", report.parquet_rows)?;
+    dict.set_item("sampled", report.sampled)?;
+    dict.set_item("problems", report.problems)?;
+    Ok(dict.into_any().unbind())
+}
+
+/// Check the integrity of a RevIndex database with one scan. Returns a dict
+/// with `source`, `format_version`, `keys`, `bad_keys`, `bad_values`,
+/// `empty_values`, `invalid_datasets`, `dataset_hashes` (a list of
+/// (name, hashes) in manifest order) and `problems` (empty when healthy).
+#[pyfunction]
+#[pyo3(name = "revindex_check")]
+fn do_revindex_check(py: Python, db_path: String) -> PyResult<PyObject> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    let report = py
+        .allow_threads(|| check_revindex(&db_path))
+        .map_err(to_py_err)?;
+    let invalid = PyDict::new(py);
+    invalid.set_item("hashes", report.invalid_datasets.hashes)?;
+    invalid.set_item(
+        "ids",
+        report
+            .invalid_datasets
+            .ids
+            .iter()
+            .copied()
+            .collect::<Vec<_>>(),
+    )?;
+    invalid.set_item("example_hashes", report.invalid_datasets.example_hashes)?;
+    let dict = PyDict::new(py);
+    dict.set_item("source", report.source)?;
+    dict.set_item("format_version", report.format_version)?;
+    dict.set_item("keys", report.keys)?;
+    dict.set_item("bad_keys", report.bad_keys)?;
+    dict.set_item("bad_values", report.bad_values)?;
+    dict.set_item("empty_values", report.empty_values)?;
+    dict.set_item("invalid_datasets", invalid)?;
+    dict.set_item("dataset_hashes", report.dataset_hashes)?;
+    dict.set_item("problems", report.problems)?;
+    Ok(dict.into_any().unbind())
+}
+
+/// Combine exports into one file, dropping duplicate (hash, source) rows and
+/// summing their LCA summary CSVs. Returns a dict with `files`, `rows_read`,
+/// `rows_written` and `duplicates`.
+#[pyfunction]
+#[pyo3(name = "concat_parquet", signature = (inputs, output, lca_info_list = None, lca_info_path = None, force = false, compression = None))]
+fn do_concat_parquet(
+    py: Python,
+    inputs: Vec<String>,
+    output: String,
+    lca_info_list: Option<Vec<String>>,
+    lca_info_path: Option<String>,
+    force: bool,
+    compression: Option<String>,
+) -> PyResult<PyObject> {
+    let options = ExportOptions {
+        force,
+        compression: compression
+            .as_deref()
+            .map(parse_compression)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        ..Default::default()
+    };
+    let inputs: Vec<Utf8PathBuf> = inputs.into_iter().map(Utf8PathBuf::from).collect();
+    let lca_infos: Vec<Utf8PathBuf> = lca_info_list
+        .unwrap_or_default()
+        .into_iter()
+        .map(Utf8PathBuf::from)
+        .collect();
+    let output = Utf8PathBuf::from(output);
+    let lca_info_path = lca_info_path.map(Utf8PathBuf::from);
+
+    let summary = py
+        .allow_threads(|| {
+            concat_parquet(
+                &inputs,
+                &output,
+                &lca_infos,
+                lca_info_path.as_deref(),
+                &options,
+            )
+        })
+        .map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("files", summary.files)?;
+    dict.set_item("rows_read", summary.rows_read)?;
+    dict.set_item("rows_written", summary.rows_written)?;
+    dict.set_item("duplicates", summary.duplicates)?;
+    Ok(dict.into_any().unbind())
+}
+
+/// Rewrite an export with new writer settings, sorted by hash with `sort`,
+/// and partitioned into a directory with `partition_by` (`source` or
+/// `hash:N`). Returns a dict with `rows_written` and the `files` written.
+#[pyfunction]
+#[pyo3(name = "rewrite_parquet", signature = (input, output, sort = false, partition_by = None, force = false, compression = None, row_group_size = None, columns = None))]
+#[allow(clippy::too_many_arguments)]
+fn do_rewrite_parquet(
+    py: Python,
+    input: String,
+    output: String,
+    sort: bool,
+    partition_by: Option<String>,
+    force: bool,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    columns: Option<Vec<String>>,
+) -> PyResult<PyObject> {
+    let partitioning = partition_by
+        .as_deref()
+        .map(str::parse::<Partitioning>)
+        .transpose()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let options = ExportOptions {
+        force,
+        compression: compression
+            .as_deref()
+            .map(parse_compression)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        row_group_size,
+        columns,
+        ..Default::default()
+    };
+    let (input, output) = (Utf8PathBuf::from(input), Utf8PathBuf::from(output));
+
+    let summary = py
+        .allow_threads(|| rewrite_parquet(&input, &output, sort, partitioning, &options))
+        .map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("rows_written", summary.rows_written)?;
+    dict.set_item("files", summary.files)?;
+    Ok(dict.into_any().unbind())
+}
+
+/// Export the collection manifest of a RevIndex to Parquet; returns the number
+/// of datasets written.
+#[pyfunction]
+#[pyo3(name = "manifest_to_parquet")]
+fn do_manifest_to_parquet(py: Python, db_path: String, output: String) -> PyResult<usize> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    let output = Utf8PathBuf::from(output);
+    py.allow_threads(|| manifest_to_parquet(&db_path, &output))
+        .map_err(to_py_err)
+}
+
+/// Export sketch collections (`.zip` or signature files) to Parquet without a
+/// RevIndex; returns the run statistics as a dict.
+#[pyfunction]
+#[pyo3(name = "sigs_to_parquet", signature = (sig_paths, output, ksize = 31, moltype = "DNA".to_string(), tax_path_list = None, lca_info_path = None, force = false, compression = None, row_group_size = None, columns = None, with_abundance = false))]
+#[allow(clippy::too_many_arguments)]
+fn do_sigs_to_parquet(
+    py: Python,
+    sig_paths: Vec<String>,
+    output: String,
+    ksize: u32,
+    moltype: String,
+    tax_path_list: Option<Vec<String>>,
+    lca_info_path: Option<String>,
+    force: bool,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    columns: Option<Vec<String>>,
+    with_abundance: bool,
+) -> PyResult<PyObject> {
+    let options = ExportOptions {
+        force,
+        compression: compression
+            .as_deref()
+            .map(parse_compression)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        row_group_size,
+        columns,
+        with_abundance,
+        ..Default::default()
+    };
+    let sig_paths: Vec<Utf8PathBuf> = sig_paths.into_iter().map(Utf8PathBuf::from).collect();
+    let tax_paths: Vec<Utf8PathBuf> = tax_path_list
+        .unwrap_or_default()
+        .into_iter()
+        .map(Utf8PathBuf::from)
+        .collect();
+    let output = Utf8PathBuf::from(output);
+    let lca_info_path = lca_info_path.map(Utf8PathBuf::from);
+
+    let stats = py
+        .allow_threads(|| {
+            sigs_to_parquet(
+                &sig_paths,
+                &output,
+                ksize,
+                &moltype,
+                tax_paths,
+                lca_info_path.as_deref(),
+                &options,
+            )
+        })
+        .map_err(to_py_err)?;
+    Ok(run_stats_to_dict(py, &stats)?.into_any().unbind())
+}
+
+#[pyfunction]
+#[pyo3(signature = (db_path, output, force = false))]
+fn do_colors_to_parquet(py: Python, db_path: String, output: String, force: bool) -> PyResult<u64> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    py.allow_threads(|| export_colors_to_parquet(db_path, Utf8PathBuf::from(output), force))
+        .map_err(to_py_err)
+}
+
+/// Report ksize, scaled, moltype, dataset count, estimated hash count and
+/// format version of a database, for checking inputs before an export.
+#[pyfunction]
+fn get_db_info(py: Python, db_path: String) -> PyResult<Bound<PyDict>> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    let info = py.allow_threads(|| db_info(&db_path)).map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("ksize", info.ksize)?;
+    dict.set_item("scaled", info.scaled)?;
+    dict.set_item("moltype", info.moltype)?;
+    dict.set_item("n_datasets", info.n_datasets)?;
+    dict.set_item("estimated_hashes", info.estimated_hashes)?;
+    dict.set_item("format_version", info.format_version)?;
+    dict.set_item("has_colors", info.has_colors)?;
+    Ok(dict)
+}
+
+/// Base columns of a version of the export schema (the current one by default), as
+/// a dict with `version` and `columns`, a list of dicts with `name`, `type`
+/// and `nullable`.
+#[pyfunction]
+#[pyo3(signature = (version = None))]
+fn get_schema(py: Python, version: Option<u32>) -> PyResult<Bound<PyDict>> {
+    let version = version.unwrap_or(EXPORT_SCHEMA_VERSION);
+    let schema = export_schema(version).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut columns = Vec::new();
+    for field in &schema.fields {
+        let column = PyDict::new(py);
+        column.set_item("name", &field.name)?;
+        column.set_item("type", type_name(field.data_type()))?;
+        column.set_item("nullable", field.is_nullable)?;
+        columns.push(column);
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("version", version)?;
+    dict.set_item("columns", columns)?;
+    Ok(dict)
+}
+
+/// Compare two databases or exports. Returns a dict with `a_only`, `b_only`,
+/// `shared` and `shared_changed` hash counts; the per-hash diff is written to
+/// `output` as Parquet when given.
+#[pyfunction]
+#[pyo3(name = "revindex_diff", signature = (a, b, output = None))]
+fn do_revindex_diff<'py>(
+    py: Python<'py>,
+    a: String,
+    b: String,
+    output: Option<String>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let (a, b) = (Utf8PathBuf::from(a), Utf8PathBuf::from(b));
+    let output = output.map(Utf8PathBuf::from);
+    let summary = py
+        .allow_threads(|| revindex_diff(&a, &b, output.as_deref()))
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("a_only", summary.a_only)?;
+    dict.set_item("b_only", summary.b_only)?;
+    dict.set_item("shared", summary.shared)?;
+    dict.set_item("shared_changed", summary.shared_changed)?;
+    Ok(dict)
+}
+
+/// Compare all datasets of a database from its shared hashes. Pairs sharing at
+/// least `min_shared` hashes are written to `output` as Parquet, and a
+/// `metric` matrix to `matrix` as CSV. Returns a dict with `datasets`,
+/// `hashes`, `pairs` and `rows_written`.
+#[pyfunction]
+#[pyo3(name = "revindex_pairwise", signature = (db_path, output = None, matrix = None, metric = "jaccard".to_string(), min_shared = 1))]
+fn do_revindex_pairwise<'py>(
+    py: Python<'py>,
+    db_path: String,
+    output: Option<String>,
+    matrix: Option<String>,
+    metric: String,
+    min_shared: u64,
+) -> PyResult<Bound<'py, PyDict>> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+    let metric = metric
+        .parse::<PairwiseMetric>()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let output = output.map(Utf8PathBuf::from);
+    let matrix = matrix.map(Utf8PathBuf::from);
+    let summary = py
+        .allow_threads(|| {
+            revindex_pairwise(
+                &db_path,
+                output.as_deref(),
+                matrix.as_deref(),
+                metric,
+                min_shared,
+            )
+        })
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("datasets", summary.datasets)?;
+    dict.set_item("hashes", summary.hashes)?;
+    dict.set_item("pairs", summary.pairs)?;
+    dict.set_item("rows_written", summary.rows_written)?;
+    Ok(dict)
+}
+
+/// Write a database and its taxonomy as a sourmash LCA database (JSON, gzipped
+/// if `output` ends in `.gz`) to `output`. Returns a dict with `ksize`, `scaled`, `datasets`,
+/// `unassigned`, `lineages` and `hashes`.
+#[pyfunction]
+#[pyo3(name = "revindex_to_lca_json", signature = (db_path, tax_path_list, output, ksize = None))]
+fn do_revindex_to_lca_json<'py>(
+    py: Python<'py>,
+    db_path: String,
+    tax_path_list: Vec<String>,
+    output: String,
+    ksize: Option<u32>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+    let tax_paths = tax_path_list.into_iter().map(Utf8PathBuf::from).collect();
+    let output = Utf8PathBuf::from(output);
+    let summary = py
+        .allow_threads(|| revindex_to_lca_json(&db_path, tax_paths, &output, ksize))
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("ksize", summary.ksize)?;
+    dict.set_item("scaled", summary.scaled)?;
+    dict.set_item("datasets", summary.datasets)?;
+    dict.set_item("unassigned", summary.unassigned)?;
+    dict.set_item("lineages", summary.lineages)?;
+    dict.set_item("hashes", summary.hashes)?;
+    Ok(dict)
+}
+
+/// Reconstruct the sketch of every dataset in a database, as dicts with `name`,
+/// `filename`, `moltype`, `ksize`, `scaled` and (sorted) `hashes`.
+#[pyfunction]
+#[pyo3(name = "revindex_to_sigs")]
+fn do_revindex_to_sigs(py: Python, db_path: String) -> PyResult<Vec<Bound<PyDict>>> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    let sketches = py
+        .allow_threads(|| revindex_to_sigs(&db_path))
+        .map_err(to_py_err)?;
+    sketches
+        .into_iter()
+        .map(|sketch| {
+            let dict = PyDict::new(py);
+            dict.set_item("name", sketch.name)?;
+            dict.set_item("filename", sketch.filename)?;
+            dict.set_item("moltype", sketch.moltype)?;
+            dict.set_item("ksize", sketch.ksize)?;
+            dict.set_item("scaled", sketch.scaled)?;
+            dict.set_item("hashes", sketch.hashes)?;
+            Ok(dict)
+        })
+        .collect()
+}
+
+/// List the datasets in a database as dicts with `name`, `filename` and `md5`,
+/// or as a pyarrow Table with those columns when `as_arrow` is set.
+#[pyfunction]
+#[pyo3(name = "list_datasets", signature = (db_path, as_arrow = false))]
+fn do_list_datasets(py: Python, db_path: String, as_arrow: bool) -> PyResult<PyObject> {
+    let db_path = Utf8PathBuf::from(db_path);
+    if !is_revindex_database(&db_path) {
+        return Err(PyValueError::new_err(format!(
+            "'{db_path}' is not a valid RevIndex database"
+        )));
+    }
+
+    let datasets = py
+        .allow_threads(|| list_datasets(&db_path))
+        .map_err(to_py_err)?;
+
+    if as_arrow {
+        let columns = PyDict::new(py);
+        columns.set_item("name", datasets.iter().map(|d| &d.name).collect::<Vec<_>>())?;
+        columns.set_item(
+            "filename",
+            datasets.iter().map(|d| &d.filename).collect::<Vec<_>>(),
+        )?;
+        columns.set_item("md5", datasets.iter().map(|d| &d.md5).collect::<Vec<_>>())?;
+        let table = py.import("pyarrow")?.call_method1("table", (columns,))?;
+        return Ok(table.unbind());
+    }
+
+    let rows = datasets
+        .iter()
+        .map(|d| {
+            let row = PyDict::new(py);
+            row.set_item("name", &d.name)?;
+            row.set_item("filename", &d.filename)?;
+            row.set_item("md5", &d.md5)?;
+            Ok(row)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(rows.into_pyobject(py)?.into_any().unbind())
+}
+
+fn estimate_to_dict<'py>(
+    py: Python<'py>,
+    estimate: &ExportEstimate,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("estimated_hashes", estimate.estimated_hashes)?;
+    dict.set_item("projected_rows", estimate.projected_rows)?;
+    dict.set_item("projected_bytes", estimate.projected_bytes)?;
+    dict.set_item("bytes_per_codec", estimate.bytes_per_codec.clone())?;
+    dict.set_item("projected_secs", estimate.projected_secs)?;
+    dict.set_item("threads", estimate.threads)?;
+
+    let databases = PyDict::new(py);
+    for db in &estimate.databases {
+        let entry = PyDict::new(py);
+        entry.set_item("ksize", db.ksize)?;
+        entry.set_item("scaled", db.scaled)?;
+        entry.set_item("n_datasets", db.n_datasets)?;
+        entry.set_item("estimated_hashes", db.estimated_hashes)?;
+        entry.set_item("projected_rows", db.projected_rows)?;
+        entry.set_item("sampled_hashes", db.sampled_hashes)?;
+        entry.set_item("taxonomy_matches", db.taxonomy_matches)?;
+        entry.set_item("classified", db.classified)?;
+        entry.set_item("projected_bytes", db.projected_bytes)?;
+        entry.set_item("bytes_per_codec", db.bytes_per_codec.clone())?;
+        entry.set_item("projected_secs", db.projected_secs)?;
+        databases.set_item(&db.source, entry)?;
+    }
+    dict.set_item("databases", databases)?;
+    Ok(dict)
+}
+
+/// Sample each database and extrapolate the rows, output size (per codec) and
+/// run time of exporting it with the settings in `options`, an `ExportConfig`
+/// whose own databases are ignored.
+#[pyfunction]
+#[pyo3(name = "estimate_export", signature = (db_paths, options = None, sample_fraction = None))]
+fn do_estimate_export<'py>(
+    py: Python<'py>,
+    db_paths: Vec<String>,
+    options: Option<PyRef<'py, ExportConfig>>,
+    sample_fraction: Option<f64>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let db_paths: Vec<Utf8PathBuf> = db_paths.into_iter().map(Utf8PathBuf::from).collect();
+    for db in &db_paths {
+        if !is_revindex_database(db) {
+            return Err(PyValueError::new_err(format!(
+                "'{db}' is not a valid RevIndex database"
+            )));
+        }
+    }
+    if let Some(fraction) = sample_fraction {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(PyValueError::new_err(format!(
+                "sample fraction must be in (0, 1] (got {fraction})"
+            )));
+        }
+    }
+
+    let (tax_paths, options) = match options {
+        Some(config) => {
+            let job = config.build(py, Arc::new(AtomicBool::new(false)))?;
+            (job.tax_paths, job.options)
+        }
+        None => (vec![], ExportOptions::default()),
+    };
+
+    let estimate = py
+        .allow_threads(|| {
+            let tax_map = load_taxonomies(tax_paths)?;
+            estimate_export(&db_paths, tax_map.as_ref(), &options, sample_fraction)
+        })
+        .map_err(to_py_err)?;
+    estimate_to_dict(py, &estimate)
+}
+
+#[pymodule]
+fn sourmash_plugin_export(py: Python, m: &Bound<PyModule>) -> PyResult<()> {
+    m.add(
+        "ExportInterruptedError",
+        py.get_type::<ExportInterruptedError>(),
+    )?;
+    m.add_function(wrap_pyfunction!(do_export_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(do_export, m)?)?;
+    m.add_function(wrap_pyfunction!(do_colors_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(get_db_info, m)?)?;
+    m.add_function(wrap_pyfunction!(get_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(do_list_datasets, m)?)?;
+    m.add_function(wrap_pyfunction!(do_estimate_export, m)?)?;
+    m.add_function(wrap_pyfunction!(export_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(clickhouse_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(pgcopy_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(do_query_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(do_parquet_gather, m)?)?;
+    m.add_function(wrap_pyfunction!(do_parquet_containment, m)?)?;
+    m.add_function(wrap_pyfunction!(classify_sig, m)?)?;
+    m.add_function(wrap_pyfunction!(do_bloom_filter_contains, m)?)?;
+    m.add_function(wrap_pyfunction!(do_parquet_to_revindex, m)?)?;
+    m.add_function(wrap_pyfunction!(do_revindex_to_sigs, m)?)?;
+    m.add_function(wrap_pyfunction!(do_revindex_to_lca_json, m)?)?;
+    m.add_function(wrap_pyfunction!(do_revindex_diff, m)?)?;
+    m.add_function(wrap_pyfunction!(do_revindex_pairwise, m)?)?;
+    m.add_function(wrap_pyfunction!(do_reannotate_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(do_parquet_lca_summary, m)?)?;
+    m.add_function(wrap_pyfunction!(do_parquet_inspect, m)?)?;
+    m.add_function(wrap_pyfunction!(do_verify_export, m)?)?;
+    m.add_function(wrap_pyfunction!(do_revindex_check, m)?)?;
+    m.add_function(wrap_pyfunction!(do_concat_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(do_rewrite_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(do_manifest_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(do_sigs_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(set_global_thread_pool, m)?)?;
+    m.add_class::<ExportHandle>()?;
+    m.add_class::<ExportConfig>()?;
+    m.add_class::<PyExportStream>()?;
+    m.add_class::<PyClickHouseStream>()?;
+    m.add_class::<PyPgCopyStream>()?;
+    Ok(())
+}
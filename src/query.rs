@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::compute::filter::filter_chunk;
+use arrow2::datatypes::Schema;
+use arrow2::io::ipc::write::{StreamWriter, WriteOptions};
+use arrow2::io::parquet::bloom_filter;
+use arrow2::io::parquet::read::{
+    get_field_columns, infer_schema, read_metadata, statistics, FileReader,
+};
+use arrow2::io::parquet::write::{Encoding, FileWriter, RowGroupIterator};
+use camino::Utf8Path;
+use std::collections::HashSet;
+use std::fs::File;
+
+use crate::export::parquet_write_options;
+
+/// Rows of an exported Parquet file whose hash is in a query set.
+pub struct HashQuery {
+    pub schema: Schema,
+    pub chunks: Vec<Chunk<Box<dyn Array>>>,
+    /// row groups that had to be read; the rest were ruled out by their
+    /// statistics or bloom filters
+    pub row_groups_read: usize,
+    pub row_groups_total: usize,
+}
+
+impl HashQuery {
+    pub fn num_rows(&self) -> usize {
+        self.chunks.iter().map(Chunk::len).sum()
+    }
+
+    /// Write the matching rows to a new Parquet file.
+    pub fn write_parquet(&self, path: &Utf8Path) -> Result<()> {
+        let options = parquet_write_options();
+        let mut writer = FileWriter::try_new(File::create(path)?, self.schema.clone(), options)?;
+        for chunk in &self.chunks {
+            let encodings = vec![vec![Encoding::Plain]; self.schema.fields.len()];
+            let row_groups = RowGroupIterator::try_new(
+                std::iter::once(Ok(chunk.clone())),
+                &self.schema,
+                options,
+                encodings,
+            )?;
+            for group in row_groups {
+                writer.write(group?)?;
+            }
+        }
+        writer.end(None)?;
+        Ok(())
+    }
+
+    /// The matching rows as an Arrow IPC stream.
+    pub fn to_ipc(&self) -> Result<Vec<u8>> {
+        let mut writer = StreamWriter::new(Vec::new(), WriteOptions { compression: None });
+        writer.start(&self.schema, None)?;
+        for chunk in &self.chunks {
+            writer.write(chunk, None)?;
+        }
+        writer.finish()?;
+        Ok(writer.into_inner())
+    }
+}
+
+/// Look up `hashes` in an exported Parquet file. Row groups whose `hash`
+/// min/max statistics (or bloom filter, when the file has one) rule out every
+/// query hash are skipped without being read.
+pub fn query_parquet(path: &Utf8Path, hashes: &[u64]) -> Result<HashQuery> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?;
+
+    let (hash_idx, hash_field) = schema
+        .fields
+        .iter()
+        .enumerate()
+        .find(|(_, field)| field.name == "hash")
+        .ok_or_else(|| anyhow!("'{path}' has no 'hash' column"))?;
+    let stats = statistics::deserialize(hash_field, &metadata.row_groups)?;
+    let not_u64 = || anyhow!("'hash' column of '{path}' is not UInt64");
+    let mins = stats
+        .min_value
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .ok_or_else(not_u64)?;
+    let maxs = stats
+        .max_value
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .ok_or_else(not_u64)?;
+
+    let mut query = hashes.to_vec();
+    query.sort_unstable();
+    query.dedup();
+
+    let row_groups_total = metadata.row_groups.len();
+    let mut selected = Vec::new();
+    let mut bitset = Vec::new();
+    for (i, row_group) in metadata.row_groups.into_iter().enumerate() {
+        // query hashes within the row group's range; no statistics, no pruning
+        let candidates = match (mins.get(i), maxs.get(i)) {
+            (Some(min), Some(max)) => {
+                let start = query.partition_point(|&h| h < min);
+                let end = query.partition_point(|&h| h <= max);
+                &query[start..end]
+            }
+            _ => &query[..],
+        };
+        if candidates.is_empty() {
+            continue;
+        }
+
+        if let Some(column) = get_field_columns(row_group.columns(), "hash").first() {
+            bitset.clear();
+            bloom_filter::read(column, &mut reader, &mut bitset)?;
+            // an empty bitset means the writer didn't add a bloom filter
+            let maybe_present = bitset.is_empty()
+                || candidates.iter().any(|&h| {
+                    bloom_filter::is_in_set(&bitset, bloom_filter::hash_native(h as i64))
+                });
+            if !maybe_present {
+                continue;
+            }
+        }
+        selected.push(row_group);
+    }
+
+    let row_groups_read = selected.len();
+    let wanted: HashSet<u64> = query.into_iter().collect();
+    let mut chunks = Vec::new();
+    for chunk in FileReader::new(reader, selected, schema.clone(), None, None, None) {
+        let chunk = chunk?;
+        let chunk_hashes = chunk.arrays()[hash_idx]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(not_u64)?;
+        let mask: BooleanArray = chunk_hashes
+            .iter()
+            .map(|h| Some(h.is_some_and(|h| wanted.contains(h))))
+            .collect();
+        let matched = filter_chunk(&chunk, &mask)?;
+        if !matched.is_empty() {
+            chunks.push(matched);
+        }
+    }
+
+    Ok(HashQuery {
+        schema,
+        chunks,
+        row_groups_read,
+        row_groups_total,
+    })
+}
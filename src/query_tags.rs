@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+use camino::Utf8Path;
+use sourmash::encodings::HashFunctions;
+use sourmash::selection::{Select, Selection};
+use sourmash::sketch::minhash::KmerMinHash;
+use std::collections::{HashMap, HashSet};
+
+use crate::sketches::load_collection;
+
+/// The queries each hash belongs to, for `--tag-queries`: with many query
+/// sketches (a zip, manifest or pathlist), one export annotates the hashes of
+/// all of them, and a `query_names` column says which queries each row came
+/// from.
+#[derive(Debug, Clone, Default)]
+pub struct QueryTags {
+    names: Vec<String>,
+    /// hash -> indices into `names`, in query order
+    by_hash: HashMap<u64, Vec<u32>>,
+}
+
+impl QueryTags {
+    /// The sketches with `ksize` and `moltype` in a query collection. Like
+    /// `load_query_hashes`, hashes aren't downsampled.
+    pub fn load(path: &Utf8Path, ksize: u32, moltype: &str) -> Result<Self> {
+        let hash_function = HashFunctions::try_from(moltype)
+            .map_err(|e| anyhow!("unknown moltype '{moltype}': {e}"))?;
+        let selection = Selection::builder()
+            .ksize(ksize)
+            .moltype(hash_function)
+            .build();
+        let collection = load_collection(path)?.select(&selection)?;
+        if collection.is_empty() {
+            anyhow::bail!("no sketches with ksize {ksize} and moltype {hash_function} in '{path}'");
+        }
+
+        let mut tags = Self::default();
+        for (idx, record) in collection.iter() {
+            let mh: KmerMinHash = collection.sig_for_dataset(idx)?.try_into()?;
+            let query = tags.names.len() as u32;
+            tags.names.push(record.name().to_string());
+            for hash in mh.mins() {
+                let queries = tags.by_hash.entry(hash).or_default();
+                if queries.last() != Some(&query) {
+                    queries.push(query);
+                }
+            }
+        }
+        eprintln!(
+            "Loaded {} query hashes from {} sketch(es) in '{path}'",
+            tags.by_hash.len(),
+            tags.names.len()
+        );
+        Ok(tags)
+    }
+
+    /// All query hashes, to restrict the export to.
+    pub fn hashes(&self) -> HashSet<u64> {
+        self.by_hash.keys().copied().collect()
+    }
+
+    /// Names of the queries with `hash`, in query order; empty for hashes of
+    /// no query.
+    pub fn names(&self, hash: u64) -> Vec<&str> {
+        self.by_hash.get(&hash).map_or_else(Vec::new, |queries| {
+            queries
+                .iter()
+                .map(|&q| self.names[q as usize].as_str())
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use arrow2::io::parquet::write::FileWriter;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::time::Instant;
+
+use crate::export::{
+    annotate_taxonomy, load_taxonomies, write_lca_info, write_records, ArrowRecord, ExportOptions,
+    LCASummary,
+};
+use crate::stats::{RunStats, StageTimer};
+
+/// Columns an export must have to be re-annotated; the taxonomy columns are
+/// recomputed.
+const REANNOTATE_COLUMNS: [&str; 5] = ["hash", "dataset_names", "ksize", "scaled", "source"];
+
+/// Rewrite an existing export with a new taxonomy: `taxonomy_list`,
+/// `lca_lineage` and `lca_rank` are recomputed from each row's `dataset_names`,
+/// without reading the RevIndex again. Compression, row group size, column
+/// selection and `force` are taken from `options`; the other options don't
+/// apply. LCA summaries of the new annotations are written to `lca_info_path`.
+pub fn reannotate_parquet(
+    input: &Utf8Path,
+    output: &Utf8Path,
+    tax_paths: Vec<Utf8PathBuf>,
+    lca_info_path: Option<&Utf8Path>,
+    options: &ExportOptions,
+) -> Result<RunStats> {
+    let run_timer = Instant::now();
+    options.validate()?;
+    let tax_map = load_taxonomies(tax_paths)?
+        .ok_or_else(|| anyhow!("re-annotating an export needs a taxonomy"))?;
+    let settings = options.writer_settings()?;
+
+    if output.exists() && !options.force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("output '{output}' already exists; use --force to overwrite it"),
+        )
+        .into());
+    }
+
+    let mut reader = File::open(input).with_context(|| format!("cannot open '{input}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?
+        .filter(|_, field| REANNOTATE_COLUMNS.contains(&field.name.as_str()));
+    let column = |name: &str| {
+        schema
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| anyhow!("'{input}' has no '{name}' column"))
+    };
+    let (hash_idx, names_idx, ksize_idx, scaled_idx, source_idx) = (
+        column("hash")?,
+        column("dataset_names")?,
+        column("ksize")?,
+        column("scaled")?,
+        column("source")?,
+    );
+
+    // write to a temp file and rename on success, as exports do
+    let tmp_path = Utf8PathBuf::from(format!("{output}.tmp"));
+    let mut writer = FileWriter::try_new(
+        File::create(&tmp_path)?,
+        settings.schema(),
+        settings.write_options,
+    )?;
+
+    let timer = StageTimer::start("reannotate");
+    let mut summaries: BTreeMap<String, LCASummary> = BTreeMap::new();
+    let mut buffer: Vec<ArrowRecord> = Vec::with_capacity(settings.row_group_size);
+    let mut rows_written = 0;
+    for chunk in FileReader::new(
+        reader,
+        metadata.row_groups,
+        schema.clone(),
+        None,
+        None,
+        None,
+    ) {
+        let chunk = chunk?;
+        let arrays = chunk.arrays();
+        let downcast_err = || anyhow!("unexpected column types in '{input}'");
+        let hashes = arrays[hash_idx]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(downcast_err)?;
+        let names = arrays[names_idx]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .ok_or_else(downcast_err)?;
+        let name_values = names
+            .values()
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+        let ksizes = arrays[ksize_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let scaleds = arrays[scaled_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let sources = arrays[source_idx]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+
+        for row in 0..chunk.len() {
+            let (start, end) = names.offsets().start_end(row);
+            let dataset_names: Vec<String> = (start..end)
+                .map(|j| name_values.value(j).to_string())
+                .collect();
+            let (taxonomy_list, lca_lineage, lca_rank) =
+                annotate_taxonomy(&dataset_names, Some(&tax_map));
+            let (ksize, scaled) = (ksizes.value(row), scaleds.value(row));
+            let source = sources.value(row).to_string();
+
+            summaries
+                .entry(source.clone())
+                .or_insert_with(|| LCASummary::new(ksize, scaled))
+                .add_rank(taxonomy_list.as_ref(), lca_rank.as_deref());
+            buffer.push(ArrowRecord {
+                hash: hashes.value(row),
+                dataset_names,
+                taxonomy_list,
+                lca_lineage,
+                lca_rank,
+                ksize,
+                scaled,
+                source,
+                abundances: None,
+            });
+
+            if buffer.len() >= settings.row_group_size {
+                write_records(&mut writer, &settings, &buffer)?;
+                rows_written += buffer.len() as u64;
+                buffer.clear();
+            }
+        }
+    }
+    if !buffer.is_empty() {
+        write_records(&mut writer, &settings, &buffer)?;
+        rows_written += buffer.len() as u64;
+    }
+    writer.end(None)?;
+    std::fs::rename(&tmp_path, output)?;
+    eprintln!("Finished writing re-annotated Parquet to {output}");
+
+    let summaries: Vec<(String, LCASummary)> = summaries.into_iter().collect();
+    write_lca_info(lca_info_path, &summaries)?;
+
+    let mut stats = RunStats {
+        output: Some(output.to_string()),
+        rows_written,
+        stages: vec![timer.finish()],
+        sources: summaries.into_iter().collect(),
+        ..Default::default()
+    };
+    stats.read_parquet(output)?;
+    stats.duration_secs = run_timer.elapsed().as_secs_f64();
+    eprint!("{stats}");
+    Ok(stats)
+}
@@ -0,0 +1,442 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::chunk::Chunk;
+use arrow2::compute::concatenate::concatenate;
+use arrow2::compute::filter::filter_chunk;
+use arrow2::compute::take::take;
+use arrow2::datatypes::Schema;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use arrow2::io::parquet::write::{
+    Encoding, FileWriter, RowGroupIterator, ThriftFileMetaData, WriteOptions,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs::File;
+
+use crate::dataset_metadata::write_dataset_metadata;
+use crate::export::{footer_metadata, ExportOptions};
+use crate::inspect::count_source_rows;
+
+/// How `rewrite_parquet` splits its output into a directory of files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partitioning {
+    /// one file per `source`, in `source=<name>/`
+    Source,
+    /// one file per `ksize`, in `ksize=<k>/`
+    Ksize,
+    /// N files by `hash % N`, in `bucket=<i>/`
+    HashBuckets(u64),
+}
+
+impl std::str::FromStr for Partitioning {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "source" => Ok(Self::Source),
+            None if s == "ksize" => Ok(Self::Ksize),
+            Some(("hash", n)) => match n.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(Self::HashBuckets(n)),
+                _ => anyhow::bail!("invalid number of hash buckets '{n}'"),
+            },
+            _ => anyhow::bail!("unknown partitioning '{s}'; expected source, ksize or hash:N"),
+        }
+    }
+}
+
+/// Files and rows written by `rewrite_parquet`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RewriteSummary {
+    pub rows_written: u64,
+    pub files: Vec<String>,
+}
+
+/// Which rows of the input go to one output file.
+enum RowFilter {
+    All,
+    Source(String),
+    Ksize(u32),
+    Bucket(u64, u64),
+}
+
+/// The column `name` of a chunk read with `schema`.
+fn chunk_column<'a>(
+    chunk: &'a Chunk<Box<dyn Array>>,
+    schema: &Schema,
+    name: &str,
+) -> Result<&'a dyn Array> {
+    let idx = schema
+        .fields
+        .iter()
+        .position(|field| field.name == name)
+        .ok_or_else(|| anyhow!("partitioning needs the '{name}' column"))?;
+    Ok(chunk.arrays()[idx].as_ref())
+}
+
+impl RowFilter {
+    fn mask(&self, chunk: &Chunk<Box<dyn Array>>, schema: &Schema) -> Result<Option<BooleanArray>> {
+        let downcast_err = || anyhow!("unexpected column types in the input");
+        Ok(match self {
+            Self::All => None,
+            Self::Source(source) => {
+                let sources = chunk_column(chunk, schema, "source")?
+                    .as_any()
+                    .downcast_ref::<Utf8Array<i32>>()
+                    .ok_or_else(downcast_err)?;
+                Some(BooleanArray::from_trusted_len_values_iter(
+                    sources.values_iter().map(|s| s == source),
+                ))
+            }
+            Self::Ksize(ksize) => {
+                let ksizes = chunk_column(chunk, schema, "ksize")?
+                    .as_any()
+                    .downcast_ref::<UInt32Array>()
+                    .ok_or_else(downcast_err)?;
+                Some(BooleanArray::from_trusted_len_values_iter(
+                    ksizes.values_iter().map(|k| k == ksize),
+                ))
+            }
+            Self::Bucket(bucket, n) => {
+                let hashes = chunk_column(chunk, schema, "hash")?
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .ok_or_else(downcast_err)?;
+                Some(BooleanArray::from_trusted_len_values_iter(
+                    hashes.values_iter().map(|h| h % n == *bucket),
+                ))
+            }
+        })
+    }
+}
+
+/// Writes chunks of any size as row groups of exactly `row_group_size` rows
+/// (except the last).
+struct RowGroupWriter {
+    writer: FileWriter<File>,
+    schema: Schema,
+    options: WriteOptions,
+    row_group_size: usize,
+    pending: Vec<Chunk<Box<dyn Array>>>,
+    pending_rows: usize,
+    rows_written: u64,
+}
+
+impl RowGroupWriter {
+    fn try_new(
+        path: &Utf8Path,
+        schema: Schema,
+        options: WriteOptions,
+        row_group_size: usize,
+    ) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("cannot create '{path}'"))?;
+        Ok(Self {
+            writer: FileWriter::try_new(file, schema.clone(), options)?,
+            schema,
+            options,
+            row_group_size,
+            pending: Vec::new(),
+            pending_rows: 0,
+            rows_written: 0,
+        })
+    }
+
+    fn push(&mut self, chunk: Chunk<Box<dyn Array>>) -> Result<()> {
+        self.pending_rows += chunk.len();
+        self.pending.push(chunk);
+        if self.pending_rows >= self.row_group_size {
+            self.flush(false)?;
+        }
+        Ok(())
+    }
+
+    /// Write the pending rows as full row groups, keeping the remainder
+    /// unless `all` is set.
+    fn flush(&mut self, all: bool) -> Result<()> {
+        if self.pending_rows == 0 {
+            return Ok(());
+        }
+        let combined = concat_chunks(std::mem::take(&mut self.pending))?;
+
+        let mut offset = 0;
+        while combined.len() - offset >= self.row_group_size || (all && offset < combined.len()) {
+            let len = self.row_group_size.min(combined.len() - offset);
+            let slice = Chunk::new(
+                combined
+                    .arrays()
+                    .iter()
+                    .map(|array| array.sliced(offset, len))
+                    .collect(),
+            );
+            self.write_row_group(slice)?;
+            offset += len;
+        }
+        if offset < combined.len() {
+            let rest = combined.len() - offset;
+            self.pending.push(Chunk::new(
+                combined
+                    .arrays()
+                    .iter()
+                    .map(|array| array.sliced(offset, rest))
+                    .collect(),
+            ));
+        }
+        self.pending_rows = combined.len() - offset;
+        Ok(())
+    }
+
+    fn write_row_group(&mut self, chunk: Chunk<Box<dyn Array>>) -> Result<()> {
+        self.rows_written += chunk.len() as u64;
+        let encodings = vec![vec![Encoding::Plain]; self.schema.fields.len()];
+        let row_groups = RowGroupIterator::try_new(
+            std::iter::once(Ok(chunk)),
+            &self.schema,
+            self.options,
+            encodings,
+        )?;
+        for group in row_groups {
+            self.writer.write(group?)?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining rows and the footer; returns the rows written and
+    /// the footer.
+    fn finish(mut self, partial: bool) -> Result<(u64, ThriftFileMetaData)> {
+        self.flush(true)?;
+        self.writer
+            .end(Some(footer_metadata(self.rows_written, partial)))?;
+        let (_, metadata) = self.writer.into_inner_and_metadata();
+        Ok((self.rows_written, metadata))
+    }
+}
+
+/// The ksizes of an export; only the `ksize` column is read.
+fn distinct_ksizes(path: &Utf8Path) -> Result<BTreeSet<u32>> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?.filter(|_, field| field.name == "ksize");
+    let mut ksizes = BTreeSet::new();
+    for chunk in FileReader::new(reader, metadata.row_groups, schema, None, None, None) {
+        let chunk = chunk?;
+        let values = chunk.arrays()[0]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(|| anyhow!("unexpected type for the 'ksize' column in '{path}'"))?;
+        ksizes.extend(values.values_iter().copied());
+    }
+    Ok(ksizes)
+}
+
+/// Concatenate chunks column by column.
+fn concat_chunks(mut chunks: Vec<Chunk<Box<dyn Array>>>) -> Result<Chunk<Box<dyn Array>>> {
+    if chunks.len() == 1 {
+        return Ok(chunks.pop().unwrap());
+    }
+    let n_columns = chunks[0].arrays().len();
+    let columns = (0..n_columns)
+        .map(|i| {
+            let arrays: Vec<&dyn Array> = chunks.iter().map(|c| c.arrays()[i].as_ref()).collect();
+            concatenate(&arrays)
+        })
+        .collect::<arrow2::error::Result<Vec<_>>>()?;
+    Ok(Chunk::new(columns))
+}
+
+/// Reorder a chunk by hash, then source.
+fn sort_chunk(chunk: &Chunk<Box<dyn Array>>, schema: &Schema) -> Result<Chunk<Box<dyn Array>>> {
+    let column = |name: &str| schema.fields.iter().position(|field| field.name == name);
+    let hash_idx = column("hash").ok_or_else(|| anyhow!("sorting needs the 'hash' column"))?;
+    let downcast_err = || anyhow!("unexpected column types in the input");
+    let hashes = chunk.arrays()[hash_idx]
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .ok_or_else(downcast_err)?;
+    let sources = column("source")
+        .map(|idx| {
+            chunk.arrays()[idx]
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .ok_or_else(downcast_err)
+        })
+        .transpose()?;
+
+    let mut indices: Vec<u32> = (0..chunk.len() as u32).collect();
+    indices.sort_unstable_by(|&a, &b| {
+        let (a, b) = (a as usize, b as usize);
+        hashes
+            .value(a)
+            .cmp(&hashes.value(b))
+            .then_with(|| match sources {
+                Some(sources) => sources.value(a).cmp(sources.value(b)),
+                None => std::cmp::Ordering::Equal,
+            })
+    });
+    let indices = UInt32Array::from_vec(indices);
+    let columns = chunk
+        .arrays()
+        .iter()
+        .map(|array| take(array.as_ref(), &indices))
+        .collect::<arrow2::error::Result<Vec<_>>>()?;
+    Ok(Chunk::new(columns))
+}
+
+/// Rewrite an existing export with a new row group size, compression or
+/// column selection (from `options`), sorted by hash with `sort`, and split
+/// into a directory of files with `partitioning`, without reading the
+/// RevIndex again. Sorting holds each output file in memory, and each
+/// partition is a separate pass over the input.
+pub fn rewrite_parquet(
+    input: &Utf8Path,
+    output: &Utf8Path,
+    sort: bool,
+    partitioning: Option<Partitioning>,
+    options: &ExportOptions,
+) -> Result<RewriteSummary> {
+    options.validate()?;
+    if output.exists() && !options.force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("output '{output}' already exists; use --force to overwrite it"),
+        )
+        .into());
+    }
+    let settings = options.writer_settings()?;
+
+    let mut reader = File::open(input).with_context(|| format!("cannot open '{input}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let partial = metadata
+        .key_value_metadata
+        .iter()
+        .flatten()
+        .any(|kv| kv.key == "export_partial" && kv.value.as_deref() == Some("true"));
+    let input_schema = infer_schema(&metadata)?;
+    let mut schema = input_schema.clone();
+    if let Some(ref names) = options.columns {
+        if let Some(missing) = names
+            .iter()
+            .find(|name| !schema.fields.iter().any(|field| &field.name == *name))
+        {
+            anyhow::bail!("'{input}' has no '{missing}' column");
+        }
+        schema = schema.filter(|_, field| names.contains(&field.name));
+    }
+
+    // (output file, rows it gets)
+    let targets: Vec<(Utf8PathBuf, RowFilter)> = match partitioning {
+        None => vec![(Utf8PathBuf::from(format!("{output}.tmp")), RowFilter::All)],
+        Some(Partitioning::Source) if !input_schema.fields.iter().any(|f| f.name == "source") => {
+            anyhow::bail!("'{input}' has no 'source' column to partition by")
+        }
+        Some(Partitioning::Source) => count_source_rows(input)?
+            .into_keys()
+            .map(|source| {
+                let path = output
+                    .join(format!("source={source}"))
+                    .join("part-0.parquet");
+                (path, RowFilter::Source(source))
+            })
+            .collect(),
+        Some(Partitioning::Ksize) if !input_schema.fields.iter().any(|f| f.name == "ksize") => {
+            anyhow::bail!("'{input}' has no 'ksize' column to partition by")
+        }
+        Some(Partitioning::Ksize) => distinct_ksizes(input)?
+            .into_iter()
+            .map(|ksize| {
+                let path = output.join(format!("ksize={ksize}")).join("part-0.parquet");
+                (path, RowFilter::Ksize(ksize))
+            })
+            .collect(),
+        Some(Partitioning::HashBuckets(n)) => (0..n)
+            .map(|bucket| {
+                let path = output
+                    .join(format!("bucket={bucket}"))
+                    .join("part-0.parquet");
+                (path, RowFilter::Bucket(bucket, n))
+            })
+            .collect(),
+    };
+
+    // all columns are read, as the filter may need some that aren't written;
+    // these are the positions of the written ones
+    let keep: Vec<usize> = schema
+        .fields
+        .iter()
+        .map(|field| {
+            input_schema
+                .fields
+                .iter()
+                .position(|f| f.name == field.name)
+                .expect("written columns come from the input schema")
+        })
+        .collect();
+
+    let mut summary = RewriteSummary::default();
+    let mut footers = Vec::new();
+    for (path, filter) in targets {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut writer = RowGroupWriter::try_new(
+            &path,
+            schema.clone(),
+            settings.write_options,
+            settings.row_group_size,
+        )?;
+        let mut sorted: Vec<Chunk<Box<dyn Array>>> = Vec::new();
+
+        let mut reader = File::open(input)?;
+        let metadata = read_metadata(&mut reader)?;
+        for chunk in FileReader::new(
+            reader,
+            metadata.row_groups,
+            input_schema.clone(),
+            None,
+            None,
+            None,
+        ) {
+            let chunk = chunk?;
+            let chunk = match filter.mask(&chunk, &input_schema)? {
+                Some(mask) => filter_chunk(&chunk, &mask)?,
+                None => chunk,
+            };
+            if chunk.is_empty() {
+                continue;
+            }
+            let chunk = Chunk::new(keep.iter().map(|&i| chunk.arrays()[i].to_boxed()).collect());
+            if sort {
+                sorted.push(chunk);
+            } else {
+                writer.push(chunk)?;
+            }
+        }
+        if !sorted.is_empty() {
+            writer.push(sort_chunk(&concat_chunks(sorted)?, &schema)?)?;
+        }
+        let (rows, footer) = writer.finish(partial)?;
+        summary.rows_written += rows;
+
+        if partitioning.is_none() {
+            std::fs::rename(&path, output)?;
+            summary.files.push(output.to_string());
+        } else {
+            let relative = path.strip_prefix(output)?.as_str().replace('\\', "/");
+            footers.push((relative, footer));
+            summary.files.push(path.to_string());
+        }
+    }
+    if partitioning.is_some() {
+        write_dataset_metadata(
+            output,
+            footers,
+            footer_metadata(summary.rows_written, partial),
+        )?;
+    }
+
+    eprintln!(
+        "Rewrote {input} to {output}: {} rows in {} file(s)",
+        summary.rows_written,
+        summary.files.len()
+    );
+    Ok(summary)
+}
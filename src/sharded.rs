@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use arrow2::io::parquet::write::{FileWriter, ThriftFileMetaData};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::dataset_metadata::write_dataset_metadata;
+use crate::export::{
+    footer_metadata, spawn_consumer, write_records, ArrowRecord, InterruptPolicy, WriteBatch,
+    WriterOutput, WriterSettings, EXPORT_SCHEMA_VERSION,
+};
+use crate::memory::MemoryMonitor;
+use crate::rewrite::Partitioning;
+
+/// Name of the dataset-level metadata file of a sharded export.
+pub const SHARDED_METADATA_FILE: &str = "_export.json";
+
+/// One file of a sharded export, as listed in `_export.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardFile {
+    /// path relative to the export directory
+    pub path: String,
+    pub rows: u64,
+}
+
+/// Contents of `_export.json`, which ties the files of a sharded export
+/// together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardedExport {
+    pub export_schema_version: u32,
+    /// `source`, `ksize` or `hash:N`
+    pub partitioning: String,
+    pub partial: bool,
+    pub rows_written: u64,
+    pub files: Vec<ShardFile>,
+}
+
+impl ShardedExport {
+    /// Read `_export.json` from the directory of a sharded export.
+    pub fn load(dir: &Utf8Path) -> Result<Self> {
+        let path = dir.join(SHARDED_METADATA_FILE);
+        let file = File::open(&path).map_err(|e| anyhow!("cannot open '{path}': {e}"))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Absolute paths of the files of an export in `dir`.
+    pub fn paths(&self, dir: &Utf8Path) -> Vec<Utf8PathBuf> {
+        self.files.iter().map(|file| dir.join(&file.path)).collect()
+    }
+}
+
+/// Relative path of the file holding `shard`, laid out like
+/// `rewrite_parquet`'s partitioned output.
+fn shard_path(partitioning: Partitioning, shard: &str) -> String {
+    match partitioning {
+        Partitioning::Source => format!("source={shard}/part-0.parquet"),
+        Partitioning::Ksize => format!("ksize={shard}/part-0.parquet"),
+        Partitioning::HashBuckets(_) => format!("bucket={shard}/part-0.parquet"),
+    }
+}
+
+/// What a shard writer thread returns: the rows written and, unless the file
+/// was left unfinished, its footer.
+type ShardOutput = (u64, Option<ThriftFileMetaData>);
+
+/// Spawn a writer thread owning one shard file; it encodes and compresses the
+/// records it receives and returns the rows written.
+fn start_shard_writer(
+    path: Utf8PathBuf,
+    settings: WriterSettings,
+    cancel_flag: Arc<AtomicBool>,
+    on_interrupt: InterruptPolicy,
+    memory: Arc<MemoryMonitor>,
+) -> Result<(
+    Sender<Vec<ArrowRecord>>,
+    thread::JoinHandle<Result<ShardOutput>>,
+)> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = File::create(&path)?;
+    let (sender, receiver): (Sender<Vec<ArrowRecord>>, Receiver<Vec<ArrowRecord>>) =
+        mpsc::channel();
+    let handle = spawn_consumer(memory.clone(), move || -> Result<ShardOutput> {
+        let mut writer = FileWriter::try_new(file, settings.schema(), settings.write_options)?;
+        let mut buffer = Vec::with_capacity(settings.row_group_size);
+        let mut rows_written = 0;
+        for records in receiver {
+            buffer.extend(records);
+            if buffer.len() >= settings.row_group_size || memory.near_limit() {
+                write_records(&mut writer, &settings, &buffer)?;
+                rows_written += buffer.len() as u64;
+                buffer.clear();
+            }
+        }
+
+        let partial = cancel_flag.load(Ordering::SeqCst);
+        if partial && on_interrupt != InterruptPolicy::Finalize {
+            return Ok((rows_written, None));
+        }
+        if !buffer.is_empty() {
+            write_records(&mut writer, &settings, &buffer)?;
+            rows_written += buffer.len() as u64;
+        }
+        writer.end(Some(footer_metadata(rows_written, partial)))?;
+        let (_, metadata) = writer.into_inner_and_metadata();
+        Ok((rows_written, Some(metadata)))
+    });
+    Ok((sender, handle))
+}
+
+/// Start a writer for a sharded export into the directory `out_dir`: a router
+/// thread splits the batches it receives by `partitioning` (per source, per
+/// ksize, or by `hash % N`) and hands them to one writer thread per shard file, so encoding
+/// and compression run on several cores. Once the channel is closed, the shard
+/// files are finalized and listed in `_export.json`, and their row groups in
+/// `_metadata`. Interrupted exports are handled per `on_interrupt`, as for a
+/// single file.
+pub(crate) fn start_sharded_writer_thread(
+    out_dir: Utf8PathBuf,
+    partitioning: Partitioning,
+    settings: WriterSettings,
+    cancel_flag: Arc<AtomicBool>,
+    on_interrupt: InterruptPolicy,
+    memory: Arc<MemoryMonitor>,
+) -> Result<(Sender<WriteBatch>, thread::JoinHandle<Result<WriterOutput>>)> {
+    if out_dir.exists() {
+        std::fs::remove_dir_all(&out_dir)?;
+    }
+    std::fs::create_dir_all(&out_dir)?;
+    let (sender, receiver): (Sender<WriteBatch>, Receiver<WriteBatch>) = mpsc::channel();
+
+    let handle = spawn_consumer(memory.clone(), move || -> Result<WriterOutput> {
+        type Shard = (
+            Sender<Vec<ArrowRecord>>,
+            thread::JoinHandle<Result<ShardOutput>>,
+        );
+        let mut shards: BTreeMap<String, Shard> = BTreeMap::new();
+        let start = |name: &str| {
+            start_shard_writer(
+                out_dir.join(shard_path(partitioning, name)),
+                settings.clone(),
+                cancel_flag.clone(),
+                on_interrupt,
+                memory.clone(),
+            )
+        };
+        if let Partitioning::HashBuckets(n) = partitioning {
+            for bucket in 0..n {
+                let name = bucket.to_string();
+                shards.insert(name.clone(), start(&name)?);
+            }
+        }
+
+        'batches: for batch in receiver {
+            memory.batch_received();
+            memory.maybe_report();
+            let mut routed: BTreeMap<String, Vec<ArrowRecord>> = BTreeMap::new();
+            for record in batch.records {
+                let shard = match partitioning {
+                    Partitioning::Source => record.source.clone(),
+                    Partitioning::Ksize => record.ksize.to_string(),
+                    Partitioning::HashBuckets(n) => (record.hash % n).to_string(),
+                };
+                routed.entry(shard).or_default().push(record);
+            }
+            for (name, records) in routed {
+                if !shards.contains_key(&name) {
+                    let shard = start(&name)?;
+                    shards.insert(name.clone(), shard);
+                }
+                // a closed channel means the shard writer failed; its error
+                // is reported on join
+                if shards[&name].0.send(records).is_err() {
+                    break 'batches;
+                }
+            }
+        }
+
+        let mut files = Vec::new();
+        let mut footers = Vec::new();
+        let mut rows_written = 0;
+        for (name, (shard_sender, handle)) in shards {
+            drop(shard_sender);
+            let (rows, footer) = handle
+                .join()
+                .map_err(|_| anyhow!("writer thread for shard '{name}' panicked"))??;
+            rows_written += rows;
+            let path = shard_path(partitioning, &name);
+            footers.extend(footer.map(|footer| (path.clone(), footer)));
+            files.push(ShardFile { path, rows });
+        }
+
+        let partial = cancel_flag.load(Ordering::SeqCst);
+        if partial && on_interrupt == InterruptPolicy::Delete {
+            std::fs::remove_dir_all(&out_dir)?;
+            eprintln!("Removed partial output {out_dir}");
+        } else if !partial || on_interrupt == InterruptPolicy::Finalize {
+            let metadata = ShardedExport {
+                export_schema_version: EXPORT_SCHEMA_VERSION,
+                partitioning: match partitioning {
+                    Partitioning::Source => "source".to_string(),
+                    Partitioning::Ksize => "ksize".to_string(),
+                    Partitioning::HashBuckets(n) => format!("hash:{n}"),
+                },
+                partial,
+                rows_written,
+                files,
+            };
+            let file = BufWriter::new(File::create(out_dir.join(SHARDED_METADATA_FILE))?);
+            serde_json::to_writer_pretty(file, &metadata)?;
+            write_dataset_metadata(&out_dir, footers, footer_metadata(rows_written, partial))?;
+        }
+        Ok(WriterOutput {
+            rows_written,
+            unclassified_rows: 0,
+            checkpoint: None,
+        })
+    });
+    Ok((sender, handle))
+}
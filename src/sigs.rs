@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+
+use crate::export::{check_db_compatibility, DatasetResolver, HASHES_CF};
+
+/// The hashes of one dataset in a RevIndex, with the manifest fields needed to
+/// turn them back into a signature.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetSketch {
+    pub name: String,
+    pub filename: String,
+    pub moltype: String,
+    pub ksize: u32,
+    pub scaled: u32,
+    /// sorted; abundances aren't stored in a RevIndex
+    pub hashes: Vec<u64>,
+}
+
+/// Invert a RevIndex's hash → datasets mapping into the hash set of each
+/// dataset, in dataset-ID order. Datasets with no hashes are included, with
+/// an empty hash set. Holds every (dataset, hash) pair in memory.
+pub fn revindex_to_sigs(db_path: &Utf8Path) -> Result<Vec<DatasetSketch>> {
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+
+    let mut sketches: Vec<DatasetSketch> = collection
+        .manifest()
+        .iter()
+        .map(|record| DatasetSketch {
+            name: record.name().to_string(),
+            filename: record.filename().to_string(),
+            moltype: record.moltype().to_string(),
+            ksize: record.ksize(),
+            scaled: *record.scaled(),
+            hashes: vec![],
+        })
+        .collect();
+
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    for (k, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+        .filter(|(k, _)| k.len() == 8)
+    {
+        let Some(datasets) = resolver.resolve(&v)? else {
+            continue;
+        };
+        let hash = LittleEndian::read_u64(&k);
+        for idx in datasets {
+            match sketches.get_mut(idx as usize) {
+                Some(sketch) => sketch.hashes.push(hash),
+                None => eprintln!("Skipping invalid dataset ID: {}", idx),
+            }
+        }
+    }
+
+    // keys are little-endian, so iteration order isn't numeric order
+    for sketch in &mut sketches {
+        sketch.hashes.sort_unstable();
+    }
+    Ok(sketches)
+}
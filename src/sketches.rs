@@ -0,0 +1,308 @@
+use anyhow::{anyhow, Result};
+use arrow2::io::parquet::write::FileWriter;
+use camino::{Utf8Path, Utf8PathBuf};
+use sourmash::collection::Collection;
+use sourmash::encodings::HashFunctions;
+use sourmash::manifest::Manifest;
+use sourmash::selection::{Select, Selection};
+use sourmash::signature::Signature;
+use sourmash::sketch::minhash::KmerMinHash;
+use sourmash::storage::{FSStorage, InnerStorage};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Instant;
+
+use crate::export::{
+    annotate_taxonomy, footer_metadata, load_taxonomies, write_lca_info, write_records,
+    ArrowRecord, ExportOptions, LCASummary,
+};
+use crate::is_revindex_database;
+use crate::stats::{RunStats, StageTimer};
+
+/// The kinds of input an export accepts. RevIndexes go through
+/// `export_revindex_to_parquet`; the sketch collections go through
+/// `sigs_to_parquet`, which supports fewer options (see the README).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// a RocksDB RevIndex directory
+    RevIndex,
+    /// a `.zip` sketch collection
+    Zip,
+    /// a standalone manifest CSV; locations are relative to its directory
+    Manifest,
+    /// a text file listing one signature file per line
+    PathList,
+    /// a signature file (`.sig`, `.sig.gz`, `.json`, ...)
+    Signature,
+}
+
+impl InputKind {
+    /// Classify `path`, or `None` if it isn't a RevIndex or an existing file.
+    pub fn detect(path: &Utf8Path) -> Option<Self> {
+        if is_revindex_database(&path.to_path_buf()) {
+            return Some(Self::RevIndex);
+        }
+        if !path.is_file() {
+            return None;
+        }
+        let name = path.as_str();
+        Some(if name.ends_with(".zip") {
+            Self::Zip
+        } else if name.ends_with(".csv") || name.ends_with(".csv.gz") {
+            Self::Manifest
+        } else if name.ends_with(".txt") {
+            Self::PathList
+        } else {
+            Self::Signature
+        })
+    }
+}
+
+/// Load a sketch collection of any `InputKind` but `RevIndex`.
+pub(crate) fn load_collection(path: &Utf8Path) -> Result<Collection> {
+    let load_err = |e: sourmash::Error| anyhow!("cannot load '{path}': {e}");
+    match InputKind::detect(path) {
+        Some(InputKind::Zip) => Collection::from_zipfile(path).map_err(load_err),
+        Some(InputKind::Manifest) => {
+            let manifest =
+                Manifest::from_reader(BufReader::new(File::open(path)?)).map_err(load_err)?;
+            let base = path.parent().unwrap_or(Utf8Path::new(""));
+            let storage = FSStorage::builder()
+                .fullpath(base.to_string())
+                .subdir("".into())
+                .build();
+            Ok(Collection::new(manifest, InnerStorage::new(storage)))
+        }
+        Some(InputKind::PathList) => {
+            let paths: Vec<Utf8PathBuf> = std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(Utf8PathBuf::from)
+                .collect();
+            Collection::from_paths(&paths).map_err(load_err)
+        }
+        Some(InputKind::Signature) => {
+            let sigs = Signature::from_path(path).map_err(load_err)?;
+            Collection::from_sigs(sigs).map_err(load_err)
+        }
+        Some(InputKind::RevIndex) => {
+            anyhow::bail!("'{path}' is a RevIndex; use export_revindex_to_parquet")
+        }
+        None => anyhow::bail!("'{path}' is not a RevIndex database or sketch collection"),
+    }
+}
+
+/// The hashes of the sketches with `ksize` and `moltype` in a query collection,
+/// for exports restricted to a query (`--query`). They aren't downsampled:
+/// FracMinHash hashes of a database at a coarser scaled are a subset of the
+/// query's, so intersecting the two keeps exactly the shared hashes.
+pub fn load_query_hashes(path: &Utf8Path, ksize: u32, moltype: &str) -> Result<HashSet<u64>> {
+    let hash_function = HashFunctions::try_from(moltype)
+        .map_err(|e| anyhow!("unknown moltype '{moltype}': {e}"))?;
+    let selection = Selection::builder()
+        .ksize(ksize)
+        .moltype(hash_function)
+        .build();
+    let collection = load_collection(path)?.select(&selection)?;
+    if collection.is_empty() {
+        anyhow::bail!("no sketches with ksize {ksize} and moltype {hash_function} in '{path}'");
+    }
+
+    let mut hashes = HashSet::new();
+    for (idx, _) in collection.iter() {
+        let mh: KmerMinHash = collection.sig_for_dataset(idx)?.try_into()?;
+        hashes.extend(mh.mins());
+    }
+    eprintln!(
+        "Loaded {} query hashes from {} sketch(es) in '{path}'",
+        hashes.len(),
+        collection.len()
+    );
+    Ok(hashes)
+}
+
+/// The datasets of one hash in an inverted collection, with their abundances
+/// while all of them track abundance.
+#[derive(Debug)]
+struct InvertedHash {
+    dataset_names: Vec<String>,
+    abundances: Option<Vec<u64>>,
+}
+
+/// Invert the selected sketches of one collection into hash -> dataset names;
+/// every sketch is downsampled to the largest scaled among them. Returns the
+/// inversion and that scaled.
+fn invert_collection(
+    path: &Utf8Path,
+    ksize: u32,
+    hash_function: HashFunctions,
+    options: &ExportOptions,
+) -> Result<(BTreeMap<u64, InvertedHash>, u32)> {
+    let selection = Selection::builder()
+        .ksize(ksize)
+        .moltype(hash_function)
+        .build();
+    let collection = load_collection(path)?.select(&selection)?;
+    if collection.is_empty() {
+        anyhow::bail!("no sketches with ksize {ksize} and moltype {hash_function} in '{path}'");
+    }
+    let scaled = collection
+        .iter()
+        .map(|(_, record)| *record.scaled())
+        .max()
+        .unwrap_or_default();
+    if scaled == 0 {
+        anyhow::bail!("'{path}' has num sketches; only scaled sketches can be exported");
+    }
+
+    let mut hashes: BTreeMap<u64, InvertedHash> = BTreeMap::new();
+    for (idx, record) in collection.iter() {
+        if !options.keeps_dataset(idx, record) {
+            continue;
+        }
+        let name = options.dataset_naming.name(idx, record);
+        let sig = collection.sig_for_dataset(idx)?;
+        let mut mh: KmerMinHash = sig.try_into()?;
+        if mh.scaled() != scaled {
+            mh = mh.downsample_scaled(scaled)?;
+        }
+        let abunds = mh.abunds();
+        for (i, hash) in mh.mins().into_iter().enumerate() {
+            let inverted = hashes.entry(hash).or_insert_with(|| InvertedHash {
+                dataset_names: vec![],
+                abundances: Some(vec![]),
+            });
+            inverted.dataset_names.push(name.clone());
+            match (&mut inverted.abundances, &abunds) {
+                (Some(list), Some(abunds)) => list.push(abunds[i]),
+                (abundances, _) => *abundances = None,
+            }
+        }
+    }
+    Ok((hashes, scaled))
+}
+
+/// Export sketch collections (see `InputKind`) to Parquet with the same schema
+/// as a RevIndex export, without building a RevIndex first: each input is a
+/// source, and its sketches with `ksize` and `moltype` are inverted into
+/// hash -> datasets in memory, one input at a time. Rows are sorted by hash
+/// within each source. Compression, row group size, column selection, `force`,
+/// `stats_json`, `hashes`, `with_abundance` and the dataset and taxonomy
+/// filters are taken from `options`; the other options don't apply.
+pub fn sigs_to_parquet(
+    sig_paths: &[Utf8PathBuf],
+    output: &Utf8Path,
+    ksize: u32,
+    moltype: &str,
+    tax_paths: Vec<Utf8PathBuf>,
+    lca_info_path: Option<&Utf8Path>,
+    options: &ExportOptions,
+) -> Result<RunStats> {
+    let run_timer = Instant::now();
+    options.validate()?;
+    options.check_taxonomy(!tax_paths.is_empty())?;
+    let hash_function = HashFunctions::try_from(moltype)
+        .map_err(|e| anyhow!("unknown moltype '{moltype}': {e}"))?;
+    let tax_map = load_taxonomies(tax_paths)?;
+    let settings = options.writer_settings()?;
+
+    if output.exists() && !options.force {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("output '{output}' already exists; use --force to overwrite it"),
+        )
+        .into());
+    }
+
+    // write to a temp file and rename on success, as exports do
+    let tmp_path = Utf8PathBuf::from(format!("{output}.tmp"));
+    let mut writer = FileWriter::try_new(
+        File::create(&tmp_path)?,
+        settings.schema(),
+        settings.write_options,
+    )?;
+
+    let timer = StageTimer::start("export");
+    let mut summaries: Vec<(String, LCASummary)> = Vec::new();
+    let mut buffer: Vec<ArrowRecord> = Vec::with_capacity(settings.row_group_size);
+    let mut rows_written = 0;
+    for path in sig_paths {
+        let source = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Cannot get basename of path: {path}"))?
+            .to_string();
+        let (hashes, scaled) = invert_collection(path, ksize, hash_function, options)?;
+        eprintln!("Inverted {} hashes from {source}", hashes.len());
+
+        let mut summary = LCASummary::new(ksize, scaled);
+        for (hash, inverted) in hashes {
+            let InvertedHash {
+                dataset_names,
+                abundances,
+            } = inverted;
+            if !options.in_hash_list(hash) || !options.in_dataset_range(dataset_names.len()) {
+                continue;
+            }
+            let (taxonomy_list, lca_lineage, lca_rank) =
+                annotate_taxonomy(&dataset_names, tax_map.as_ref());
+            if !options.keeps_annotation(
+                taxonomy_list.as_ref(),
+                lca_lineage.as_deref(),
+                lca_rank.as_deref(),
+            ) {
+                continue;
+            }
+            summary.add_rank(taxonomy_list.as_ref(), lca_rank.as_deref());
+            buffer.push(ArrowRecord {
+                hash,
+                dataset_names,
+                taxonomy_list,
+                lca_lineage,
+                lca_rank,
+                ksize,
+                scaled,
+                source: source.clone(),
+                abundances,
+            });
+
+            if buffer.len() >= settings.row_group_size {
+                write_records(&mut writer, &settings, &buffer)?;
+                rows_written += buffer.len() as u64;
+                buffer.clear();
+            }
+        }
+        summaries.push((source, summary));
+    }
+    if !buffer.is_empty() {
+        write_records(&mut writer, &settings, &buffer)?;
+        rows_written += buffer.len() as u64;
+    }
+    writer.end(Some(footer_metadata(rows_written, false)))?;
+    std::fs::rename(&tmp_path, output)?;
+    eprintln!("Finished writing Parquet to {output}");
+
+    write_lca_info(lca_info_path, &summaries)?;
+    if let Some(ref anonymizer) = options.anonymize {
+        anonymizer.write_mapping()?;
+    }
+    if let Some(ref table) = options.lineage_table {
+        table.write()?;
+    }
+
+    let mut stats = RunStats {
+        output: Some(output.to_string()),
+        rows_written,
+        stages: vec![timer.finish()],
+        sources: summaries.into_iter().collect(),
+        ..Default::default()
+    };
+    stats.read_parquet(output)?;
+    stats.duration_secs = run_timer.elapsed().as_secs_f64();
+    eprint!("{stats}");
+    if let Some(path) = options.stats_json.as_deref() {
+        stats.write_json(path)?;
+    }
+    Ok(stats)
+}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::{Duration, Instant};
+
+use crate::export::LCASummary;
+use crate::memory::format_bytes;
+
+/// User + system CPU time used by this process so far.
+fn process_cpu_time() -> Duration {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes into the provided struct
+    let ret = unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) };
+    if ret != 0 {
+        return Duration::ZERO;
+    }
+    // SAFETY: initialized above (zeroed, then filled by getrusage)
+    let usage = unsafe { usage.assume_init() };
+    let to_duration = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+/// Wall and CPU time spent in one stage of an export.
+#[derive(Debug, Serialize)]
+pub struct StageTiming {
+    pub name: String,
+    pub wall_secs: f64,
+    pub cpu_secs: f64,
+}
+
+/// Measures a stage from creation until `finish`.
+pub(crate) struct StageTimer {
+    name: &'static str,
+    wall: Instant,
+    cpu: Duration,
+}
+
+impl StageTimer {
+    pub fn start(name: &'static str) -> Self {
+        Self {
+            name,
+            wall: Instant::now(),
+            cpu: process_cpu_time(),
+        }
+    }
+
+    pub fn finish(self) -> StageTiming {
+        StageTiming {
+            name: self.name.to_string(),
+            wall_secs: self.wall.elapsed().as_secs_f64(),
+            cpu_secs: process_cpu_time().saturating_sub(self.cpu).as_secs_f64(),
+        }
+    }
+}
+
+// hashes kept as examples of invalid dataset IDs, per database
+const MAX_EXAMPLE_HASHES: usize = 5;
+
+/// Dataset IDs in a database's hash values that its manifest doesn't have,
+/// e.g. after an interrupted RevIndex build. They are left out of the rows.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InvalidDatasets {
+    /// hashes with at least one invalid dataset ID
+    pub hashes: u64,
+    /// the invalid dataset IDs
+    pub ids: BTreeSet<u32>,
+    /// the first few affected hashes
+    pub example_hashes: Vec<u64>,
+}
+
+impl InvalidDatasets {
+    pub(crate) fn record(&mut self, hash: u64, ids: &[u32]) {
+        self.hashes += 1;
+        self.ids.extend(ids);
+        if self.example_hashes.len() < MAX_EXAMPLE_HASHES {
+            self.example_hashes.push(hash);
+        }
+    }
+}
+
+/// Summary of a finished export, printed to stderr, optionally saved as JSON,
+/// and returned to Python callers.
+#[derive(Debug, Default, Serialize)]
+pub struct RunStats {
+    /// final output path; unset when no output was written
+    pub output: Option<String>,
+    /// rows in the output; excludes `unclassified_rows`
+    pub rows_written: u64,
+    /// rows written to the separate unclassified output, if any
+    pub unclassified_rows: u64,
+    pub row_groups: usize,
+    pub bytes_on_disk: u64,
+    pub uncompressed_bytes: u64,
+    pub compression_ratio: f64,
+    /// keys that were not 8-byte hashes or whose dataset list could not be parsed
+    pub skipped_keys: u64,
+    /// per-source dataset IDs that aren't in the manifest; only sources with any
+    pub invalid_datasets: BTreeMap<String, InvalidDatasets>,
+    pub stages: Vec<StageTiming>,
+    pub duration_secs: f64,
+    /// per-source hash counts and LCA rank counts
+    pub sources: BTreeMap<String, LCASummary>,
+}
+
+impl RunStats {
+    /// Fill in row group and size information from a written Parquet file.
+    pub fn read_parquet(&mut self, path: &Utf8Path) -> Result<()> {
+        self.read_parquet_files(&[path.to_path_buf()])
+    }
+
+    /// Like `read_parquet`, summed over the files of a sharded export.
+    pub fn read_parquet_files(&mut self, paths: &[Utf8PathBuf]) -> Result<()> {
+        let mut compressed = 0;
+        self.row_groups = 0;
+        self.bytes_on_disk = 0;
+        self.uncompressed_bytes = 0;
+        for path in paths {
+            let mut reader = File::open(path)?;
+            let metadata = arrow2::io::parquet::read::read_metadata(&mut reader)?;
+            self.row_groups += metadata.row_groups.len();
+            self.bytes_on_disk += std::fs::metadata(path)?.len();
+            for rg in &metadata.row_groups {
+                self.uncompressed_bytes += rg.total_byte_size() as u64;
+                compressed += rg.compressed_size() as u64;
+            }
+        }
+        if compressed > 0 {
+            self.compression_ratio = self.uncompressed_bytes as f64 / compressed as f64;
+        }
+        Ok(())
+    }
+
+    pub fn write_json(&self, path: &Utf8Path) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Run statistics:")?;
+        writeln!(f, "  rows written: {}", self.rows_written)?;
+        if self.unclassified_rows > 0 {
+            writeln!(f, "  unclassified rows: {}", self.unclassified_rows)?;
+        }
+        writeln!(f, "  row groups: {}", self.row_groups)?;
+        writeln!(
+            f,
+            "  bytes on disk: {} ({} uncompressed, ratio {:.2})",
+            format_bytes(self.bytes_on_disk),
+            format_bytes(self.uncompressed_bytes),
+            self.compression_ratio
+        )?;
+        writeln!(f, "  skipped keys: {}", self.skipped_keys)?;
+        for (source, invalid) in &self.invalid_datasets {
+            let ids: Vec<String> = invalid.ids.iter().map(|id| id.to_string()).collect();
+            writeln!(
+                f,
+                "  invalid dataset IDs in {source}: {} in {} hashes (e.g. hash {})",
+                ids.join(", "),
+                invalid.hashes,
+                invalid.example_hashes.first().copied().unwrap_or_default()
+            )?;
+        }
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "  {}: {:.1}s wall, {:.1}s CPU",
+                stage.name, stage.wall_secs, stage.cpu_secs
+            )?;
+        }
+        Ok(())
+    }
+}
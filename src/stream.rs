@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use arrow2::array::Array;
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::Schema;
+use arrow2::io::ipc::write::{StreamWriter, WriteOptions};
+use camino::Utf8PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use crate::export::{
+    check_db_compatibility, check_params_consistency, load_taxonomies, process_revindex,
+    ExportInterrupted, ExportOptions, InterruptPolicy, ScanTotals, WriteBatch, WriterFailed,
+    WriterSettings,
+};
+use crate::memory::MemoryMonitor;
+use crate::merge::{join_merge_thread, start_merge_thread};
+use crate::progress::Reporter;
+
+// batches the scan may run ahead of a slow consumer
+const STREAM_QUEUE_DEPTH: usize = 4;
+
+/// An export delivered as Arrow chunks instead of a Parquet file.
+///
+/// Databases are scanned one after another on a background thread, which stays
+/// a few batches ahead of the consumer. Dropping the stream cancels the scan.
+/// Checkpointing, `on_interrupt` and the output settings other than column
+/// selection don't apply.
+pub struct ExportStream {
+    receiver: Receiver<WriteBatch>,
+    settings: WriterSettings,
+    memory: Arc<MemoryMonitor>,
+    cancel_flag: Arc<AtomicBool>,
+    producer: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl ExportStream {
+    pub fn start(
+        db_paths: Vec<Utf8PathBuf>,
+        tax_paths: Vec<Utf8PathBuf>,
+        options: ExportOptions,
+    ) -> Result<Self> {
+        options.validate()?;
+        options.check_taxonomy(!tax_paths.is_empty())?;
+        for db_path in &db_paths {
+            check_db_compatibility(db_path)?;
+        }
+        if db_paths.len() > 1 && !options.allow_mixed_params {
+            check_params_consistency(&db_paths, options.ksize)?;
+        }
+        let tax_map = load_taxonomies(tax_paths)?;
+        let settings = options.writer_settings()?;
+        let cancel_flag = options.cancel_flag.clone().unwrap_or_default();
+        let memory =
+            Arc::new(MemoryMonitor::new(options.max_memory).with_max_queued(STREAM_QUEUE_DEPTH));
+        let reporter = Reporter::new(
+            options.progress_json.as_deref(),
+            options.progress_callback.clone(),
+        )?;
+
+        let (sender, receiver) = mpsc::channel();
+        let producer = {
+            let memory = memory.clone();
+            let cancel_flag = cancel_flag.clone();
+            thread::spawn(move || -> Result<()> {
+                let totals = ScanTotals::default();
+                let merging = options.merge.then(|| start_merge_thread(memory.clone()));
+                let db_sender = merging
+                    .as_ref()
+                    .map_or(&sender, |(merge_sender, _)| merge_sender);
+                for db_path in &db_paths {
+                    let scanned = process_revindex(
+                        db_path,
+                        Some(db_sender),
+                        tax_map.as_ref(),
+                        &options,
+                        None,
+                        &reporter,
+                        &memory,
+                        &totals,
+                        cancel_flag.clone(),
+                    );
+                    if let Err(e) = scanned {
+                        // report the merge thread's own error if it stopped the scan
+                        return Err(match merging {
+                            Some((merge_sender, merge_handle)) if e.is::<WriterFailed>() => {
+                                drop(merge_sender);
+                                join_merge_thread(merge_handle).err().unwrap_or(e)
+                            }
+                            _ => e,
+                        });
+                    }
+                    if cancel_flag.load(Ordering::SeqCst) {
+                        anyhow::bail!("export stream was cancelled");
+                    }
+                }
+                if let Some((merge_sender, merge_handle)) = merging {
+                    drop(merge_sender);
+                    join_merge_thread(merge_handle)?.send(
+                        &sender,
+                        tax_map.as_ref(),
+                        &options,
+                        &memory,
+                    )?;
+                }
+                Ok(())
+            })
+        };
+
+        Ok(Self {
+            receiver,
+            settings,
+            memory,
+            cancel_flag,
+            producer: Some(producer),
+        })
+    }
+
+    /// Schema of the streamed chunks (after column selection).
+    pub fn schema(&self) -> Schema {
+        self.settings.schema()
+    }
+
+    /// Collect the whole export in memory as an Arrow IPC stream. Meant for
+    /// small databases; a cancelled export returns `ExportInterrupted`.
+    pub fn into_ipc(mut self) -> Result<Vec<u8>> {
+        let mut writer = StreamWriter::new(Vec::new(), WriteOptions { compression: None });
+        writer.start(&self.schema(), None)?;
+        for chunk in self.by_ref() {
+            let chunk = chunk.map_err(|e| {
+                if self.cancel_flag.load(Ordering::SeqCst) {
+                    ExportInterrupted {
+                        rows_written: 0,
+                        policy: InterruptPolicy::Delete,
+                    }
+                    .into()
+                } else {
+                    e
+                }
+            })?;
+            writer.write(&chunk, None)?;
+        }
+        writer.finish()?;
+        Ok(writer.into_inner())
+    }
+}
+
+impl Iterator for ExportStream {
+    type Item = Result<Chunk<Box<dyn Array>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.receiver.recv() {
+                Ok(batch) => {
+                    self.memory.batch_received();
+                    if !batch.records.is_empty() {
+                        return Some(self.settings.chunk(&batch.records));
+                    }
+                }
+                // the scan has finished; report how it ended, once
+                Err(_) => {
+                    return match self.producer.take()?.join() {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(Err(e)),
+                        Err(_) => Some(Err(anyhow!("export thread panicked"))),
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ExportStream {
+    fn drop(&mut self) {
+        self.cancel_flag.store(true, Ordering::SeqCst);
+        // unblock a scan waiting for us to catch up
+        while self.receiver.try_recv().is_ok() {
+            self.memory.batch_received();
+        }
+    }
+}
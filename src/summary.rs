@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::Utf8Path;
+use std::collections::BTreeMap;
+use std::fs::File;
+
+use crate::export::{write_lca_info, LCASummary};
+
+// columns read to rebuild an LCA summary
+const SUMMARY_COLUMNS: [&str; 5] = ["taxonomy_list", "lca_rank", "ksize", "scaled", "source"];
+
+/// Rebuild the LCA summary of an existing export from its `taxonomy_list` and
+/// `lca_rank` columns, as the export itself would have written it: one summary
+/// per source, plus a combined summary per ksize/scaled when there are several
+/// sources. With `by_source` unset, all sources at a ksize/scaled are counted
+/// together, labelled `all_k{ksize}_sc{scaled}`. The summaries are printed and, with
+/// `lca_info_path`, written as CSV.
+pub fn parquet_lca_summary(
+    path: &Utf8Path,
+    lca_info_path: Option<&Utf8Path>,
+    by_source: bool,
+) -> Result<BTreeMap<String, LCASummary>> {
+    let mut reader = File::open(path).with_context(|| format!("cannot open '{path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema =
+        infer_schema(&metadata)?.filter(|_, field| SUMMARY_COLUMNS.contains(&field.name.as_str()));
+    let column = |name: &str| {
+        schema
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| anyhow!("'{path}' has no '{name}' column"))
+    };
+    let (taxonomy_idx, rank_idx, ksize_idx, scaled_idx, source_idx) = (
+        column("taxonomy_list")?,
+        column("lca_rank")?,
+        column("ksize")?,
+        column("scaled")?,
+        column("source")?,
+    );
+
+    // keyed by (label, ksize, scaled), so pooled summaries keep ksizes apart
+    let mut summaries: BTreeMap<(String, u32, u32), LCASummary> = BTreeMap::new();
+    for chunk in FileReader::new(
+        reader,
+        metadata.row_groups,
+        schema.clone(),
+        None,
+        None,
+        None,
+    ) {
+        let chunk = chunk?;
+        let arrays = chunk.arrays();
+        let downcast_err = || anyhow!("unexpected column types in '{path}'");
+        let taxonomy = arrays[taxonomy_idx]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .ok_or_else(downcast_err)?;
+        let taxonomy_values = taxonomy
+            .values()
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+        let ranks = arrays[rank_idx]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+        let ksizes = arrays[ksize_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let scaleds = arrays[scaled_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let sources = arrays[source_idx]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+
+        for row in 0..chunk.len() {
+            let (ksize, scaled) = (ksizes.value(row), scaleds.value(row));
+            let label = if by_source { sources.value(row) } else { "all" };
+            // exports write unset taxonomy lists as empty; both count as unclassified
+            let (start, end) = taxonomy.offsets().start_end(row);
+            let taxonomy_list: Vec<String> = (start..end)
+                .map(|j| taxonomy_values.value(j).to_string())
+                .collect();
+            summaries
+                .entry((label.to_string(), ksize, scaled))
+                .or_insert_with(|| LCASummary::new(ksize, scaled))
+                .add_rank(Some(&taxonomy_list), ranks.get(row));
+        }
+    }
+
+    let summaries: Vec<(String, LCASummary)> = summaries
+        .into_iter()
+        .map(|((label, ksize, scaled), summary)| match by_source {
+            true => (label, summary),
+            false => (format!("{label}_k{ksize}_sc{scaled}"), summary),
+        })
+        .collect();
+    write_lca_info(lca_info_path, &summaries)?;
+    Ok(summaries.into_iter().collect())
+}
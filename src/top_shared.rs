@@ -0,0 +1,97 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use csv::Writer;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// One hash of the `--top-shared` report. Hashes order by dataset count, and
+/// lower hashes first among equal counts.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SharedHash {
+    dataset_count: usize,
+    hash: Reverse<u64>,
+    lca_rank: Option<String>,
+    lca_lineage: Option<String>,
+}
+
+/// The `n` hashes found in the most datasets of one database, for
+/// `--top-shared`: widely shared hashes are often conserved or contaminant
+/// k-mers. Only `n` hashes are held at a time.
+#[derive(Debug, Clone)]
+pub(crate) struct TopShared {
+    source: String,
+    n: usize,
+    /// min-heap, so the least shared of the kept hashes is the one replaced
+    heap: BinaryHeap<Reverse<SharedHash>>,
+}
+
+impl TopShared {
+    pub fn new(source: &str, n: usize) -> Self {
+        Self {
+            source: source.to_string(),
+            n,
+            heap: BinaryHeap::with_capacity(n + 1),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Consider one exported hash, found in `dataset_count` datasets.
+    pub fn add(
+        &mut self,
+        hash: u64,
+        dataset_count: usize,
+        lca_lineage: Option<&str>,
+        lca_rank: Option<&str>,
+    ) {
+        if self.n == 0 {
+            return;
+        }
+        // ties with the least shared kept hash keep the earlier (lower) one
+        if self.heap.len() == self.n
+            && self
+                .heap
+                .peek()
+                .is_some_and(|Reverse(least)| least.dataset_count >= dataset_count)
+        {
+            return;
+        }
+        self.heap.push(Reverse(SharedHash {
+            dataset_count,
+            hash: Reverse(hash),
+            lca_rank: lca_rank.map(str::to_string),
+            lca_lineage: lca_lineage.map(str::to_string),
+        }));
+        if self.heap.len() > self.n {
+            self.heap.pop();
+        }
+    }
+}
+
+/// Write the most widely shared hashes of each source: one row per `source`
+/// and `hash` with its `dataset_count`, `lca_rank` and `lca_lineage`, most
+/// shared first. Returns the number of rows.
+pub(crate) fn write_top_shared(path: &Utf8Path, top: &[TopShared]) -> Result<usize> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record(["source", "hash", "dataset_count", "lca_rank", "lca_lineage"])?;
+    let mut n_rows = 0;
+    for source in top {
+        let mut hashes: Vec<&SharedHash> = source.heap.iter().map(|Reverse(h)| h).collect();
+        hashes.sort_by(|a, b| b.cmp(a));
+        for shared in hashes {
+            writer.serialize((
+                &source.source,
+                shared.hash.0,
+                shared.dataset_count,
+                shared.lca_rank.as_deref().unwrap_or_default(),
+                shared.lca_lineage.as_deref().unwrap_or_default(),
+            ))?;
+            n_rows += 1;
+        }
+    }
+    writer.flush()?;
+    eprintln!("Wrote {n_rows} widely shared hashes to {path}");
+    Ok(n_rows)
+}
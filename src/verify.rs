@@ -0,0 +1,281 @@
+use anyhow::{anyhow, Context, Result};
+use arrow2::array::*;
+use arrow2::io::parquet::read::{infer_schema, read_metadata, FileReader};
+use camino::Utf8Path;
+use serde::Serialize;
+use sourmash::index::revindex::{RevIndex, RevIndexOps};
+use std::fs::File;
+
+use crate::export::{
+    abundance_fields, check_db_compatibility, contamination_field, create_schema, dataset_names,
+    disagreement_fields, lineage_id_field, query_names_field, select_ksize, DatasetResolver,
+    SketchParams, EXPORT_SCHEMA_VERSION, HASHES_CF,
+};
+use crate::naming::DatasetNaming;
+
+// columns an export needs to be verified against its database
+const REQUIRED_COLUMNS: [&str; 5] = ["hash", "dataset_names", "ksize", "scaled", "source"];
+
+// individual hash mismatches listed in a report before summarizing the rest
+const MAX_REPORTED_HASHES: usize = 10;
+
+/// Result of cross-checking an export against one of its source databases.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// `source` value of the database's rows (its basename)
+    pub source: String,
+    /// hashes the database would export
+    pub db_hashes: u64,
+    /// rows in the export with this source
+    pub parquet_rows: u64,
+    /// rows whose dataset names were checked against the database
+    pub sampled: u64,
+    /// every divergence found; empty when the export matches
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Sampled rows of the database's source: (hash, sorted dataset names).
+type SampledRow = (u64, Vec<String>);
+
+/// Cross-check `parquet_path` against `db_path`, one of the databases it was
+/// exported from: the schema must match the export schema, the footer must be
+/// complete, ksize and scaled must match the database, the export must have a
+/// row for every hash in the database, and about `sample_size` rows are looked
+/// up in the database to compare dataset names. Exports made with sampling,
+/// sharding, `--since` or `--merge` will report count differences.
+pub fn verify_export(
+    parquet_path: &Utf8Path,
+    db_path: &Utf8Path,
+    sample_size: usize,
+) -> Result<VerifyReport> {
+    let source = db_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot get basename of path: {db_path}"))?
+        .to_string();
+    let mut report = VerifyReport {
+        source: source.clone(),
+        ..Default::default()
+    };
+
+    let mut reader =
+        File::open(parquet_path).with_context(|| format!("cannot open '{parquet_path}'"))?;
+    let metadata = read_metadata(&mut reader)?;
+    let schema = infer_schema(&metadata)?;
+
+    // schema: every column must be an export column with the export's type
+    let mut expected = create_schema().fields;
+    expected.extend(abundance_fields());
+    expected.push(lineage_id_field());
+    expected.push(contamination_field());
+    expected.extend(disagreement_fields());
+    expected.push(query_names_field());
+    for field in &schema.fields {
+        match expected.iter().find(|f| f.name == field.name) {
+            None => report
+                .problems
+                .push(format!("unexpected column '{}'", field.name)),
+            Some(f) if f.data_type() != field.data_type() => report.problems.push(format!(
+                "column '{}' has type {:?}, expected {:?}",
+                field.name,
+                field.data_type(),
+                f.data_type()
+            )),
+            Some(_) => {}
+        }
+    }
+    for name in REQUIRED_COLUMNS {
+        if !schema.fields.iter().any(|f| f.name == name) {
+            anyhow::bail!("'{parquet_path}' has no '{name}' column");
+        }
+    }
+
+    // footer: a complete export records how many hashes it wrote
+    let footer = |key: &str| {
+        metadata
+            .key_value_metadata
+            .iter()
+            .flatten()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.clone())
+    };
+    if let Some(version) = footer("export_schema_version") {
+        if version
+            .parse::<u32>()
+            .map_or(true, |v| v > EXPORT_SCHEMA_VERSION)
+        {
+            report.problems.push(format!(
+                "footer records export schema version {version}, but this version of the \
+                 plugin only knows versions up to {EXPORT_SCHEMA_VERSION}"
+            ));
+        }
+    }
+    if footer("export_partial").as_deref() == Some("true") {
+        report
+            .problems
+            .push("footer marks the export as partial".to_string());
+    }
+    match footer("hashes_written") {
+        Some(written) if written != metadata.num_rows.to_string() => report.problems.push(format!(
+            "footer records {written} hashes written, but the file has {} rows",
+            metadata.num_rows
+        )),
+        Some(_) => {}
+        None => report
+            .problems
+            .push("footer has no 'hashes_written' entry".to_string()),
+    }
+
+    // database
+    check_db_compatibility(db_path)?;
+    let revindex = RevIndex::open(db_path, true, None)
+        .map_err(|e| anyhow!("cannot open RocksDB database. Error is: {e}"))?;
+    let revindex = match revindex {
+        RevIndex::Plain(revindex) => revindex,
+    };
+    let collection = revindex.collection();
+    let SketchParams { ksize, scaled, .. } = select_ksize(collection.manifest(), db_path, None)?;
+
+    // export rows for this source: count them, check params, sample some
+    let stride = (metadata.num_rows / sample_size.max(1)).max(1);
+    let schema = schema.filter(|_, field| REQUIRED_COLUMNS.contains(&field.name.as_str()));
+    let column = |name: &str| {
+        schema
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .expect("required columns were checked above")
+    };
+    let (hash_idx, names_idx, ksize_idx, scaled_idx, source_idx) = (
+        column("hash"),
+        column("dataset_names"),
+        column("ksize"),
+        column("scaled"),
+        column("source"),
+    );
+    let mut sampled: Vec<SampledRow> = Vec::new();
+    let mut wrong_params = 0u64;
+    let mut position = 0usize;
+    for chunk in FileReader::new(
+        reader,
+        metadata.row_groups,
+        schema.clone(),
+        None,
+        None,
+        None,
+    ) {
+        let chunk = chunk?;
+        let arrays = chunk.arrays();
+        let downcast_err = || anyhow!("unexpected column types in '{parquet_path}'");
+        let hashes = arrays[hash_idx]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(downcast_err)?;
+        let names = arrays[names_idx]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .ok_or_else(downcast_err)?;
+        let name_values = names
+            .values()
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+        let ksizes = arrays[ksize_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let scaleds = arrays[scaled_idx]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or_else(downcast_err)?;
+        let sources = arrays[source_idx]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .ok_or_else(downcast_err)?;
+
+        for row in 0..chunk.len() {
+            position += 1;
+            if sources.value(row) != source {
+                continue;
+            }
+            report.parquet_rows += 1;
+            if (ksizes.value(row), scaleds.value(row)) != (ksize, scaled) {
+                wrong_params += 1;
+            }
+            if position % stride == 0 {
+                let (start, end) = names.offsets().start_end(row);
+                let mut row_names: Vec<String> = (start..end)
+                    .map(|j| name_values.value(j).to_string())
+                    .collect();
+                row_names.sort_unstable();
+                sampled.push((hashes.value(row), row_names));
+            }
+        }
+    }
+    if report.parquet_rows == 0 {
+        report
+            .problems
+            .push(format!("the export has no rows with source '{source}'"));
+    }
+    if wrong_params > 0 {
+        report.problems.push(format!(
+            "{wrong_params} rows don't have the database's ksize {ksize} and scaled {scaled}"
+        ));
+    }
+
+    // hashes the database would export: 8-byte keys with resolvable datasets
+    let db = revindex.db.as_ref();
+    let cf = db.cf_handle(HASHES_CF).expect("Missing 'hashes' CF");
+    let mut resolver = DatasetResolver::new(db)?;
+    for (k, v) in db
+        .iterator_cf(&cf, rocksdb::IteratorMode::Start)
+        .filter_map(Result::ok)
+        .filter(|(k, _)| k.len() == 8)
+    {
+        if resolver.resolve(&v)?.is_some() {
+            report.db_hashes += 1;
+        }
+    }
+    if report.db_hashes != report.parquet_rows {
+        report.problems.push(format!(
+            "the database has {} hashes, but the export has {} rows for it",
+            report.db_hashes, report.parquet_rows
+        ));
+    }
+
+    // sampled rows: same datasets as in the database
+    let mut mismatched = 0usize;
+    for (hash, row_names) in &sampled {
+        let value = db.get_pinned_cf(&cf, hash.to_le_bytes())?;
+        let problem = match value.map(|v| resolver.resolve(&v)).transpose()?.flatten() {
+            None => format!("hash {hash} is not in the database"),
+            Some(datasets) => {
+                let mut db_names = dataset_names(collection, datasets, &DatasetNaming::default());
+                db_names.sort_unstable();
+                if &db_names == row_names {
+                    continue;
+                }
+                format!(
+                    "hash {hash} has datasets {row_names:?} in the export but {db_names:?} in the database"
+                )
+            }
+        };
+        mismatched += 1;
+        if mismatched <= MAX_REPORTED_HASHES {
+            report.problems.push(problem);
+        }
+    }
+    if mismatched > MAX_REPORTED_HASHES {
+        report.problems.push(format!(
+            "... {} more sampled hashes differ",
+            mismatched - MAX_REPORTED_HASHES
+        ));
+    }
+    report.sampled = sampled.len() as u64;
+    Ok(report)
+}
@@ -4,6 +4,10 @@ Tests for export
 
 import os
 import csv
+import json
+import math
+import zipfile
+from collections import Counter
 import pytest
 import polars as pl
 import pandas as pd
@@ -334,6 +338,7 @@ def test_rocksdb_revindex_to_parquet_test6_multiple_revindex_diff_scaled(runtmp,
         tax_csv,
         "--lca-info",
         lca_csv,
+        "--allow-mixed-params",
     )
 
     captured = capfd.readouterr()
@@ -566,3 +571,3628 @@ def test_rocksdb_revindex_to_parquet_test6_multiple_revindex_same_scaled(runtmp,
             all(row[k] == expected_row[k] for k in expected_row)
             for row in lca_df.to_dict(orient="records")
         ), f"Expected row not found: {expected_row}. row found: {row}"
+
+
+def test_rocksdb_revindex_to_parquet_read_options(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--readahead-size",
+        str(2 * 1024 * 1024),
+        "--no-fill-cache",
+        "--pin-data",
+        "--snapshot",
+    )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    assert os.path.exists(out_parquet), f"Expected output file at {out_parquet}."
+
+    df = pl.read_parquet(out_parquet)
+    assert len(df) == 23910
+    assert df[0, "hash"] == 15249706293397504
+
+
+def test_rocksdb_revindex_to_parquet_secondary(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    secondary = runtmp.output("test6.secondary")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--secondary",
+        "--secondary-path",
+        secondary,
+    )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    assert f"Opening secondary instance at '{secondary}'" in captured.err
+    df = pl.read_parquet(out_parquet)
+    assert len(df) == 23910
+
+
+def test_colors_to_parquet_no_colors_cf(runtmp, capfd):
+    # test6.rocksdb is a plain revindex, without a colors column family
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.colors.parquet")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "colors_to_parquet", revindex, "--output", out_parquet
+        )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    assert "does not have a 'colors' column family" in captured.err
+    assert not os.path.exists(out_parquet)
+
+
+def test_colors_to_parquet_no_clobber(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.colors.parquet")
+    with open(out_parquet, "w") as fp:
+        fp.write("keep me")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "colors_to_parquet", revindex, "--output", out_parquet
+        )
+    assert "already exists" in runtmp.last_result.err
+
+    # with --force, the database is read (and rejected) before the output is touched
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "colors_to_parquet", revindex, "-o", out_parquet, "--force"
+        )
+    assert "does not have a 'colors' column family" in runtmp.last_result.err
+    with open(out_parquet) as fp:
+        assert fp.read() == "keep me"
+    assert not os.path.exists(f"{out_parquet}.tmp")
+
+
+def test_rocksdb_revindex_to_parquet_not_a_rocksdb(runtmp, capfd):
+    # a directory that passes the quick CURRENT-file check but isn't a RocksDB
+    fake_db = runtmp.output("fake.rocksdb")
+    os.mkdir(fake_db)
+    with open(os.path.join(fake_db, "CURRENT"), "w") as fp:
+        fp.write("MANIFEST-000001\n")
+    out_parquet = runtmp.output("fake.parquet")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "revindex_to_parquet", fake_db, "--output", out_parquet
+        )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    assert f"Error: cannot read column families of '{fake_db}'" in captured.err
+    assert not os.path.exists(out_parquet)
+
+
+def test_rocksdb_revindex_to_parquet_shards(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+
+    total = 0
+    for shard in range(2):
+        out_parquet = runtmp.output(f"test6.shard{shard}.parquet")
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "--output",
+            out_parquet,
+            "--shard",
+            str(shard),
+            "--num-shards",
+            "2",
+        )
+
+        df = pl.read_parquet(out_parquet)
+        assert all(h % 2 == shard for h in df["hash"])
+        total += len(df)
+
+    assert total == 23910
+
+
+def test_rocksdb_revindex_to_parquet_shard_without_num_shards(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "--output",
+            out_parquet,
+            "--shard",
+            "0",
+        )
+
+    assert "shard and num_shards must be given together" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_checkpoint(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    checkpoint = runtmp.output("test6.ckpt")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--checkpoint",
+        checkpoint,
+        "--checkpoint-rows",
+        "1000",
+    )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    assert "Combined 1 part file(s) into" in captured.err
+    # parts and checkpoint are cleaned up after a complete export
+    assert not os.path.exists(checkpoint)
+    assert not os.path.exists(out_parquet + ".part00000")
+
+    df = pl.read_parquet(out_parquet)
+    assert len(df) == 23910
+    assert df[0, "hash"] == 15249706293397504
+
+
+def test_rocksdb_revindex_to_parquet_footer_metadata(runtmp, capfd):
+    import pyarrow.parquet as pq
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "--output", out_parquet)
+
+    metadata = pq.read_metadata(out_parquet).metadata
+    print(metadata)
+    assert metadata[b"export_partial"] == b"false"
+    assert metadata[b"hashes_written"] == b"23910"
+
+
+def test_rocksdb_revindex_to_parquet_no_clobber(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    with open(out_parquet, "w") as fp:
+        fp.write("not a parquet file")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "revindex_to_parquet", revindex, "--output", out_parquet
+        )
+
+    captured = capfd.readouterr()
+    print(captured.err)
+    assert "already exists; use --force to overwrite it" in captured.err
+    with open(out_parquet) as fp:
+        assert fp.read() == "not a parquet file"
+
+    # --force overwrites, and no temp file is left behind
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "--output", out_parquet, "--force"
+    )
+    assert not os.path.exists(out_parquet + ".tmp")
+    df = pl.read_parquet(out_parquet)
+    assert len(df) == 23910
+
+
+def test_rocksdb_revindex_to_parquet_dry_run(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--taxonomy",
+        tax_csv,
+        "--dry-run",
+    )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    assert "ksize: 31, scaled: 1000" in captured.err
+    assert "datasets with taxonomy:" in captured.err
+    assert "projected output size:" in captured.err
+    assert "No output was written (--dry-run)." in captured.err
+    assert not os.path.exists(out_parquet)
+
+
+def test_rocksdb_revindex_lca_summary_only(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_lca = runtmp.output("test6.lca.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--taxonomy",
+        tax_csv,
+        "--lca-info",
+        out_lca,
+        "--lca-summary-only",
+    )
+
+    captured = capfd.readouterr()
+    print(captured.out)
+    print(captured.err)
+
+    # no parquet is written, not even with the default name
+    assert not os.path.exists(runtmp.output("test6.rocksdb.parquet"))
+    assert not os.path.exists("test6.rocksdb.parquet")
+
+    lca_df = pd.read_csv(out_lca)
+    print(lca_df)
+    species = lca_df[lca_df["lca_rank"] == "species"]
+    assert species["count"].iloc[0] == 23901
+    assert species["percent"].iloc[0] == 99.96
+
+
+def test_rocksdb_revindex_to_parquet_every_nth(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--every-nth",
+        "10",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    assert len(df) == 2391
+    # the first hash is always kept
+    assert df[0, "hash"] == 15249706293397504
+
+
+def test_rocksdb_revindex_to_parquet_sample_fraction(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out1 = runtmp.output("test6.sample1.parquet")
+    out2 = runtmp.output("test6.sample2.parquet")
+
+    for out in (out1, out2):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "--output",
+            out,
+            "--sample-fraction",
+            "0.1",
+        )
+
+    df1 = pl.read_parquet(out1)
+    df2 = pl.read_parquet(out2)
+    # deterministic, and roughly the requested fraction
+    assert df1["hash"].to_list() == df2["hash"].to_list()
+    assert 1500 < len(df1) < 3300
+
+
+def test_rocksdb_revindex_to_parquet_limit(runtmp, capfd):
+    revindex1 = get_test_data("test6.rocksdb")
+    revindex2 = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("limited.parquet")
+    out_lca = runtmp.output("limited.lca.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex1,
+        revindex2,
+        "--output",
+        out_parquet,
+        "--taxonomy",
+        tax_csv,
+        "--lca-info",
+        out_lca,
+        "--limit",
+        "50",
+        "--allow-mixed-params",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    assert len(df) == 100
+    assert df.filter(pl.col("source") == "test6.rocksdb").height == 50
+
+    lca_df = pd.read_csv(out_lca)
+    print(lca_df)
+    for source in ("test6.rocksdb", "podar-ref-subset.branch0_9_13.internal.rocksdb"):
+        assert lca_df[lca_df["source"] == source]["count"].sum() == 50
+
+
+def test_rocksdb_revindex_to_parquet_progress_json(runtmp, capfd):
+    revindex = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("progress.parquet")
+    progress_file = runtmp.output("progress.jsonl")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--progress-json",
+        progress_file,
+    )
+
+    with open(progress_file) as fp:
+        events = [json.loads(line) for line in fp]
+    print(events)
+
+    stages = [e["stage"] for e in events]
+    assert stages[0] == "scan"
+    assert "scan_done" in stages
+    assert stages[-1] == "done"
+    assert events[-1]["processed"] == len(pl.read_parquet(out_parquet))
+    assert all(e["db"] == "podar-ref-subset.branch0_9_13.internal.rocksdb" for e in events if e["stage"] == "scan")
+
+
+def test_rocksdb_revindex_to_parquet_max_memory(runtmp, capfd):
+    # a tiny budget forces early flushes but must not change the output
+    revindex = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("capped.parquet")
+    out_parquet2 = runtmp.output("uncapped.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--max-memory",
+        "1M",
+    )
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet2)
+
+    df = pl.read_parquet(out_parquet).sort("hash")
+    df2 = pl.read_parquet(out_parquet2).sort("hash")
+    assert df.equals(df2)
+
+
+def test_rocksdb_revindex_to_parquet_stats_json(runtmp, capfd):
+    revindex = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("stats.parquet")
+    stats_json = runtmp.output("stats.json")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--stats-json",
+        stats_json,
+    )
+
+    captured = capfd.readouterr()
+    print(captured.err)
+    assert "Run statistics:" in captured.err
+
+    with open(stats_json) as fp:
+        stats = json.load(fp)
+    assert stats["rows_written"] == len(pl.read_parquet(out_parquet))
+    assert stats["row_groups"] >= 1
+    assert stats["bytes_on_disk"] == os.path.getsize(out_parquet)
+    assert stats["skipped_keys"] == 0
+    assert [s["name"] for s in stats["stages"]] == ["export", "lca_summary"]
+
+
+def test_do_export_to_parquet_raises(runtmp):
+    # the Rust entry point raises Python exceptions instead of returning a status
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("raises.parquet")
+
+    with pytest.raises(
+        ValueError, match="is not a RevIndex database or sketch collection"
+    ):
+        rust.do_export_to_parquet([runtmp.output("nope")], out_parquet)
+
+    with pytest.raises(ValueError, match="shard and num_shards must be given together"):
+        rust.do_export_to_parquet([revindex], out_parquet, shard=1)
+
+    with pytest.raises(IOError):
+        rust.do_export_to_parquet(
+            [revindex], out_parquet, tax_path_list=[runtmp.output("missing.csv")]
+        )
+
+
+def test_do_export_to_parquet_returns_stats(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("stats.parquet")
+
+    stats = rust.do_export_to_parquet([revindex], out_parquet, [tax_csv])
+    print(stats)
+
+    df = pl.read_parquet(out_parquet)
+    assert stats["output"] == out_parquet
+    assert stats["rows_written"] == len(df)
+    assert stats["duration_secs"] > 0
+    source = stats["sources"]["test6.rocksdb"]
+    assert source["count"] == len(df)
+    assert (
+        sum(source["rank_counts"].values())
+        + source["no_lca"]
+        + source["unclassified"]
+        == source["count"]
+    )
+
+
+def test_do_export_to_parquet_progress_callback(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("callback.parquet")
+
+    calls = []
+    rust.do_export_to_parquet(
+        [revindex],
+        out_parquet,
+        progress_callback=lambda db, processed, total: calls.append(
+            (db, processed, total)
+        ),
+    )
+    print(calls)
+
+    assert calls
+    db, processed, total = calls[-1]
+    assert db == "podar-ref-subset.branch0_9_13.internal.rocksdb"
+    assert processed == total == len(pl.read_parquet(out_parquet))
+
+
+def test_do_export_to_parquet_cancel_handle(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("cancelled.parquet")
+
+    handle = rust.ExportHandle()
+    assert not handle.cancelled
+    handle.cancel()
+    assert handle.cancelled
+
+    with pytest.raises(rust.ExportInterruptedError, match="export was interrupted"):
+        rust.do_export_to_parquet([revindex], out_parquet, handle=handle)
+
+    # the default policy still finalizes a valid (empty) file
+    assert len(pl.read_parquet(out_parquet)) == 0
+
+
+@pytest.mark.parametrize("policy", ["finalize", "keep", "delete"])
+def test_do_export_to_parquet_on_interrupt(runtmp, policy):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("cancelled.parquet")
+    unclassified = runtmp.output("unclassified.parquet")
+
+    handle = rust.ExportHandle()
+    handle.cancel()
+    with pytest.raises(rust.ExportInterruptedError):
+        rust.do_export_to_parquet(
+            [revindex],
+            out_parquet,
+            tax_path_list=[tax_csv],
+            unclassified_output=unclassified,
+            on_interrupt=policy,
+            handle=handle,
+        )
+
+    if policy == "finalize":
+        # valid files, marked as partial in the footer
+        for path in (out_parquet, unclassified):
+            info = rust.parquet_inspect(path)
+            assert info["metadata"]["export_partial"] == "true"
+            assert not os.path.exists(path + ".tmp")
+    elif policy == "keep":
+        # the unfinished files are left as they were, without a footer
+        assert not os.path.exists(out_parquet)
+        assert not os.path.exists(unclassified)
+        for path in (out_parquet + ".tmp", unclassified + ".tmp"):
+            with open(path, "rb") as fp:
+                data = fp.read()
+            assert len(data) <= 4 or not data.endswith(b"PAR1")
+            with pytest.raises(Exception):
+                pl.read_parquet(path)
+    else:
+        for path in (out_parquet, unclassified):
+            assert not os.path.exists(path)
+            assert not os.path.exists(path + ".tmp")
+
+
+def test_rocksdb_revindex_to_parquet_interrupted_exit_code(runtmp, monkeypatch):
+    # an interrupted export exits with 130, like a shell does after SIGINT
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    export = rust.do_export_to_parquet
+
+    def cancelled_export(*args, **kwargs):
+        handle = rust.ExportHandle()
+        handle.cancel()
+        return export(*args, handle=handle, **kwargs)
+
+    monkeypatch.setattr(rust, "do_export_to_parquet", cancelled_export)
+    out_parquet = runtmp.output("cancelled.parquet")
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            get_test_data("test6.rocksdb"),
+            "-o",
+            out_parquet,
+            "--on-interrupt",
+            "delete",
+        )
+    assert runtmp.last_result.status == 130
+    assert "export was interrupted" in runtmp.last_result.err
+    assert not os.path.exists(out_parquet)
+
+
+def test_do_export_to_parquet_releases_gil(runtmp):
+    # another Python thread keeps running while the export is in progress
+    import threading
+    import time
+
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("gil.parquet")
+
+    ticks = []
+    done = threading.Event()
+
+    def ticker():
+        while not done.is_set():
+            ticks.append(time.monotonic())
+            time.sleep(0.001)
+
+    thread = threading.Thread(target=ticker)
+    thread.start()
+    try:
+        start = time.monotonic()
+        rust.do_export_to_parquet([revindex], out_parquet)
+        end = time.monotonic()
+    finally:
+        done.set()
+        thread.join()
+
+    assert any(start < t < end for t in ticks)
+
+
+def test_export_config(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("config.parquet")
+
+    config = (
+        rust.ExportConfig([revindex], out_parquet)
+        .taxonomy([tax_csv])
+        .compression("snappy")
+        .row_group_size(100)
+        .columns(["source", "hash", "lca_rank"])
+    )
+    stats = rust.export(config)
+    print(stats)
+
+    df = pl.read_parquet(out_parquet)
+    assert df.columns == ["hash", "lca_rank", "source"]
+    assert stats["rows_written"] == len(df)
+    assert stats["row_groups"] == (len(df) + 99) // 100
+
+    with pytest.raises(ValueError, match="unknown column 'nope'"):
+        rust.export(rust.ExportConfig([revindex], out_parquet).columns(["nope"]))
+
+    with pytest.raises(ValueError, match="unknown compression 'bzip2'"):
+        rust.export(rust.ExportConfig([revindex], out_parquet).compression("bzip2"))
+
+
+def test_rocksdb_revindex_to_parquet_config_file(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("from_config.parquet")
+    out_lca = runtmp.output("from_config.lca.csv")
+    config = runtmp.output("export.toml")
+
+    with open(config, "w") as fp:
+        fp.write(
+            f"""
+database = ["{revindex}"]
+output = "{runtmp.output('ignored.parquet')}"
+taxonomy = ["{tax_csv}"]
+lca-info = "{out_lca}"
+columns = ["hash", "lca_rank"]
+limit = 10
+"""
+        )
+
+    # command-line flags win over the config file
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        "--config",
+        config,
+        "-o",
+        out_parquet,
+        "--limit",
+        "20",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    assert df.columns == ["hash", "lca_rank"]
+    assert len(df) == 20
+    assert os.path.exists(out_lca)
+    assert not os.path.exists(runtmp.output("ignored.parquet"))
+
+
+def test_rocksdb_revindex_to_parquet_config_file_unknown_key(runtmp, capfd):
+    config = runtmp.output("export.toml")
+    with open(config, "w") as fp:
+        fp.write('no-such-option = true\n')
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash("scripts", "revindex_to_parquet", "--config", config)
+
+    assert "unknown option 'no-such-option'" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_from_file(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    pattern = os.path.join(os.path.dirname(revindex), "test6.rocks*")
+    out_parquet = runtmp.output("from_file.parquet")
+    pathlist = runtmp.output("dbs.txt")
+
+    # a glob and a plain path to the same database: exported once
+    with open(pathlist, "w") as fp:
+        fp.write(f"# per-chunk databases\n{pattern}\n\n{revindex}\n")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", "--from-file", pathlist, "-o", out_parquet
+    )
+
+    df = pl.read_parquet(out_parquet)
+    assert df["source"].unique().to_list() == ["test6.rocksdb"]
+    assert df["hash"].is_unique().all()
+
+    with open(pathlist, "w") as fp:
+        fp.write(runtmp.output("missing-*.rocksdb") + "\n")
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "revindex_to_parquet", "--from-file", pathlist, "-o", out_parquet
+        )
+    assert "no databases match" in runtmp.last_result.err
+
+
+def test_get_db_info(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    info = rust.get_db_info(get_test_data("test6.rocksdb"))
+    print(info)
+
+    assert info["ksize"] == 31
+    assert info["scaled"] == 1000
+    assert info["moltype"] == "DNA"
+    assert info["n_datasets"] > 0
+    assert info["estimated_hashes"] > 0
+    assert not info["has_colors"]
+
+    with pytest.raises(ValueError, match="not a valid RevIndex database"):
+        rust.get_db_info(runtmp.output("nope.rocksdb"))
+
+
+def test_list_datasets(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    datasets = rust.list_datasets(revindex)
+    print(datasets)
+
+    assert len(datasets) == rust.get_db_info(revindex)["n_datasets"]
+    for d in datasets:
+        assert set(d) == {"name", "filename", "md5"}
+        assert len(d["md5"]) == 32
+
+    pytest.importorskip("pyarrow")
+    table = rust.list_datasets(revindex, as_arrow=True)
+    assert table.column_names == ["name", "filename", "md5"]
+    assert table.num_rows == len(datasets)
+
+
+def test_estimate_export(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+
+    est = rust.estimate_export([revindex])
+    print(est)
+
+    db = est["databases"]["test6.rocksdb"]
+    assert db["ksize"] == 31
+    assert db["projected_rows"] == est["projected_rows"] > 0
+    assert db["taxonomy_matches"] is None
+    assert set(est["bytes_per_codec"]) == {
+        "zstd",
+        "snappy",
+        "lz4",
+        "gzip",
+        "brotli",
+        "uncompressed",
+    }
+    assert est["projected_bytes"] > 0
+
+    # options shrink the projected output
+    config = rust.ExportConfig([]).taxonomy([tax_csv]).shard(0, 2)
+    sharded = rust.estimate_export([revindex], config, sample_fraction=0.5)
+    db = sharded["databases"]["test6.rocksdb"]
+    assert db["taxonomy_matches"] > 0
+    assert db["projected_rows"] < est["projected_rows"]
+
+    with pytest.raises(ValueError, match="sample fraction"):
+        rust.estimate_export([revindex], sample_fraction=2.0)
+
+
+def test_export_stream(runtmp):
+    pa = pytest.importorskip("pyarrow")
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+
+    rust.export(rust.ExportConfig([revindex], out_parquet).taxonomy([tax_csv]))
+    expected = pl.read_parquet(out_parquet)
+
+    config = rust.ExportConfig([revindex]).taxonomy([tax_csv]).columns(["hash", "lca_rank"])
+    stream = rust.export_stream(config)
+    table = pa.RecordBatchReader.from_stream(stream).read_all()
+
+    assert table.column_names == ["hash", "lca_rank"]
+    assert table.num_rows == len(expected)
+    assert sorted(table.column("hash").to_pylist()) == sorted(expected["hash"].to_list())
+
+    with pytest.raises(RuntimeError, match="already consumed"):
+        pa.RecordBatchReader.from_stream(stream)
+
+
+def test_export_in_memory(runtmp):
+    pytest.importorskip("pyarrow")
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+
+    stats = rust.do_export_to_parquet([revindex], out_parquet, [tax_csv])
+    table = rust.do_export_to_parquet([revindex], None, [tax_csv])
+    print(table)
+
+    assert table.num_rows == stats["rows_written"]
+    assert table.schema.names == pl.read_parquet(out_parquet).columns
+
+    table = rust.export(rust.ExportConfig([revindex]).limit(10))
+    assert table.num_rows == 10
+
+
+def test_query_parquet(runtmp, capfd):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    matches = runtmp.output("matches.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--row-group-size",
+        "100",
+    )
+    df = pl.read_parquet(out_parquet)
+    wanted = df["hash"].sort().to_list()[::500]
+
+    hashes_file = runtmp.output("hashes.txt")
+    with open(hashes_file, "w") as fp:
+        fp.write(f"{wanted[-1]}\n")
+
+    runtmp.sourmash(
+        "scripts",
+        "query_parquet",
+        out_parquet,
+        *[str(h) for h in wanted[:-1]],
+        "1",
+        "--hashes-file",
+        hashes_file,
+        "-o",
+        matches,
+    )
+
+    captured = capfd.readouterr()
+    print(captured.err)
+
+    found = pl.read_parquet(matches)
+    assert sorted(found["hash"].unique().to_list()) == wanted
+    assert found.columns == df.columns
+    assert "row groups" in captured.err
+
+
+def test_parquet_gather(runtmp, capfd):
+    import sourmash
+    from sourmash.sourmash_args import SaveSignaturesToLocation
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    query_sig = runtmp.output("query.sig")
+    out_csv = runtmp.output("gather.csv")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "--output", out_parquet
+    )
+
+    query = list(
+        sourmash.load_file_as_signatures(get_test_data("test6.sig.zip"), ksize=31)
+    )[0]
+    with SaveSignaturesToLocation(query_sig) as save_sig:
+        save_sig.add(query)
+
+    runtmp.sourmash(
+        "scripts",
+        "parquet_gather",
+        query_sig,
+        out_parquet,
+        "-o",
+        out_csv,
+        "--threshold-bp",
+        "0",
+    )
+
+    with open(out_csv, newline="") as fp:
+        rows = list(csv.DictReader(fp))
+    print(rows)
+
+    assert rows[0]["name"] == query.name
+    assert float(rows[0]["containment"]) == 1.0
+    assert float(rows[0]["f_unique_to_query"]) == 1.0
+    assert rows[0]["source"] == "test6.rocksdb"
+
+
+def test_parquet_containment(runtmp):
+    import sourmash
+    from sourmash.sourmash_args import SaveSignaturesToLocation
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    query_sig = runtmp.output("query.sig")
+    out_csv = runtmp.output("containment.csv")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "--output", out_parquet
+    )
+
+    queries = list(
+        sourmash.load_file_as_signatures(get_test_data("test6.sig.zip"), ksize=31)
+    )[:2]
+    with SaveSignaturesToLocation(query_sig) as save_sig:
+        for query in queries:
+            save_sig.add(query)
+
+    runtmp.sourmash(
+        "scripts", "parquet_containment", query_sig, out_parquet, "-o", out_csv
+    )
+
+    with open(out_csv, newline="") as fp:
+        rows = list(csv.DictReader(fp))
+    print(rows)
+
+    for query in queries:
+        query_rows = [row for row in rows if row["query"] == query.name]
+        assert query_rows[0]["name"] == query.name
+        assert float(query_rows[0]["containment"]) == 1.0
+        assert query_rows[0]["source"] == "test6.rocksdb"
+        containments = [float(row["containment"]) for row in query_rows]
+        assert containments == sorted(containments, reverse=True)
+
+def test_classify_sig(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    query = get_test_data("test6.sig.zip")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        out_parquet,
+        "--taxonomy",
+        tax_csv,
+    )
+
+    from_parquet = runtmp.output("from_parquet.csv")
+    runtmp.sourmash(
+        "scripts", "classify_sig", query, "--parquet", out_parquet, "-o", from_parquet
+    )
+    from_db = runtmp.output("from_db.csv")
+    runtmp.sourmash(
+        "scripts", "classify_sig", query, "--db", revindex, "-t", tax_csv, "-o", from_db
+    )
+
+    with open(from_parquet, newline="") as fp:
+        parquet_rows = list(csv.DictReader(fp))
+    with open(from_db, newline="") as fp:
+        db_rows = list(csv.DictReader(fp))
+    print(parquet_rows)
+
+    assert parquet_rows
+    assert parquet_rows == db_rows
+    for row in parquet_rows:
+        assert row["status"] == "found"
+        assert row["lineage"].startswith("d__Bacteria")
+        assert float(row["f_domain"]) > 0
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "classify_sig", query, "--db", revindex, "-o", from_db
+        )
+
+
+def test_parquet_to_revindex(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    rebuilt = runtmp.output("rebuilt.rocksdb")
+    rebuilt_parquet = runtmp.output("rebuilt.parquet")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "--output", out_parquet
+    )
+    runtmp.sourmash("scripts", "parquet_to_revindex", out_parquet, "-o", rebuilt)
+
+    orig_info = rust.get_db_info(revindex)
+    info = rust.get_db_info(rebuilt)
+    assert (info["ksize"], info["scaled"]) == (orig_info["ksize"], orig_info["scaled"])
+    assert info["n_datasets"] == orig_info["n_datasets"]
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", rebuilt, "--output", rebuilt_parquet
+    )
+
+    def hash_to_names(path):
+        df = pl.read_parquet(path)
+        return {
+            row["hash"]: sorted(row["dataset_names"]) for row in df.iter_rows(named=True)
+        }
+
+    assert hash_to_names(rebuilt_parquet) == hash_to_names(out_parquet)
+
+    # refuses to overwrite an existing database
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash("scripts", "parquet_to_revindex", out_parquet, "-o", rebuilt)
+
+
+def test_revindex_to_sigs(runtmp):
+    import sourmash
+
+    revindex = get_test_data("test6.rocksdb")
+    out_zip = runtmp.output("rebuilt.sig.zip")
+
+    runtmp.sourmash("scripts", "revindex_to_sigs", revindex, "-o", out_zip)
+
+    orig = {
+        ss.name: ss
+        for ss in sourmash.load_file_as_signatures(get_test_data("test6.sig.zip"), ksize=31)
+    }
+    rebuilt = list(sourmash.load_file_as_signatures(out_zip))
+    print([ss.name for ss in rebuilt])
+
+    assert {ss.name for ss in rebuilt} == set(orig)
+    for ss in rebuilt:
+        expected = orig[ss.name].minhash.downsample(scaled=ss.minhash.scaled)
+        assert set(ss.minhash.hashes) == set(expected.hashes)
+
+
+def test_revindex_to_sqlite(runtmp):
+    import sourmash
+
+    revindex = get_test_data("test6.rocksdb")
+    out_db = runtmp.output("test6.sqldb")
+
+    runtmp.sourmash("scripts", "revindex_to_sqlite", revindex, "-o", out_db)
+
+    orig = {
+        ss.name: ss
+        for ss in sourmash.load_file_as_signatures(get_test_data("test6.sig.zip"), ksize=31)
+    }
+    index = sourmash.load_file_as_index(out_db)
+    loaded = list(index.signatures())
+    assert {ss.name for ss in loaded} == set(orig)
+    for ss in loaded:
+        expected = orig[ss.name].minhash.downsample(scaled=ss.minhash.scaled)
+        assert set(ss.minhash.hashes) == set(expected.hashes)
+
+    # the index answers searches directly
+    query = next(iter(orig.values()))
+    results = list(index.prefetch(query, threshold_bp=0))
+    assert query.name in {sr.signature.name for sr in results}
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash("scripts", "revindex_to_sqlite", revindex, "-o", out_db)
+    assert "already exists" in runtmp.last_result.err
+
+
+def test_revindex_to_lca_json(runtmp):
+    import sourmash
+    from sourmash.lca.lca_db import LCA_Database
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_db = runtmp.output("test6.lca.json.gz")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_lca_json", revindex, "-t", tax_csv, "-o", out_db
+    )
+
+    db = LCA_Database.load(out_db)
+    orig = list(
+        sourmash.load_file_as_signatures(get_test_data("test6.sig.zip"), ksize=31)
+    )
+    assert db.ksize == 31
+    assert set(db._ident_to_name.values()) == {ss.name for ss in orig}
+    assert db._lid_to_lineage
+
+    # each dataset's hashes point back at it
+    for ss in orig:
+        ident = ss.name.split()[0]
+        idx = db._ident_to_idx[ident]
+        mh = ss.minhash.downsample(scaled=db.scaled)
+        assert all(idx in db._hashval_to_idx[h] for h in mh.hashes)
+
+
+def test_revindex_to_lca_json_blank_rank(runtmp):
+    import gzip
+    import json
+
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax = pl.read_csv(get_test_data("test6.taxonomy.csv"))
+    # no class for the first lineage
+    ident = tax["ident"][0]
+    tax = tax.with_columns(
+        pl.when(pl.col("ident") == ident)
+        .then(None)
+        .otherwise(pl.col("class"))
+        .alias("class")
+    )
+    tax_csv = runtmp.output("blank.taxonomy.csv")
+    tax.write_csv(tax_csv)
+    out_db = runtmp.output("test6.lca.json.gz")
+
+    rust.revindex_to_lca_json(revindex, [tax_csv], out_db)
+
+    with gzip.open(out_db, "rt") as fp:
+        db = json.load(fp)
+    idx = db["ident_to_idx"][ident]
+    lineage = dict(db["lid_to_lineage"][str(db["idx_to_lid"][str(idx)])])
+    row = tax.row(0, named=True)
+    assert lineage["class"] == ""
+    assert lineage["superkingdom"] == row["domain"]
+    for rank in ["phylum", "order", "family", "genus", "species"]:
+        assert lineage[rank] == row[rank]
+
+
+def test_revindex_diff(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    sampled = runtmp.output("sampled.parquet")
+    out_diff = runtmp.output("diff.parquet")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "--output", out_parquet
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "--output",
+        sampled,
+        "--sample-fraction",
+        "0.5",
+    )
+
+    runtmp.sourmash("scripts", "revindex_diff", revindex, sampled, "-o", out_diff)
+    print(runtmp.last_result.err)
+
+    n_all = len(pl.read_parquet(out_parquet))
+    n_sampled = len(pl.read_parquet(sampled))
+    diff = pl.read_parquet(out_diff)
+
+    assert len(diff) == n_all
+    assert diff["hash"].is_sorted()
+    statuses = dict(diff["status"].value_counts().iter_rows())
+    assert statuses.get("shared", 0) == n_sampled
+    assert statuses.get("a_only", 0) == n_all - n_sampled
+    assert "b_only" not in statuses
+    assert not diff["membership_changed"].any()
+
+
+def test_rocksdb_revindex_to_parquet_merge(runtmp):
+    import shutil
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    db_a = runtmp.output("a.rocksdb")
+    db_b = runtmp.output("b.rocksdb")
+    shutil.copytree(revindex, db_a)
+    shutil.copytree(revindex, db_b)
+    single = runtmp.output("single.parquet")
+    merged = runtmp.output("merged.parquet")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", single, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        db_a,
+        db_b,
+        "-o",
+        merged,
+        "-t",
+        tax_csv,
+        "--merge",
+    )
+
+    single_df = pl.read_parquet(single).sort("hash")
+    merged_df = pl.read_parquet(merged)
+    print(merged_df)
+
+    # one row per hash, not one per source
+    assert len(merged_df) == len(single_df)
+    assert merged_df["hash"].is_sorted()
+    assert set(merged_df["source"]) == {"a.rocksdb;b.rocksdb"}
+    assert merged_df["lca_lineage"].to_list() == single_df["lca_lineage"].to_list()
+
+
+def test_rocksdb_revindex_to_parquet_since(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    sampled = runtmp.output("sampled.parquet")
+    update = runtmp.output("update.parquet")
+    nothing_new = runtmp.output("nothing_new.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        sampled,
+        "--sample-fraction",
+        "0.5",
+    )
+
+    # only the hashes missing from the previous export are written
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", update, "--since", sampled
+    )
+    full_hashes = set(pl.read_parquet(full)["hash"])
+    sampled_hashes = set(pl.read_parquet(sampled)["hash"])
+    update_hashes = set(pl.read_parquet(update)["hash"])
+    assert update_hashes == full_hashes - sampled_hashes
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", nothing_new, "--since", full
+    )
+    assert len(pl.read_parquet(nothing_new)) == 0
+
+
+def test_reannotate_parquet(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    untaxed = runtmp.output("untaxed.parquet")
+    taxed = runtmp.output("taxed.parquet")
+    reannotated = runtmp.output("reannotated.parquet")
+    out_lca = runtmp.output("reannotated.lca.csv")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", untaxed)
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", taxed, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "reannotate_parquet",
+        untaxed,
+        "-t",
+        tax_csv,
+        "-o",
+        reannotated,
+        "--lca-info",
+        out_lca,
+    )
+
+    expected = pl.read_parquet(taxed).sort("hash")
+    df = pl.read_parquet(reannotated).sort("hash")
+    assert df.columns == expected.columns
+    assert df["lca_lineage"].to_list() == expected["lca_lineage"].to_list()
+    assert df["lca_rank"].to_list() == expected["lca_rank"].to_list()
+
+    lca_df = pd.read_csv(out_lca)
+    species = lca_df[lca_df["lca_rank"] == "species"]
+    assert species["count"].iloc[0] == 23901
+
+
+def test_parquet_lca_summary(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    export_lca = runtmp.output("export.lca.csv")
+    summary_lca = runtmp.output("summary.lca.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--lca-info",
+        export_lca,
+    )
+    runtmp.sourmash(
+        "scripts", "parquet_lca_summary", out_parquet, "--lca-info", summary_lca
+    )
+
+    # the summary from the export is reproduced exactly
+    assert pd.read_csv(summary_lca).equals(pd.read_csv(export_lca))
+
+
+def test_parquet_inspect(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+    runtmp.sourmash("scripts", "parquet_inspect", out_parquet, "--json")
+
+    info = json.loads(runtmp.last_result.out)
+    df = pl.read_parquet(out_parquet)
+    assert info["num_rows"] == len(df)
+    assert [name for name, _ in info["schema"]] == df.columns
+    assert sum(rg["num_rows"] for rg in info["row_groups"]) == len(df)
+    assert info["metadata"]["export_partial"] == "false"
+    assert info["metadata"]["hashes_written"] == str(len(df))
+    assert info["source_rows"] == {"test6.rocksdb": len(df)}
+
+
+def test_verify_export(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    sampled = runtmp.output("sampled.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+    runtmp.sourmash("scripts", "verify_export", out_parquet, revindex)
+    assert "The export matches its databases." in runtmp.last_result.err
+
+    # a subsampled export is missing hashes
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        sampled,
+        "--sample-fraction",
+        "0.1",
+    )
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash("scripts", "verify_export", sampled, revindex)
+    assert "hashes, but the export has" in runtmp.last_result.err
+
+
+def test_concat_parquet(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    full = runtmp.output("full.parquet")
+    full_lca = runtmp.output("full.lca.csv")
+    combined = runtmp.output("combined.parquet")
+    combined_lca = runtmp.output("combined.lca.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        full,
+        "-t",
+        tax_csv,
+        "--lca-info",
+        full_lca,
+    )
+    shards, shard_lcas = [], []
+    for i in range(2):
+        shard = runtmp.output(f"shard{i}.parquet")
+        shard_lca = runtmp.output(f"shard{i}.lca.csv")
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            shard,
+            "-t",
+            tax_csv,
+            "--lca-info",
+            shard_lca,
+            "--shard",
+            str(i),
+            "--num-shards",
+            "2",
+        )
+        shards.append(shard)
+        shard_lcas.append(shard_lca)
+
+    # shard 0 given twice: its rows are only written once
+    runtmp.sourmash(
+        "scripts",
+        "concat_parquet",
+        *shards,
+        shards[0],
+        "-o",
+        combined,
+        "--lca-info-inputs",
+        *shard_lcas,
+        "--lca-info",
+        combined_lca,
+    )
+
+    expected = pl.read_parquet(full).sort("hash")
+    df = pl.read_parquet(combined).sort("hash")
+    assert df.equals(expected)
+
+    full_df = pd.read_csv(full_lca).set_index("lca_rank")
+    combined_df = pd.read_csv(combined_lca).set_index("lca_rank")
+    assert combined_df["count"].to_dict() == full_df["count"].to_dict()
+
+
+def test_rewrite_parquet(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    sorted_parquet = runtmp.output("sorted.parquet")
+    buckets = runtmp.output("buckets")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+    runtmp.sourmash(
+        "scripts",
+        "rewrite_parquet",
+        out_parquet,
+        "-o",
+        sorted_parquet,
+        "--sort",
+        "--row-group-size",
+        "1000",
+        "--compression",
+        "snappy",
+    )
+
+    expected = pl.read_parquet(out_parquet)
+    df = pl.read_parquet(sorted_parquet)
+    assert df["hash"].to_list() == sorted(expected["hash"].to_list())
+    assert df.sort("hash").equals(expected.sort("hash"))
+    info = rust.parquet_inspect(sorted_parquet)
+    assert all(rg["num_rows"] <= 1000 for rg in info["row_groups"])
+
+    runtmp.sourmash(
+        "scripts",
+        "rewrite_parquet",
+        out_parquet,
+        "-o",
+        buckets,
+        "--partition-by",
+        "hash:4",
+    )
+    total = 0
+    for i in range(4):
+        part = pl.read_parquet(os.path.join(buckets, f"bucket={i}", "part-0.parquet"))
+        assert all(h % 4 == i for h in part["hash"].to_list())
+        total += len(part)
+    assert total == len(expected)
+
+
+def test_manifest_to_parquet(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.manifest.parquet")
+
+    runtmp.sourmash("scripts", "manifest_to_parquet", revindex, "-o", out_parquet)
+
+    df = pl.read_parquet(out_parquet)
+    datasets = rust.list_datasets(revindex)
+    assert df.columns == [
+        "idx",
+        "name",
+        "md5",
+        "ksize",
+        "scaled",
+        "moltype",
+        "n_hashes",
+        "filename",
+    ]
+    assert df["idx"].to_list() == list(range(len(datasets)))
+    assert df["name"].to_list() == [d["name"] for d in datasets]
+    assert df["md5"].to_list() == [d["md5"] for d in datasets]
+    assert (df["n_hashes"] > 0).all()
+
+
+def test_sigs_to_parquet(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    sigs = get_test_data("test6.sig.zip")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    from_revindex = runtmp.output("revindex.parquet")
+    from_sigs = runtmp.output("sigs.parquet")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", from_revindex, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts", "sigs_to_parquet", sigs, "-o", from_sigs, "-k", "31", "-t", tax_csv
+    )
+
+    expected = pl.read_parquet(from_revindex).sort("hash")
+    df = pl.read_parquet(from_sigs).sort("hash")
+    assert df.columns == expected.columns
+    assert df["source"].unique().to_list() == ["test6.sig.zip"]
+    assert df["hash"].to_list() == expected["hash"].to_list()
+    assert [sorted(n) for n in df["dataset_names"].to_list()] == [
+        sorted(n) for n in expected["dataset_names"].to_list()
+    ]
+    assert df["lca_lineage"].to_list() == expected["lca_lineage"].to_list()
+
+
+def test_revindex_to_parquet_sketch_collection(runtmp):
+    # sketch collections are routed to the sigs_to_parquet exporter
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    sigs = get_test_data("test6.sig.zip")
+    from_revindex = runtmp.output("revindex.parquet")
+    from_zip = runtmp.output("zip.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", from_revindex)
+    runtmp.sourmash("scripts", "revindex_to_parquet", sigs, "-o", from_zip, "-k", "31")
+
+    expected = pl.read_parquet(from_revindex).sort("hash")
+    df = pl.read_parquet(from_zip).sort("hash")
+    assert df["source"].unique().to_list() == ["test6.sig.zip"]
+    assert df["hash"].to_list() == expected["hash"].to_list()
+
+    with pytest.raises(ValueError, match="not supported for sketch collections"):
+        rust.do_export_to_parquet(
+            [sigs], runtmp.output("sharded.parquet"), shard=0, num_shards=2
+        )
+
+    with pytest.raises(ValueError, match="cannot be exported together"):
+        rust.do_export_to_parquet([revindex, sigs], runtmp.output("mixed.parquet"))
+
+
+def test_rocksdb_revindex_to_parquet_picklist(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    full = runtmp.output("full.parquet")
+    included = runtmp.output("included.parquet")
+    excluded = runtmp.output("excluded.parquet")
+    picklist = runtmp.output("picklist.csv")
+
+    with open(picklist, "w") as fp:
+        fp.write("accession\nGCF_001881345\n")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", full, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        included,
+        "-t",
+        tax_csv,
+        "--picklist",
+        f"{picklist}:accession:identprefix",
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        excluded,
+        "--picklist",
+        f"{picklist}:accession:identprefix:exclude",
+    )
+
+    def picked(names):
+        return [n for n in names if n.startswith("GCF_001881345.")]
+
+    df = pl.read_parquet(included)
+    assert len(df) > 0
+    assert all(picked(names) == names for names in df["dataset_names"].to_list())
+    assert df["lca_rank"].unique().to_list() == ["species"]
+
+    # every hash of the picked dataset, and only those
+    rows = pl.read_parquet(full).select("hash", "dataset_names").iter_rows()
+    expected = [hash for hash, names in rows if picked(names)]
+    assert sorted(df["hash"].to_list()) == sorted(expected)
+
+    df = pl.read_parquet(excluded)
+    assert not any(picked(names) for names in df["dataset_names"].to_list())
+
+
+def test_rocksdb_revindex_to_parquet_dataset_patterns(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("patterns.parquet")
+    patterns = runtmp.output("patterns.txt")
+
+    with open(patterns, "w") as fp:
+        fp.write("# Shewanella, except OS185\nOS185$\n")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--include-dataset",
+        "Shewanella",
+        "--exclude-dataset",
+        f"@{patterns}",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    names = {name for row in df["dataset_names"].to_list() for name in row}
+    assert names == {"GCF_000021665.1 Shewanella baltica OS223"}
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out_parquet,
+            "-f",
+            "--include-dataset",
+            "(unclosed",
+        )
+    assert "invalid dataset pattern" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_dataset_count(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    shared = runtmp.output("shared.parquet")
+    unique = runtmp.output("unique.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", shared, "--min-datasets", "2"
+    )
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", unique, "--max-datasets", "1"
+    )
+
+    counts = pl.read_parquet(full)["dataset_names"].list.len()
+    shared_counts = pl.read_parquet(shared)["dataset_names"].list.len()
+    unique_counts = pl.read_parquet(unique)["dataset_names"].list.len()
+    assert len(shared_counts) > 0 and shared_counts.min() >= 2
+    assert len(unique_counts) > 0 and unique_counts.max() == 1
+    assert len(shared_counts) + len(unique_counts) == len(counts)
+
+
+def test_rocksdb_revindex_to_parquet_lineage_filter(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    by_lca = runtmp.output("lca.parquet")
+    by_any = runtmp.output("any.parquet")
+    prefix = "d__Bacteria;p__Bacteroidota;c__Bacteroidia;o__Bacteroidales;f__Bacteroidaceae;g__Prevotella"
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        by_lca,
+        "-t",
+        tax_csv,
+        "--lineage-filter",
+        prefix,
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        by_any,
+        "-t",
+        tax_csv,
+        "--lineage-filter",
+        prefix,
+        "--lineage-filter-mode",
+        "any",
+    )
+
+    df = pl.read_parquet(by_lca)
+    assert len(df) > 0
+    assert all(
+        lineage == prefix or lineage.startswith(prefix + ";")
+        for lineage in df["lca_lineage"].to_list()
+    )
+
+    # any-mode also keeps hashes shared with other genera
+    df_any = pl.read_parquet(by_any)
+    assert len(df_any) >= len(df)
+    assert all(
+        any(lineage.startswith(prefix) for lineage in lineages)
+        for lineages in df_any["taxonomy_list"].to_list()
+    )
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("no-tax.parquet"),
+            "--lineage-filter",
+            prefix,
+        )
+    assert "needs a taxonomy" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_min_lca_rank(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    full = runtmp.output("full.parquet")
+    genus = runtmp.output("genus.parquet")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", full, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        genus,
+        "-t",
+        tax_csv,
+        "--min-lca-rank",
+        "genus",
+        "--drop-unclassified",
+    )
+
+    expected = pl.read_parquet(full).filter(
+        pl.col("lca_rank").is_in(["genus", "species"])
+    )
+    df = pl.read_parquet(genus)
+    assert set(df["lca_rank"].unique().to_list()) <= {"genus", "species"}
+    assert sorted(df["hash"].to_list()) == sorted(expected["hash"].to_list())
+
+
+def test_rocksdb_revindex_to_parquet_unclassified_output(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    classified = runtmp.output("classified.parquet")
+    unclassified = runtmp.output("unclassified.parquet")
+    tax_csv = runtmp.output("partial-tax.csv")
+
+    # leave the Shewanella genomes out of the taxonomy
+    with open(get_test_data("test6.taxonomy.csv")) as fp:
+        lines = [line for line in fp if "Shewanella" not in line]
+    with open(tax_csv, "w") as fp:
+        fp.writelines(lines)
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", full, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        classified,
+        "-t",
+        tax_csv,
+        "--unclassified-output",
+        unclassified,
+    )
+
+    df = pl.read_parquet(classified)
+    df_un = pl.read_parquet(unclassified)
+    assert len(df) > 0 and len(df_un) > 0
+    assert df["taxonomy_list"].list.len().min() > 0
+    assert df_un["taxonomy_list"].list.len().max() == 0
+    assert sorted(df["hash"].to_list() + df_un["hash"].to_list()) == sorted(
+        pl.read_parquet(full)["hash"].to_list()
+    )
+
+
+def test_rocksdb_revindex_to_parquet_hash_list(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    from_text = runtmp.output("from_text.parquet")
+    from_parquet = runtmp.output("from_parquet.parquet")
+    hash_txt = runtmp.output("hashes.txt")
+    hash_parquet = runtmp.output("hashes.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    wanted = pl.read_parquet(full)["hash"].head(10)
+    with open(hash_txt, "w") as fp:
+        fp.write("# hashes of interest\n")
+        fp.writelines(f"{h}\n" for h in wanted.to_list() + [1])
+    pl.DataFrame({"hash": wanted}).write_parquet(hash_parquet)
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        from_text,
+        "--hash-list",
+        hash_txt,
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        from_parquet,
+        "--hash-list",
+        hash_parquet,
+    )
+
+    # hashes that aren't in the database are ignored
+    for path in (from_text, from_parquet):
+        df = pl.read_parquet(path)
+        assert sorted(df["hash"].to_list()) == sorted(wanted.to_list())
+
+
+def test_rocksdb_revindex_to_parquet_query(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    out = runtmp.output("query.parquet")
+    query = runtmp.output("query.sig.gz")
+
+    # one of the database's own sketches as the query
+    with zipfile.ZipFile(get_test_data("test6.sig.zip")) as zf:
+        with open(query, "wb") as fp:
+            fp.write(zf.read("signatures/0ebd36ff45fc2810808789667f4aad84.sig.gz"))
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out, "--query", query
+    )
+
+    full_df = pl.read_parquet(full)
+    df = pl.read_parquet(out)
+    assert 0 < df.height < full_df.height
+    assert set(df["hash"].to_list()) <= set(full_df["hash"].to_list())
+    # every exported hash is in the query's dataset
+    names = df["dataset_names"].to_list()
+    assert set(names[0]).intersection(*names)
+
+
+def test_rocksdb_revindex_to_parquet_tag_queries(runtmp):
+    import sourmash
+    from sourmash.sourmash_args import SaveSignaturesToLocation
+
+    revindex = get_test_data("test6.rocksdb")
+    out = runtmp.output("tagged.parquet")
+    queries_zip = runtmp.output("queries.zip")
+
+    queries = list(
+        sourmash.load_file_as_signatures(get_test_data("test6.sig.zip"), ksize=31)
+    )[:2]
+    with SaveSignaturesToLocation(queries_zip) as save_sig:
+        for query in queries:
+            save_sig.add(query)
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out,
+        "--query",
+        queries_zip,
+        "--tag-queries",
+    )
+
+    df = pl.read_parquet(out)
+    assert df.height > 0
+    query_names = {query.name for query in queries}
+    for names, tags in zip(df["dataset_names"].to_list(), df["query_names"].to_list()):
+        assert tags and set(tags) <= query_names
+        # a hash of a query's own dataset is tagged with that query
+        assert query_names.intersection(names) <= set(tags)
+
+    # the hashes tagged with a query are those of its own dataset
+    for query in queries:
+        tagged = df.filter(pl.col("query_names").list.contains(query.name))
+        own = df.filter(pl.col("dataset_names").list.contains(query.name))
+        assert tagged["hash"].sort().to_list() == own["hash"].sort().to_list()
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "revindex_to_parquet", revindex, "-o", out, "--tag-queries"
+        )
+    assert "tag_queries needs a query" in runtmp.last_result.err
+
+def test_rocksdb_revindex_to_parquet_prefetch(runtmp):
+    import sourmash
+
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    out = runtmp.output("prefetch.parquet")
+    empty = runtmp.output("empty.parquet")
+    query = runtmp.output("query.sig.gz")
+
+    with zipfile.ZipFile(get_test_data("test6.sig.zip")) as zf:
+        with open(query, "wb") as fp:
+            fp.write(zf.read("signatures/0ebd36ff45fc2810808789667f4aad84.sig.gz"))
+    query_name = sourmash.load_one_signature(query, ksize=31).name
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out, "--prefetch", query
+    )
+
+    full_df = pl.read_parquet(full)
+    df = pl.read_parquet(out)
+    assert 0 < df.height <= full_df.height
+    # the query's own dataset is kept with all of its hashes
+    in_query = pl.col("dataset_names").list.contains(query_name)
+    assert df.filter(in_query).height == full_df.filter(in_query).height
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        empty,
+        "--prefetch",
+        query,
+        "--prefetch-threshold-bp",
+        str(10**12),
+    )
+    assert pl.read_parquet(empty).height == 0
+
+def test_rocksdb_revindex_to_parquet_ksize(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    k31 = runtmp.output("k31.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", k31, "-k", "31")
+    assert pl.read_parquet(k31).equals(pl.read_parquet(full))
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "revindex_to_parquet", revindex, "-o", k31, "-k", "21", "-f"
+        )
+    assert "no sketches with ksize 21; available ksizes: 31" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_mixed_params(runtmp):
+    revindex1 = get_test_data("test6.rocksdb")
+    revindex2 = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    out_parquet = runtmp.output("mixed.parquet")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts", "revindex_to_parquet", revindex1, revindex2, "-o", out_parquet
+        )
+    err = runtmp.last_result.err
+    assert "the databases have different sketch parameters" in err
+    assert "ksize  scaled  moltype" in err
+    assert "--allow-mixed-params" in err
+    assert not os.path.exists(out_parquet)
+
+
+def test_rocksdb_revindex_to_parquet_strict(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    strict = runtmp.output("strict.parquet")
+    stats_json = runtmp.output("stats.json")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", full)
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        strict,
+        "--strict",
+        "--stats-json",
+        stats_json,
+    )
+
+    # a database without invalid dataset IDs exports the same with --strict
+    assert pl.read_parquet(strict).equals(pl.read_parquet(full))
+    with open(stats_json) as fp:
+        stats = json.load(fp)
+    assert stats["invalid_datasets"] == {}
+
+
+def test_revindex_check(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    counts_csv = runtmp.output("counts.csv")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+    runtmp.sourmash(
+        "scripts", "revindex_check", revindex, "--dataset-counts", counts_csv
+    )
+    assert "No problems found." in runtmp.last_result.err
+
+    # per-dataset counts add up to the dataset memberships in an export
+    counts = pd.read_csv(counts_csv)
+    assert set(counts["source"]) == {"test6.rocksdb"}
+    assert (counts["hashes"] > 0).all()
+    df = pl.read_parquet(out_parquet)
+    assert counts["hashes"].sum() == df["dataset_names"].list.len().sum()
+
+
+def test_rocksdb_revindex_to_parquet_dataset_summary(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    summary_parquet = runtmp.output("test6.datasets.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--dataset-summary",
+        summary_parquet,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    summary = pl.read_parquet(summary_parquet)
+    assert summary.height == 6
+    assert set(summary["source"]) == {"test6.rocksdb"}
+    assert summary["hashes"].sum() == df["dataset_names"].list.len().sum()
+    assert summary["unique_hashes"].sum() == df.filter(
+        pl.col("dataset_names").list.len() == 1
+    ).height
+
+    rank_cols = [c for c in summary.columns if c.startswith("lca_")]
+    assert rank_cols[0] == "lca_domain" and rank_cols[-1] == "lca_species"
+    for row in summary.iter_rows(named=True):
+        assert row["unique_hashes"] <= row["hashes"]
+        with_lca = sum(row[c] for c in rank_cols)
+        assert row["classified_fraction"] == pytest.approx(with_lca / row["hashes"])
+
+
+def test_rocksdb_revindex_to_parquet_lineage_summary(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    summary_csv = runtmp.output("test6.lineages.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--lineage-summary",
+        summary_csv,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    summary = pd.read_csv(summary_csv)
+    assert set(summary["source"]) == {"test6.rocksdb"}
+
+    # every rank accounts for all hashes
+    for rank, rows in summary.groupby("rank"):
+        assert rows["hashes"].sum() == len(df), rank
+
+    # clade counts include every hash whose LCA is at or below the lineage
+    lineages = df["lca_lineage"].to_list()
+    for row in summary[summary["lineage"] != "unclassified"].itertuples():
+        below = sum(
+            1
+            for lca in lineages
+            if lca == row.lineage or (lca or "").startswith(row.lineage + ";")
+        )
+        assert row.hashes == below, row.lineage
+
+
+def test_rocksdb_revindex_to_parquet_kraken_report(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    report = runtmp.output("test6.kreport")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--kraken-report",
+        report,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    with open(report) as fp:
+        rows = [line.rstrip("\n").split("\t") for line in fp]
+    assert all(len(row) == 6 for row in rows)
+    by_code = {}
+    for percent, clade, direct, code, taxid, name in rows:
+        by_code.setdefault(code, []).append((int(clade), int(direct), name))
+
+    # unclassified and root cover every hash
+    unclassified = sum(clade for clade, _, _ in by_code.get("U", []))
+    (root,) = by_code["R"]
+    assert root[2] == "root"
+    assert unclassified + root[0] == len(df)
+
+    # direct counts add up to the classified hashes, and domains to root
+    assert sum(int(row[2]) for row in rows if row[3] not in "UR") == root[0]
+    assert sum(clade for clade, _, _ in by_code["D"]) == root[0]
+
+    # names are indented by rank
+    domain = by_code["D"][0][2]
+    assert domain.startswith("  ") and not domain.startswith("   ")
+
+
+def test_rocksdb_revindex_to_parquet_krona(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    krona_tsv = runtmp.output("test6.krona.tsv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--krona",
+        krona_tsv,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    with open(krona_tsv) as fp:
+        rows = [line.rstrip("\n").split("\t") for line in fp]
+
+    # counts cover every hash, one line per LCA lineage
+    assert sum(int(row[0]) for row in rows) == len(df)
+    lineages = df["lca_lineage"].drop_nulls().unique()
+    classified = [row for row in rows if row[1:] != ["unclassified"]]
+    assert len(classified) == len([lin for lin in lineages if lin])
+    assert all(not name.startswith(("d__", "s__")) for row in rows for name in row[1:])
+
+
+def test_rocksdb_revindex_to_parquet_biom(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    biom_json = runtmp.output("test6.biom")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--biom",
+        biom_json,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    with open(biom_json) as fp:
+        table = json.load(fp)
+
+    assert table["format"] == "Biological Observation Matrix 1.0.0"
+    assert table["matrix_type"] == "sparse"
+    assert [col["id"] for col in table["columns"]] == ["test6.rocksdb"]
+    assert table["shape"] == [len(table["rows"]), 1]
+
+    # one observation per LCA lineage, counting every hash once
+    counts = {table["rows"][row]["id"]: n for row, _, n in table["data"]}
+    assert sum(counts.values()) == len(df)
+    for lineage, n in counts.items():
+        if lineage != "unclassified":
+            assert n == (df["lca_lineage"] == lineage).sum()
+    for row in table["rows"]:
+        assert row["metadata"]["taxonomy"] == row["id"].split(";")
+
+
+def test_rocksdb_revindex_to_parquet_hdf5(runtmp):
+    pytest.importorskip("pyarrow")
+    h5py = pytest.importorskip("h5py")
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    out_hdf5 = runtmp.output("test6.h5")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out_parquet, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_hdf5,
+        "-t",
+        tax_csv,
+        "--format",
+        "hdf5",
+    )
+    assert not os.path.exists(out_hdf5 + ".tmp.parquet")
+
+    df = pl.read_parquet(out_parquet)
+    with h5py.File(out_hdf5) as f:
+        assert list(f["hash"][:]) == df["hash"].to_list()
+        assert list(f["ksize"][:]) == df["ksize"].to_list()
+
+        # strings are codes into a table of values, -1 for null
+        ranks = f["lca_rank/values"].asstr()[:]
+        assert [
+            ranks[code] if code >= 0 else None for code in f["lca_rank/codes"][:]
+        ] == df["lca_rank"].to_list()
+
+        # lists are ragged: codes between consecutive offsets
+        names = f["dataset_names/values"].asstr()[:]
+        codes = f["dataset_names/codes"][:]
+        offsets = f["dataset_names/offsets"][:]
+        assert len(offsets) == len(df) + 1
+        for i, expected in enumerate(df["dataset_names"].to_list()):
+            row = codes[offsets[i] : offsets[i + 1]]
+            assert [names[code] for code in row] == expected
+
+
+def test_rocksdb_revindex_to_parquet_zarr(runtmp):
+    pytest.importorskip("pyarrow")
+    zarr = pytest.importorskip("zarr")
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    out_zarr = runtmp.output("test6.zarr")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out_parquet, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_zarr,
+        "-t",
+        tax_csv,
+        "--format",
+        "zarr",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    store = zarr.open_group(out_zarr, mode="r")
+    assert list(store["hash"][:]) == df["hash"].to_list()
+    assert list(store["dataset_count"][:]) == [
+        len(names) for names in df["dataset_names"].to_list()
+    ]
+    lineages = list(store["lca_lineage/values"][:])
+    assert [
+        lineages[code] if code >= 0 else None
+        for code in store["lca_lineage/codes"][:]
+    ] == df["lca_lineage"].to_list()
+
+    # an existing store is only replaced with --force
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out_zarr,
+            "--format",
+            "zarr",
+        )
+
+
+def test_rocksdb_revindex_to_parquet_hash2taxid(runtmp):
+    pytest.importorskip("pyarrow")
+    import numpy as np
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    out_tsv = runtmp.output("test6.hash2taxid.tsv")
+    out_bin = runtmp.output("test6.bin")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out_parquet, "-t", tax_csv
+    )
+    for out in (out_tsv, out_bin):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out,
+            "-t",
+            tax_csv,
+            "--format",
+            "hash2taxid",
+        )
+
+    df = pl.read_parquet(out_parquet)
+    table = pd.read_csv(out_tsv, sep="\t", dtype={"hash": "uint64"})
+    assert table["hash"].tolist() == df["hash"].to_list()
+
+    # taxids resolve to the LCA lineages through the nodes table
+    nodes = pd.read_csv(out_tsv + ".nodes.tsv", sep="\t", keep_default_na=False)
+    lineage_of = dict(zip(nodes["taxid"], nodes["lineage"]))
+    assert lineage_of[1] == ""
+    assert all(
+        lineage_of[parent] != lineage
+        for parent, lineage in zip(nodes["parent_taxid"], nodes["lineage"])
+        if lineage
+    )
+    expected = [lineage or None for lineage in df["lca_lineage"].to_list()]
+    assert [lineage_of.get(t) for t in table["taxid"]] == expected
+
+    records = np.fromfile(out_bin, dtype=[("hash", "<u8"), ("taxid", "<u4")])
+    assert records["hash"].tolist() == table["hash"].tolist()
+    assert records["taxid"].tolist() == table["taxid"].tolist()
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("untaxed.tsv"),
+            "--format",
+            "hash2taxid",
+        )
+    assert "--format hash2taxid needs --taxonomy" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_hash2taxid_blank_rank(runtmp):
+    pytest.importorskip("pyarrow")
+
+    revindex = get_test_data("test6.rocksdb")
+    tax = pl.read_csv(get_test_data("test6.taxonomy.csv"))
+    # no family for the first lineage
+    ident = tax["ident"][0]
+    tax = tax.with_columns(
+        pl.when(pl.col("ident") == ident)
+        .then(None)
+        .otherwise(pl.col("family"))
+        .alias("family")
+    )
+    tax_csv = runtmp.output("blank.taxonomy.csv")
+    tax.write_csv(tax_csv)
+    out_tsv = runtmp.output("test6.hash2taxid.tsv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_tsv,
+        "-t",
+        tax_csv,
+        "--format",
+        "hash2taxid",
+    )
+
+    nodes = pd.read_csv(out_tsv + ".nodes.tsv", sep="\t", keep_default_na=False)
+    by_lineage = {row.lineage: row for row in nodes.itertuples()}
+    by_taxid = {row.taxid: row for row in nodes.itertuples()}
+    # blank ranks get no node
+    assert (nodes["name"] != "").all()
+
+    row = tax.row(0, named=True)
+    order = ";".join(row[rank] for rank in ["domain", "phylum", "class", "order"])
+    genus = f"{order};;{row['genus']}"
+    species = f"{genus};{row['species']}"
+    assert by_lineage[species].rank == "species"
+    assert by_lineage[genus].rank == "genus"
+    assert by_taxid[by_lineage[genus].parent_taxid].lineage == order
+    assert by_lineage[order].rank == "order"
+
+
+def test_rocksdb_revindex_to_parquet_format_removes_tmp(runtmp, monkeypatch):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out = runtmp.output("test6.tsv")
+
+    # a failed export
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out,
+            "-t",
+            tax_csv,
+            "--format",
+            "hash2taxid",
+            "--unclassified-output",
+            runtmp.output("missing-dir/unclassified.parquet"),
+        )
+    assert os.listdir(runtmp.location) == []
+
+    # an interrupted export that keeps its partial output
+    export = rust.do_export_to_parquet
+
+    def cancelled_export(*args, **kwargs):
+        handle = rust.ExportHandle()
+        handle.cancel()
+        return export(*args, handle=handle, **kwargs)
+
+    monkeypatch.setattr(rust, "do_export_to_parquet", cancelled_export)
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out,
+            "-t",
+            tax_csv,
+            "--format",
+            "hash2taxid",
+            "--on-interrupt",
+            "keep",
+        )
+    assert runtmp.last_result.status == 130
+    assert os.listdir(runtmp.location) == []
+
+
+def test_rocksdb_revindex_to_parquet_bloom_filter(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out = runtmp.output("test6.parquet")
+    bloom = runtmp.output("test6.bloom")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out,
+        "--bloom-filter",
+        bloom,
+        "--bloom-filter-fpp",
+        "0.001",
+    )
+
+    # a split-block filter is whole 32-byte blocks, with no false negatives
+    assert os.path.getsize(bloom) % 32 == 0
+    hashes = pl.read_parquet(out)["hash"].to_list()
+    assert all(rust.bloom_filter_contains(bloom, hashes))
+
+    # hashes not in the export are rejected, up to the false positive rate
+    exported = set(hashes)
+    others = [h for h in range(1, 2000) if h not in exported]
+    false_positives = sum(rust.bloom_filter_contains(bloom, others))
+    assert false_positives <= 0.01 * len(others)
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("bad.parquet"),
+            "--bloom-filter",
+            bloom,
+            "--bloom-filter-fpp",
+            "1.5",
+        )
+    assert "false positive rate must be in (0, 1)" in runtmp.last_result.err
+
+    # a filter that can't be written leaves no output either
+    failed = runtmp.output("failed.parquet")
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            failed,
+            "--bloom-filter",
+            runtmp.output("missing-dir/test6.bloom"),
+        )
+    assert not os.path.exists(failed)
+    assert not os.path.exists(failed + ".tmp")
+
+
+def test_rocksdb_revindex_to_parquet_cooccurrence(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    pairs_csv = runtmp.output("test6.pairs.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--cooccurrence",
+        pairs_csv,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    pairs = pd.read_csv(pairs_csv)
+    assert list(pairs.columns) == ["source", "dataset_a", "dataset_b", "shared_hashes"]
+
+    # each hash adds one to every pair of its datasets
+    expected = {}
+    for names in df["dataset_names"].to_list():
+        names = sorted(names)
+        for i, a in enumerate(names):
+            for b in names[i + 1 :]:
+                expected[(a, b)] = expected.get((a, b), 0) + 1
+    found = {
+        tuple(sorted((row.dataset_a, row.dataset_b))): row.shared_hashes
+        for row in pairs.itertuples()
+    }
+    assert found == expected
+
+    # thresholded
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--force",
+        "--cooccurrence",
+        pairs_csv,
+        "--cooccurrence-min-shared",
+        "1000000",
+    )
+    assert len(pd.read_csv(pairs_csv)) == 0
+
+
+def test_rocksdb_revindex_to_parquet_lineage_hll(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    hll_csv = runtmp.output("test6.hll.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--lineage-hll",
+        hll_csv,
+        "--hll-rank",
+        "family",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    hll = pd.read_csv(hll_csv)
+    assert set(hll["rank"]) == {"family"}
+
+    # each hash counts once for every family among its datasets
+    expected = {}
+    for lineages in df["taxonomy_list"].to_list():
+        families = {";".join(lin.split(";")[:5]) for lin in lineages or []}
+        for family in families:
+            expected[family] = expected.get(family, 0) + 1
+    assert dict(zip(hll["lineage"], hll["hashes"])) == expected
+
+    # one database: every hash is distinct, up to the HLL error
+    for row in hll.itertuples():
+        assert abs(row.distinct_hashes - row.hashes) <= 0.1 * row.hashes + 5
+
+
+def test_rocksdb_revindex_to_parquet_lca_info_classified(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    podar = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("out.parquet")
+    lca_csv = runtmp.output("out.lca.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        podar,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--lca-info",
+        lca_csv,
+        "--allow-mixed-params",
+    )
+
+    lca_df = pd.read_csv(lca_csv)
+    test6 = lca_df[lca_df["source"] == "test6.rocksdb"]
+    # most general rank first
+    assert list(test6["lca_rank"]) == ["order", "family", "species"]
+    assert list(test6["cumulative_count"]) == [23910, 23909, 23901]
+    assert test6["percent_classified"].sum() == pytest.approx(100.0, abs=0.02)
+
+    # percent of classified is left empty where there is no LCA
+    podar_rows = lca_df[lca_df["source"] != "test6.rocksdb"]
+    assert list(podar_rows["lca_rank"]) == ["unclassified"]
+    assert podar_rows["percent_classified"].isna().all()
+    assert podar_rows["cumulative_count"].isna().all()
+
+
+def test_rocksdb_revindex_to_parquet_deterministic(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    podar = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+
+    outputs = []
+    for run in range(2):
+        out_parquet = runtmp.output(f"run{run}.parquet")
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            podar,
+            "-o",
+            out_parquet,
+            "-t",
+            tax_csv,
+            "--allow-mixed-params",
+            "--deterministic",
+        )
+        with open(out_parquet, "rb") as fp:
+            outputs.append(fp.read())
+
+    assert outputs[0] == outputs[1]
+
+    # databases come out in the order given
+    df = pl.read_parquet(runtmp.output("run0.parquet"))
+    sources = df["source"].to_list()
+    assert sources[0] == "test6.rocksdb"
+    assert sources[-1] == "podar-ref-subset.branch0_9_13.internal.rocksdb"
+    assert sources.index(sources[-1]) == sources.count("test6.rocksdb")
+
+
+def test_export_schema_version(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+    info = rust.parquet_inspect(out_parquet)
+    version = int(info["metadata"]["export_schema_version"])
+
+    schema = rust.get_schema()
+    assert schema["version"] == version
+    df = pl.read_parquet(out_parquet)
+    assert [col["name"] for col in schema["columns"]] == df.columns
+    assert rust.get_schema(version) == schema
+
+    with pytest.raises(ValueError, match="unknown export schema version"):
+        rust.get_schema(version + 1)
+
+
+def test_rocksdb_revindex_to_parquet_sharded_output(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    single = runtmp.output("single.parquet")
+    sharded = runtmp.output("sharded.parquet")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", single)
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        sharded,
+        "--sharded-output",
+        "hash:4",
+    )
+
+    assert os.path.isdir(sharded)
+    with open(os.path.join(sharded, "_export.json")) as fp:
+        metadata = json.load(fp)
+    assert metadata["partitioning"] == "hash:4"
+    assert not metadata["partial"]
+    assert len(metadata["files"]) == 4
+
+    expected = pl.read_parquet(single)
+    assert metadata["rows_written"] == len(expected)
+    parts = []
+    for bucket, entry in enumerate(metadata["files"]):
+        assert entry["path"] == f"bucket={bucket}/part-0.parquet"
+        part = pl.read_parquet(os.path.join(sharded, entry["path"]))
+        assert len(part) == entry["rows"]
+        assert all(h % 4 == bucket for h in part["hash"].to_list())
+        parts.append(part)
+    df = pl.concat(parts)
+    assert df.sort("hash").equals(expected.sort("hash"))
+
+    # no clobbering without --force
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            sharded,
+            "--sharded-output",
+            "source",
+        )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        sharded,
+        "--sharded-output",
+        "source",
+        "--force",
+    )
+    with open(os.path.join(sharded, "_export.json")) as fp:
+        metadata = json.load(fp)
+    assert [entry["path"] for entry in metadata["files"]] == [
+        "source=test6.rocksdb/part-0.parquet"
+    ]
+    assert not os.path.exists(os.path.join(sharded, "bucket=0"))
+
+    # --force replaces a single file with a directory, and the other way round
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        single,
+        "--partition-by-hash",
+        "2",
+        "--force",
+    )
+    assert os.path.isdir(single)
+    assert not os.path.exists(single + ".tmp")
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", sharded, "--force"
+    )
+    assert os.path.isfile(sharded)
+    assert pl.read_parquet(sharded).sort("hash").equals(expected.sort("hash"))
+
+
+def test_rocksdb_revindex_to_parquet_partition_by_hash(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    full = runtmp.output("full.parquet")
+    sample = runtmp.output("sample.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        full,
+        "--partition-by-hash",
+        "3",
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        sample,
+        "--partition-by-hash",
+        "3",
+        "--sample-fraction",
+        "0.5",
+    )
+
+    with open(os.path.join(full, "_export.json")) as fp:
+        assert json.load(fp)["partitioning"] == "hash:3"
+
+    def bucket(path, b):
+        return pl.read_parquet(os.path.join(path, f"bucket={b}/part-0.parquet"))
+
+    # joining bucket by bucket gives the same rows as a global join
+    full_df = pl.concat(bucket(full, b) for b in range(3))
+    sample_df = pl.concat(bucket(sample, b) for b in range(3))
+    expected = full_df.join(sample_df, on="hash").sort("hash")
+    joined = pl.concat(
+        bucket(full, b).join(bucket(sample, b), on="hash") for b in range(3)
+    ).sort("hash")
+    assert len(joined) > 0
+    assert joined.equals(expected)
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("both.parquet"),
+            "--partition-by-hash",
+            "3",
+            "--sharded-output",
+            "source",
+        )
+
+
+def test_rocksdb_revindex_to_parquet_writer_error(runtmp):
+    # the writer can't create its file: the export stops with the writer's error
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("missing-dir/test6.parquet")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+
+    err = runtmp.last_result.err
+    assert "No such file or directory" in err
+    assert "closed channel" not in err
+    assert "Finished writing Parquet" not in err
+
+
+def test_rocksdb_revindex_to_parquet_failure_removes_tmp(runtmp):
+    # the main file is created before the unclassified one fails
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    unclassified = runtmp.output("missing-dir/unclassified.parquet")
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out_parquet,
+            "-t",
+            get_test_data("test6.taxonomy.csv"),
+            "--unclassified-output",
+            unclassified,
+        )
+
+    assert "No such file or directory" in runtmp.last_result.err
+    assert not os.path.exists(out_parquet)
+    assert not os.path.exists(out_parquet + ".tmp")
+
+
+def test_rocksdb_revindex_to_parquet_dataset_name(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    datasets = rust.list_datasets(revindex)
+    md5s = {d["md5"] for d in datasets}
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--dataset-name",
+        "md5,name",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    names = set(df["dataset_names"].explode())
+    assert names == md5s
+
+    # dataset IDs; an unknown field is rejected
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--dataset-name",
+        "idx",
+        "--force",
+    )
+    df = pl.read_parquet(out_parquet)
+    names = set(df["dataset_names"].explode())
+    assert names == {str(idx) for idx in range(len(datasets))}
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            out_parquet,
+            "--dataset-name",
+            "accession",
+            "--force",
+        )
+    assert "unknown dataset name field 'accession'" in runtmp.last_result.err
+
+
+def test_rocksdb_revindex_to_parquet_anonymize(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    plain_parquet = runtmp.output("plain.parquet")
+    out_parquet = runtmp.output("anon.parquet")
+    again_parquet = runtmp.output("again.parquet")
+    mapping_csv = runtmp.output("names.csv")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", plain_parquet, "-t", tax_csv
+    )
+    for output in (out_parquet, again_parquet):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            output,
+            "-t",
+            tax_csv,
+            "--anonymize",
+            mapping_csv,
+            "--anonymize-salt",
+            "s3cret",
+        )
+
+    plain = pl.read_parquet(plain_parquet).sort("hash")
+    df = pl.read_parquet(out_parquet).sort("hash")
+    # the same salt gives the same names
+    assert df.equals(pl.read_parquet(again_parquet).sort("hash"))
+
+    with open(mapping_csv, newline="") as fp:
+        mapping = {row["dataset"]: row["anonymized"] for row in csv.DictReader(fp)}
+    assert set(mapping) == set(plain["dataset_names"].explode())
+    assert all(name.startswith("ds_") and len(name) == 35 for name in mapping.values())
+    assert len(set(mapping.values())) == len(mapping)
+
+    # names are replaced after the taxonomy lookup
+    assert df["hash"].to_list() == plain["hash"].to_list()
+    assert df["lca_lineage"].to_list() == plain["lca_lineage"].to_list()
+    for names, anonymized in zip(
+        plain["dataset_names"].to_list(), df["dataset_names"].to_list()
+    ):
+        assert anonymized == [mapping[name] for name in names]
+
+
+def test_rocksdb_revindex_to_parquet_dataset_labels(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    labels_csv = runtmp.output("labels.csv")
+    datasets = rust.list_datasets(revindex)
+
+    # label the first dataset by md5 and the second by name
+    with open(labels_csv, "w", newline="") as fp:
+        w = csv.writer(fp)
+        w.writerow(["name", "md5", "label"])
+        w.writerow(["", datasets[0]["md5"], "strain_A"])
+        w.writerow([datasets[1]["name"], "", "strain_B"])
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--dataset-labels",
+        labels_csv,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    names = set(df["dataset_names"].explode())
+    expected = {d["name"] or d["filename"] for d in datasets[2:]}
+    assert names == {"strain_A", "strain_B"} | expected
+
+
+def test_revindex_to_adbc_sqlite(runtmp):
+    adbc_sqlite = pytest.importorskip("adbc_driver_sqlite.dbapi")
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    db = runtmp.output("test6.sqlite")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out_parquet, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_adbc",
+        revindex,
+        "-t",
+        tax_csv,
+        "--uri",
+        f"sqlite:{db}",
+        "--table",
+        "hashes",
+        "--columns",
+        "lca_lineage",
+        "source",
+    )
+    assert "Loaded" in runtmp.last_result.err
+
+    expected = pl.read_parquet(out_parquet)
+    with adbc_sqlite.connect(db) as conn:
+        with conn.cursor() as cursor:
+            cursor.execute("SELECT lca_lineage, source FROM hashes")
+            rows = cursor.fetchall()
+    assert len(rows) == len(expected)
+    assert {source for _, source in rows} == {"test6.rocksdb"}
+    assert sorted(lineage or "" for lineage, _ in rows) == sorted(
+        lineage or "" for lineage in expected["lca_lineage"].to_list()
+    )
+
+    # the table exists now, so creating it again fails
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_adbc",
+            revindex,
+            "--uri",
+            f"sqlite:{db}",
+            "--table",
+            "hashes",
+            "--columns",
+            "source",
+        )
+
+
+def read_varint(data, pos):
+    value = shift = 0
+    while True:
+        byte = data[pos]
+        pos += 1
+        value |= (byte & 0x7F) << shift
+        shift += 7
+        if byte < 0x80:
+            return value, pos
+
+
+def test_clickhouse_stream(runtmp):
+    from sourmash_plugin_export import sourmash_plugin_export as rust
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    rust.export(rust.ExportConfig([revindex], out_parquet).taxonomy([tax_csv]))
+
+    columns = ["hash", "dataset_names", "lca_lineage"]
+    config = rust.ExportConfig([revindex]).taxonomy([tax_csv]).columns(columns)
+    stream = rust.clickhouse_stream(config)
+    create = stream.create_table_sql("gtdb")
+    assert "`dataset_names` Array(String)" in create
+    assert "`lca_lineage` Nullable(String)" in create
+    assert create.endswith("ENGINE = MergeTree ORDER BY hash")
+    assert stream.insert_sql("gtdb") == (
+        "INSERT INTO gtdb (`hash`, `dataset_names`, `lca_lineage`) FORMAT RowBinary"
+    )
+
+    # decode the RowBinary rows
+    data = b"".join(stream)
+    rows = []
+    pos = 0
+    while pos < len(data):
+        hash_ = int.from_bytes(data[pos : pos + 8], "little")
+        pos += 8
+        n, pos = read_varint(data, pos)
+        names = []
+        for _ in range(n):
+            length, pos = read_varint(data, pos)
+            names.append(data[pos : pos + length].decode())
+            pos += length
+        lineage = None
+        is_null = data[pos]
+        pos += 1
+        if not is_null:
+            length, pos = read_varint(data, pos)
+            lineage = data[pos : pos + length].decode()
+            pos += length
+        rows.append((hash_, names, lineage))
+
+    expected = pl.read_parquet(out_parquet).select(columns).rows()
+    assert sorted(rows) == sorted((h, list(n), l) for h, n, l in expected)
+
+
+def test_revindex_to_pgcopy(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    out_csv = runtmp.output("test6.copy.csv")
+    out_binary = runtmp.output("test6.copy")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", out_parquet, "-t", tax_csv
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_pgcopy",
+        revindex,
+        "-t",
+        tax_csv,
+        "--format",
+        "csv",
+        "-o",
+        out_csv,
+        "--columns",
+        "hash",
+        "dataset_names",
+        "lca_rank",
+    )
+
+    expected = pl.read_parquet(out_parquet)
+    with open(out_csv, newline="") as fp:
+        rows = list(csv.reader(fp))
+    assert len(rows) == len(expected)
+    by_hash = {int(h): (names, rank) for h, names, rank in rows}
+    for h, names, rank in expected.select(
+        "hash", "dataset_names", "lca_rank"
+    ).iter_rows():
+        assert by_hash[h][0] == "{" + ",".join(f'"{n}"' for n in names) + "}"
+        assert by_hash[h][1] == (rank or "")
+
+    # binary: the PGCOPY signature, then one tuple per row, then the trailer
+    runtmp.sourmash(
+        "scripts", "revindex_to_pgcopy", revindex, "-t", tax_csv, "-o", out_binary
+    )
+    with open(out_binary, "rb") as fp:
+        data = fp.read()
+    assert data.startswith(b"PGCOPY\n\xff\r\n\x00")
+    assert data.endswith(b"\xff\xff")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_pgcopy",
+        revindex,
+        "--table",
+        "gtdb",
+        "--print-sql",
+        "--columns",
+        "hash",
+        "source",
+    )
+    assert runtmp.last_result.out.splitlines() == [
+        "CREATE TABLE IF NOT EXISTS gtdb (hash numeric(20) NOT NULL, source text NOT NULL);",
+        "COPY gtdb (hash, source) FROM STDIN (FORMAT binary);",
+    ]
+
+
+def test_sigs_to_parquet_with_abundance(runtmp):
+    import sourmash
+    from sourmash.save_load import SaveSignaturesToLocation
+
+    abund = sourmash.MinHash(n=0, ksize=31, scaled=1, track_abundance=True)
+    abund.set_abundances({1: 3, 2: 1, 5: 7})
+    flat = sourmash.MinHash(n=0, ksize=31, scaled=1)
+    flat.add_many([5, 9])
+    sigs = runtmp.output("mixed.sig")
+    with SaveSignaturesToLocation(sigs) as save_sigs:
+        save_sigs.add(sourmash.SourmashSignature(abund, name="abund"))
+        save_sigs.add(sourmash.SourmashSignature(flat, name="flat"))
+    out_parquet = runtmp.output("mixed.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "sigs_to_parquet",
+        sigs,
+        "-o",
+        out_parquet,
+        "-k",
+        "31",
+        "--with-abundance",
+    )
+
+    df = pl.read_parquet(out_parquet).sort("hash")
+    assert df.columns[-2:] == ["abundances", "total_abundance"]
+    # hashes shared with a sketch without abundances have none
+    assert df["abundances"].to_list() == [[3], [1], None, None]
+    assert df["total_abundance"].to_list() == [3, 1, None, None]
+
+
+def test_rocksdb_revindex_to_parquet_with_abundance(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "--with-abundance",
+    )
+
+    # test6 was sketched without abundances, so the columns are all null
+    df = pl.read_parquet(out_parquet)
+    assert df["abundances"].null_count() == len(df)
+    assert df["total_abundance"].null_count() == len(df)
+
+    runtmp.sourmash("scripts", "verify_export", out_parquet, revindex)
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("merged.parquet"),
+            "--with-abundance",
+            "--merge",
+        )
+
+
+def test_rocksdb_revindex_to_parquet_taxonomy_stdin(runtmp):
+    # the taxonomy is read by the Rust extension from the process's stdin,
+    # so this runs the command in a subprocess
+    import subprocess
+    import sys
+
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    from_file = runtmp.output("from_file.parquet")
+    from_stdin = runtmp.output("from_stdin.parquet")
+
+    runtmp.sourmash(
+        "scripts", "revindex_to_parquet", revindex, "-o", from_file, "-t", tax_csv
+    )
+    with open(tax_csv, "rb") as fp:
+        subprocess.run(
+            [
+                sys.executable,
+                "-m",
+                "sourmash",
+                "scripts",
+                "revindex_to_parquet",
+                revindex,
+                "-o",
+                from_stdin,
+                "-t",
+                "-",
+            ],
+            stdin=fp,
+            check=True,
+        )
+
+    expected = pl.read_parquet(from_file)
+    df = pl.read_parquet(from_stdin)
+    assert df["lca_lineage"].to_list() == expected["lca_lineage"].to_list()
+    assert df["lca_lineage"].drop_nulls().len() > 0
+
+    # stdin can only be read once
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("twice.parquet"),
+            "-t",
+            "-",
+            "-",
+        )
+
+
+def test_rocksdb_revindex_to_parquet_lineage_table(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    lineages_parquet = runtmp.output("test6.lineages.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--lineage-table",
+        lineages_parquet,
+    )
+
+    df = pl.read_parquet(out_parquet)
+    lineages = pl.read_parquet(lineages_parquet)
+    assert lineages.columns == [
+        "lineage_id",
+        "domain",
+        "phylum",
+        "class",
+        "order",
+        "family",
+        "genus",
+        "species",
+        "lineage",
+    ]
+    assert lineages["lineage_id"].to_list() == list(range(1, len(lineages) + 1))
+    assert sorted(lineages["lineage"].to_list()) == sorted(
+        df.filter(pl.col("lca_lineage") != "")["lca_lineage"].drop_nulls().unique()
+    )
+
+    # joining on the ID gives back each hash's LCA lineage
+    joined = df.join(
+        lineages, left_on="lca_lineage_id", right_on="lineage_id", how="left"
+    )
+    for lca, lineage in joined.select("lca_lineage", "lineage").iter_rows():
+        assert lineage == (lca or None)
+    assert all(d.startswith("d__") for d in lineages["domain"].to_list())
+
+    # the lineage table needs a taxonomy
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("notax.parquet"),
+            "--lineage-table",
+            runtmp.output("notax.lineages.parquet"),
+        )
+
+
+def test_rocksdb_revindex_to_parquet_dataset_metadata(runtmp):
+    pa_ds = pytest.importorskip("pyarrow.dataset")
+    pq = pytest.importorskip("pyarrow.parquet")
+    revindex = get_test_data("test6.rocksdb")
+    sharded = runtmp.output("sharded.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        sharded,
+        "--sharded-output",
+        "hash:4",
+    )
+    with open(os.path.join(sharded, "_export.json")) as fp:
+        export = json.load(fp)
+
+    # _metadata lists the row groups of every file
+    metadata = pq.read_metadata(os.path.join(sharded, "_metadata"))
+    assert metadata.num_rows == export["rows_written"]
+    paths = set()
+    for i in range(metadata.num_row_groups):
+        paths.add(metadata.row_group(i).column(0).file_path)
+    assert paths <= {entry["path"] for entry in export["files"]}
+    common = pq.read_schema(os.path.join(sharded, "_common_metadata"))
+    assert common.names == pq.read_schema(
+        os.path.join(sharded, export["files"][0]["path"])
+    ).names
+
+    dataset = pa_ds.parquet_dataset(os.path.join(sharded, "_metadata"))
+    assert dataset.to_table().num_rows == export["rows_written"]
+
+    # rewrite_parquet --partition-by writes them too
+    single = runtmp.output("single.parquet")
+    partitioned = runtmp.output("partitioned")
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", single)
+    runtmp.sourmash(
+        "scripts",
+        "rewrite_parquet",
+        single,
+        "-o",
+        partitioned,
+        "--partition-by",
+        "hash:2",
+    )
+    metadata = pq.read_metadata(os.path.join(partitioned, "_metadata"))
+    assert metadata.num_rows == pq.read_metadata(single).num_rows
+    assert os.path.exists(os.path.join(partitioned, "_common_metadata"))
+
+
+def test_rocksdb_revindex_to_parquet_ksize_partitions(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    podar = get_test_data("podar-ref-subset.branch0_9_13.internal.rocksdb")
+    single = runtmp.output("single.parquet")
+    by_ksize = runtmp.output("by_ksize.parquet")
+
+    # databases with one ksize still make a single file
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        podar,
+        "-o",
+        single,
+        "--allow-mixed-params",
+    )
+    assert os.path.isfile(single)
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        by_ksize,
+        "--sharded-output",
+        "ksize",
+    )
+    with open(os.path.join(by_ksize, "_export.json")) as fp:
+        metadata = json.load(fp)
+    assert metadata["partitioning"] == "ksize"
+    assert [entry["path"] for entry in metadata["files"]] == [
+        "ksize=31/part-0.parquet"
+    ]
+
+    rewritten = runtmp.output("rewritten")
+    runtmp.sourmash(
+        "scripts",
+        "rewrite_parquet",
+        single,
+        "-o",
+        rewritten,
+        "--partition-by",
+        "ksize",
+    )
+    part = pl.read_parquet(os.path.join(rewritten, "ksize=31", "part-0.parquet"))
+    assert part.sort("hash", "source").equals(
+        pl.read_parquet(single).sort("hash", "source")
+    )
+
+
+def test_rocksdb_revindex_to_parquet_top_shared(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+    shared_csv = runtmp.output("test6.shared.csv")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--top-shared",
+        shared_csv,
+        "--top-shared-n",
+        "5",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    shared = pd.read_csv(shared_csv, keep_default_na=False)
+    assert list(shared.columns) == [
+        "source",
+        "hash",
+        "dataset_count",
+        "lca_rank",
+        "lca_lineage",
+    ]
+    assert len(shared) == 5
+    assert (shared["source"] == "test6.rocksdb").all()
+
+    # the most widely shared hashes, most shared first, lower hashes first
+    expected = (
+        df.with_columns(pl.col("dataset_names").list.len().alias("dataset_count"))
+        .sort(["dataset_count", "hash"], descending=[True, False])
+        .head(5)
+    )
+    assert shared["hash"].tolist() == expected["hash"].to_list()
+    assert shared["dataset_count"].tolist() == expected["dataset_count"].to_list()
+    assert shared["lca_lineage"].tolist() == [
+        lineage or "" for lineage in expected["lca_lineage"].to_list()
+    ]
+
+
+def test_rocksdb_revindex_to_parquet_flag_contamination(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    domain = runtmp.output("domain.parquet")
+    family = runtmp.output("family.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        domain,
+        "-t",
+        tax_csv,
+        "--flag-contamination",
+    )
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        family,
+        "-t",
+        tax_csv,
+        "--flag-contamination",
+        "family",
+    )
+
+    ranks = ["domain", "phylum", "class", "order", "family", "genus", "species"]
+    for path, rank in [(domain, "domain"), (family, "family")]:
+        df = pl.read_parquet(path)
+        assert df.columns[-1] == "contamination_flag"
+        for taxonomy, lca_rank, flag in df.select(
+            "taxonomy_list", "lca_rank", "contamination_flag"
+        ).iter_rows():
+            if not taxonomy:
+                assert flag is None
+            else:
+                above = lca_rank is None or ranks.index(lca_rank) < ranks.index(rank)
+                assert flag == above
+
+    # test6 genomes share an order, so only the family flag is ever set
+    assert not pl.read_parquet(domain)["contamination_flag"].any()
+    assert pl.read_parquet(family)["contamination_flag"].any()
+
+    with pytest.raises(SourmashCommandFailed):
+        runtmp.sourmash(
+            "scripts",
+            "revindex_to_parquet",
+            revindex,
+            "-o",
+            runtmp.output("bad.parquet"),
+            "-t",
+            tax_csv,
+            "--flag-contamination",
+            "kingdom",
+        )
+
+
+def test_rocksdb_revindex_to_parquet_disagreement_metrics(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    tax_csv = get_test_data("test6.taxonomy.csv")
+    out_parquet = runtmp.output("test6.parquet")
+
+    runtmp.sourmash(
+        "scripts",
+        "revindex_to_parquet",
+        revindex,
+        "-o",
+        out_parquet,
+        "-t",
+        tax_csv,
+        "--disagreement-metrics",
+    )
+
+    df = pl.read_parquet(out_parquet)
+    assert df.columns[-3:] == [
+        "distinct_genera",
+        "distinct_species",
+        "lineage_entropy",
+    ]
+    for taxonomy, genera, species, entropy in df.select(
+        "taxonomy_list", "distinct_genera", "distinct_species", "lineage_entropy"
+    ).iter_rows():
+        if not taxonomy:
+            assert genera is None and species is None and entropy is None
+            continue
+        ranks = [lineage.split(";") for lineage in taxonomy]
+        assert genera == len({";".join(r[:6]) for r in ranks if len(r) > 5})
+        assert species == len({";".join(r[:7]) for r in ranks if len(r) > 6})
+        counts = Counter(taxonomy)
+        expected = -sum(
+            c / len(taxonomy) * math.log2(c / len(taxonomy)) for c in counts.values()
+        )
+        assert entropy == pytest.approx(expected)
+        if len(counts) == 1:
+            assert entropy == 0
+    assert df["lineage_entropy"].max() > 0
+
+
+def test_revindex_pairwise(runtmp):
+    revindex = get_test_data("test6.rocksdb")
+    out_parquet = runtmp.output("test6.parquet")
+    pairs_parquet = runtmp.output("test6.pairs.parquet")
+    matrix_csv = runtmp.output("test6.matrix.csv")
+
+    runtmp.sourmash("scripts", "revindex_to_parquet", revindex, "-o", out_parquet)
+    runtmp.sourmash(
+        "scripts",
+        "revindex_pairwise",
+        revindex,
+        "-o",
+        pairs_parquet,
+        "--matrix",
+        matrix_csv,
+    )
+
+    # sizes and shared hashes, from the export
+    sizes = Counter()
+    shared = Counter()
+    for names in pl.read_parquet(out_parquet)["dataset_names"].to_list():
+        names = sorted(set(names))
+        sizes.update(names)
+        for i, a in enumerate(names):
+            for b in names[i + 1 :]:
+                shared[(a, b)] += 1
+
+    pairs = pl.read_parquet(pairs_parquet)
+    assert len(pairs) == len(shared)
+    assert pairs["shared_hashes"].to_list() == sorted(
+        pairs["shared_hashes"].to_list(), reverse=True
+    )
+    for row in pairs.iter_rows(named=True):
+        a, b = row["dataset_a"], row["dataset_b"]
+        n = shared[tuple(sorted((a, b)))]
+        assert row["shared_hashes"] == n
+        assert (row["hashes_a"], row["hashes_b"]) == (sizes[a], sizes[b])
+        assert row["containment_a_in_b"] == pytest.approx(n / sizes[a])
+        assert row["containment_b_in_a"] == pytest.approx(n / sizes[b])
+        assert row["max_containment"] == pytest.approx(n / min(sizes[a], sizes[b]))
+        assert row["jaccard"] == pytest.approx(n / (sizes[a] + sizes[b] - n))
+
+    # a symmetric Jaccard matrix, with one on the diagonal
+    matrix = pd.read_csv(matrix_csv)
+    names = list(matrix.columns)
+    assert len(matrix) == len(names) == len(sizes)
+    for i, a in enumerate(names):
+        assert matrix.iloc[i, i] == 1.0
+        for j, b in enumerate(names):
+            assert matrix.iloc[i, j] == pytest.approx(matrix.iloc[j, i])
+            if a != b:
+                n = shared[tuple(sorted((a, b)))]
+                assert matrix.iloc[i, j] == pytest.approx(
+                    n / (sizes[a] + sizes[b] - n)
+                )